@@ -0,0 +1,55 @@
+use std::time::{Duration, Instant};
+
+use log::warn;
+
+use crate::client::interface::{Notification, Severity};
+
+/// Delay imposed on the requests to an api after it reported being rate limited,
+/// doubled each time the api reports it again
+#[derive(Debug)]
+pub struct Backoff {
+    delay: Duration,
+    /// time before which no request should be sent
+    until: Option<Instant>,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            delay: Self::INITIAL_DELAY,
+            until: None,
+        }
+    }
+}
+
+impl Backoff {
+    const INITIAL_DELAY: Duration = Duration::from_secs(2);
+    const MAX_DELAY: Duration = Duration::from_secs(15 * 60);
+
+    /// returns `true` while requests should be held back
+    pub fn is_waiting(&self) -> bool {
+        self.until.is_some_and(|until| Instant::now() < until)
+    }
+
+    /// time at which requests can be sent again, if they are held back
+    pub fn until(&self) -> Option<Instant> {
+        self.until.filter(|until| Instant::now() < *until)
+    }
+
+    /// Hold back the requests to the api of `client` after it reported a rate limit,
+    /// returns the notification telling the user when requests resume
+    pub fn hold(&mut self, client: &str) -> Notification {
+        let delay = self.delay;
+        self.until = Some(Instant::now() + delay);
+        self.delay = (delay * 2).min(Self::MAX_DELAY);
+        warn!("[{client}] Rate limited, waiting {}s", delay.as_secs());
+        let message = format!("{client} is rate limited, retrying in {}s", delay.as_secs());
+        Notification::new(Severity::Warning, message)
+    }
+
+    /// Reset the delay once a request succeeded
+    pub fn success(&mut self) {
+        self.delay = Self::INITIAL_DELAY;
+        self.until = None;
+    }
+}
@@ -0,0 +1,108 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+};
+
+use crate::client::interface::{Answer, PlaylistChange, SongInfo};
+
+/// Fingerprints of the songs of each playlist as last sent to the orchestrator, indexed by
+/// the id of the playlist, so that sending a playlist again only carries what changed
+#[derive(Debug, Default)]
+pub struct SentPlaylists {
+    songs: HashMap<String, Vec<u64>>,
+}
+
+impl SentPlaylists {
+    /// Turn an [Answer::Playlist] already sent into an [Answer::PlaylistDelta] and remember
+    /// the songs carried by `answer`. The title and cover of a playlist sent as a delta
+    /// are refreshed by the list of playlists
+    pub fn track(&mut self, answer: Answer) -> Answer {
+        match answer {
+            Answer::PlaylistList(playlists) => {
+                self.songs = playlists
+                    .iter()
+                    .map(|playlist| (playlist.id.clone(), fingerprints(&playlist.songs)))
+                    .collect();
+                Answer::PlaylistList(playlists)
+            }
+            Answer::Playlist(playlist) => {
+                let new = fingerprints(&playlist.songs);
+                let old = self.songs.get(&playlist.id);
+                let changes = old.map(|old| diff(old, &new, &playlist.songs));
+                self.songs.insert(playlist.id.clone(), new);
+                match changes {
+                    Some(changes) => Answer::PlaylistDelta {
+                        id: playlist.id,
+                        length: playlist.length,
+                        changes,
+                    },
+                    None => Answer::Playlist(playlist),
+                }
+            }
+            Answer::PlaylistPage {
+                id,
+                offset,
+                songs,
+                total,
+            } => {
+                // the orchestrator ignores the pages leaving a gap in the playlist
+                if let Some(sent) = self.songs.get_mut(&id).filter(|s| offset <= s.len()) {
+                    sent.truncate(offset);
+                    sent.extend(fingerprints(&songs));
+                }
+                Answer::PlaylistPage {
+                    id,
+                    offset,
+                    songs,
+                    total,
+                }
+            }
+            answer => answer,
+        }
+    }
+}
+
+fn fingerprints(songs: &[SongInfo]) -> Vec<u64> {
+    songs
+        .iter()
+        .map(|song| {
+            let mut hasher = DefaultHasher::new();
+            song.hash(&mut hasher);
+            hasher.finish()
+        })
+        .collect()
+}
+
+/// Changes turning the songs fingerprinted `old` into `songs`, fingerprinted `new`,
+/// covering the range between their common start and their common end
+fn diff(old: &[u64], new: &[u64], songs: &[SongInfo]) -> Vec<PlaylistChange> {
+    let start = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let end = old[start..]
+        .iter()
+        .rev()
+        .zip(new[start..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let removed = old.len() - start - end;
+    let added = &songs[start..new.len() - end];
+    if removed > 0 && removed == added.len() {
+        return vec![PlaylistChange::Replace {
+            index: start,
+            songs: added.to_vec(),
+        }];
+    }
+    let mut changes = Vec::new();
+    if removed > 0 {
+        changes.push(PlaylistChange::Remove {
+            index: start,
+            count: removed,
+        });
+    }
+    if !added.is_empty() {
+        changes.push(PlaylistChange::Insert {
+            index: start,
+            songs: added.to_vec(),
+        });
+    }
+    changes
+}
@@ -0,0 +1,82 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Result};
+use log::{debug, error};
+use tokio::{process::Command, sync::mpsc::Sender};
+
+use crate::{
+    client::interface::{Answer, DownloadProgress, SongInfo},
+    config,
+};
+
+/// Download `song` into `folder`, local files are copied and remote ones are fetched
+/// with `yt-dlp`, returns the path of the downloaded file
+pub async fn download_song(song: &SongInfo, folder: &Path) -> Result<PathBuf> {
+    if let Some(path) = song.url.strip_prefix("file://") {
+        let path = Path::new(path);
+        let name = path
+            .file_name()
+            .ok_or_else(|| anyhow!("{} is not a file", path.display()))?;
+        let target = folder.join(name);
+        tokio::fs::copy(path, &target).await?;
+        return Ok(target);
+    }
+    // the id keeps the names unique when several songs have the same title
+    let template = folder.join("%(title)s [%(id)s].%(ext)s");
+    let output = Command::new("yt-dlp")
+        .args([
+            "--format",
+            "bestaudio/best",
+            "--no-playlist",
+            "--print",
+            "after_move:filepath",
+        ])
+        .arg("--output")
+        .arg(&template)
+        .arg(&song.url)
+        .output()
+        .await?;
+    if !output.status.success() {
+        let errors = String::from_utf8_lossy(&output.stderr);
+        bail!("yt-dlp failed: {}", errors.trim());
+    }
+    let path = String::from_utf8_lossy(&output.stdout);
+    Ok(PathBuf::from(path.trim()))
+}
+
+/// Download `songs` one after the other into the configured folder,
+/// reporting the progress through `answer_tx`
+pub async fn download(songs: Vec<SongInfo>, answer_tx: Sender<Answer>) {
+    let folder = config::get_config().download_folder;
+    if let Err(err) = tokio::fs::create_dir_all(&folder).await {
+        error!("[Download] Cannot create {}: {err}", folder.display());
+    }
+    let total = songs.len();
+    for (done, song) in songs.iter().enumerate() {
+        let mut progress = DownloadProgress {
+            title: song.title.clone(),
+            done,
+            total,
+            error: None,
+        };
+        if answer_tx.send(progress.clone().into()).await.is_err() {
+            // the client has quit
+            return;
+        }
+        match download_song(song, &folder).await {
+            Ok(path) => debug!("[Download] {} saved to {}", song.title, path.display()),
+            Err(err) => {
+                error!("[Download] Failed to download {}: {err}", song.title);
+                progress.error = Some(err.to_string());
+                let _ = answer_tx.send(progress.into()).await;
+            }
+        }
+    }
+    let progress = DownloadProgress {
+        title: String::new(),
+        done: total,
+        total,
+        error: None,
+    };
+    let _ = answer_tx.send(progress.into()).await;
+}
@@ -0,0 +1,461 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+use tokio::{process::Command, sync::mpsc};
+
+use crate::{client::interface::SongInfo, config, json_store};
+
+/// Metadata that can be retrieved by a [MetadataProvider]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Metadata {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub year: Option<u16>,
+    /// MusicBrainz id of the recording, identical for all the copies of a song
+    pub recording_id: Option<String>,
+}
+
+impl Metadata {
+    /// fill the missing fields of `song`, fields already set are left untouched
+    pub fn merge_into(&self, song: &mut SongInfo) {
+        if let (true, Some(title)) = (song.title.is_empty(), &self.title) {
+            song.title = title.clone();
+        }
+        if let (true, Some(artist)) = (song.artist.is_empty(), &self.artist) {
+            song.artist = artist.clone();
+        }
+        if let (true, Some(album)) = (song.album.is_empty(), &self.album) {
+            song.album = album.clone();
+        }
+        if song.year.is_none() {
+            song.year = self.year;
+        }
+    }
+}
+
+type Lookup<'a> = Pin<Box<dyn Future<Output = Result<Option<Metadata>>> + Send + 'a>>;
+
+/// Service used to find the metadata of a song
+pub trait MetadataProvider: Send + Sync {
+    /// returns `Ok(None)` if the song is unknown to the service
+    fn lookup<'a>(&'a self, song: &'a SongInfo) -> Lookup<'a>;
+    /// minimum delay between two lookups, as required by the service
+    fn min_delay(&self) -> Duration;
+    /// returns `false` if the provider cannot handle `song`
+    fn supports(&self, _song: &SongInfo) -> bool {
+        true
+    }
+}
+
+/// Create an http client identified by a meaningful user agent
+/// as requested by MusicBrainz and AcoustID
+fn http_client() -> Result<reqwest::Client> {
+    Ok(reqwest::Client::builder()
+        .user_agent(concat!(
+            "yama/",
+            env!("CARGO_PKG_VERSION"),
+            " ( https://github.com/sofamaniac/yama )"
+        ))
+        .build()?)
+}
+
+pub struct MusicBrainz {
+    client: reqwest::Client,
+}
+
+impl MusicBrainz {
+    const URL: &'static str = "https://musicbrainz.org/ws/2/recording";
+    /// score (out of 100) below which a result is considered a mismatch
+    const MIN_SCORE: u64 = 90;
+
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            client: http_client()?,
+        })
+    }
+
+    async fn search(&self, song: &SongInfo) -> Result<Option<Metadata>> {
+        let mut query = format!("recording:\"{}\"", escape(&song.title));
+        if !song.artist.is_empty() {
+            query.push_str(&format!(" AND artist:\"{}\"", escape(&song.artist)));
+        }
+        let response: serde_json::Value = self
+            .client
+            .get(Self::URL)
+            .query(&[("query", query.as_str()), ("fmt", "json"), ("limit", "1")])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let recording = match response["recordings"].get(0) {
+            Some(recording) => recording,
+            None => return Ok(None),
+        };
+        if recording["score"].as_u64().unwrap_or_default() < Self::MIN_SCORE {
+            return Ok(None);
+        }
+        let artist = recording["artist-credit"]
+            .as_array()
+            .map(|credits| {
+                credits
+                    .iter()
+                    .filter_map(|credit| credit["name"].as_str())
+                    .collect::<Vec<&str>>()
+                    .join(", ")
+            })
+            .filter(|artist| !artist.is_empty());
+        let release = recording["releases"].get(0);
+        let album = release
+            .and_then(|r| r["title"].as_str())
+            .map(|title| title.to_string());
+        let year = release
+            .and_then(|r| r["date"].as_str())
+            .and_then(|date| date.get(..4))
+            .and_then(|year| year.parse().ok());
+        Ok(Some(Metadata {
+            title: None,
+            artist,
+            album,
+            year,
+            recording_id: recording["id"].as_str().map(|id| id.to_string()),
+        }))
+    }
+}
+
+impl MetadataProvider for MusicBrainz {
+    fn lookup<'a>(&'a self, song: &'a SongInfo) -> Lookup<'a> {
+        Box::pin(self.search(song))
+    }
+
+    fn min_delay(&self) -> Duration {
+        // https://musicbrainz.org/doc/MusicBrainz_API/Rate_Limiting
+        Duration::from_secs(1)
+    }
+}
+
+/// Chromaprint fingerprint of an audio file as computed by `fpcalc`
+#[derive(Debug, Clone, Deserialize)]
+pub struct Fingerprint {
+    pub duration: f64,
+    pub fingerprint: String,
+}
+
+impl Fingerprint {
+    /// Compute the fingerprint of the file at `path`,
+    /// requires `fpcalc` from chromaprint to be installed
+    pub async fn compute(path: &Path) -> Result<Self> {
+        let output = Command::new("fpcalc").arg("-json").arg(path).output().await?;
+        if !output.status.success() {
+            bail!(
+                "fpcalc failed on {}: {}",
+                path.display(),
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+        Ok(serde_json::from_slice(&output.stdout)?)
+    }
+}
+
+/// Identify local files from their audio fingerprint, which works for untagged files
+pub struct AcoustId {
+    client: reqwest::Client,
+    key: String,
+}
+
+impl AcoustId {
+    const URL: &'static str = "https://api.acoustid.org/v2/lookup";
+    /// score (out of 1) below which a result is considered a mismatch
+    const MIN_SCORE: f64 = 0.8;
+
+    pub fn new(key: String) -> Result<Self> {
+        Ok(Self {
+            client: http_client()?,
+            key,
+        })
+    }
+
+    async fn identify(&self, song: &SongInfo) -> Result<Option<Metadata>> {
+        let path = match song.url.strip_prefix("file://") {
+            Some(path) => Path::new(path),
+            None => return Ok(None),
+        };
+        let fingerprint = Fingerprint::compute(path).await?;
+        let duration = (fingerprint.duration as u64).to_string();
+        // the fingerprint is too long to be sent in the url
+        let response: serde_json::Value = self
+            .client
+            .post(Self::URL)
+            .form(&[
+                ("client", self.key.as_str()),
+                ("meta", "recordings releases"),
+                ("duration", duration.as_str()),
+                ("fingerprint", fingerprint.fingerprint.as_str()),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let result = match response["results"].get(0) {
+            Some(result) if result["score"].as_f64().unwrap_or_default() >= Self::MIN_SCORE => {
+                result
+            }
+            _ => return Ok(None),
+        };
+        let recording = match result["recordings"].get(0) {
+            Some(recording) => recording,
+            None => return Ok(None),
+        };
+        let artist = recording["artists"]
+            .as_array()
+            .map(|artists| {
+                artists
+                    .iter()
+                    .filter_map(|artist| artist["name"].as_str())
+                    .collect::<Vec<&str>>()
+                    .join(", ")
+            })
+            .filter(|artist| !artist.is_empty());
+        let release = recording["releases"].get(0);
+        Ok(Some(Metadata {
+            title: recording["title"].as_str().map(|title| title.to_string()),
+            artist,
+            album: release
+                .and_then(|r| r["title"].as_str())
+                .map(|title| title.to_string()),
+            year: release
+                .and_then(|r| r["date"]["year"].as_u64())
+                .and_then(|year| u16::try_from(year).ok()),
+            recording_id: recording["id"].as_str().map(|id| id.to_string()),
+        }))
+    }
+}
+
+impl MetadataProvider for AcoustId {
+    fn lookup<'a>(&'a self, song: &'a SongInfo) -> Lookup<'a> {
+        Box::pin(self.identify(song))
+    }
+
+    fn min_delay(&self) -> Duration {
+        // https://acoustid.org/webservice limits to 3 requests per second
+        Duration::from_millis(334)
+    }
+
+    fn supports(&self, song: &SongInfo) -> bool {
+        song.url.starts_with("file://")
+    }
+}
+
+/// Escape the special characters of the lucene query syntax
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Query `providers` in order until one of them knows `song`, a provider failing is skipped,
+/// `None` if none of them knows the song and a lookup failed
+async fn query(
+    providers: &[Box<dyn MetadataProvider>],
+    song: &SongInfo,
+) -> Option<Option<Metadata>> {
+    let mut failed = false;
+    for provider in providers.iter().filter(|p| p.supports(song)) {
+        match provider.lookup(song).await {
+            Ok(None) => continue,
+            Ok(found) => return Some(found),
+            Err(err) => {
+                debug!("[Enrichment] Lookup of {} failed: {err}", song.title);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        None
+    } else {
+        Some(None)
+    }
+}
+
+/// Url of a song looked up and the metadata found, `None` if the lookup failed
+type LookupResult = (String, Option<Option<Metadata>>);
+
+/// Fill missing metadata of songs, lookups results are cached on disk
+/// to avoid querying the provider again
+pub struct Enricher {
+    /// providers queried in order until one of them knows the song
+    providers: Arc<Vec<Box<dyn MetadataProvider>>>,
+    /// lookups results indexed by song url, `None` if the provider found nothing
+    cache: HashMap<String, Option<Metadata>>,
+    cache_path: PathBuf,
+    /// time after which the songs whose lookup failed are looked up again, indexed by url
+    retries: HashMap<String, Instant>,
+    last_lookup: Option<Instant>,
+    /// url of the song being looked up in the background
+    pending: Option<String>,
+    results_tx: mpsc::Sender<LookupResult>,
+    results_rx: mpsc::Receiver<LookupResult>,
+    /// the cache changed since it was last saved
+    modified: bool,
+    last_save: Instant,
+}
+
+impl Enricher {
+    /// time after which a lookup is given up on
+    const LOOKUP_TIMEOUT: Duration = Duration::from_secs(30);
+    /// minimum delay between two writes of the cache
+    const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+    /// delay before looking up again a song whose lookup failed
+    const RETRY_DELAY: Duration = Duration::from_secs(60 * 60);
+
+    /// Create the enricher if enabled in the config,
+    /// AcoustID is only used if an api key is provided
+    pub fn from_config() -> Option<Self> {
+        let config = config::get_config();
+        if !config.metadata_enrichment {
+            return None;
+        }
+        let mut providers: Vec<Box<dyn MetadataProvider>> = Vec::new();
+        if !config.acoustid_key.is_empty() {
+            match AcoustId::new(config.acoustid_key) {
+                Ok(provider) => providers.push(Box::new(provider)),
+                Err(err) => error!("[Enrichment] Failed to create AcoustID client: {err}"),
+            }
+        }
+        match MusicBrainz::new() {
+            Ok(provider) => providers.push(Box::new(provider)),
+            Err(err) => error!("[Enrichment] Failed to create MusicBrainz client: {err}"),
+        }
+        if providers.is_empty() {
+            None
+        } else {
+            Some(Self::new(providers))
+        }
+    }
+
+    pub fn new(providers: Vec<Box<dyn MetadataProvider>>) -> Self {
+        let mut cache_path = config::get_dirs().cache_dir().to_path_buf();
+        cache_path.push("metadata_cache.json");
+        let cache = json_store::load(&cache_path);
+        let (results_tx, results_rx) = mpsc::channel(1);
+        Self {
+            providers: Arc::new(providers),
+            cache,
+            cache_path,
+            retries: HashMap::new(),
+            last_lookup: None,
+            pending: None,
+            results_tx,
+            results_rx,
+            modified: false,
+            last_save: Instant::now(),
+        }
+    }
+
+    /// fill the missing fields of `song` with the cached metadata if any
+    pub fn merge_cached(&self, song: &mut SongInfo) {
+        if let Some(metadata) = self.get_cached(&song.url) {
+            metadata.merge_into(song)
+        }
+    }
+
+    pub fn get_cached(&self, url: &str) -> Option<&Metadata> {
+        self.cache.get(url)?.as_ref()
+    }
+
+    /// returns `true` if `song` is missing some metadata and was never looked up,
+    /// or if its last lookup failed more than [Enricher::RETRY_DELAY] ago
+    pub fn needs_lookup(&self, song: &SongInfo) -> bool {
+        let retry_due = match self.retries.get(&song.url) {
+            Some(retry) => Instant::now() >= *retry,
+            None => true,
+        };
+        (song.artist.is_empty() || song.album.is_empty() || song.year.is_none())
+            && !self.cache.contains_key(&song.url)
+            && retry_due
+    }
+
+    /// returns `true` if no lookup is running and if enough time has passed
+    /// since the last one to query any of the providers
+    pub fn is_ready(&self) -> bool {
+        if self.pending.is_some() {
+            return false;
+        }
+        let delay = self
+            .providers
+            .iter()
+            .map(|p| p.min_delay())
+            .max()
+            .unwrap_or_default();
+        self.last_lookup
+            .map_or(true, |last| last.elapsed() >= delay)
+    }
+
+    /// Query the providers for `song` in the background, the lookup is given up on
+    /// after [Enricher::LOOKUP_TIMEOUT] and its result is cached by [Enricher::collect]
+    pub fn lookup(&mut self, song: SongInfo) {
+        self.last_lookup = Some(Instant::now());
+        self.pending = Some(song.url.clone());
+        let providers = self.providers.clone();
+        let results = self.results_tx.clone();
+        tokio::spawn(async move {
+            let lookup = query(&providers, &song);
+            let metadata = match tokio::time::timeout(Self::LOOKUP_TIMEOUT, lookup).await {
+                Ok(metadata) => metadata,
+                Err(_) => {
+                    debug!("[Enrichment] Lookup of {} timed out", song.title);
+                    None
+                }
+            };
+            let _ = results.send((song.url, metadata)).await;
+        });
+    }
+
+    /// Cache the results of the lookups finished, failed lookups are not cached so that
+    /// they can be retried after [Enricher::RETRY_DELAY], returns the urls of the songs
+    /// whose result was cached
+    pub fn collect(&mut self) -> Vec<String> {
+        let mut urls = Vec::new();
+        while let Ok((url, metadata)) = self.results_rx.try_recv() {
+            self.pending = None;
+            match metadata {
+                Some(metadata) => {
+                    self.retries.remove(&url);
+                    self.cache.insert(url.clone(), metadata);
+                    self.modified = true;
+                    urls.push(url);
+                }
+                None => {
+                    self.retries.insert(url, Instant::now() + Self::RETRY_DELAY);
+                }
+            }
+        }
+        if self.modified && self.last_save.elapsed() >= Self::SAVE_INTERVAL {
+            self.save();
+        }
+        urls
+    }
+
+    fn save(&mut self) {
+        self.modified = false;
+        self.last_save = Instant::now();
+        json_store::save(&self.cache_path, &self.cache);
+    }
+}
+
+impl Drop for Enricher {
+    fn drop(&mut self) {
+        if self.modified {
+            self.save();
+        }
+    }
+}
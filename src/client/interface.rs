@@ -1,6 +1,6 @@
-use std::{fmt::Display, time::Duration};
+use std::{collections::HashMap, fmt::Display, time::Duration};
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use tokio::sync::oneshot;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -9,6 +9,17 @@ pub enum Request {
     Get(GetRequest),
     Set(SetRequest),
     Command(String),
+    /// check that the credentials and connections are still valid,
+    /// e.g. after the system resumed from suspend
+    Revalidate,
+    /// forget the session and authenticate again, e.g. after the session expired
+    Reconnect,
+    /// save the songs in the download folder
+    Download(Vec<SongInfo>),
+    /// start or stop keeping the playlist with this id offline
+    ToggleOffline(String),
+    /// create a playlist made of songs of the client
+    CreatePlaylist { title: String, songs: Vec<SongInfo> },
 }
 
 impl From<PlayerAction> for Request {
@@ -29,6 +40,7 @@ impl From<SetRequest> for Request {
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum PlayerAction {
+    /// pause the playback if `true`, resume it otherwise
     PlayPause(bool),
     PlayPauseToggle,
     Stop,
@@ -36,41 +48,189 @@ pub enum PlayerAction {
     ShuffleToggle,
     Autoplay(bool),
     AutoplayToggle,
-    Seek { dt: i64, mode: SeekMode },
+    #[serde(deserialize_with = "SeekMode::deserialize_compat")]
+    Seek(SeekMode),
     Prev,
     Next,
     SetVolume(Volume),
+    /// silence the player or restore its volume, without changing the volume setting
+    MuteToggle,
     SetTrackList(PlaylistInfo),
     SetRepeat(Repeat),
     CycleRepeat,
+    /// play the song at the given index of the tracklist
+    PlayIndex(usize),
+    /// gain in dB of each band of [EQUALIZER_BANDS], kept across songs
+    SetEqualizer(Vec<i8>),
+    /// add `song` to the tracklist, right after the current song if `next` is set
+    /// or at its end otherwise
+    Enqueue { song: SongInfo, next: bool },
+    NextChapter,
+    /// go back to the start of the current chapter, or of the previous one
+    PrevChapter,
+    /// jump to the chapter at the given index of [`PlayerInfo::chapters`]
+    SetChapter(usize),
+    /// speed of the playback in percent, capped to [MIN_SPEED] and [MAX_SPEED]
+    SetSpeed(u16),
+    /// weights in hundredths of the songs indexed by url, used by the next weighted shuffles
+    SetShuffleWeights(HashMap<String, u32>),
 }
+
+/// slowest speed of the playback, in percent
+pub const MIN_SPEED: u16 = 25;
+/// fastest speed of the playback, in percent
+pub const MAX_SPEED: u16 = 400;
+
+/// center frequency in Hz of each band of the equalizer
+pub const EQUALIZER_BANDS: [u32; 10] = [31, 62, 125, 250, 500, 1000, 2000, 4000, 8000, 16000];
+/// highest boost or cut of an equalizer band, in dB
+pub const EQUALIZER_MAX_GAIN: i8 = 12;
 #[derive(Debug, Clone, Serialize, Deserialize, Copy, PartialEq, Eq)]
 pub enum SeekMode {
+    /// position from the start of the song
+    Absolute(Duration),
+    /// offset from the current position in milliseconds, negative to seek backwards
+    Relative(i64),
+    /// position as a percentage of the song
+    AbsolutePercent(u8),
+    /// offset as a percentage of the song, negative to seek backwards
+    RelativePercent(i64),
+}
+
+/// mode of the former `Seek { dt, mode }` action, kept to read older configurations
+#[derive(Deserialize)]
+enum LegacySeekMode {
     Absolute,
     Relative,
     AbsolutePercent,
     RelativePercent,
 }
 
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SeekRepr {
+    Current(SeekMode),
+    /// `dt` is in seconds, or in percent for the percent modes
+    Legacy { dt: i64, mode: LegacySeekMode },
+}
+
+impl SeekMode {
+    /// Read a seek mode, also accepting the `{ dt, mode }` form of older configurations
+    fn deserialize_compat<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(match SeekRepr::deserialize(deserializer)? {
+            SeekRepr::Current(mode) => mode,
+            SeekRepr::Legacy { dt, mode } => match mode {
+                LegacySeekMode::Absolute => {
+                    Self::Absolute(Duration::from_secs(u64::try_from(dt).unwrap_or(0)))
+                }
+                LegacySeekMode::Relative => Self::Relative(dt.saturating_mul(1000)),
+                LegacySeekMode::AbsolutePercent => Self::AbsolutePercent(dt.clamp(0, 100) as u8),
+                LegacySeekMode::RelativePercent => Self::RelativePercent(dt),
+            },
+        })
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Copy, PartialEq, Eq)]
 pub enum Volume {
     Absolute(usize),
     Relative(isize),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum GetRequest {
     PlaylistList,
     Playlist(String),
     PlayerInfo,
+    /// search songs matching the query
+    Search(String),
+    /// features supported by the backend, answered with [Answer::Capabilities]
+    Capabilities,
+    /// artists of the library, answered with [Answer::Artists]
+    Artists,
+    /// albums of the artist with this id, answered with [Answer::Albums]
+    Albums(String),
+    /// album with this id along with its songs, answered with [Answer::Album]
+    Album(String),
+    /// songs similar to this one to keep playing once the tracklist ends,
+    /// answered with [Answer::Related]
+    Related(SongInfo),
+}
+
+/// Features supported by a backend, the requests needing a missing feature are not sent
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct Capabilities {
+    /// answers [GetRequest::Search]
+    pub can_search: bool,
+    /// handles [SetRequest] and [Request::CreatePlaylist]
+    pub can_edit_playlists: bool,
+    /// plays songs, otherwise its playlists can only be browsed
+    pub can_play: bool,
+    pub can_seek: bool,
+    /// handles [PlayerAction::Enqueue] and [PlayerAction::PlayIndex]
+    pub supports_queue: bool,
+    /// answers [GetRequest::Artists], [GetRequest::Albums] and [GetRequest::Album]
+    pub can_browse_artists: bool,
+    /// answers [GetRequest::Related]
+    pub suggests_related: bool,
+}
+
+/// a backend which does not advertise its capabilities is assumed to support everything
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self {
+            can_search: true,
+            can_edit_playlists: true,
+            can_play: true,
+            can_seek: true,
+            supports_queue: true,
+            can_browse_artists: true,
+            suggests_related: true,
+        }
+    }
+}
+
+impl Capabilities {
+    /// Feature missing to handle `request`, if any
+    pub fn missing(&self, request: &Request) -> Option<&'static str> {
+        match request {
+            Request::Get(GetRequest::Search(_)) if !self.can_search => Some("searching"),
+            Request::Get(GetRequest::Artists | GetRequest::Albums(_) | GetRequest::Album(_))
+                if !self.can_browse_artists =>
+            {
+                Some("browsing artists")
+            }
+            Request::Get(GetRequest::Related(_)) if !self.suggests_related => Some("related songs"),
+            Request::Set(_) | Request::CreatePlaylist { .. } if !self.can_edit_playlists => {
+                Some("editing playlists")
+            }
+            Request::PlayerAction(action) => self.missing_action(action),
+            _ => None,
+        }
+    }
+
+    /// Feature missing to handle `action`, if any
+    pub fn missing_action(&self, action: &PlayerAction) -> Option<&'static str> {
+        match action {
+            _ if !self.can_play => Some("playback"),
+            PlayerAction::Seek(_) if !self.can_seek => Some("seeking"),
+            PlayerAction::Enqueue { .. } | PlayerAction::PlayIndex(_) if !self.supports_queue => {
+                Some("a queue")
+            }
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum SetRequest {
-    AddSongToPlaylist { song: String, playlist: String },
-    RemoveSongFromPlaylist { song: String, playlist: String },
+    /// the local files are found by their url, their ids being only their file names
+    AddSongToPlaylist { song: SongInfo, playlist: String },
+    RemoveSongFromPlaylist { song: SongInfo, playlist: String },
 }
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
 pub struct PlayerInfo {
     /// current playback status
     pub playback: Playback,
@@ -84,8 +244,22 @@ pub struct PlayerInfo {
     pub autoplay: bool,
     pub repeat: Repeat,
     pub volume: u8,
+    pub muted: bool,
     pub position: Duration,
     pub can_seek: bool,
+    /// chapters of the current song, empty if it has none
+    pub chapters: Vec<Chapter>,
+    /// index in [`Self::chapters`] of the chapter playing
+    pub chapter: Option<usize>,
+    /// speed of the playback in percent, none if the player cannot change it
+    pub speed: Option<u16>,
+}
+/// Part of a song, like a track of a mix or a chapter of an audiobook
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Chapter {
+    pub title: String,
+    /// position of the start of the chapter in the song
+    pub start: Duration,
 }
 #[derive(Debug, Clone, Serialize, Deserialize, Copy, PartialEq, Eq, Default)]
 pub enum Repeat {
@@ -104,7 +278,7 @@ impl Display for Repeat {
         write!(f, "{text}")
     }
 }
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub enum Playback {
     #[default]
     Stop,
@@ -121,14 +295,39 @@ impl Display for Playback {
         write!(f, "{text}")
     }
 }
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash, Default)]
 pub struct SongInfo {
     pub title: String,
     pub artist: String,
+    pub album: String,
+    pub year: Option<u16>,
     pub cover_url: String,
     pub id: String,
     pub url: String,
     pub duration: Duration,
+    /// the song is listed by its client but cannot be played, e.g. a private video
+    #[serde(default)]
+    pub unavailable: bool,
+}
+
+/// Labeled text field of a [Widget::Form]
+#[derive(Debug, Clone, Default)]
+pub struct FormField {
+    pub label: String,
+    /// initial value, replaced by the text typed
+    pub value: String,
+    /// display the value as asterisks, for passwords and tokens
+    pub hidden: bool,
+}
+
+impl FormField {
+    pub fn new(label: impl Into<String>, hidden: bool) -> Self {
+        Self {
+            label: label.into(),
+            value: String::new(),
+            hidden,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -152,13 +351,25 @@ pub enum Widget {
         content: String,
         backchannel: oneshot::Sender<String>,
     },
+    /// Several text fields filled at once, the values are sent back in the order of the fields
+    Form {
+        title: String,
+        fields: Vec<FormField>,
+        backchannel: oneshot::Sender<Vec<String>>,
+    },
 }
 
 impl Widget {
     pub fn captures_output(&self) -> bool {
+        matches!(self, Widget::PromptBox { .. } | Widget::Form { .. })
+    }
+    /// Entries of a [Widget::Checkboxes] or a [Widget::Radioboxes]
+    pub fn choices(&self) -> Option<&Vec<(bool, String)>> {
         match self {
-            Widget::Alert { .. } => false,
-            _ => true,
+            Widget::Checkboxes { content, .. } | Widget::Radioboxes { content, .. } => {
+                Some(content)
+            }
+            _ => None,
         }
     }
     pub fn title(&self) -> &String {
@@ -166,17 +377,98 @@ impl Widget {
             Widget::Alert { title, .. }
             | Widget::Checkboxes { title, .. }
             | Widget::Radioboxes { title, .. }
-            | Widget::PromptBox { title, .. } => title,
+            | Widget::PromptBox { title, .. }
+            | Widget::Form { title, .. } => title,
         }
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+/// Short message displayed in the status bar for a few seconds,
+/// unlike [Widget::Alert] it does not interrupt the user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Notification {
+    pub message: String,
+    pub severity: Severity,
+}
+
+impl Notification {
+    pub fn new(severity: Severity, message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            severity,
+        }
+    }
+}
+
+/// Progress of a [Request::Download], sent before each song
+/// and once all of them have been processed
+#[derive(Debug, Clone)]
+pub struct DownloadProgress {
+    /// song being downloaded
+    pub title: String,
+    /// number of songs already processed
+    pub done: usize,
+    pub total: usize,
+    /// set if the download of the song failed
+    pub error: Option<String>,
+}
+
+impl DownloadProgress {
+    pub fn is_finished(&self) -> bool {
+        self.done >= self.total
+    }
+}
+
 #[derive(Debug)]
 pub enum Answer {
     PlayerInfo(PlayerInfo),
     PlaylistList(Vec<PlaylistInfo>),
     Playlist(PlaylistInfo),
+    /// Songs of the playlist `id` starting at `offset`, sent while a large playlist is loading,
+    /// `total` being the number of songs expected once it is fully loaded
+    PlaylistPage {
+        id: String,
+        offset: usize,
+        songs: Vec<SongInfo>,
+        total: usize,
+    },
+    /// Changes to the songs of the playlist `id` since it was last sent, `length` being
+    /// the number of songs expected once it is fully loaded
+    PlaylistDelta {
+        id: String,
+        length: usize,
+        changes: Vec<PlaylistChange>,
+    },
     Widget(Widget),
+    SearchResults {
+        query: String,
+        songs: Vec<SongInfo>,
+    },
+    Notification(Notification),
+    Download(DownloadProgress),
+    Capabilities(Capabilities),
+    Artists(Vec<ArtistInfo>),
+    /// Albums of the artist whose id is `artist`
+    Albums {
+        artist: String,
+        albums: Vec<AlbumInfo>,
+    },
+    Album(AlbumInfo),
+    /// Songs similar to the song whose id is `seed`
+    Related {
+        seed: String,
+        songs: Vec<SongInfo>,
+    },
+    /// The backend is rate limited, the request is answered once the limit is lifted
+    /// or dropped if it is a refresh
+    Busy(GetRequest),
     Ok,
 }
 
@@ -185,6 +477,47 @@ impl From<Widget> for Answer {
         Answer::Widget(value)
     }
 }
+impl From<DownloadProgress> for Answer {
+    fn from(value: DownloadProgress) -> Self {
+        Answer::Download(value)
+    }
+}
+impl From<Notification> for Answer {
+    fn from(value: Notification) -> Self {
+        Answer::Notification(value)
+    }
+}
+
+/// Modification of the songs of a playlist, sent in an [Answer::PlaylistDelta]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum PlaylistChange {
+    /// `songs` inserted before the song at `index`
+    Insert { index: usize, songs: Vec<SongInfo> },
+    /// `count` songs removed starting at `index`
+    Remove { index: usize, count: usize },
+    /// the songs starting at `index` replaced by `songs`
+    Replace { index: usize, songs: Vec<SongInfo> },
+}
+
+impl PlaylistChange {
+    /// Apply the change to the songs of `playlist`, the ranges going past the end being shortened
+    pub fn apply(self, playlist: &mut Vec<SongInfo>) {
+        let len = playlist.len();
+        match self {
+            PlaylistChange::Insert { index, songs } => {
+                let index = index.min(len);
+                playlist.splice(index..index, songs);
+            }
+            PlaylistChange::Remove { index, count } => {
+                playlist.drain(index.min(len)..(index + count).min(len));
+            }
+            PlaylistChange::Replace { index, songs } => {
+                let end = (index + songs.len()).min(len);
+                playlist.splice(index.min(len)..end, songs);
+            }
+        }
+    }
+}
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
 pub struct PlaylistInfo {
@@ -193,4 +526,78 @@ pub struct PlaylistInfo {
     pub cover_url: String,
     pub id: String,
     pub songs: Vec<SongInfo>,
+    #[serde(default)]
+    pub description: String,
+    /// name of the account owning the playlist
+    #[serde(default)]
+    pub owner: String,
+    /// who can see the playlist, if the backend knows it
+    #[serde(default)]
+    pub visibility: Option<Visibility>,
+    /// songs can be added to and removed from the playlist
+    #[serde(default)]
+    pub editable: bool,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Visibility {
+    Public,
+    /// only visible to those knowing its link
+    Unlisted,
+    Private,
+}
+impl Display for Visibility {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match &self {
+            Visibility::Public => "Public",
+            Visibility::Unlisted => "Unlisted",
+            Visibility::Private => "Private",
+        };
+        write!(f, "{text}")
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ArtistInfo {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub cover_url: String,
+    /// number of albums of the artist, if the backend knows it
+    #[serde(default)]
+    pub albums: Option<usize>,
+}
+
+impl Display for ArtistInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.albums {
+            Some(albums) => write!(f, "{} ({albums})", self.name),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AlbumInfo {
+    pub id: String,
+    pub title: String,
+    /// name of the artist of the album
+    pub artist: String,
+    #[serde(default)]
+    pub year: Option<u16>,
+    #[serde(default)]
+    pub cover_url: String,
+    pub length: usize,
+    /// only sent in an [Answer::Album], the albums listed in an [Answer::Albums] have no songs
+    #[serde(default)]
+    pub songs: Vec<SongInfo>,
+}
+
+impl Display for AlbumInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.year {
+            Some(year) => write!(f, "{} ({year})", self.title),
+            None => write!(f, "{}", self.title),
+        }
+    }
 }
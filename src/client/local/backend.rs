@@ -1,19 +1,59 @@
-use std::{fs, path::PathBuf, time::Duration};
+#[cfg(feature = "musicbrainz")]
+use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-use log::debug;
+use anyhow::{anyhow, bail};
+use log::{debug, error};
+use rand::{seq::SliceRandom, thread_rng};
 use tokio::sync::{broadcast, mpsc};
 use tokio_util::sync::CancellationToken;
 
+#[cfg(feature = "musicbrainz")]
+use crate::client::{enrichment::Enricher, interface::Widget};
 use crate::{
-    client::interface::{Answer, GetRequest, PlaylistInfo, Request, SongInfo},
+    client::{
+        delta::SentPlaylists,
+        download,
+        interface::{
+            AlbumInfo, Answer, ArtistInfo, Capabilities, GetRequest, Notification, PlaylistInfo,
+            Request, SetRequest, Severity, SongInfo, Visibility,
+        },
+    },
     config,
+    doctor::Check,
+    playlist_file::{self, PlaylistFormat},
+};
+
+/// pace at which the songs missing metadata are looked up
+const ENRICH_INTERVAL: Duration = Duration::from_secs(1);
+/// only the virtual playlists can be edited, the folders are left untouched
+const CAPABILITIES: Capabilities = Capabilities {
+    can_search: true,
+    can_edit_playlists: true,
+    can_play: true,
+    can_seek: true,
+    supports_queue: true,
+    can_browse_artists: true,
+    suggests_related: true,
 };
+/// separates the artist from the title in the id of an album, tags never span several lines
+const ALBUM_SEPARATOR: char = '\n';
 
 pub struct Backend {
     request_rx: broadcast::Receiver<Request>,
     answer_tx: mpsc::Sender<Answer>,
     cancel_token: CancellationToken,
     folders: Vec<PlaylistInfo>,
+    /// where the virtual playlists of this source are saved
+    playlists: PathBuf,
+    sent: SentPlaylists,
+    #[cfg(feature = "musicbrainz")]
+    enricher: Option<Enricher>,
 }
 
 impl Backend {
@@ -21,69 +61,483 @@ impl Backend {
         request_rx: broadcast::Receiver<Request>,
         answer_tx: mpsc::Sender<Answer>,
         cancel_token: CancellationToken,
+        folders: Vec<PathBuf>,
+        playlists: PathBuf,
     ) -> Self {
-        let config = config::get_config();
-        let folders = config.folders;
         debug!("Folders to scan {:?}", folders);
         let folders = find_subfolders(folders);
-        let folders = folders
+        let mut folders: Vec<PlaylistInfo> = folders
             .iter()
             .map(get_playlist)
             .filter(|p| p.length > 0)
             .collect();
+        folders.extend(load_virtual_playlists(&playlists));
+        #[cfg(feature = "musicbrainz")]
+        let enricher = Enricher::from_config();
+        #[cfg(feature = "musicbrainz")]
+        if let Some(enricher) = &enricher {
+            for song in folders.iter_mut().flat_map(|p| p.songs.iter_mut()) {
+                merge_metadata(enricher, song)
+            }
+        }
         Self {
             request_rx,
             answer_tx,
             cancel_token,
             folders,
+            playlists,
+            sent: SentPlaylists::default(),
+            #[cfg(feature = "musicbrainz")]
+            enricher,
         }
     }
 
     pub async fn main_loop(&mut self) {
-        let delay = Duration::from_millis(100);
-        let mut interval = tokio::time::interval(delay);
-        while !self.cancel_token.is_cancelled() {
+        let mut enrich_interval = tokio::time::interval(ENRICH_INTERVAL);
+        let enrich = cfg!(feature = "musicbrainz");
+        loop {
             use tokio::sync::broadcast::error;
-            match self.request_rx.try_recv() {
-                Ok(request) => self.handle_request(request).await,
-                Err(err) => match err {
-                    error::TryRecvError::Empty => (),
-                    error::TryRecvError::Closed => self.cancel_token.cancel(),
-                    error::TryRecvError::Lagged(_) => {
+            tokio::select! {
+                _ = self.cancel_token.cancelled() => break,
+                _ = enrich_interval.tick(), if enrich => self.enrich_next().await,
+                request = self.request_rx.recv() => match request {
+                    Ok(request) => self.handle_request(request).await,
+                    Err(error::RecvError::Closed) => self.cancel_token.cancel(),
+                    Err(error::RecvError::Lagged(_)) => {
                         // resubscribe to broadcast ignoring all messages
                         // pending
                         self.request_rx = self.request_rx.resubscribe()
                     }
                 },
             }
-            interval.tick().await;
         }
     }
 
-    async fn handle_request(&self, request: Request) {
+    #[cfg(not(feature = "musicbrainz"))]
+    async fn enrich_next(&mut self) {}
+
+    /// Look up the metadata of the next song missing some, if the enricher is ready
+    #[cfg(feature = "musicbrainz")]
+    async fn enrich_next(&mut self) {
+        let enricher = match self.enricher.as_mut() {
+            Some(enricher) => enricher,
+            None => return,
+        };
+        let urls = enricher.collect();
+        // the same file can appear in several folders
+        for s in self.folders.iter_mut().flat_map(|p| p.songs.iter_mut()) {
+            if urls.contains(&s.url) {
+                merge_metadata(enricher, s)
+            }
+        }
+        if !enricher.is_ready() {
+            return;
+        }
+        let song = self
+            .folders
+            .iter()
+            .flat_map(|p| p.songs.iter())
+            .find(|s| enricher.needs_lookup(s))
+            .cloned();
+        if let Some(song) = song {
+            enricher.lookup(song);
+        }
+    }
+
+    async fn handle_request(&mut self, request: Request) {
         match request {
             Request::PlayerAction(_) => (),
             Request::Get(request) => self.handle_get(request).await,
-            Request::Set(_) => todo!(),
-            Request::Command(_) => (),
+            Request::Set(SetRequest::AddSongToPlaylist { song, playlist }) => {
+                let notification = match self.add_song(&song.url, &playlist) {
+                    Ok(title) => Notification::new(Severity::Info, format!("Added to {title}")),
+                    Err(err) => {
+                        error!("[Local] Failed to add {} to {playlist}: {err}", song.url);
+                        let message = format!("Failed to add the song: {err}");
+                        Notification::new(Severity::Error, message)
+                    }
+                };
+                let _ = self.answer_tx.send(notification.into()).await;
+            }
+            Request::Set(SetRequest::RemoveSongFromPlaylist { song, playlist }) => {
+                let notification = match self.remove_song(&song.url, &playlist) {
+                    Ok(title) => Notification::new(Severity::Info, format!("Removed from {title}")),
+                    Err(err) => {
+                        error!(
+                            "[Local] Failed to remove {} from {playlist}: {err}",
+                            song.url
+                        );
+                        let message = format!("Failed to remove the song: {err}");
+                        Notification::new(Severity::Error, message)
+                    }
+                };
+                let _ = self.answer_tx.send(notification.into()).await;
+            }
+            Request::Command(command) => self.handle_command(command).await,
+            Request::Revalidate | Request::Reconnect => (),
+            Request::Download(songs) => {
+                tokio::spawn(download::download(songs, self.answer_tx.clone()));
+            }
+            // local songs are always available offline
+            Request::ToggleOffline(_) => (),
+            Request::CreatePlaylist { title, songs } => {
+                let notification = match self.create_playlist(title.clone(), songs) {
+                    Ok(_) => Notification::new(Severity::Info, format!("Created {title}")),
+                    Err(err) => {
+                        error!("[Local] Failed to create {title}: {err}");
+                        let message = format!("Failed to create {title}: {err}");
+                        Notification::new(Severity::Error, message)
+                    }
+                };
+                let _ = self.answer_tx.send(notification.into()).await;
+            }
+        }
+    }
+
+    /// Save a virtual playlist named `title` made of `songs`, the files are not moved
+    fn create_playlist(&mut self, title: String, songs: Vec<SongInfo>) -> anyhow::Result<()> {
+        let mut playlist = PlaylistInfo {
+            title,
+            length: songs.len(),
+            songs,
+            visibility: Some(Visibility::Private),
+            editable: true,
+            ..Default::default()
+        };
+        let path = playlist_file::export(&playlist, PlaylistFormat::Json, &self.playlists)?;
+        playlist.id = path.display().to_string();
+        self.folders.retain(|p| p.id != playlist.id);
+        self.folders.push(playlist);
+        Ok(())
+    }
+
+    /// Append the song whose url is `song` to the virtual playlist `playlist`,
+    /// returns the title of the playlist, folders cannot be modified
+    fn add_song(&mut self, song: &str, playlist: &str) -> anyhow::Result<String> {
+        let song = self
+            .folders
+            .iter()
+            .flat_map(|p| p.songs.iter())
+            .find(|s| s.url == song)
+            .cloned()
+            .ok_or_else(|| anyhow!("unknown song"))?;
+        if !PathBuf::from(playlist).starts_with(&self.playlists) {
+            bail!("songs can only be added to the playlists created in yama");
+        }
+        let playlist = self
+            .folders
+            .iter_mut()
+            .find(|p| p.id == playlist)
+            .ok_or_else(|| anyhow!("unknown playlist"))?;
+        playlist.songs.push(song);
+        playlist.length = playlist.songs.len();
+        playlist_file::export(playlist, PlaylistFormat::Json, &self.playlists)?;
+        Ok(playlist.title.clone())
+    }
+
+    /// Remove the first occurrence of the song whose url is `song` from the virtual
+    /// playlist `playlist`, returns the title of the playlist, files are never deleted
+    fn remove_song(&mut self, song: &str, playlist: &str) -> anyhow::Result<String> {
+        if !PathBuf::from(playlist).starts_with(&self.playlists) {
+            bail!("songs can only be removed from the playlists created in yama");
+        }
+        let playlist = self
+            .folders
+            .iter_mut()
+            .find(|p| p.id == playlist)
+            .ok_or_else(|| anyhow!("unknown playlist"))?;
+        let index = playlist
+            .songs
+            .iter()
+            .position(|s| s.url == song)
+            .ok_or_else(|| anyhow!("song not found in {}", playlist.title))?;
+        playlist.songs.remove(index);
+        playlist.length = playlist.songs.len();
+        playlist_file::export(playlist, PlaylistFormat::Json, &self.playlists)?;
+        Ok(playlist.title.clone())
+    }
+
+    async fn handle_command(&self, command: String) {
+        match command.as_str() {
+            #[cfg(feature = "musicbrainz")]
+            "duplicates" => self.send_duplicates().await,
+            _ => (),
+        }
+    }
+
+    /// Send an alert listing the files identified as the same recording
+    #[cfg(feature = "musicbrainz")]
+    async fn send_duplicates(&self) {
+        let enricher = match &self.enricher {
+            Some(enricher) => enricher,
+            None => return,
+        };
+        let mut recordings: HashMap<&str, Vec<&SongInfo>> = HashMap::new();
+        for song in self.folders.iter().flat_map(|p| p.songs.iter()) {
+            if let Some(id) = enricher
+                .get_cached(&song.url)
+                .and_then(|m| m.recording_id.as_deref())
+            {
+                let songs = recordings.entry(id).or_default();
+                if !songs.iter().any(|s| s.url == song.url) {
+                    songs.push(song)
+                }
+            }
         }
+        let content: Vec<String> = recordings
+            .values()
+            .filter(|songs| songs.len() > 1)
+            .map(|songs| {
+                let files: Vec<String> = songs.iter().map(|s| format!("  {}", s.url)).collect();
+                format!("{}:\n{}", songs[0].title, files.join("\n"))
+            })
+            .collect();
+        let content = if content.is_empty() {
+            "No duplicates found".to_string()
+        } else {
+            content.join("\n")
+        };
+        let _ = self
+            .answer_tx
+            .send(
+                Widget::Alert {
+                    title: "Duplicates".to_string(),
+                    content,
+                }
+                .into(),
+            )
+            .await;
     }
 
-    async fn handle_get(&self, request: GetRequest) {
+    async fn handle_get(&mut self, request: GetRequest) {
         match request {
             GetRequest::PlaylistList => {
-                let _ = self
-                    .answer_tx
-                    .send(Answer::PlaylistList(self.folders.clone()))
-                    .await;
+                let answer = self.sent.track(Answer::PlaylistList(self.folders.clone()));
+                let _ = self.answer_tx.send(answer).await;
             }
             GetRequest::Playlist(id) => {
                 let playlist = self.folders.iter().find(|p| p.id == id).unwrap().clone();
-                let _ = self.answer_tx.send(Answer::Playlist(playlist)).await;
+                let answer = self.sent.track(Answer::Playlist(playlist));
+                let _ = self.answer_tx.send(answer).await;
             }
             GetRequest::PlayerInfo => (),
+            GetRequest::Search(query) => {
+                let songs = self.search(&query);
+                let _ = self
+                    .answer_tx
+                    .send(Answer::SearchResults { query, songs })
+                    .await;
+            }
+            GetRequest::Capabilities => {
+                let _ = self
+                    .answer_tx
+                    .send(Answer::Capabilities(CAPABILITIES))
+                    .await;
+            }
+            GetRequest::Artists => {
+                let artists = self
+                    .library()
+                    .into_iter()
+                    .map(|(artist, albums)| ArtistInfo {
+                        id: artist.to_string(),
+                        name: or_unknown(artist, "Unknown artist"),
+                        cover_url: albums
+                            .values()
+                            .flatten()
+                            .map(|song| song.cover_url.clone())
+                            .find(|cover| !cover.is_empty())
+                            .unwrap_or_default(),
+                        albums: Some(albums.len()),
+                    })
+                    .collect();
+                let _ = self.answer_tx.send(Answer::Artists(artists)).await;
+            }
+            GetRequest::Albums(artist) => {
+                let albums = match self.library().get(artist.as_str()) {
+                    Some(albums) => albums
+                        .iter()
+                        .map(|(title, songs)| make_album(&artist, title, songs, false))
+                        .collect(),
+                    None => Vec::new(),
+                };
+                let _ = self.answer_tx.send(Answer::Albums { artist, albums }).await;
+            }
+            GetRequest::Album(id) => {
+                let (artist, title) = match id.split_once(ALBUM_SEPARATOR) {
+                    Some(album) => album,
+                    None => return,
+                };
+                let library = self.library();
+                if let Some(songs) = library.get(artist).and_then(|albums| albums.get(title)) {
+                    let album = make_album(artist, title, songs, true);
+                    let _ = self.answer_tx.send(Answer::Album(album)).await;
+                }
+            }
+            GetRequest::Related(song) => {
+                let songs = self.related(&song);
+                let answer = Answer::Related {
+                    seed: song.id,
+                    songs,
+                };
+                let _ = self.answer_tx.send(answer).await;
+            }
+        }
+    }
+
+    /// Other songs of the artist of `song` in a random order,
+    /// or of its album if the artist is unknown
+    fn related(&self, song: &SongInfo) -> Vec<SongInfo> {
+        let library = self.library();
+        let mut songs: Vec<SongInfo> = if !song.artist.is_empty() {
+            library
+                .get(song.artist.as_str())
+                .into_iter()
+                .flat_map(|albums| albums.values().flatten())
+                .filter(|s| s.url != song.url)
+                .map(|s| (*s).clone())
+                .collect()
+        } else {
+            library
+                .values()
+                .filter_map(|albums| albums.get(song.album.as_str()))
+                .flatten()
+                .filter(|s| !song.album.is_empty() && s.url != song.url)
+                .map(|s| (*s).clone())
+                .collect()
+        };
+        songs.shuffle(&mut thread_rng());
+        songs
+    }
+
+    /// Songs of every folder grouped by artist then by album according to their tags,
+    /// each file being listed once
+    fn library(&self) -> BTreeMap<&str, BTreeMap<&str, Vec<&SongInfo>>> {
+        let mut library: BTreeMap<&str, BTreeMap<&str, Vec<&SongInfo>>> = BTreeMap::new();
+        let mut listed = HashSet::new();
+        for song in self.folders.iter().flat_map(|p| p.songs.iter()) {
+            if listed.insert(&song.url) {
+                library
+                    .entry(song.artist.as_str())
+                    .or_default()
+                    .entry(song.album.as_str())
+                    .or_default()
+                    .push(song);
+            }
         }
+        library
     }
+
+    /// Songs whose title, artist or album contain every word of `query`, ignoring case
+    fn search(&self, query: &str) -> Vec<SongInfo> {
+        let words: Vec<String> = query.split_whitespace().map(|w| w.to_lowercase()).collect();
+        let mut songs: Vec<SongInfo> = Vec::new();
+        for song in self.folders.iter().flat_map(|p| p.songs.iter()) {
+            let text = format!("{} {} {}", song.title, song.artist, song.album).to_lowercase();
+            if words.iter().all(|w| text.contains(w)) && !songs.iter().any(|s| s.url == song.url)
+            {
+                songs.push(song.clone())
+            }
+        }
+        songs
+    }
+}
+
+/// Album `title` of `artist` made of `songs`, which are only included if `with_songs` is set
+fn make_album(artist: &str, title: &str, songs: &[&SongInfo], with_songs: bool) -> AlbumInfo {
+    AlbumInfo {
+        id: format!("{artist}{ALBUM_SEPARATOR}{title}"),
+        title: or_unknown(title, "Unknown album"),
+        artist: or_unknown(artist, "Unknown artist"),
+        year: songs.iter().find_map(|song| song.year),
+        cover_url: songs
+            .iter()
+            .map(|song| song.cover_url.clone())
+            .find(|cover| !cover.is_empty())
+            .unwrap_or_default(),
+        length: songs.len(),
+        songs: if with_songs {
+            songs.iter().map(|song| (*song).clone()).collect()
+        } else {
+            Vec::new()
+        },
+    }
+}
+
+/// `tag`, or `unknown` for the songs missing it
+fn or_unknown(tag: &str, unknown: &str) -> String {
+    if tag.is_empty() {
+        unknown.to_string()
+    } else {
+        tag.to_string()
+    }
+}
+
+/// Fill the missing metadata of `song` from the cache, untagged files are titled after
+/// their file name in which case the title is replaced as well
+#[cfg(feature = "musicbrainz")]
+fn merge_metadata(enricher: &Enricher, song: &mut SongInfo) {
+    if let Some(metadata) = enricher.get_cached(&song.url) {
+        if let (true, Some(title)) = (song.title == song.id, &metadata.title) {
+            song.title = title.clone();
+        }
+        metadata.merge_into(song)
+    }
+}
+
+/// Check that each configured folder, of every local source, can be read
+pub fn diagnose() -> Vec<Check> {
+    let config = config::get_config();
+    let sources = config.local_sources.into_values().flatten();
+    config
+        .folders
+        .into_iter()
+        .chain(sources)
+        .map(|folder| {
+            let result = fs::read_dir(&folder)
+                .map(|entries| format!("{} entries", entries.count()))
+                .map_err(|err| anyhow!("cannot read folder: {err}"));
+            Check::new(format!("local folder {}", folder.display()), result)
+        })
+        .collect()
+}
+
+/// Folder of the virtual playlists of the source `source`, those of the other sources
+/// are kept in subfolders of the one of the default `local` source
+pub fn virtual_folder(source: &str) -> PathBuf {
+    let folder = config::get_dirs().data_dir().join("playlists");
+    match source {
+        "local" => folder,
+        _ => folder.join(source),
+    }
+}
+
+/// Playlists made of songs from any folder, created by importing or copying a playlist
+fn load_virtual_playlists(folder: &Path) -> Vec<PlaylistInfo> {
+    let files = match fs::read_dir(folder) {
+        Ok(files) => files,
+        Err(_) => return Vec::new(),
+    };
+    files
+        .filter_map(|file| file.ok())
+        .filter_map(|file| {
+            let path = file.path();
+            if !path.is_file() {
+                return None;
+            }
+            match playlist_file::import(&path) {
+                Ok(playlist) => Some(PlaylistInfo {
+                    id: path.display().to_string(),
+                    visibility: Some(Visibility::Private),
+                    editable: true,
+                    ..playlist
+                }),
+                Err(err) => {
+                    error!("[Local] Cannot read playlist {}: {err}", path.display());
+                    None
+                }
+            }
+        })
+        .collect()
 }
 
 fn find_subfolders(folders: Vec<PathBuf>) -> Vec<PathBuf> {
@@ -118,6 +572,11 @@ fn get_playlist(folder: &PathBuf) -> PlaylistInfo {
             cover_url: Default::default(),
             songs,
             id: folder.display().to_string(),
+            description: String::new(),
+            owner: String::new(),
+            visibility: Some(Visibility::Private),
+            // the files of a folder are never moved nor deleted
+            editable: false,
         }
     } else {
         debug!("Checking folder {:?} failed", folder);
@@ -146,10 +605,13 @@ fn make_song(path: &PathBuf) -> Option<SongInfo> {
         Some(SongInfo {
             title: song.title.unwrap_or(song.file_name.clone()),
             artist: Default::default(),
+            album: Default::default(),
+            year: None,
             cover_url: Default::default(),
             id: song.file_name,
             url: format!("file://{}", abs_path.display()),
             duration: Duration::from_secs_f64(song._duration.unwrap_or_default()),
+            unavailable: false,
         })
     } else {
         None
@@ -1,12 +1,21 @@
+use std::path::PathBuf;
+
 use anyhow::Result;
 use tokio::sync::broadcast::Sender as BroadSender;
 use tokio::sync::mpsc::{self, Receiver as MpscReceiver, Sender as MpscSender};
+use tokio::task::JoinHandle;
 use tokio_util::sync::CancellationToken;
 
-use crate::client::interface::{Answer, Request};
+use crate::{
+    client::{
+        interface::{Answer, Request},
+        ClientFactory,
+    },
+    config,
+};
 
 use super::super::mpv::PlayerHandler;
-use super::backend::Backend;
+use super::backend::{self, Backend};
 
 pub struct Client {
     /// channel on which request are received
@@ -20,12 +29,18 @@ pub struct Client {
     /// cancel token shared with [Backend] and [PlayerHandler]
     /// is automatically cancel when [Self::cancel_token_frontend] is cancelled
     cancel_token_backend: CancellationToken,
+    /// folders scanned by [Backend]
+    folders: Vec<PathBuf>,
+    /// folder of the virtual playlists of [Backend]
+    playlists: PathBuf,
 }
 impl Client {
     pub fn create(
         receiver: MpscReceiver<Request>,
         sender: MpscSender<Answer>,
         cancel_token_frontend: CancellationToken,
+        folders: Vec<PathBuf>,
+        playlists: PathBuf,
     ) -> Self {
         let (request_tx, _) = tokio::sync::broadcast::channel(10);
         let cancel_token_backend = cancel_token_frontend.child_token();
@@ -35,6 +50,8 @@ impl Client {
             request_tx,
             cancel_token_frontend,
             cancel_token_backend,
+            folders,
+            playlists,
         }
     }
     pub async fn main_loop(&mut self) -> Result<()> {
@@ -43,11 +60,14 @@ impl Client {
             self.request_tx.subscribe(),
             answer_tx.clone(),
             self.cancel_token_backend.clone(),
+            self.folders.clone(),
+            self.playlists.clone(),
         );
         let mut player = PlayerHandler::new(
             self.request_tx.subscribe(),
             answer_tx.clone(),
             self.cancel_token_backend.clone(),
+            false,
         );
         let task_backend = tokio::spawn(async move { backend.main_loop().await });
         let task_player = tokio::spawn(async move { player.main_loop().await });
@@ -88,3 +108,65 @@ impl Client {
         self.cancel_token_backend.cancel()
     }
 }
+
+/// Starts the local files client, scanning the `folders` of the config
+pub struct Factory;
+
+impl ClientFactory for Factory {
+    fn name(&self) -> &str {
+        "local"
+    }
+
+    fn spawn(
+        &self,
+        receiver: MpscReceiver<Request>,
+        sender: MpscSender<Answer>,
+        cancel_token: CancellationToken,
+    ) -> JoinHandle<Result<()>> {
+        let folders = config::get_config().folders;
+        let playlists = backend::virtual_folder("local");
+        let mut client = Client::create(receiver, sender, cancel_token, folders, playlists);
+        tokio::spawn(async move { client.main_loop().await })
+    }
+}
+
+/// Names of the local sources set in the config
+pub fn source_names() -> Vec<String> {
+    config::get_config().local_sources.into_keys().collect()
+}
+
+/// Starts a local files client showing the folders of a named source of the config,
+/// e.g. `Podcasts`, as a source distinct from `local`
+pub struct Source {
+    name: String,
+    folders: Vec<PathBuf>,
+}
+
+impl Source {
+    /// Factory of the local source `name`, if it is set in the config
+    pub fn from_config(name: &str) -> Option<Self> {
+        let folders = config::get_config().local_sources.remove(name)?;
+        Some(Self {
+            name: name.to_string(),
+            folders,
+        })
+    }
+}
+
+impl ClientFactory for Source {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn spawn(
+        &self,
+        receiver: MpscReceiver<Request>,
+        sender: MpscSender<Answer>,
+        cancel_token: CancellationToken,
+    ) -> JoinHandle<Result<()>> {
+        let folders = self.folders.clone();
+        let playlists = backend::virtual_folder(&self.name);
+        let mut client = Client::create(receiver, sender, cancel_token, folders, playlists);
+        tokio::spawn(async move { client.main_loop().await })
+    }
+}
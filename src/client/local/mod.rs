@@ -1,3 +1,4 @@
 pub mod backend;
+pub use backend::diagnose;
 pub mod handler;
-pub use handler::Client;
+pub use handler::{source_names, Factory, Source};
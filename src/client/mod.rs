@@ -1,9 +1,107 @@
+#[cfg(any(feature = "spotify", feature = "youtube"))]
+pub mod backoff;
+#[cfg(any(feature = "local", feature = "youtube", feature = "spotify"))]
+pub mod delta;
+#[cfg(any(feature = "local", feature = "youtube"))]
+pub mod download;
+#[cfg(feature = "musicbrainz")]
+pub mod enrichment;
 pub mod interface;
 #[cfg(feature = "mpv")]
-mod mpv;
+pub mod mpv;
 #[cfg(feature = "local")]
 pub mod local;
+#[cfg(feature = "youtube")]
+pub mod offline;
+#[cfg(feature = "plugins")]
+pub mod plugin;
+#[cfg(feature = "mpv")]
+pub mod positions;
 #[cfg(feature = "spotify")]
 pub mod spotify;
 #[cfg(feature = "youtube")]
 pub mod youtube;
+
+use anyhow::Result;
+use tokio::{
+    sync::mpsc::{self, Receiver, Sender},
+    task::JoinHandle,
+};
+use tokio_util::sync::CancellationToken;
+
+use interface::{Answer, Request};
+
+/// Describes a backend and starts its client, each backend registers its factory
+/// in [FACTORIES] behind the feature flag it requires
+pub trait ClientFactory: Sync {
+    /// name of the client, used in the config and by the `client` command
+    fn name(&self) -> &str;
+
+    /// number of requests and answers buffered by the channels to the client
+    fn channel_capacity(&self) -> usize {
+        32
+    }
+
+    /// Start the client on its own task, receiving the requests on `receiver`
+    /// and sending back its answers on `sender`
+    fn spawn(
+        &self,
+        receiver: Receiver<Request>,
+        sender: Sender<Answer>,
+        cancel_token: CancellationToken,
+    ) -> JoinHandle<Result<()>>;
+}
+
+/// factories of the clients compiled in, in the order in which they are started by default
+pub static FACTORIES: &[&dyn ClientFactory] = &[
+    #[cfg(feature = "local")]
+    &local::Factory,
+    #[cfg(feature = "youtube")]
+    &youtube::Factory,
+    #[cfg(feature = "spotify")]
+    &spotify::Factory,
+];
+
+/// Names of the clients compiled in
+pub fn names() -> impl Iterator<Item = &'static str> {
+    FACTORIES.iter().map(|factory| factory.name())
+}
+
+/// Names of the clients that can be started, including the local sources and the plugins
+pub fn available() -> Vec<String> {
+    let names = names().map(String::from);
+    #[cfg(feature = "local")]
+    let names = names.chain(local::source_names());
+    #[cfg(feature = "plugins")]
+    let names = names.chain(plugin::names());
+    names.collect()
+}
+
+/// Channels to a client running on its own task
+pub struct Spawned {
+    pub request_tx: Sender<Request>,
+    pub answer_rx: Receiver<Answer>,
+    pub task: JoinHandle<Result<()>>,
+}
+
+/// Start the client `name`, returns `None` if no such client is compiled in
+pub fn spawn(name: &str, cancel_token: CancellationToken) -> Option<Spawned> {
+    #[cfg(feature = "local")]
+    let source = local::Source::from_config(name);
+    #[cfg(feature = "plugins")]
+    let plugin = plugin::Factory::from_config(name);
+    let factory = FACTORIES.iter().copied().find(|f| f.name() == name);
+    #[cfg(feature = "local")]
+    let factory = factory.or(source.as_ref().map(|s| s as &dyn ClientFactory));
+    #[cfg(feature = "plugins")]
+    let factory = factory.or(plugin.as_ref().map(|p| p as &dyn ClientFactory));
+    let factory = factory?;
+    let (request_tx, request_rx) = mpsc::channel(factory.channel_capacity());
+    let (answer_tx, answer_rx) = mpsc::channel(factory.channel_capacity());
+    let task = factory.spawn(request_rx, answer_tx, cancel_token);
+    Some(Spawned {
+        request_tx,
+        answer_rx,
+        task,
+    })
+}
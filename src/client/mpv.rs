@@ -1,61 +1,217 @@
-use std::time::Duration;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
 
-use libmpv::{Mpv};
+use anyhow::anyhow;
+use libmpv::{
+    events::{Event, PropertyData},
+    Format, Mpv,
+};
 
 use log::{debug, error};
-use rand::seq::SliceRandom;
-use rand::thread_rng;
 use tokio::sync::broadcast::Receiver;
-use tokio::sync::mpsc::Sender;
+use tokio::sync::mpsc::{self, Sender, UnboundedReceiver, UnboundedSender};
 use tokio_util::sync::CancellationToken;
 
 use crate::client::interface::{
-    Answer, GetRequest, Playback, PlayerAction, PlayerInfo, PlaylistInfo, Repeat, Request,
-    SeekMode, SongInfo, Volume,
+    Answer, Chapter, GetRequest, Notification, Playback, PlayerAction, PlayerInfo, PlaylistInfo,
+    Repeat, Request, SeekMode, Severity, SongInfo, Volume, EQUALIZER_BANDS, EQUALIZER_MAX_GAIN,
+    MAX_SPEED, MIN_SPEED,
 };
+use crate::client::positions::SavedPositions;
+use crate::config;
+use crate::doctor::Check;
+use crate::shuffle;
+
+/// number of volume changes during a fade
+const FADE_STEPS: i64 = 10;
+/// time between two writes of the saved positions
+const POSITIONS_FLUSH: Duration = Duration::from_secs(60);
 
 pub struct Player {
-    player: Mpv,
+    player: Arc<Mpv>,
     stopped: bool,
+    /// last values of the properties observed by [Self::observe]
+    observed: Observed,
+    /// highest volume in percent, above 100 the sound is amplified
+    max_volume: i64,
+}
+
+/// Properties of mpv kept up to date from the property changes
+#[derive(Debug, Default)]
+struct Observed {
+    duration: Duration,
+    time_pos: Duration,
+    eof: bool,
+    idle: bool,
+    chapters: Vec<Chapter>,
+}
+
+/// Events forwarded by the thread waiting on mpv
+#[derive(Debug)]
+pub enum MpvEvent {
+    /// the playback was paused or resumed
+    Pause,
+    TimePos(f64),
+    Duration(f64),
+    Eof(bool),
+    Idle(bool),
+    /// the playback restarted after a seek or the loading of a file
+    Restart,
+    /// the number of chapters changed, usually because a new file was loaded
+    Chapters,
 }
 
 pub struct State {
     pub duration: Duration,
     pub time_pos: Duration,
     pub volume: i64,
+    pub muted: bool,
     pub playpause: Playback,
     pub eof: bool,
+    pub chapters: Vec<Chapter>,
+    pub chapter: Option<usize>,
+    /// speed of the playback in percent
+    pub speed: u16,
+    /// false for live streams and while nothing is loaded
+    pub seekable: bool,
 }
 
 impl Player {
-    pub fn new() -> Self {
+    pub fn new(video: bool) -> Self {
         let player = Mpv::new().unwrap();
-        player.set_property("video", false).unwrap();
         player.set_property("ytdl", true).unwrap();
-        Self {
-            player,
+        let max_volume = config::get_config().max_volume as i64;
+        // mpv refuses a maximum below 100, a lower one is only enforced by `set_volume`
+        if let Err(err) = player.set_property("volume-max", max_volume.max(100)) {
+            error!("Failed to set the maximum volume to {max_volume}: {err:?}");
+        }
+        let player = Self {
+            player: Arc::new(player),
             stopped: true,
+            observed: Observed::default(),
+            max_volume,
+        };
+        player.set_video(video);
+        player
+    }
+
+    /// Enable or disable the video output, youtube-dl selects the streams when a file is
+    /// loaded so that the change only affects the next songs
+    pub fn set_video(&self, video: bool) {
+        let value = if video { "auto" } else { "no" };
+        if let Err(err) = self.player.set_property("video", value) {
+            error!("Failed to set video to {value}: {err:?}");
+        }
+    }
+
+    /// Forward the changes of the properties describing the playback to `events`,
+    /// from a thread waiting on mpv until `events` is closed
+    pub fn observe(&self, events: UnboundedSender<MpvEvent>) {
+        let player = Arc::clone(&self.player);
+        let spawned = thread::Builder::new()
+            .name("mpv-events".to_string())
+            .spawn(move || forward_events(&player, &events));
+        if let Err(err) = spawned {
+            error!("Failed to observe mpv: {err}");
+        }
+    }
+
+    /// Update the observed properties with `event`
+    pub fn apply(&mut self, event: &MpvEvent) {
+        let seconds = |secs: f64| Duration::from_secs_f64(secs.max(0.));
+        match *event {
+            MpvEvent::TimePos(secs) => self.observed.time_pos = seconds(secs),
+            MpvEvent::Duration(secs) => {
+                self.observed.duration = seconds(secs);
+                // the list may change without its length changing
+                self.load_chapters();
+            }
+            MpvEvent::Chapters => self.load_chapters(),
+            MpvEvent::Eof(eof) => self.observed.eof = eof,
+            MpvEvent::Idle(idle) => self.observed.idle = idle,
+            MpvEvent::Pause | MpvEvent::Restart => (),
         }
     }
 
     pub fn get_state(&self) -> State {
-        let duration: i64 = self.player.get_property("duration").unwrap_or_default();
-        let duration = Duration::from_secs(duration as u64);
-        let time_pos: i64 = self.player.get_property("time-pos").unwrap_or_default();
-        let time_pos = Duration::from_secs(time_pos as u64);
         let volume = self.player.get_property("volume").unwrap_or_default();
-        let eof: bool = self.player.get_property("eof-reached").unwrap_or_default()
-            || self.player.get_property("idle-active").unwrap_or_default();
+        let muted = self.muted();
         let playback_status = self.get_playback_status();
         State {
-            duration,
-            time_pos,
+            duration: self.observed.duration,
+            time_pos: self.observed.time_pos,
             volume,
+            muted,
             playpause: playback_status,
-            eof,
+            eof: self.observed.eof || self.observed.idle,
+            chapters: self.observed.chapters.clone(),
+            chapter: self.chapter(),
+            speed: self.speed(),
+            seekable: self.player.get_property("seekable").unwrap_or_default(),
+        }
+    }
+
+    /// speed of the playback in percent
+    fn speed(&self) -> u16 {
+        let speed: f64 = self.player.get_property("speed").unwrap_or(1.);
+        (speed * 100.).round() as u16
+    }
+
+    pub fn set_speed(&self, percent: u16) {
+        let speed = f64::from(percent.clamp(MIN_SPEED, MAX_SPEED)) / 100.;
+        if let Err(err) = self.player.set_property("speed", speed) {
+            error!("Failed to set the speed to {speed}: {err:?}");
         }
     }
 
+    /// Read the chapters of the file playing, untitled ones are numbered
+    fn load_chapters(&mut self) {
+        let count: i64 = self.player.get_property("chapters").unwrap_or_default();
+        self.observed.chapters = (0..count)
+            .map(|i| {
+                let title: String = self
+                    .player
+                    .get_property(&format!("chapter-list/{i}/title"))
+                    .unwrap_or_default();
+                let start: f64 = self
+                    .player
+                    .get_property(&format!("chapter-list/{i}/time"))
+                    .unwrap_or_default();
+                Chapter {
+                    title: if title.is_empty() {
+                        format!("Chapter {}", i + 1)
+                    } else {
+                        title
+                    },
+                    start: Duration::from_secs_f64(start.max(0.)),
+                }
+            })
+            .collect();
+    }
+
+    /// index of the chapter playing, none before the first one or without chapters
+    fn chapter(&self) -> Option<usize> {
+        let chapter: i64 = self.player.get_property("chapter").ok()?;
+        usize::try_from(chapter).ok()
+    }
+
+    pub fn set_chapter(&self, index: usize) {
+        if let Err(err) = self.player.set_property("chapter", index as i64) {
+            error!("Failed to go to chapter {index}: {err:?}");
+        }
+    }
+
+    /// go `offset` chapters forward, backwards if negative
+    pub fn add_chapter(&self, offset: i64) {
+        self.player
+            .command("add", &["chapter", &offset.to_string()])
+            .unwrap_or(());
+    }
+
     pub fn get_playback_status(&self) -> Playback {
         if self.is_stopped() {
             Playback::Stop
@@ -78,10 +234,23 @@ impl Player {
         }
     }
 
-    pub fn play(&mut self, url: &str) {
+    /// Play `url` from `start`, or from its beginning
+    pub fn play(&mut self, url: &str, start: Option<Duration>) {
+        // the start option applies to every file loaded after it is set
+        let start = match start {
+            Some(start) => format!("{:.3}", start.as_secs_f64()),
+            None => "none".to_string(),
+        };
+        if let Err(err) = self.player.set_property("start", start.as_str()) {
+            error!("Failed to set the start of the song to {start}: {err:?}");
+        }
         // It is necessary to surround the url with quotes to avoid errors
         match self.player.command("loadfile", &[&format!("\"{url}\"")]) {
-            Ok(_) => self.stopped = false,
+            Ok(_) => {
+                self.stopped = false;
+                self.observed.eof = false;
+                self.observed.idle = false;
+            }
             Err(e) => error!("error loading file {:?}", e),
         };
     }
@@ -91,12 +260,22 @@ impl Player {
     }
 
     pub fn incr_volume(&self, dv: i64) {
-        let volume = self.get_volume();
-        let volume = std::cmp::min(volume + dv, 100);
-        let volume = std::cmp::max(volume, 0);
+        self.set_volume(self.get_volume() + dv);
+    }
+
+    pub fn set_volume(&self, volume: i64) {
+        let volume = volume.clamp(0, self.max_volume);
         let _ = self.player.set_property("volume", volume);
     }
 
+    pub fn muted(&self) -> bool {
+        self.player.get_property("mute").unwrap_or_default()
+    }
+
+    pub fn mute_toggle(&self) {
+        let _ = self.player.set_property("mute", !self.muted());
+    }
+
     pub fn stop(&mut self) {
         self.player
             .command("stop", &[])
@@ -108,11 +287,13 @@ impl Player {
         self.stopped
     }
 
-    pub fn seek_relative(&self, dt: i32) {
-        self.player.seek_forward(f64::from(dt)).unwrap_or(()); // silent failure
+    /// seek by `dt` milliseconds, backwards if negative
+    pub fn seek_relative(&self, dt: i64) {
+        let dt = dt as f64 / 1000.;
+        self.player.seek_forward(dt).unwrap_or(()); // silent failure
     }
 
-    pub fn seek_percent(&self, percent: usize) {
+    pub fn seek_percent(&self, percent: u8) {
         // seek_percent_absolute is the same as seek_percent
         // because of a typo in the lib
         // self.player.seek_percent_absolute(pct).unwrap();
@@ -120,9 +301,16 @@ impl Player {
             .command("seek", &[&format!("{percent}"), "absolute-percent"])
             .unwrap_or(());
     }
-    fn seek_absolute(&self, dt: i64) {
+    /// seek by `percent` of the song, backwards if negative
+    pub fn seek_percent_relative(&self, percent: i64) {
         self.player
-            .command("seek", &[&format!("{dt}"), "absolute"])
+            .command("seek", &[&format!("{percent}"), "relative-percent"])
+            .unwrap_or(());
+    }
+    fn seek_absolute(&self, position: Duration) {
+        let position = format!("{:.3}", position.as_secs_f64());
+        self.player
+            .command("seek", &[&position, "absolute"])
             .unwrap_or(());
     }
 
@@ -142,6 +330,88 @@ impl Player {
             }
         }
     }
+
+    /// Apply the gain in dB of each band of [EQUALIZER_BANDS] through the ffmpeg equalizer,
+    /// the audio filters are kept when a new file is loaded
+    pub fn set_equalizer(&self, gains: &[i8]) {
+        let filters: Vec<String> = EQUALIZER_BANDS
+            .iter()
+            .zip(gains)
+            .filter(|(_, gain)| **gain != 0)
+            .map(|(freq, gain)| {
+                let gain = (*gain).clamp(-EQUALIZER_MAX_GAIN, EQUALIZER_MAX_GAIN);
+                format!("equalizer=f={freq}:width_type=o:width=1:g={gain}")
+            })
+            .collect();
+        let value = if filters.is_empty() {
+            String::new()
+        } else {
+            format!("lavfi=[{}]", filters.join(","))
+        };
+        if let Err(err) = self.player.set_property("af", value.as_str()) {
+            error!("Failed to set audio filters to {value}: {err:?}");
+        }
+    }
+}
+
+/// properties observed, along with the format in which they are reported
+const OBSERVED: [(&str, Format); 6] = [
+    ("pause", Format::Flag),
+    ("time-pos", Format::Double),
+    ("duration", Format::Double),
+    ("eof-reached", Format::Flag),
+    ("idle-active", Format::Flag),
+    ("chapters", Format::Int64),
+];
+
+/// Wait for the events of `player` and send those of interest to `events`
+fn forward_events(player: &Mpv, events: &UnboundedSender<MpvEvent>) {
+    let mut context = player.create_event_context();
+    let _ = context.disable_deprecated_events();
+    for (id, (name, format)) in OBSERVED.into_iter().enumerate() {
+        if let Err(err) = context.observe_property(name, format, id as u64) {
+            error!("Failed to observe {name}: {err:?}");
+        }
+    }
+    while !events.is_closed() {
+        // the timeout lets the thread notice that the handler is gone
+        let event = match context.wait_event(1.) {
+            Some(Ok(event)) => event,
+            Some(Err(err)) => {
+                debug!("mpv event error: {err:?}");
+                continue;
+            }
+            None => continue,
+        };
+        let event = match event {
+            Event::PropertyChange { name, change, .. } => match (name, change) {
+                ("pause", PropertyData::Flag(_)) => MpvEvent::Pause,
+                ("time-pos", PropertyData::Double(secs)) => MpvEvent::TimePos(secs),
+                ("duration", PropertyData::Double(secs)) => MpvEvent::Duration(secs),
+                ("eof-reached", PropertyData::Flag(eof)) => MpvEvent::Eof(eof),
+                ("idle-active", PropertyData::Flag(idle)) => MpvEvent::Idle(idle),
+                ("chapters", PropertyData::Int64(_)) => MpvEvent::Chapters,
+                _ => continue,
+            },
+            Event::PlaybackRestart => MpvEvent::Restart,
+            Event::Shutdown => break,
+            _ => continue,
+        };
+        if events.send(event).is_err() {
+            break;
+        }
+    }
+}
+
+/// Check that libmpv can be loaded and report its version
+pub fn diagnose() -> Check {
+    let result = Mpv::new()
+        .map(|mpv| {
+            mpv.get_property::<String>("mpv-version")
+                .unwrap_or_else(|_| "unknown version".to_string())
+        })
+        .map_err(|err| anyhow!("cannot create mpv instance: {err:?}"));
+    Check::new("libmpv", result)
 }
 
 pub struct PlaylistHandler {
@@ -151,6 +421,11 @@ pub struct PlaylistHandler {
     indices: Option<Vec<usize>>,
     /// index in `indices` of the current song if one is playing
     current: Option<usize>,
+    /// indices in the playlist of the songs played recently, the most recent last,
+    /// kept at the end of the order when shuffling
+    recent: VecDeque<usize>,
+    /// weights of the songs for the weighted shuffle, sent by the orchestrator
+    weights: HashMap<String, u32>,
 }
 
 impl PlaylistHandler {
@@ -159,6 +434,8 @@ impl PlaylistHandler {
             playlist: None,
             indices: None,
             current: None,
+            recent: VecDeque::new(),
+            weights: HashMap::new(),
         }
     }
     pub fn is_some(&self) -> bool {
@@ -171,30 +448,116 @@ impl PlaylistHandler {
         self.indices = Some((0..playlist.songs.len()).collect());
         self.playlist = Some(playlist);
         self.current = Some(0);
+        self.recent.clear();
     }
+    /// Shuffle the songs following [config::Config::shuffle_mode],
+    /// the current song stays the current one
     pub fn shuffle(&mut self) {
-        if self.indices.is_some() {
-            self.indices.as_mut().unwrap().shuffle(&mut thread_rng())
+        let current = self.get_current();
+        if let Some(indices) = self.shuffled_indices() {
+            self.indices = Some(indices);
+        }
+        if let (Some(current), Some(indices)) = (current, self.indices.as_mut()) {
+            indices.retain(|i| *i != current);
+            indices.insert(0, current);
+            self.current = Some(0);
+        }
+    }
+    /// Shuffle the songs again to start a new loop of the playlist from its first song
+    pub fn restart_shuffled(&mut self) {
+        if let Some(indices) = self.shuffled_indices() {
+            self.indices = Some(indices);
+        }
+        self.current = Some(0);
+    }
+    fn shuffled_indices(&self) -> Option<Vec<usize>> {
+        let playlist = self.playlist.as_ref()?;
+        let recent: Vec<usize> = self.recent.iter().copied().collect();
+        let mode = config::get_config().shuffle_mode;
+        Some(shuffle::shuffle(
+            &playlist.songs,
+            &recent,
+            mode,
+            &self.weights,
+        ))
+    }
+    /// Remember that the current song was played, only the songs played since
+    /// half of the playlist are kept
+    fn mark_played(&mut self) {
+        let (current, playlist) = match (self.get_current(), &self.playlist) {
+            (Some(current), Some(playlist)) => (current, playlist),
+            _ => return,
+        };
+        let max_len = playlist.songs.len() / 2;
+        self.recent.retain(|i| *i != current);
+        self.recent.push_back(current);
+        while self.recent.len() > max_len {
+            self.recent.pop_front();
         }
     }
     pub fn unshuffle(&mut self) {
         if let Some(playlist) = &self.playlist {
+            // indices in the playlist and in the playing order are now the same
+            self.current = self.get_current();
             self.indices = Some((0..playlist.songs.len()).collect());
         }
     }
+    /// returns `true` if the song at `position` in the playing order can be played
+    fn is_playable(&self, position: usize) -> bool {
+        match (&self.playlist, &self.indices) {
+            (Some(playlist), Some(indices)) => indices
+                .get(position)
+                .and_then(|&index| playlist.songs.get(index))
+                .is_some_and(|song| !song.unavailable),
+            _ => false,
+        }
+    }
+    /// go to the next song that can be played, staying on the current one if there is none
     pub fn next(&mut self) {
-        if let Some(indices) = &self.indices {
-            if let Some(current) = self.current {
-                self.current = Some((current + 1).min(indices.len() - 1));
-            }
+        let len = self.indices.as_ref().map_or(0, Vec::len);
+        if let Some(current) = self.current {
+            let next = (current + 1..len).find(|&position| self.is_playable(position));
+            self.current = Some(next.unwrap_or(current));
         }
     }
+    /// go to the previous song that can be played, staying on the current one if there is none
     pub fn prev(&mut self) {
-        if self.indices.is_some() {
-            if let Some(current) = self.current {
-                if let Some(val) = current.checked_sub(1) {
-                    self.current = Some(val)
-                }
+        if let Some(current) = self.current {
+            let prev = (0..current)
+                .rev()
+                .find(|&position| self.is_playable(position));
+            self.current = Some(prev.unwrap_or(current));
+        }
+    }
+    /// Add `song` to the playlist, to be played after the current song if `next` is set
+    /// or after every other song otherwise
+    pub fn enqueue(&mut self, song: SongInfo, next: bool) {
+        let (playlist, indices) = match (&mut self.playlist, &mut self.indices) {
+            (Some(playlist), Some(indices)) => (playlist, indices),
+            _ => {
+                let playlist = PlaylistInfo {
+                    title: "Queue".to_string(),
+                    length: 1,
+                    songs: vec![song],
+                    ..Default::default()
+                };
+                self.set_playlist(playlist);
+                return;
+            }
+        };
+        playlist.songs.push(song);
+        playlist.length = playlist.songs.len();
+        let index = playlist.songs.len() - 1;
+        match self.current {
+            Some(current) if next => indices.insert(current + 1, index),
+            _ => indices.push(index),
+        }
+    }
+    /// go to the song at `index` in the playlist, whatever the playing order
+    pub fn go_to(&mut self, index: usize) {
+        if let Some(indices) = &self.indices {
+            if let Some(position) = indices.iter().position(|i| *i == index) {
+                self.current = Some(position);
             }
         }
     }
@@ -233,7 +596,12 @@ pub struct PlayerHandler {
     shuffle: bool,
     autoplay: bool,
     repeat: Repeat,
+    /// whether a window displays the video
+    video: bool,
     cancel_token: CancellationToken,
+    events: UnboundedReceiver<MpvEvent>,
+    /// where the long songs were left
+    positions: SavedPositions,
 }
 
 impl PlayerHandler {
@@ -241,8 +609,11 @@ impl PlayerHandler {
         request_rx: Receiver<Request>,
         answer_tx: Sender<Answer>,
         cancel_token: CancellationToken,
+        video: bool,
     ) -> Self {
-        let player = Player::new();
+        let player = Player::new(video);
+        let (events_tx, events) = mpsc::unbounded_channel();
+        player.observe(events_tx);
         Self {
             player,
             request_rx,
@@ -252,17 +623,23 @@ impl PlayerHandler {
             shuffle: false,
             autoplay: false,
             repeat: Repeat::Off,
+            video,
             cancel_token,
+            events,
+            positions: SavedPositions::new(),
         }
     }
 
     pub async fn main_loop(&mut self) {
-        let mut update_interval = tokio::time::interval(Duration::from_millis(100));
+        let mut flush_interval = tokio::time::interval(POSITIONS_FLUSH);
         loop {
-            let update_delay = update_interval.tick();
             tokio::select! {
-                _ = self.cancel_token.cancelled() => break,
-                _ = update_delay => self.update(),
+                _ = self.cancel_token.cancelled() => {
+                    self.save_position();
+                    break;
+                }
+                _ = flush_interval.tick() => self.positions.flush(),
+                Some(event) = self.events.recv() => self.handle_event(event).await,
                 maybe_request = self.request_rx.recv() => {
                     use tokio::sync::broadcast::error as error;
                     match maybe_request {
@@ -277,12 +654,26 @@ impl PlayerHandler {
                 }
             }
         }
+        self.positions.flush();
+    }
+    async fn handle_event(&mut self, event: MpvEvent) {
+        self.player.apply(&event);
+        match event {
+            // the position alone is not worth an update, it is sent along the player info
+            MpvEvent::TimePos(_) => return,
+            MpvEvent::Eof(true) | MpvEvent::Idle(true) => self.track_ended(),
+            _ => (),
+        }
+        self.send_info().await
     }
-    fn update(&mut self) {
+    fn track_ended(&mut self) {
         let state = self.player.get_state();
         if state.playpause != Playback::Play {
             return;
         }
+        if let Some(song) = self.playlist.current_song().filter(|_| state.eof) {
+            self.positions.forget(&song);
+        }
         if self.autoplay && self.playlist.current_song().is_some() && state.eof {
             // go to next song if current one is finished
             self.weak_next()
@@ -292,13 +683,27 @@ impl PlayerHandler {
     async fn handle_request(&mut self, request: Request) {
         match request {
             Request::PlayerAction(action) => {
-                self.handle_action(action);
+                self.handle_action(action).await;
                 self.send_info().await
             }
             Request::Get(GetRequest::PlayerInfo) => self.send_info().await,
+            Request::Command(command) if command == "video" => self.toggle_video().await,
             _ => (),
         }
     }
+    async fn toggle_video(&mut self) {
+        self.video = !self.video;
+        self.player.set_video(self.video);
+        let message = if self.video {
+            "Video enabled, starting from the next song"
+        } else {
+            "Video disabled, starting from the next song"
+        };
+        let notification = Notification::new(Severity::Info, message);
+        if self.answer_tx.send(notification.into()).await.is_err() {
+            self.cancel_token.cancel();
+        }
+    }
     /// send back the player state through [`Self::answer_tx`]
     /// if the channel is closed, cancel [`Self::cancel_token`]
     async fn send_info(&mut self) {
@@ -317,8 +722,12 @@ impl PlayerHandler {
             autoplay: self.autoplay,
             repeat: self.repeat,
             volume: state.volume as u8,
+            muted: state.muted,
             position: state.time_pos,
-            can_seek: true,
+            can_seek: state.seekable,
+            chapters: state.chapters,
+            chapter: state.chapter,
+            speed: Some(state.speed),
         };
         if self.answer_tx.send(Answer::PlayerInfo(info)).await.is_err() {
             self.cancel_token.cancel();
@@ -327,7 +736,32 @@ impl PlayerHandler {
 
     /// handle action received by the handler
     /// and send back information on completion
-    fn handle_action(&mut self, action: PlayerAction) {
+    async fn handle_action(&mut self, action: PlayerAction) {
+        // fade out before interrupting the current song
+        let fade = match action {
+            PlayerAction::PlayPause(target) => target,
+            PlayerAction::PlayPauseToggle
+            | PlayerAction::Stop
+            | PlayerAction::Prev
+            | PlayerAction::Next
+            | PlayerAction::PlayIndex(_) => true,
+            _ => false,
+        };
+        let interrupts = fade
+            || matches!(
+                action,
+                PlayerAction::PlayPause(_) | PlayerAction::SetTrackList(_)
+            );
+        if interrupts {
+            self.save_position();
+        }
+        let volume = if fade { self.fade_out().await } else { None };
+        let resumes = match action {
+            PlayerAction::PlayPause(target) => !target,
+            PlayerAction::PlayPauseToggle => true,
+            _ => false,
+        };
+        let fade_in = if resumes { self.silence_paused() } else { None };
         match action {
             PlayerAction::PlayPause(target) => {
                 if target != self.player.paused() {
@@ -340,16 +774,85 @@ impl PlayerHandler {
             PlayerAction::ShuffleToggle => self.shuffle_toggle(),
             PlayerAction::Autoplay(target) => self.autoplay(target),
             PlayerAction::AutoplayToggle => self.autoplay_toggle(),
-            PlayerAction::Seek { dt, mode } => self.seek(dt, mode),
+            PlayerAction::Seek(mode) => self.seek(mode),
             PlayerAction::Prev => self.strong_prev(),
             PlayerAction::Next => self.strong_next(),
             PlayerAction::SetVolume(volume) => self.set_volume(volume),
+            PlayerAction::MuteToggle => self.player.mute_toggle(),
             PlayerAction::SetTrackList(tracks) => {
                 debug!("Setting track list");
                 self.playlist.set_playlist(tracks)
             }
             PlayerAction::SetRepeat(repeat) => self.set_repeat(repeat),
             PlayerAction::CycleRepeat => self.cycle_repeat(),
+            PlayerAction::PlayIndex(index) => {
+                self.playlist.go_to(index);
+                self.play_playlist();
+            }
+            PlayerAction::SetEqualizer(gains) => self.player.set_equalizer(&gains),
+            PlayerAction::Enqueue { song, next } => {
+                let started = self.playlist.is_some();
+                self.playlist.enqueue(song, next);
+                if !started {
+                    // nothing was queued, play the song right away
+                    self.autoplay(true);
+                }
+            }
+            PlayerAction::NextChapter => self.player.add_chapter(1),
+            PlayerAction::PrevChapter => self.player.add_chapter(-1),
+            PlayerAction::SetChapter(index) => self.player.set_chapter(index),
+            PlayerAction::SetSpeed(percent) => self.player.set_speed(percent),
+            PlayerAction::SetShuffleWeights(weights) => self.playlist.weights = weights,
+        }
+        if let Some(volume) = volume {
+            self.player.set_volume(volume);
+        }
+        if let Some(volume) = fade_in {
+            self.fade_in(volume).await;
+        }
+    }
+    /// Remember where the current song is, to resume it from there if it is long enough
+    fn save_position(&self) {
+        let state = self.player.get_state();
+        if state.playpause == Playback::Stop {
+            return;
+        }
+        if let Some(song) = self.playlist.current_song() {
+            let duration = state.duration.max(song.duration);
+            self.positions.update(&song, state.time_pos, duration);
+        }
+    }
+    /// Progressively lower the volume to 0 if something is playing,
+    /// returns the volume to restore once the song has been interrupted
+    async fn fade_out(&self) -> Option<i64> {
+        let duration = Duration::from_millis(config::get_config().fade_duration_ms);
+        if duration.is_zero() || self.player.get_playback_status() != Playback::Play {
+            return None;
+        }
+        let volume = self.player.get_volume();
+        for step in (0..FADE_STEPS).rev() {
+            self.player.set_volume(volume * step / FADE_STEPS);
+            tokio::time::sleep(duration / FADE_STEPS as u32).await;
+        }
+        Some(volume)
+    }
+    /// Set the volume to 0 if the playback is paused, before resuming it,
+    /// returns the volume to fade in to
+    fn silence_paused(&self) -> Option<i64> {
+        let duration = Duration::from_millis(config::get_config().fade_duration_ms);
+        if duration.is_zero() || self.player.get_playback_status() != Playback::Pause {
+            return None;
+        }
+        let volume = self.player.get_volume();
+        self.player.set_volume(0);
+        Some(volume)
+    }
+    /// Progressively raise the volume from 0 to `volume`
+    async fn fade_in(&self, volume: i64) {
+        let duration = Duration::from_millis(config::get_config().fade_duration_ms);
+        for step in 1..=FADE_STEPS {
+            tokio::time::sleep(duration / FADE_STEPS as u32).await;
+            self.player.set_volume(volume * step / FADE_STEPS);
         }
     }
     fn shuffle(&mut self, target: bool) {
@@ -393,22 +896,39 @@ impl PlayerHandler {
             self.play_playlist();
         } else {
             // otherwise go to start of current song
-            self.seek(0, SeekMode::Absolute);
+            self.seek(SeekMode::Absolute(Duration::ZERO));
         }
     }
     fn play_playlist(&mut self) {
-        if let Some(song) = self.playlist.current_song() {
-            self.player.play(&song.url);
+        if self
+            .playlist
+            .current_song()
+            .is_some_and(|song| song.unavailable)
+        {
+            // unavailable songs are listed but skipped
+            self.playlist.next();
+        }
+        let song = self
+            .playlist
+            .current_song()
+            .filter(|song| !song.unavailable);
+        if let Some(song) = song {
+            let start = self.positions.get(&song);
+            if let Some(start) = start {
+                debug!("Resuming {} at {start:?}", song.url);
+            }
+            self.player.play(&song.url, start);
+            self.playlist.mark_played();
             debug!("Playing {}", song.url);
         }
     }
 
-    fn seek(&self, dt: i64, mode: SeekMode) {
+    fn seek(&self, mode: SeekMode) {
         match mode {
-            SeekMode::Absolute => self.player.seek_absolute(dt),
-            SeekMode::Relative => self.player.seek_relative(dt as i32),
-            SeekMode::AbsolutePercent => self.player.seek_percent(dt as usize),
-            SeekMode::RelativePercent => todo!(),
+            SeekMode::Absolute(position) => self.player.seek_absolute(position),
+            SeekMode::Relative(dt) => self.player.seek_relative(dt),
+            SeekMode::AbsolutePercent(percent) => self.player.seek_percent(percent),
+            SeekMode::RelativePercent(percent) => self.player.seek_percent_relative(percent),
         }
     }
 
@@ -438,12 +958,15 @@ impl PlayerHandler {
     /// goes to next track in playlist
     /// respecting [`Self::repeat`]
     fn weak_next(&mut self) {
-        if self.repeat != Repeat::Song {
-            self.playlist.next();
-        }
         if self.repeat == Repeat::Playlist && self.playlist.is_at_end() {
-            //return to begin of playlist
-            self.playlist.current = Some(0)
+            //return to begin of playlist, in a new order if shuffled
+            if self.shuffle {
+                self.playlist.restart_shuffled();
+            } else {
+                self.playlist.current = Some(0)
+            }
+        } else if self.repeat != Repeat::Song {
+            self.playlist.next();
         }
         self.play_playlist();
     }
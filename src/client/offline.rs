@@ -0,0 +1,219 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Sender;
+
+use crate::{
+    client::{
+        download,
+        interface::{Answer, DownloadProgress, PlaylistInfo, SongInfo},
+    },
+    config, json_store,
+};
+
+/// Copies of the playlists kept offline, saved on disk for each client
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct OfflineStore {
+    /// downloaded files indexed by playlist id then by song id
+    playlists: HashMap<String, HashMap<String, PathBuf>>,
+    #[serde(skip)]
+    path: PathBuf,
+    #[serde(skip)]
+    folder: PathBuf,
+}
+
+/// Changes needed to bring the offline copy of a playlist in line with the remote one
+#[derive(Debug, Default)]
+pub struct SyncPlan {
+    /// songs not downloaded yet
+    pub missing: Vec<SongInfo>,
+    /// ids and files of the songs no longer in the playlist
+    pub removed: Vec<(String, PathBuf)>,
+}
+
+impl OfflineStore {
+    /// Load the store of the client named `client`, files are downloaded
+    /// in a subfolder of the download folder
+    pub fn load(client: &str) -> Self {
+        let mut path = config::get_dirs().data_dir().to_path_buf();
+        path.push(format!("{client}_offline.json"));
+        let store: Self = json_store::load(&path);
+        let folder = config::get_config()
+            .download_folder
+            .join("offline")
+            .join(client);
+        Self {
+            path,
+            folder,
+            ..store
+        }
+    }
+
+    fn save(&self) {
+        json_store::save(&self.path, self);
+    }
+
+    pub fn is_offline(&self, playlist: &str) -> bool {
+        self.playlists.contains_key(playlist)
+    }
+
+    pub fn playlists(&self) -> impl Iterator<Item = &String> {
+        self.playlists.keys()
+    }
+
+    /// Start or stop keeping `playlist` offline, returns `true` if it is now kept offline,
+    /// the files of a playlist no longer kept offline are deleted
+    pub fn toggle(&mut self, playlist: &str) -> bool {
+        let offline = match self.playlists.remove(playlist) {
+            Some(_) => {
+                let folder = self.playlist_folder(playlist);
+                if let Err(err) = fs::remove_dir_all(&folder) {
+                    error!("[Offline] Failed to delete {}: {err}", folder.display());
+                }
+                false
+            }
+            None => {
+                self.playlists.insert(playlist.to_string(), HashMap::new());
+                true
+            }
+        };
+        self.save();
+        offline
+    }
+
+    /// Downloaded file of the song `id`, if any of the playlists kept offline contains it
+    pub fn local_file(&self, id: &str) -> Option<&PathBuf> {
+        self.playlists.values().find_map(|songs| songs.get(id))
+    }
+
+    /// Play the downloaded files of `playlist` instead of streaming them
+    pub fn use_local_files(&self, playlist: &mut PlaylistInfo) {
+        for song in playlist.songs.iter_mut() {
+            if let Some(path) = self.local_file(&song.id) {
+                song.url = format!("file://{}", path.display());
+            }
+        }
+    }
+
+    fn playlist_folder(&self, playlist: &str) -> PathBuf {
+        self.folder.join(playlist)
+    }
+
+    /// Compare the offline copy of `playlist` with its songs,
+    /// files deleted from the disk are downloaded again
+    pub fn plan(&self, playlist: &PlaylistInfo) -> SyncPlan {
+        let files = match self.playlists.get(&playlist.id) {
+            Some(files) => files,
+            None => return SyncPlan::default(),
+        };
+        let missing = playlist
+            .songs
+            .iter()
+            .filter(|song| !files.get(&song.id).is_some_and(|path| path.exists()))
+            .cloned()
+            .collect();
+        let removed = files
+            .iter()
+            .filter(|(id, _)| !playlist.songs.iter().any(|song| &song.id == *id))
+            .map(|(id, path)| (id.clone(), path.clone()))
+            .collect();
+        SyncPlan { missing, removed }
+    }
+
+    /// Record the file of `song`, returns `false` if `playlist` is no longer kept offline
+    fn insert(&mut self, playlist: &str, song: &str, path: PathBuf) -> bool {
+        match self.playlists.get_mut(playlist) {
+            Some(files) => {
+                files.insert(song.to_string(), path);
+                self.save();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn remove(&mut self, playlist: &str, song: &str) {
+        if let Some(files) = self.playlists.get_mut(playlist) {
+            files.remove(song);
+            self.save();
+        }
+    }
+}
+
+/// Bring the offline copy of `playlist` up to date, fetching the new songs
+/// and deleting the ones removed, the progress is reported through `answer_tx`
+pub async fn sync(
+    store: Arc<Mutex<OfflineStore>>,
+    playlist: PlaylistInfo,
+    answer_tx: Sender<Answer>,
+) {
+    let (plan, folder) = {
+        let store = store.lock().unwrap();
+        (store.plan(&playlist), store.playlist_folder(&playlist.id))
+    };
+    debug!(
+        "[Offline] Syncing {}: {} new songs, {} removed",
+        playlist.title,
+        plan.missing.len(),
+        plan.removed.len()
+    );
+    for (id, path) in plan.removed {
+        if let Err(err) = tokio::fs::remove_file(&path).await {
+            error!("[Offline] Failed to delete {}: {err}", path.display());
+        }
+        store.lock().unwrap().remove(&playlist.id, &id);
+    }
+    if plan.missing.is_empty() {
+        return;
+    }
+    if let Err(err) = tokio::fs::create_dir_all(&folder).await {
+        error!("[Offline] Cannot create {}: {err}", folder.display());
+    }
+    let total = plan.missing.len();
+    for (done, song) in plan.missing.iter().enumerate() {
+        if !store.lock().unwrap().is_offline(&playlist.id) {
+            // no longer kept offline
+            break;
+        }
+        let mut progress = DownloadProgress {
+            title: song.title.clone(),
+            done,
+            total,
+            error: None,
+        };
+        if answer_tx.send(progress.clone().into()).await.is_err() {
+            return;
+        }
+        match download::download_song(song, &folder).await {
+            Ok(path) => {
+                let kept = store
+                    .lock()
+                    .unwrap()
+                    .insert(&playlist.id, &song.id, path.clone());
+                if !kept {
+                    // toggled off during the download
+                    let _ = tokio::fs::remove_file(&path).await;
+                    break;
+                }
+            }
+            Err(err) => {
+                error!("[Offline] Failed to download {}: {err}", song.title);
+                progress.error = Some(err.to_string());
+                let _ = answer_tx.send(progress.into()).await;
+            }
+        }
+    }
+    let progress = DownloadProgress {
+        title: String::new(),
+        done: total,
+        total,
+        error: None,
+    };
+    let _ = answer_tx.send(progress.into()).await;
+}
@@ -0,0 +1,215 @@
+use std::process::Stdio;
+
+use anyhow::{anyhow, Context, Result};
+use log::{error, warn};
+use serde::Deserialize;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    process::Command,
+    sync::mpsc::{Receiver, Sender},
+    task::JoinHandle,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    client::{
+        interface::{
+            AlbumInfo, Answer, ArtistInfo, Capabilities, Notification, PlayerInfo, PlaylistChange,
+            PlaylistInfo, Request, Severity, SongInfo, Widget,
+        },
+        ClientFactory,
+    },
+    config::{self, PluginConfig},
+};
+
+/// Answers a plugin can send, the interactive widgets of [Answer] cannot cross the process
+#[derive(Debug, Deserialize)]
+pub enum PluginAnswer {
+    PlayerInfo(PlayerInfo),
+    PlaylistList(Vec<PlaylistInfo>),
+    Playlist(PlaylistInfo),
+    PlaylistPage {
+        id: String,
+        offset: usize,
+        songs: Vec<SongInfo>,
+        total: usize,
+    },
+    PlaylistDelta {
+        id: String,
+        length: usize,
+        changes: Vec<PlaylistChange>,
+    },
+    Alert {
+        title: String,
+        content: String,
+    },
+    SearchResults {
+        query: String,
+        songs: Vec<SongInfo>,
+    },
+    Notification(Notification),
+    Capabilities(Capabilities),
+    Artists(Vec<ArtistInfo>),
+    Albums {
+        artist: String,
+        albums: Vec<AlbumInfo>,
+    },
+    Album(AlbumInfo),
+    Related {
+        seed: String,
+        songs: Vec<SongInfo>,
+    },
+}
+
+impl From<PluginAnswer> for Answer {
+    fn from(value: PluginAnswer) -> Self {
+        match value {
+            PluginAnswer::PlayerInfo(info) => Answer::PlayerInfo(info),
+            PluginAnswer::PlaylistList(list) => Answer::PlaylistList(list),
+            PluginAnswer::Playlist(playlist) => Answer::Playlist(playlist),
+            PluginAnswer::PlaylistPage {
+                id,
+                offset,
+                songs,
+                total,
+            } => Answer::PlaylistPage {
+                id,
+                offset,
+                songs,
+                total,
+            },
+            PluginAnswer::PlaylistDelta {
+                id,
+                length,
+                changes,
+            } => Answer::PlaylistDelta {
+                id,
+                length,
+                changes,
+            },
+            PluginAnswer::Alert { title, content } => Widget::Alert { title, content }.into(),
+            PluginAnswer::SearchResults { query, songs } => Answer::SearchResults { query, songs },
+            PluginAnswer::Notification(notification) => notification.into(),
+            PluginAnswer::Capabilities(capabilities) => Answer::Capabilities(capabilities),
+            PluginAnswer::Artists(artists) => Answer::Artists(artists),
+            PluginAnswer::Albums { artist, albums } => Answer::Albums { artist, albums },
+            PluginAnswer::Album(album) => Answer::Album(album),
+            PluginAnswer::Related { seed, songs } => Answer::Related { seed, songs },
+        }
+    }
+}
+
+/// Names of the plugins set in the config
+pub fn names() -> Vec<String> {
+    config::get_config().plugins.into_keys().collect()
+}
+
+/// Starts the plugin configured under `name`, a backend run as a subprocess.
+/// yama writes each [Request] as a line of JSON on the standard input of the plugin,
+/// e.g. `{"Get":"PlaylistList"}`, and reads each line of its standard output as a
+/// [PluginAnswer], e.g. `{"Playlist":{"title":"Mix",...}}`. Durations are written as
+/// `{"secs":12,"nanos":0}` and the lines written on the standard error are logged.
+/// A plugin not answering `{"Get":"Capabilities"}` is assumed to support every request
+pub struct Factory {
+    name: String,
+    config: PluginConfig,
+}
+
+impl Factory {
+    /// Factory of the plugin `name`, if it is set in the config
+    pub fn from_config(name: &str) -> Option<Self> {
+        let config = config::get_config().plugins.remove(name)?;
+        Some(Self {
+            name: name.to_string(),
+            config,
+        })
+    }
+}
+
+impl ClientFactory for Factory {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn spawn(
+        &self,
+        receiver: Receiver<Request>,
+        sender: Sender<Answer>,
+        cancel_token: CancellationToken,
+    ) -> JoinHandle<Result<()>> {
+        let mut command = Command::new(&self.config.command);
+        command
+            .args(&self.config.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        let name = self.name.clone();
+        tokio::spawn(async move {
+            let result = run(&name, command, receiver, &sender, cancel_token).await;
+            if let Err(err) = &result {
+                error!("[Plugin] {name} stopped: {err:#}");
+                let message = format!("Plugin {name} stopped: {err}");
+                let _ = sender
+                    .send(Notification::new(Severity::Error, message).into())
+                    .await;
+            }
+            result
+        })
+    }
+}
+
+/// Forward the requests to the plugin and its answers back until it exits or is cancelled
+async fn run(
+    name: &str,
+    mut command: Command,
+    mut receiver: Receiver<Request>,
+    sender: &Sender<Answer>,
+    cancel_token: CancellationToken,
+) -> Result<()> {
+    let mut child = command
+        .spawn()
+        .with_context(|| format!("cannot start {:?}", command.as_std().get_program()))?;
+    let mut stdin = child.stdin.take().ok_or(anyhow!("no standard input"))?;
+    let stdout = child.stdout.take().ok_or(anyhow!("no standard output"))?;
+    let mut stdout = BufReader::new(stdout).lines();
+    if let Some(stderr) = child.stderr.take() {
+        let mut stderr = BufReader::new(stderr).lines();
+        let name = name.to_string();
+        tokio::spawn(async move {
+            while let Ok(Some(line)) = stderr.next_line().await {
+                warn!("[Plugin] {name}: {line}");
+            }
+        });
+    }
+    loop {
+        tokio::select! {
+            _ = cancel_token.cancelled() => break,
+            request = receiver.recv() => {
+                let request = match request {
+                    Some(request) => request,
+                    None => break,
+                };
+                let mut line = serde_json::to_string(&request)?;
+                line.push('\n');
+                stdin.write_all(line.as_bytes()).await.context("cannot write request")?;
+            }
+            line = stdout.next_line() => {
+                let line = match line.context("cannot read answer")? {
+                    Some(line) => line,
+                    None => return Err(anyhow!("the plugin exited")),
+                };
+                match serde_json::from_str::<PluginAnswer>(&line) {
+                    Ok(answer) => {
+                        if sender.send(answer.into()).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(err) => warn!("[Plugin] {name} sent an invalid answer: {err}"),
+                }
+            }
+        }
+    }
+    let _ = child.kill().await;
+    Ok(())
+}
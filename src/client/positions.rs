@@ -0,0 +1,99 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+use crate::{client::interface::SongInfo, config, json_store, ratings::song_key};
+
+/// songs left earlier than this start from the beginning again
+const MIN_PROGRESS: Duration = Duration::from_secs(30);
+/// songs left closer than this to their end are considered finished
+const END_MARGIN: Duration = Duration::from_secs(30);
+
+/// positions shared by every mpv client, loaded on first use
+static POSITIONS: Mutex<Option<Positions>> = Mutex::new(None);
+
+#[derive(Debug)]
+struct Positions {
+    /// positions indexed by song key
+    songs: HashMap<String, Duration>,
+    path: PathBuf,
+    /// whether some changes are not saved yet
+    modified: bool,
+}
+
+impl Positions {
+    fn load() -> Self {
+        let mut path = config::get_dirs().data_dir().to_path_buf();
+        path.push("positions.json");
+        let songs = json_store::load(&path);
+        Self {
+            songs,
+            path,
+            modified: false,
+        }
+    }
+}
+
+/// Positions at which the long songs played by mpv were left, saved on disk so that they
+/// resume from there. They are kept in memory, shared by every mpv client, and only written
+/// by [SavedPositions::flush]
+#[derive(Debug)]
+pub struct SavedPositions;
+
+impl SavedPositions {
+    pub fn new() -> Self {
+        Self
+    }
+
+    fn lock(&self) -> MutexGuard<'static, Option<Positions>> {
+        let mut positions = POSITIONS.lock().unwrap_or_else(|err| err.into_inner());
+        if positions.is_none() {
+            *positions = Some(Positions::load());
+        }
+        positions
+    }
+
+    /// Position at which `song` was left, if it was
+    pub fn get(&self, song: &SongInfo) -> Option<Duration> {
+        self.lock()
+            .as_ref()
+            .and_then(|positions| positions.songs.get(song_key(song)).copied())
+    }
+
+    /// Remember that `song`, lasting `duration`, was left at `position`,
+    /// only the songs lasting at least `resume_min_minutes` are remembered
+    pub fn update(&self, song: &SongInfo, position: Duration, duration: Duration) {
+        let min_minutes = config::get_config().resume_min_minutes;
+        if min_minutes == 0 || duration < Duration::from_secs(min_minutes * 60) {
+            return;
+        }
+        if position < MIN_PROGRESS || position + END_MARGIN >= duration {
+            self.forget(song);
+            return;
+        }
+        if let Some(positions) = self.lock().as_mut() {
+            positions.songs.insert(song_key(song).to_string(), position);
+            positions.modified = true;
+        }
+    }
+
+    /// Start `song` from the beginning the next time it is played
+    pub fn forget(&self, song: &SongInfo) {
+        if let Some(positions) = self.lock().as_mut() {
+            if positions.songs.remove(song_key(song)).is_some() {
+                positions.modified = true;
+            }
+        }
+    }
+
+    /// Write the positions on disk if they changed since the last time
+    pub fn flush(&self) {
+        if let Some(positions) = self.lock().as_mut().filter(|positions| positions.modified) {
+            json_store::save(&positions.path, &positions.songs);
+            positions.modified = false;
+        }
+    }
+}
@@ -1,28 +1,44 @@
 use core::fmt::{self, Display};
-use std::{fs::File, io::BufReader, path::PathBuf, time::Duration};
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use futures::StreamExt;
 use google_youtube3::chrono::TimeDelta;
 use log::{debug, error, warn};
 use rspotify::{
-    clients::{pagination::Paginator, BaseClient, OAuthClient},
+    clients::{BaseClient, OAuthClient},
+    http::HttpError,
     model::{
-        CurrentPlaybackContext, CurrentUserQueue, Device, FullTrack, PlayableItem, PlaylistId,
-        PlaylistItem, RepeatState, SimplifiedPlaylist,
+        CurrentPlaybackContext, CurrentUserQueue, Device, FullTrack, Offset, Page, PlayableId,
+        PlayableItem, PlaylistId, PlaylistItem, RepeatState, SearchResult, SearchType,
+        SimplifiedPlaylist, TrackId,
     },
-    scopes, AuthCodeSpotify, ClientResult, Credentials, OAuth,
+    scopes, AuthCodeSpotify, ClientError, Credentials, OAuth,
 };
 use serde::{Deserialize, Serialize};
-use tokio::sync::{broadcast::Receiver, mpsc::Sender, oneshot};
+use tokio::{
+    net::TcpListener,
+    sync::{broadcast::Receiver, mpsc::Sender, oneshot},
+};
 use tokio_util::sync::CancellationToken;
 
+use super::{cache::SnapshotCache, redirect};
 use crate::{
-    client::interface::{
-        Answer, GetRequest, Playback, PlayerAction, PlayerInfo, PlaylistInfo, Repeat, Request,
-        SeekMode, SongInfo, Volume, Widget,
+    client::{
+        backoff::Backoff,
+        delta::SentPlaylists,
+        interface::{
+            Answer, Capabilities, GetRequest, Notification, Playback, PlayerAction, PlayerInfo,
+            PlaylistInfo, Repeat, Request, SeekMode, SetRequest, Severity, SongInfo, Visibility,
+            Volume, Widget,
+        },
     },
     config,
+    doctor::{self, Check},
+    secrets,
 };
 
 #[derive(Debug, Clone)]
@@ -54,48 +70,81 @@ pub struct Playlist<'a> {
     title: String,
     cover_url: String,
     length: usize,
+    /// offset of the next page of items to fetch, None once the playlist is fully loaded
+    next_offset: Option<u32>,
+    /// version of the playlist, changed by spotify whenever the playlist is modified
+    snapshot_id: String,
+    /// name of the user owning the playlist
+    owner: String,
+    visibility: Option<Visibility>,
+    editable: bool,
 }
 
 impl<'a> Playlist<'a> {
-    pub fn new(playlist: SimplifiedPlaylist) -> Self {
+    /// Playlist listed by spotify, only editable by `user` unless it is collaborative
+    pub fn new(playlist: SimplifiedPlaylist, user: Option<&str>) -> Self {
         let cover_url = if let Some(cover) = playlist.images.first() {
             cover.url.clone()
         } else {
             String::new()
         };
+        let visibility = match playlist.public {
+            Some(true) => Some(Visibility::Public),
+            Some(false) => Some(Visibility::Private),
+            None => None,
+        };
+        let owned = user.is_some_and(|user| playlist.owner.id.to_string() == user);
         Self {
             id: playlist.id,
             songs: Vec::new(),
             title: playlist.name,
             cover_url,
             length: playlist.tracks.total as usize,
+            next_offset: Some(0),
+            snapshot_id: playlist.snapshot_id,
+            owner: playlist.owner.display_name.unwrap_or_default(),
+            visibility,
+            editable: owned || playlist.collaborative,
         }
     }
     pub fn get_songs(&self) -> Vec<SongInfo> {
         self.songs.clone()
     }
-    pub async fn load<'b>(&mut self, mut pages: Paginator<'b, ClientResult<PlaylistItem>>) {
-        if !self.songs.is_empty() {
-            // already loaded
-            return;
-        }
-        let mut songs: Vec<SongInfo> = Vec::new();
-        while let Some(page) = pages.next().await {
-            if let Ok(item) = page {
-                if let Some(track) = item.track {
-                    match track {
-                        rspotify::model::PlayableItem::Track(track) => {
-                            if track.id.is_none() {
-                                continue;
-                            };
-                            songs.push(track.into())
-                        }
-                        rspotify::model::PlayableItem::Episode(_) => todo!(),
+    /// Use `songs` as the whole content of the playlist
+    fn set_songs(&mut self, songs: Vec<SongInfo>) {
+        self.length = songs.len();
+        self.songs = songs;
+        self.next_offset = None;
+    }
+    fn is_loaded(&self) -> bool {
+        self.next_offset.is_none()
+    }
+    /// Append the tracks of `page` to the songs, returns the number of songs added
+    fn add_page(&mut self, page: Page<PlaylistItem>) -> usize {
+        let before = self.songs.len();
+        for item in page.items {
+            if let Some(track) = item.track {
+                match track {
+                    rspotify::model::PlayableItem::Track(track) => {
+                        if track.id.is_none() {
+                            continue;
+                        };
+                        self.songs.push(track.into())
                     }
+                    rspotify::model::PlayableItem::Episode(_) => todo!(),
                 }
             }
         }
-        self.songs = songs;
+        self.next_offset = page.next.map(|_| page.offset + page.limit);
+        if self.is_loaded() {
+            self.length = self.songs.len();
+        }
+        self.songs.len() - before
+    }
+    /// Fetch the songs again after the playlist was modified
+    pub fn reload(&mut self) {
+        self.songs.clear();
+        self.next_offset = Some(0);
     }
     pub fn get_info(&self) -> PlaylistInfo {
         PlaylistInfo {
@@ -104,10 +153,40 @@ impl<'a> Playlist<'a> {
             cover_url: self.cover_url.clone(),
             id: self.id.to_string(),
             songs: self.get_songs(),
+            // the description is not listed along with the playlists
+            description: String::new(),
+            owner: self.owner.clone(),
+            visibility: self.visibility,
+            editable: self.editable,
         }
     }
 }
 
+/// names of the client secrets and of the token in the keyring
+const SECRET_NAME: &str = "spotify_secrets";
+const TOKEN_NAME: &str = "spotify_token";
+/// time after which the cached list of devices is refreshed
+const DEVICES_TTL: Duration = Duration::from_secs(30);
+/// time given to the user to authorize yama before asking to paste the redirection
+const REDIRECT_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+/// delay between two pages of playlist items fetched in the background
+const LOAD_INTERVAL: Duration = Duration::from_millis(200);
+/// number of playlist items fetched per request, the maximum allowed by the api
+const PAGE_SIZE: u32 = 100;
+/// time after which the list of playlists is fetched again to detect modified playlists
+const PLAYLISTS_TTL: Duration = Duration::from_secs(60);
+/// the playlists of the account are edited, the songs are played on a spotify device,
+/// the library is only browsed by playlist
+const CAPABILITIES: Capabilities = Capabilities {
+    can_search: true,
+    can_edit_playlists: true,
+    can_play: true,
+    can_seek: true,
+    supports_queue: true,
+    can_browse_artists: false,
+    suggests_related: true,
+};
+
 #[derive(Serialize, Deserialize)]
 struct Creds {
     pub id: String,
@@ -123,7 +202,37 @@ pub struct Backend<'a> {
     shuffled: bool,
     autoplay: bool,
     last_info: PlayerInfo,
+    /// set by [PlayerAction::Stop] until the playback resumes, spotify only knows pausing
+    stopped: bool,
+    /// volume to restore when unmuting, set while muted as spotify has no mute
+    unmuted_volume: Option<u8>,
     device: Option<Device>,
+    /// cached list of available devices
+    devices: Vec<Device>,
+    /// last time `devices` was fetched
+    devices_updated: Option<Instant>,
+    /// playlist selected by the user, loaded before the others
+    priority: Option<String>,
+    /// last time the list of playlists was fetched
+    playlists_updated: Option<Instant>,
+    cache: SnapshotCache,
+    backoff: Backoff,
+    sent: SentPlaylists,
+    /// id of the user logged in, owning the playlists which can be edited
+    user: Option<String>,
+}
+
+/// Check that the credentials can be read and that the api can be reached
+pub async fn diagnose() -> Vec<Check> {
+    let location = config::get_config().spotify_secret_location;
+    let creds = secrets::read(SECRET_NAME, Path::new(&location))
+        .and_then(|creds| Ok(serde_json::from_str::<Creds>(&creds)?))
+        .map(|creds| format!("client id {}", creds.id))
+        .map_err(|err| anyhow::anyhow!("cannot read {location}: {err}"));
+    vec![
+        Check::new("spotify credentials", creds),
+        Check::new("spotify api", doctor::reachable("api.spotify.com").await),
+    ]
 }
 
 impl<'a> Backend<'a> {
@@ -132,29 +241,33 @@ impl<'a> Backend<'a> {
         answer_tx: Sender<Answer>,
         cancel_token: CancellationToken,
     ) -> Result<Self> {
-        let file = File::open(config::get_config().spotify_secret_location).unwrap();
-        let reader = BufReader::new(file);
-        let creds: Creds = serde_json::from_reader(reader).unwrap();
+        let location = config::get_config().spotify_secret_location;
+        let creds = secrets::read(SECRET_NAME, Path::new(&location))?;
+        let creds: Creds = serde_json::from_str(&creds)?;
         let creds = Credentials::new(&creds.id, &creds.secret);
-        let dirs = config::get_dirs();
-        let cache = dirs.cache_dir();
-        let mut cache = PathBuf::from(cache);
-        cache.push("spotify_token_cache.json");
         let config = rspotify::Config {
-            cache_path: cache,
-            token_cached: true,
+            cache_path: Self::token_cache(),
+            // the token is saved by `save_token` when kept in the keyring
+            token_cached: !secrets::in_keyring(),
             token_refreshing: true,
             ..Default::default()
         };
 
         let oauth = OAuth {
             redirect_uri: "http://localhost:8888/callback".to_string(),
-            scopes: scopes!("user-read-recently-played"),
+            scopes: scopes!("user-read-recently-played", "playlist-modify-private"),
             ..Default::default()
         };
 
         let spotify = AuthCodeSpotify::with_config(creds, oauth, config);
-        if let Ok(Some(token)) = spotify.read_token_cache(true).await {
+        let token = if secrets::in_keyring() {
+            secrets::read(TOKEN_NAME, &Self::token_cache())
+                .ok()
+                .and_then(|token| serde_json::from_str(&token).ok())
+        } else {
+            spotify.read_token_cache(true).await.ok().flatten()
+        };
+        if let Some(token) = token {
             // this is stupid, read_token_cache does not update the token
             *spotify.get_token().lock().await.unwrap() = Some(token)
         }
@@ -167,7 +280,17 @@ impl<'a> Backend<'a> {
             shuffled: false,
             autoplay: false,
             last_info: PlayerInfo::default(),
+            unmuted_volume: None,
             device: None,
+            stopped: false,
+            devices: Vec::new(),
+            devices_updated: None,
+            priority: None,
+            playlists_updated: None,
+            cache: SnapshotCache::load(),
+            backoff: Backoff::default(),
+            sent: SentPlaylists::default(),
+            user: None,
         })
     }
 
@@ -175,13 +298,25 @@ impl<'a> Backend<'a> {
         // Obtaining the access token
         // self.reconnect().await;
         self.check_connection().await;
+        // the device may already be shuffling or repeating
+        if let Some(context) = self.get_playback_state().await {
+            self.sync_options(&context);
+        }
         let connection_check_duration = Duration::from_secs(5);
         let mut connection_check_delay = tokio::time::interval(connection_check_duration);
+        let mut devices_refresh_delay = tokio::time::interval(DEVICES_TTL);
+        let mut load_delay = tokio::time::interval(LOAD_INTERVAL);
         loop {
             let connection_check = connection_check_delay.tick();
+            let devices_refresh = devices_refresh_delay.tick();
+            let load = load_delay.tick();
             tokio::select! {
                 // _ = connection_check => self.check_connection().await,
                 _ = connection_check => self.check_connection().await,
+                // keep the cache warm so that device commands answer immediately
+                _ = devices_refresh, if !self.backoff.is_waiting() => self.refresh_devices().await,
+                // fetch the playlists one page at a time to keep answering requests
+                _ = load, if !self.backoff.is_waiting() => self.load_next_page().await,
                 _ = self.cancel_token.cancelled() => break,
                 request = self.request_rx.recv() => {
                     use tokio::sync::broadcast::error as error;
@@ -204,9 +339,77 @@ impl<'a> Backend<'a> {
         log::info!("[Spotify] Reconnecting");
         let url = self.spotify.get_authorize_url(false).unwrap();
         log::debug!("{url}");
+        let listener = match redirect::listen(&self.spotify.oauth.redirect_uri).await {
+            Ok(listener) => Some(listener),
+            Err(err) => {
+                warn!("[Spotify] Cannot listen for the redirection: {err}");
+                None
+            }
+        };
         if let Err(err) = open::that(url.clone()) {
             warn!("Could not open browser: {err}");
         }
+        let response = match listener {
+            Some(listener) => self.capture_redirect(listener, &url).await,
+            None => None,
+        };
+        let response = match response {
+            Some(response) => Some(response),
+            None => self.prompt_redirect(&url).await,
+        };
+        if let Some(response) = response {
+            if let Some(code) = self.spotify.parse_response_code(&response) {
+                if let Err(err) = self.spotify.request_token(&code).await {
+                    error!("Request token failed {err}");
+                }
+                self.save_token().await;
+            }
+        }
+    }
+    /// File in which the token is cached
+    fn token_cache() -> PathBuf {
+        let mut cache = config::get_dirs().cache_dir().to_path_buf();
+        cache.push("spotify_token_cache.json");
+        cache
+    }
+    /// Save the token to its file or to the keyring
+    async fn save_token(&self) {
+        let result = if secrets::in_keyring() {
+            let token = self.spotify.get_token().lock().await.unwrap().clone();
+            serde_json::to_string(&token)
+                .map_err(anyhow::Error::from)
+                .and_then(|token| secrets::write(TOKEN_NAME, &Self::token_cache(), &token))
+        } else {
+            self.spotify.write_token_cache().await.map_err(anyhow::Error::from)
+        };
+        if let Err(err) = result {
+            error!("Writing to cache failed {err}");
+        }
+    }
+    /// Wait for the browser to be redirected once the user authorized yama at `url`,
+    /// returns the url it was redirected to
+    async fn capture_redirect(&self, listener: TcpListener, url: &str) -> Option<String> {
+        let message = format!("Authorize yama in your browser, or go to {url}");
+        let _ = self
+            .answer_tx
+            .send(Notification::new(Severity::Info, message).into())
+            .await;
+        let redirect_uri = &self.spotify.oauth.redirect_uri;
+        let capture = redirect::capture(listener, redirect_uri);
+        match tokio::time::timeout(REDIRECT_TIMEOUT, capture).await {
+            Ok(Ok(response)) => Some(response),
+            Ok(Err(err)) => {
+                error!("[Spotify] Failed to capture the redirection: {err}");
+                None
+            }
+            Err(_) => {
+                warn!("[Spotify] No redirection received");
+                None
+            }
+        }
+    }
+    /// Ask the user to paste the url the browser was redirected to after authorizing yama
+    async fn prompt_redirect(&self, url: &str) -> Option<String> {
         let (sender, recv) = oneshot::channel();
         let msg = format!("Go to {url}, and paste back the resulting url");
         if let Err(err) = self
@@ -223,16 +426,26 @@ impl<'a> Backend<'a> {
         {
             debug!("Error while sending auth url: {err}");
         }
-        if let Ok(code) = recv.await {
-            if let Some(code) = self.spotify.parse_response_code(&code) {
-                if let Err(err) = self.spotify.request_token(&code).await {
-                    error!("Request token failed {err}");
-                }
-                if let Err(err) = self.spotify.write_token_cache().await {
-                    error!("Writing to cache failed {err}");
-                }
+        recv.await.ok()
+    }
+    /// Refresh the token, going through the whole authorization again if it cannot be refreshed
+    async fn force_reconnect(&mut self) {
+        log::info!("[Spotify] Refreshing the token");
+        match self.spotify.refresh_token().await {
+            Ok(()) => {
+                self.save_token().await;
+                let message = "Reconnected to Spotify";
+                let _ = self
+                    .answer_tx
+                    .send(Notification::new(Severity::Info, message).into())
+                    .await;
+            }
+            Err(err) => {
+                warn!("[Spotify] Failed to refresh the token: {err}");
+                self.reconnect().await;
             }
         }
+        self.backoff.success();
     }
     async fn check_connection(&self) {
         debug!("[Spotify] Checking connection");
@@ -245,23 +458,154 @@ impl<'a> Backend<'a> {
         match request {
             Request::PlayerAction(action) => self.handle_player(action).await,
             Request::Get(get) => self.handle_get(get).await,
-            Request::Set(_) => todo!(),
+            Request::Set(SetRequest::AddSongToPlaylist { song, playlist }) => {
+                let notification = match self.add_song(&song.id, &playlist).await {
+                    Ok(title) => Notification::new(Severity::Info, format!("Added to {title}")),
+                    Err(err) => {
+                        error!("[Spotify] Failed to add {} to {playlist}: {err}", song.id);
+                        let message = format!("Failed to add the song: {err}");
+                        Notification::new(Severity::Error, message)
+                    }
+                };
+                let _ = self.answer_tx.send(notification.into()).await;
+            }
+            Request::Set(SetRequest::RemoveSongFromPlaylist { song, playlist }) => {
+                let notification = match self.remove_song(&song.id, &playlist).await {
+                    Ok(title) => Notification::new(Severity::Info, format!("Removed from {title}")),
+                    Err(err) => {
+                        error!("[Spotify] Failed to remove {} from {playlist}: {err}", song.id);
+                        let message = format!("Failed to remove the song: {err}");
+                        Notification::new(Severity::Error, message)
+                    }
+                };
+                let _ = self.answer_tx.send(notification.into()).await;
+            }
             Request::Command(command) => self.handle_command(command).await,
+            Request::Revalidate => self.revalidate().await,
+            Request::Reconnect => self.force_reconnect().await,
+            Request::Download(_) => {
+                let message = "Spotify songs cannot be downloaded";
+                let _ = self
+                    .answer_tx
+                    .send(Notification::new(Severity::Warning, message).into())
+                    .await;
+            }
+            Request::ToggleOffline(_) => {
+                let message = "Spotify playlists cannot be kept offline";
+                let _ = self
+                    .answer_tx
+                    .send(Notification::new(Severity::Warning, message).into())
+                    .await;
+            }
+            Request::CreatePlaylist { title, songs } => {
+                let notification = match self.create_playlist(&title, songs).await {
+                    Ok(_) => Notification::new(Severity::Info, format!("Created {title}")),
+                    Err(err) => {
+                        error!("[Spotify] Failed to create {title}: {err}");
+                        let message = format!("Failed to create {title}: {err}");
+                        Notification::new(Severity::Error, message)
+                    }
+                };
+                let _ = self.answer_tx.send(notification.into()).await;
+            }
+        }
+    }
+
+    /// Create a private playlist named `title` made of `songs`
+    async fn create_playlist(&mut self, title: &str, songs: Vec<SongInfo>) -> Result<()> {
+        let user = self.spotify.me().await?;
+        let playlist = self
+            .spotify
+            .user_playlist_create(user.id, title, Some(false), None, None)
+            .await?;
+        let tracks: Vec<TrackId> = songs
+            .iter()
+            .filter_map(|s| TrackId::from_uri(&s.id).ok())
+            .collect();
+        // the api accepts at most 100 items per request
+        for chunk in tracks.chunks(100) {
+            let items = chunk.iter().map(|t| PlayableId::Track(t.clone()));
+            self.spotify
+                .playlist_add_items(playlist.id.clone(), items, None)
+                .await?;
+        }
+        let cover_url = match playlist.images.first() {
+            Some(cover) => cover.url.clone(),
+            None => String::new(),
+        };
+        self.playlists.push(Playlist {
+            id: playlist.id,
+            length: songs.len(),
+            songs,
+            title: playlist.name,
+            cover_url,
+            next_offset: None,
+            // fetched with the next list of playlists
+            snapshot_id: String::new(),
+        });
+        Ok(())
+    }
+
+    /// Append the track `song` to the playlist `playlist`, returns the title of the playlist
+    async fn add_song(&mut self, song: &str, playlist: &str) -> Result<String> {
+        let track = TrackId::from_uri(song)?;
+        let playlist = self
+            .playlists
+            .iter_mut()
+            .find(|p| p.id.to_string() == playlist)
+            .ok_or_else(|| anyhow!("unknown playlist"))?;
+        let items = [PlayableId::Track(track)];
+        let result = self
+            .spotify
+            .playlist_add_items(playlist.id.clone(), items, None)
+            .await?;
+        playlist.snapshot_id = result.snapshot_id;
+        playlist.reload();
+        self.priority = Some(playlist.id.to_string());
+        Ok(playlist.title.clone())
+    }
+
+    /// Remove every occurrence of the track `song` from the playlist `playlist`,
+    /// returns the title of the playlist
+    async fn remove_song(&mut self, song: &str, playlist: &str) -> Result<String> {
+        let track = TrackId::from_uri(song)?;
+        let playlist = self
+            .playlists
+            .iter_mut()
+            .find(|p| p.id.to_string() == playlist)
+            .ok_or_else(|| anyhow!("unknown playlist"))?;
+        let items = [PlayableId::Track(track)];
+        let result = self
+            .spotify
+            .playlist_remove_all_occurrences_of_items(playlist.id.clone(), items, None)
+            .await?;
+        playlist.snapshot_id = result.snapshot_id;
+        playlist.reload();
+        self.priority = Some(playlist.id.to_string());
+        Ok(playlist.title.clone())
+    }
+
+    /// Check the token and look for the selected device again, as its id may have changed
+    async fn revalidate(&mut self) {
+        self.check_connection().await;
+        self.refresh_devices().await;
+        if let Some(device) = &self.device {
+            let name = device.name.clone();
+            self.device = self.find_device_by_name(&name).await;
         }
     }
 
     async fn handle_get<'b>(&'b mut self, get: GetRequest) {
         match get {
             GetRequest::PlaylistList => {
-                if self.playlists.is_empty() {
+                let fresh = matches!(self.playlists_updated,
+                    Some(updated) if updated.elapsed() < PLAYLISTS_TTL);
+                if !fresh && !self.backoff.is_waiting() {
                     self.get_playlists().await;
                 }
-                let _ = self
-                    .answer_tx
-                    .send(Answer::PlaylistList(
-                        self.playlists.iter().map(|p| p.get_info()).collect(),
-                    ))
-                    .await;
+                let list = self.playlists.iter().map(|p| p.get_info()).collect();
+                let answer = self.sent.track(Answer::PlaylistList(list));
+                let _ = self.answer_tx.send(answer).await;
             }
             GetRequest::Playlist(id) => {
                 let playlist = self
@@ -269,14 +613,70 @@ impl<'a> Backend<'a> {
                     .iter()
                     .find(|p| p.id.to_string() == id)
                     .unwrap();
+                let selected = self.priority.as_deref() != Some(id.as_str());
+                let selected = selected && !playlist.is_loaded();
+                if !playlist.is_loaded() {
+                    self.priority = Some(id);
+                }
+                let answer = self.sent.track(Answer::Playlist(playlist.get_info()));
+                let _ = self.answer_tx.send(answer).await;
+                // the loader moves on to the playlist selected, its first page is fetched
+                // right away rather than at the next tick
+                if selected && !self.backoff.is_waiting() {
+                    self.load_next_page().await;
+                }
+            }
+            GetRequest::PlayerInfo => {
+                let info = if self.backoff.is_waiting() {
+                    self.last_info.clone()
+                } else {
+                    self.player_info().await
+                };
+                let _ = self.answer_tx.send(Answer::PlayerInfo(info)).await;
+            }
+            GetRequest::Search(query) => {
+                let songs = self.search(&query).await;
                 let _ = self
                     .answer_tx
-                    .send(Answer::Playlist(playlist.get_info()))
+                    .send(Answer::SearchResults { query, songs })
                     .await;
             }
-            GetRequest::PlayerInfo => {
-                let info = self.player_info().await;
-                let _ = self.answer_tx.send(Answer::PlayerInfo(info)).await;
+            GetRequest::Capabilities => {
+                let _ = self
+                    .answer_tx
+                    .send(Answer::Capabilities(CAPABILITIES))
+                    .await;
+            }
+            GetRequest::Artists | GetRequest::Albums(_) | GetRequest::Album(_) => (),
+            GetRequest::Related(song) => {
+                // the recommendations are no longer available to new applications,
+                // the songs of the same artist are searched instead
+                let songs = self
+                    .search(&format!("artist:\"{}\"", song.artist))
+                    .await
+                    .into_iter()
+                    .filter(|s| s.id != song.id)
+                    .collect();
+                let answer = Answer::Related {
+                    seed: song.id,
+                    songs,
+                };
+                let _ = self.answer_tx.send(answer).await;
+            }
+        }
+    }
+
+    async fn search(&self, query: &str) -> Vec<SongInfo> {
+        match self
+            .spotify
+            .search(query, SearchType::Track, None, None, Some(10), None)
+            .await
+        {
+            Ok(SearchResult::Tracks(page)) => page.items.into_iter().map(|t| t.into()).collect(),
+            Ok(_) => Vec::new(),
+            Err(err) => {
+                error!("[Spotify] Search failed: {err}");
+                Vec::new()
             }
         }
     }
@@ -285,19 +685,115 @@ impl<'a> Backend<'a> {
         log::debug!("trying to get playlists");
         let mut pages = self.spotify.current_user_playlists();
         log::debug!("got playlist");
+        let mut fetched = Vec::new();
         while let Some(page) = pages.next().await {
-            if let Ok(playlist) = page {
-                self.playlists.push(Playlist::new(playlist));
+            match page {
+                Ok(playlist) => fetched.push(playlist),
+                Err(err) => {
+                    error!("[Spotify] Failed to get playlists: {err}");
+                    self.rate_limited(&err).await;
+                    return;
+                }
+            }
+        }
+        self.playlists_updated = Some(Instant::now());
+        if self.user.is_none() {
+            match self.spotify.me().await {
+                Ok(user) => self.user = Some(user.id.to_string()),
+                Err(err) => error!("[Spotify] Failed to get the current user: {err}"),
+            }
+        }
+        // only the playlists modified since they were loaded are fetched again
+        let mut previous = std::mem::take(&mut self.playlists);
+        for playlist in fetched {
+            let unchanged = previous
+                .iter()
+                .position(|p| p.id == playlist.id && p.snapshot_id == playlist.snapshot_id);
+            let playlist = match unchanged {
+                Some(index) => previous.swap_remove(index),
+                None => {
+                    let mut playlist = Playlist::new(playlist, self.user.as_deref());
+                    let id = playlist.id.to_string();
+                    if let Some(songs) = self.cache.get(&id, &playlist.snapshot_id) {
+                        playlist.set_songs(songs.clone());
+                    }
+                    playlist
+                }
+            };
+            self.playlists.push(playlist);
+        }
+        let ids: Vec<String> = self.playlists.iter().map(|p| p.id.to_string()).collect();
+        self.cache.retain(&ids);
+    }
+    /// Fetch the next page of items of the selected playlist, or of the first playlist
+    /// not fully loaded, and send the songs it contains
+    async fn load_next_page(&mut self) {
+        let priority = self.priority.clone();
+        let playlist = self
+            .playlists
+            .iter_mut()
+            .filter(|p| !p.is_loaded())
+            .min_by_key(|p| priority.as_deref() != Some(p.id.to_string().as_str()));
+        let playlist = match playlist {
+            Some(playlist) => playlist,
+            None => return,
+        };
+        let page = self
+            .spotify
+            .playlist_items_manual(
+                playlist.id.clone(),
+                None,
+                None,
+                Some(PAGE_SIZE),
+                playlist.next_offset,
+            )
+            .await;
+        let page = match page {
+            Ok(page) => page,
+            Err(err) => {
+                error!("[Spotify] Failed to load {}: {err}", playlist.title);
+                if !is_rate_limited(&err) {
+                    // keep the songs loaded so far rather than retrying endlessly
+                    playlist.next_offset = None;
+                }
+                self.rate_limited(&err).await;
+                return;
             }
+        };
+        self.backoff.success();
+        let added = playlist.add_page(page);
+        if playlist.is_loaded() {
+            let id = playlist.id.to_string();
+            let songs = playlist.songs.clone();
+            self.cache.insert(id, playlist.snapshot_id.clone(), songs);
         }
-        for playlist in self.playlists.iter_mut() {
-            let pages = self.spotify.playlist_items(playlist.id.clone(), None, None);
-            playlist.load(pages).await;
+        let offset = playlist.songs.len() - added;
+        let answer = Answer::PlaylistPage {
+            id: playlist.id.to_string(),
+            offset,
+            songs: playlist.songs[offset..].to_vec(),
+            total: playlist.length,
+        };
+        let answer = self.sent.track(answer);
+        let _ = self.answer_tx.send(answer).await;
+    }
+    /// Return the cached list of devices, only querying the api if it has expired
+    async fn get_devices(&mut self) -> Vec<Device> {
+        let fresh = matches!(self.devices_updated, Some(updated) if updated.elapsed() < DEVICES_TTL);
+        if !fresh {
+            self.refresh_devices().await;
         }
+        self.devices.clone()
     }
-    async fn get_devices(&self) -> Vec<Device> {
+    async fn refresh_devices(&mut self) {
         debug!("[Spotify] Getting devices");
-        self.spotify.device().await.unwrap_or_default()
+        match self.spotify.device().await {
+            Ok(devices) => {
+                self.devices = devices;
+                self.devices_updated = Some(Instant::now());
+            }
+            Err(err) => error!("[Spotify] Failed to get devices: {err}"),
+        }
     }
     fn get_device_id(&self) -> Option<String> {
         self.device.as_ref().map(|d| d.id.clone().unwrap_or_default())
@@ -315,14 +811,49 @@ impl<'a> Backend<'a> {
         debug!("[Spotify] pausing");
         let _ = self.spotify.pause_playback(self.get_device_id().as_deref()).await;
     }
+    /// Pause and go back to the start of the song, reported as stopped until resumed
+    async fn stop(&mut self) {
+        debug!("[Spotify] stopping");
+        self.pause().await;
+        let device = self.get_device_id();
+        let _ = self.spotify.seek_track(TimeDelta::zero(), device.as_deref()).await;
+        self.stopped = true;
+        if config::get_config().spotify_release_device {
+            self.device = None;
+        }
+        self.last_info = PlayerInfo {
+            playback: Playback::Stop,
+            shuffled: self.shuffled,
+            autoplay: self.autoplay,
+            repeat: self.last_info.repeat,
+            volume: self.last_info.volume,
+            muted: self.last_info.muted,
+            ..Default::default()
+        };
+    }
     async fn shuffle(&mut self, target: bool) {
         debug!("[Spotify] shuffling");
-        let _ = self.spotify.shuffle(target, self.get_device_id().as_deref()).await;
-        self.shuffled = target;
+        match self.spotify.shuffle(target, self.get_device_id().as_deref()).await {
+            Ok(()) => {
+                self.shuffled = target;
+                self.last_info.shuffled = target;
+            }
+            Err(err) => error!("[Spotify] Failed to set the shuffle state: {err}"),
+        }
     }
-    async fn set_repeat(&self, repeat: Repeat) {
+    async fn set_repeat(&mut self, repeat: Repeat) {
         debug!("[Spotify] setting repeat state");
-        let _ = self.spotify.repeat(repeat.into(), self.get_device_id().as_deref()).await;
+        match self.spotify.repeat(repeat.into(), self.get_device_id().as_deref()).await {
+            Ok(()) => self.last_info.repeat = repeat,
+            Err(err) => error!("[Spotify] Failed to set the repeat state: {err}"),
+        }
+    }
+    /// Take the shuffle and repeat states of the device, they can be changed from
+    /// any other Spotify client
+    fn sync_options(&mut self, context: &CurrentPlaybackContext) {
+        self.shuffled = context.shuffle_state;
+        self.last_info.shuffled = context.shuffle_state;
+        self.last_info.repeat = context.repeat_state.into();
     }
     async fn playpause_toggle(&self) {
         debug!("[Spotify] playpause");
@@ -333,88 +864,214 @@ impl<'a> Backend<'a> {
         }
     }
     async fn player_info(&mut self) -> PlayerInfo {
-        let context = self.get_playback_state().await;
+        let playback = self
+            .spotify
+            .current_playback(None, None as Option<Vec<_>>)
+            .await;
+        let context = match playback {
+            Ok(context) => context,
+            Err(err) => {
+                // the most frequent request, the first to hit the rate limit
+                self.rate_limited(&err).await;
+                None
+            }
+        };
         if context.is_none() {
             debug!("[Spotify] no playback context");
             return self.last_info.clone();
         };
         let context = context.unwrap();
+        self.sync_options(&context);
+        if self.stopped && !context.is_playing {
+            // the paused song is not shown until the playback resumes
+            return self.last_info.clone();
+        }
+        self.stopped = false;
         debug!("[Spotify] getting queue");
-        let queue = self.spotify.current_user_queue().await.expect("No queue");
+        let queue = match self.spotify.current_user_queue().await {
+            Ok(queue) => queue,
+            Err(err) => {
+                error!("[Spotify] Failed to get the queue: {err}");
+                self.rate_limited(&err).await;
+                return self.last_info.clone();
+            }
+        };
+        self.backoff.success();
+        let song_info: Option<SongInfo> = context.item.clone().map(|track| track.into());
+        let (tracklist, track_index) = self.make_tracklist(&context, queue, song_info.as_ref());
         self.last_info = PlayerInfo {
             playback: if context.is_playing {
                 Playback::Play
             } else {
                 Playback::Pause
             },
-            song_info: context.item.map(|track| track.into()),
-            tracklist: queue.into(),
-            track_index: Some(0),
+            song_info,
+            tracklist,
+            track_index,
             shuffled: self.shuffled,
             autoplay: context.is_playing,
             repeat: context.repeat_state.into(),
             volume: context.device.volume_percent.unwrap_or_default() as u8,
+            muted: self.unmuted_volume.is_some(),
             position: context
                 .progress
                 .unwrap_or_default()
                 .to_std()
                 .unwrap_or_default(),
             can_seek: true,
+            ..Default::default()
         };
         debug!("[Spotify] Sending info");
         self.last_info.clone()
     }
+    /// Build the tracklist of the current playback along with the index of `current` in it.
+    /// If the playback context is one of the user's playlists, the playlist is used as the
+    /// tracklist, otherwise fall back on the user queue preceded by the current track
+    fn make_tracklist(
+        &self,
+        context: &CurrentPlaybackContext,
+        queue: CurrentUserQueue,
+        current: Option<&SongInfo>,
+    ) -> (PlaylistInfo, Option<usize>) {
+        let playlist = context
+            .context
+            .as_ref()
+            .and_then(|ctxt| self.playlists.iter().find(|p| p.id.to_string() == ctxt.uri));
+        if let (Some(playlist), Some(current)) = (playlist, current) {
+            // the same track can appear multiple times in a playlist,
+            // in which case the first occurrence is used
+            let index = playlist.songs.iter().position(|s| s.id == current.id);
+            if index.is_some() {
+                return (playlist.get_info(), index);
+            }
+        }
+        let index = queue.currently_playing.as_ref().map(|_| 0);
+        (queue.into(), index)
+    }
 
     async fn handle_player(&mut self, action: PlayerAction) {
         match action {
-            PlayerAction::PlayPause(target) => self.playpause(target).await,
+            PlayerAction::PlayPause(paused) => self.playpause(paused).await,
             PlayerAction::PlayPauseToggle => self.playpause_toggle().await,
-            PlayerAction::Stop => self.pause().await,
+            PlayerAction::Stop => self.stop().await,
             PlayerAction::Shuffle(target) => self.shuffle(target).await,
             PlayerAction::ShuffleToggle => self.shuffle(!self.shuffled).await,
             PlayerAction::Autoplay(target) => self.autoplay(target).await,
             PlayerAction::AutoplayToggle => self.autoplay(!self.autoplay).await,
-            PlayerAction::Seek { dt, mode } => self.seek(dt, mode).await,
+            PlayerAction::Seek(mode) => self.seek(mode).await,
             PlayerAction::Prev => self.prev().await,
             PlayerAction::Next => self.next().await,
-            PlayerAction::SetVolume(volume) => self.set_volume(volume).await,
-            PlayerAction::SetTrackList(tracklist) => self.set_tracklist(tracklist).await,
+            PlayerAction::SetVolume(volume) => {
+                // changing the volume unmutes
+                self.unmuted_volume = None;
+                self.set_volume(volume).await
+            }
+            PlayerAction::MuteToggle => self.mute_toggle().await,
+            PlayerAction::SetTrackList(tracklist) => self.set_tracklist(tracklist, None).await,
             PlayerAction::SetRepeat(repeat) => self.set_repeat(repeat).await,
             PlayerAction::CycleRepeat => self.cycle_repeat().await,
+            PlayerAction::PlayIndex(index) => self.play_index(index).await,
+            PlayerAction::Enqueue { song, .. } => self.enqueue(&song).await,
+            PlayerAction::SetEqualizer(_) => {
+                let message = "Spotify does not support the equalizer";
+                let notification = Notification::new(Severity::Warning, message);
+                let _ = self.answer_tx.send(notification.into()).await;
+            }
+            PlayerAction::NextChapter | PlayerAction::PrevChapter | PlayerAction::SetChapter(_) => {
+                let message = "Spotify does not support chapters";
+                let notification = Notification::new(Severity::Warning, message);
+                let _ = self.answer_tx.send(notification.into()).await;
+            }
+            PlayerAction::SetSpeed(_) => {
+                let message = "Spotify does not support changing the speed";
+                let notification = Notification::new(Severity::Warning, message);
+                let _ = self.answer_tx.send(notification.into()).await;
+            }
+            // Spotify shuffles the songs itself
+            PlayerAction::SetShuffleWeights(_) => (),
+        }
+    }
+
+    /// Add `song` to the queue of the user, Spotify only allows to play it next
+    async fn enqueue(&self, song: &SongInfo) {
+        let track = match TrackId::from_uri(&song.id) {
+            Ok(track) => track,
+            Err(err) => {
+                error!("[Spotify] Cannot enqueue {}: {err}", song.id);
+                return;
+            }
+        };
+        let device = self.get_device_id();
+        if let Err(err) = self
+            .spotify
+            .add_item_to_queue(PlayableId::Track(track), device.as_deref())
+            .await
+        {
+            error!("[Spotify] Failed to enqueue {}: {err}", song.title);
         }
     }
 
-    async fn set_tracklist(&self, tracklist: PlaylistInfo) {
+    /// Restart the current tracklist from the song at `index`
+    async fn play_index(&self, index: usize) {
+        let tracklist = self.last_info.tracklist.clone();
+        if let Some(song) = tracklist.songs.get(index) {
+            let offset = Offset::Uri(song.id.clone());
+            self.set_tracklist(tracklist, Some(offset)).await;
+        }
+    }
+
+    /// Play `tracklist`, starting from `offset` if provided
+    async fn set_tracklist(&self, tracklist: PlaylistInfo, offset: Option<Offset>) {
         let playlist = self
             .playlists
             .iter()
-            .find(|p| p.id.to_string() == tracklist.id)
-            .unwrap();
-        let _ = self
-            .spotify
-            .start_context_playback(
-                rspotify::prelude::PlayContextId::Playlist(playlist.id.clone()),
-                None,
-                None,
-                Some(TimeDelta::zero()),
-            )
-            .await;
+            .find(|p| p.id.to_string() == tracklist.id);
+        if let Some(playlist) = playlist {
+            let _ = self
+                .spotify
+                .start_context_playback(
+                    rspotify::prelude::PlayContextId::Playlist(playlist.id.clone()),
+                    None,
+                    offset,
+                    Some(TimeDelta::zero()),
+                )
+                .await;
+        } else {
+            // not one of the user's playlists (e.g. search results),
+            // so play the tracks one after the other
+            let tracks: Vec<PlayableId> = tracklist
+                .songs
+                .iter()
+                .filter_map(|s| TrackId::from_uri(&s.id).ok())
+                .map(PlayableId::Track)
+                .collect();
+            let _ = self
+                .spotify
+                .start_uris_playback(
+                    tracks,
+                    self.get_device_id().as_deref(),
+                    offset,
+                    Some(TimeDelta::zero()),
+                )
+                .await;
+        }
     }
 
-    async fn playpause(&self, target: bool) {
-        if target {
-            let _ = self.spotify.resume_playback(self.get_device_id().as_deref(), None).await;
-        } else {
+    /// pause the playback if `paused`, resume it otherwise
+    async fn playpause(&self, paused: bool) {
+        if paused {
             self.pause().await;
+        } else {
+            let _ = self.spotify.resume_playback(self.get_device_id().as_deref(), None).await;
         }
     }
 
     async fn autoplay(&mut self, target: bool) {
-        self.playpause(target).await;
+        self.playpause(!target).await;
         self.autoplay = target;
     }
 
-    async fn cycle_repeat(&self) {
+    async fn cycle_repeat(&mut self) {
         if let Ok(Some(playback)) = self
             .spotify
             .current_playback(None, None as Option<Vec<_>>)
@@ -434,18 +1091,32 @@ impl<'a> Backend<'a> {
             .await
             .unwrap_or_default()
     }
+    /// Hold the requests back if `error` reports a rate limit, returns `true` if it does
+    async fn rate_limited(&mut self, error: &ClientError) -> bool {
+        if !is_rate_limited(error) {
+            return false;
+        }
+        let notification = self.backoff.hold("Spotify");
+        let _ = self.answer_tx.send(notification.into()).await;
+        true
+    }
 
     async fn set_volume(&self, volume: Volume) {
         match volume {
             Volume::Absolute(target) => {
-                let _ = self.spotify.volume(target as u8, self.get_device_id().as_deref()).await;
+                // spotify cannot amplify the sound
+                let target = target.min(100) as u8;
+                let _ = self.spotify.volume(target, self.get_device_id().as_deref()).await;
             }
             Volume::Relative(delta) => {
                 let volume = self.get_volume().await;
                 let _ = self
                     .spotify
                     .volume(
-                        volume.checked_add_signed(delta as i32).unwrap_or_default() as u8,
+                        volume
+                            .checked_add_signed(delta as i32)
+                            .unwrap_or_default()
+                            .min(100) as u8,
                         self.get_device_id().as_deref(),
                     )
                     .await;
@@ -453,6 +1124,21 @@ impl<'a> Backend<'a> {
         }
     }
 
+    /// Set the volume to 0, or back to the volume it had before being muted
+    async fn mute_toggle(&mut self) {
+        let volume = match self.unmuted_volume.take() {
+            Some(volume) => volume,
+            None => {
+                self.unmuted_volume = Some(self.get_volume().await as u8);
+                0
+            }
+        };
+        let device = self.get_device_id();
+        if let Err(err) = self.spotify.volume(volume, device.as_deref()).await {
+            error!("[Spotify] Failed to set the volume to {volume}: {err}");
+        }
+    }
+
     async fn get_volume(&self) -> u32 {
         if let Some(context) = self.get_playback_state().await {
             context.device.volume_percent.unwrap_or_default()
@@ -461,7 +1147,7 @@ impl<'a> Backend<'a> {
         }
     }
 
-    async fn seek(&self, dt: i64, mode: SeekMode) {
+    async fn seek(&self, mode: SeekMode) {
         let progress = self
             .get_playback_state()
             .await
@@ -483,21 +1169,16 @@ impl<'a> Backend<'a> {
             })
             .unwrap_or_default()
             .unwrap_or_default();
+        // positions in milliseconds
+        let progress = progress.as_millis() as i64;
+        let length = length.as_millis() as i64;
         let target = match mode {
-            SeekMode::Absolute => Duration::from_secs(dt as u64),
-            SeekMode::Relative => {
-                let len = length.as_secs().checked_add_signed(dt).unwrap_or_default();
-                Duration::from_secs(len)
-            }
-            SeekMode::AbsolutePercent => {
-                let target = length.as_secs() * (dt as u64) / 100;
-                Duration::from_secs(target)
-            }
-            SeekMode::RelativePercent => {
-                let target = progress.as_secs() + length.as_secs() * (dt as u64) / 100;
-                Duration::from_secs(target)
-            }
+            SeekMode::Absolute(position) => position.as_millis() as i64,
+            SeekMode::Relative(dt) => progress + dt,
+            SeekMode::AbsolutePercent(percent) => length * i64::from(percent) / 100,
+            SeekMode::RelativePercent(percent) => progress + length * percent / 100,
         };
+        let target = Duration::from_millis(target.max(0) as u64);
         let _ = self
             .spotify
             .seek_track(TimeDelta::from_std(target).unwrap_or_default(), self.get_device_id().as_deref())
@@ -531,12 +1212,23 @@ impl<'a> Backend<'a> {
         }   
     }
 
-    async fn find_device_by_name(&self, name: &str) -> Option<Device> {
+    async fn find_device_by_name(&mut self, name: &str) -> Option<Device> {
         let devices = self.get_devices().await;
         devices.into_iter().find(|d| d.name == name)
     }
 }
 
+/// returns `true` if `error` is the status 429 sent when too many requests were sent
+fn is_rate_limited(error: &ClientError) -> bool {
+    match error {
+        ClientError::Http(err) => match err.as_ref() {
+            HttpError::StatusCode(response) => response.status() == 429,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
 impl From<Repeat> for RepeatState {
     fn from(value: Repeat) -> Self {
         match value {
@@ -566,25 +1258,44 @@ impl From<FullTrack> for SongInfo {
         } else {
             String::new()
         };
+        // release dates are formatted as YYYY-MM-DD with a precision
+        // that can be restricted to the year only
+        let year = track
+            .album
+            .release_date
+            .as_ref()
+            .and_then(|date| date.get(..4))
+            .and_then(|year| year.parse().ok());
         SongInfo {
             title: track.name,
             artist: track.artists.iter().map(|a| a.name.clone()).collect(),
+            album: track.album.name,
+            year,
             cover_url,
             id: track.id.unwrap().to_string(),
             url: track.href.unwrap_or_default(),
             duration: track.duration.to_std().unwrap_or_default(),
+            unavailable: false,
         }
     }
 }
 
 impl From<CurrentUserQueue> for PlaylistInfo {
+    /// The resulting playlist starts with the currently playing item if any
     fn from(value: CurrentUserQueue) -> Self {
+        let songs: Vec<SongInfo> = value
+            .currently_playing
+            .into_iter()
+            .chain(value.queue)
+            .map(|item| item.into())
+            .collect();
         Self {
             title: String::new(),
-            length: value.queue.len(),
+            length: songs.len(),
             cover_url: String::new(),
             id: String::new(),
-            songs: value.queue.into_iter().map(|item| item.into()).collect(),
+            songs,
+            ..Default::default()
         }
     }
 }
@@ -0,0 +1,57 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{client::interface::SongInfo, config, json_store};
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CachedPlaylist {
+    snapshot_id: String,
+    songs: Vec<SongInfo>,
+}
+
+/// Songs of the fully loaded playlists, saved on disk along with the snapshot they belong to
+/// so that unchanged playlists are not fetched again
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SnapshotCache {
+    /// cached playlists indexed by id
+    playlists: HashMap<String, CachedPlaylist>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl SnapshotCache {
+    pub fn load() -> Self {
+        let mut path = config::get_dirs().cache_dir().to_path_buf();
+        path.push("spotify_playlists.json");
+        let cache: Self = json_store::load(&path);
+        Self { path, ..cache }
+    }
+
+    fn save(&self) {
+        json_store::save(&self.path, self);
+    }
+
+    /// Songs of the playlist `id`, if they were cached for the snapshot `snapshot_id`
+    pub fn get(&self, id: &str, snapshot_id: &str) -> Option<&Vec<SongInfo>> {
+        self.playlists
+            .get(id)
+            .filter(|cached| cached.snapshot_id == snapshot_id)
+            .map(|cached| &cached.songs)
+    }
+
+    pub fn insert(&mut self, id: String, snapshot_id: String, songs: Vec<SongInfo>) {
+        let cached = CachedPlaylist { snapshot_id, songs };
+        self.playlists.insert(id, cached);
+        self.save();
+    }
+
+    /// Forget the playlists whose id is not in `ids`
+    pub fn retain(&mut self, ids: &[String]) {
+        let len = self.playlists.len();
+        self.playlists.retain(|id, _| ids.contains(id));
+        if self.playlists.len() != len {
+            self.save();
+        }
+    }
+}
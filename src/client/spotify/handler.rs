@@ -3,10 +3,13 @@ use std::time::Duration;
 use anyhow::Result;
 use tokio::sync::broadcast::Sender as BroadSender;
 use tokio::sync::mpsc::{self, Receiver as MpscReceiver, Sender as MpscSender};
-use tokio::task::JoinSet;
+use tokio::task::{JoinHandle, JoinSet};
 use tokio_util::sync::CancellationToken;
 
-use crate::client::interface::{Answer, Request};
+use crate::client::{
+    interface::{Answer, Request},
+    ClientFactory,
+};
 
 use super::backend::Backend;
 
@@ -92,3 +95,22 @@ impl Client {
         }
     }
 }
+
+/// Starts the Spotify client
+pub struct Factory;
+
+impl ClientFactory for Factory {
+    fn name(&self) -> &str {
+        "spotify"
+    }
+
+    fn spawn(
+        &self,
+        receiver: MpscReceiver<Request>,
+        sender: MpscSender<Answer>,
+        cancel_token: CancellationToken,
+    ) -> JoinHandle<Result<()>> {
+        let mut client = Client::create(receiver, sender, cancel_token);
+        tokio::spawn(async move { client.main_loop().await })
+    }
+}
@@ -1,3 +1,6 @@
 mod backend;
+mod cache;
+mod redirect;
+pub use backend::diagnose;
 pub mod handler;
-pub use handler::Client;
+pub use handler::Factory;
@@ -0,0 +1,70 @@
+use std::net::{Ipv4Addr, SocketAddr};
+
+use anyhow::{anyhow, Result};
+use log::debug;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+
+/// page displayed in the browser once the code is captured
+const DONE_PAGE: &str = "<html><body>yama is authorized, you can close this tab.</body></html>";
+/// size above which the request of the browser is not read further
+const MAX_REQUEST: usize = 8192;
+
+/// Split `redirect_uri`, e.g. `http://localhost:8888/callback`, into its authority and path
+fn split_uri(redirect_uri: &str) -> Result<(&str, &str)> {
+    let rest = redirect_uri
+        .strip_prefix("http://")
+        .ok_or_else(|| anyhow!("only http redirect uris can be captured"))?;
+    Ok(match rest.find('/') {
+        Some(index) => rest.split_at(index),
+        None => (rest, "/"),
+    })
+}
+
+/// Listen on the local port of `redirect_uri`
+pub async fn listen(redirect_uri: &str) -> Result<TcpListener> {
+    let (authority, _) = split_uri(redirect_uri)?;
+    let port = match authority.rsplit_once(':') {
+        Some((_, port)) => port.parse()?,
+        None => 80,
+    };
+    let address = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+    Ok(TcpListener::bind(address).await?)
+}
+
+/// Wait for the browser to be redirected to `redirect_uri`, returns the url it was
+/// redirected to, which contains the authorization code
+pub async fn capture(listener: TcpListener, redirect_uri: &str) -> Result<String> {
+    let (authority, path) = split_uri(redirect_uri)?;
+    loop {
+        let (mut stream, _) = listener.accept().await?;
+        let mut request = Vec::new();
+        let mut buffer = [0; 1024];
+        while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < MAX_REQUEST {
+            let read = stream.read(&mut buffer).await?;
+            if read == 0 {
+                break;
+            }
+            request.extend_from_slice(&buffer[..read]);
+        }
+        let request = String::from_utf8_lossy(&request);
+        // the request line looks like `GET /callback?code=... HTTP/1.1`
+        let target = request.split_whitespace().nth(1).unwrap_or_default();
+        debug!("[Spotify] Redirected to {target}");
+        if target.split('?').next() != Some(path) {
+            // e.g. the favicon requested by the browser
+            let _ = stream
+                .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n")
+                .await;
+            continue;
+        }
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\n\r\n{DONE_PAGE}",
+            DONE_PAGE.len()
+        );
+        let _ = stream.write_all(response.as_bytes()).await;
+        return Ok(format!("http://{authority}{target}"));
+    }
+}
@@ -1,39 +1,83 @@
 use tokio::sync::broadcast::Receiver as BroadReceiver;
 use tokio::sync::mpsc::{Receiver as MpscReceiver, Sender as MpscSender};
 extern crate google_youtube3 as youtube3;
-use anyhow::Result;
+use anyhow::{bail, Result};
 use google_youtube3::hyper::client::HttpConnector;
 use google_youtube3::hyper_rustls::HttpsConnector;
 use google_youtube3::oauth2::authenticator_delegate::InstalledFlowDelegate;
+use google_youtube3::oauth2::storage::{TokenInfo, TokenStorage};
 use log::{debug, error};
-use std::collections::{HashMap, VecDeque};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::default::Default;
 use std::future::Future;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
-use std::time::Duration;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio_util::sync::CancellationToken;
 
-use youtube3::api::{Playlist as YtPlaylist, PlaylistItemListResponse, Video};
-use youtube3::api::{PlaylistItem, PlaylistListResponse};
+use youtube3::api::{Playlist as YtPlaylist, PlaylistItemListResponse, SearchResult, Video};
+use youtube3::api::{PlaylistItem, PlaylistItemSnippet, PlaylistListResponse};
+use youtube3::api::{PlaylistSnippet, PlaylistStatus, ResourceId};
 use youtube3::{hyper, hyper_rustls, oauth2, YouTube};
 
-use crate::{client::interface::{Answer, GetRequest, PlaylistInfo, Request, SongInfo, Widget}, config};
+use super::cache::{EtagCache, VideoCache, VideoDetails};
+
+#[cfg(feature = "musicbrainz")]
+use crate::client::enrichment::Enricher;
+use crate::{
+    client::{
+        backoff::Backoff,
+        delta::SentPlaylists,
+        download,
+        interface::{
+            Answer, Capabilities, GetRequest, Notification, PlaylistInfo, Request, SetRequest,
+            Severity, SongInfo, Visibility, Widget,
+        },
+        offline::{self, OfflineStore},
+    },
+    config,
+    doctor::{self, Check},
+    secrets,
+};
 
 type Hub = YouTube<HttpsConnector<HttpConnector>>;
 const MAX_RESULT: u32 = 50;
+/// parts of the playlists fetched from the api
+const PLAYLIST_PARTS: [&str; 3] = ["snippet", "contentDetails", "status"];
+/// id of the playlist of the videos liked by the user
+const LIKED_VIDEOS: &str = "LL";
+/// pace at which the songs missing metadata are looked up
+const ENRICH_INTERVAL: Duration = Duration::from_secs(1);
+/// the playlists of the account are edited, the songs are played by mpv,
+/// videos have no artist nor album to browse
+const CAPABILITIES: Capabilities = Capabilities {
+    can_search: true,
+    can_edit_playlists: true,
+    can_play: true,
+    can_seek: true,
+    supports_queue: true,
+    can_browse_artists: false,
+    suggests_related: true,
+};
 
-#[derive(Debug, Clone)]
-struct Song {
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(super) struct Song {
     artist: String,
     title: String,
     id: String,
+    /// id of the item of the playlist containing the video, empty for search results
+    item_id: String,
     art_url: String,
     duration: Duration,
+    /// the video is private, deleted or blocked in the region
+    unavailable: bool,
 }
 
 impl Song {
     pub fn new(song: PlaylistItem) -> Self {
+        let item_id = song.id.clone().unwrap_or_default();
         let snippet = song.clone().snippet.unwrap_or_default();
         let content_details = song.clone().content_details.unwrap_or_default();
         let title = snippet.clone().title.unwrap_or_default();
@@ -52,19 +96,44 @@ impl Song {
         Song {
             title,
             id,
+            item_id,
             art_url,
             artist,
             duration: Default::default(),
+            unavailable: false,
+        }
+    }
+    pub fn from_search(result: SearchResult) -> Self {
+        let snippet = result.snippet.unwrap_or_default();
+        let id = result.id.unwrap_or_default().video_id.unwrap_or_default();
+        let art_url = snippet
+            .thumbnails
+            .unwrap_or_default()
+            .default
+            .unwrap_or_default()
+            .url
+            .unwrap_or_default();
+        Song {
+            title: snippet.title.unwrap_or_default(),
+            id,
+            item_id: String::new(),
+            art_url,
+            artist: snippet.channel_title.unwrap_or_default(),
+            duration: Default::default(),
+            unavailable: false,
         }
     }
     pub fn info(&self) -> SongInfo {
         SongInfo {
             title: self.title.clone(),
             artist: self.artist.clone(),
+            album: String::new(),
+            year: None,
             cover_url: self.art_url.clone(),
             id: self.id.clone(),
             url: format!("https://youtu.be/{}", self.id),
             duration: self.duration,
+            unavailable: self.unavailable,
         }
     }
 }
@@ -85,6 +154,12 @@ struct Playlist {
     next_page_token: Option<String>,
     /// Index in the playlists list
     index: usize,
+    /// version of the playlist, changed by youtube whenever the playlist is modified
+    etag: String,
+    description: String,
+    /// name of the channel owning the playlist
+    owner: String,
+    visibility: Option<Visibility>,
 }
 
 impl Playlist {
@@ -94,6 +169,7 @@ impl Playlist {
         let title = snippet.title.unwrap_or_default();
         let length = details.item_count.unwrap_or_default() as usize;
         let id = playlist.clone().id.unwrap_or_default();
+        let etag = playlist.etag.clone().unwrap_or_default();
         let art_url = snippet
             .thumbnails
             .unwrap_or_default()
@@ -101,6 +177,13 @@ impl Playlist {
             .unwrap_or_default()
             .url
             .unwrap_or_default();
+        let status = playlist.status.unwrap_or_default();
+        let visibility = match status.privacy_status.as_deref() {
+            Some("public") => Some(Visibility::Public),
+            Some("unlisted") => Some(Visibility::Unlisted),
+            Some("private") => Some(Visibility::Private),
+            _ => None,
+        };
         Self {
             title,
             id,
@@ -109,8 +192,18 @@ impl Playlist {
             art_url,
             next_page_token: Some(String::new()),
             index: index.unwrap_or_default(),
+            etag,
+            description: snippet.description.unwrap_or_default(),
+            owner: snippet.channel_title.unwrap_or_default(),
+            visibility,
         }
     }
+    /// Use `songs` as the whole content of the playlist
+    fn set_songs(&mut self, songs: Vec<Song>) {
+        self.length = songs.len();
+        self.songs = songs;
+        self.next_page_token = None;
+    }
     pub fn id(&self) -> String {
         self.id.clone()
     }
@@ -124,20 +217,32 @@ impl Playlist {
             length: self.length,
             cover_url: self.art_url.clone(),
             songs: self.vec_songs_info(),
+            description: self.description.clone(),
+            owner: self.owner.clone(),
+            visibility: self.visibility,
+            // videos are liked and unliked rather than added to the liked videos
+            editable: self.id != LIKED_VIDEOS,
         }
     }
-    async fn add_songs(&mut self, songs: &PlaylistItemListResponse, hub: &Hub) {
+    async fn add_songs(
+        &mut self,
+        songs: &PlaylistItemListResponse,
+        hub: &Hub,
+        videos: &mut VideoCache,
+    ) -> Result<()> {
         let songs_items = songs.clone().items.unwrap_or_default();
         let songs: Vec<Song> = songs_items.iter().map(|s| Song::new(s.clone())).collect();
-        let songs: Vec<Song> = self.filter(&songs, hub).await;
+        // unavailable videos are kept so that the songs match the items of the playlist
+        let songs: Vec<Song> = Self::check_availability(&songs, hub, videos).await?;
         for s in songs {
             self.songs.push(s);
         }
+        Ok(())
     }
-    async fn load_page(&mut self, hub: &Hub) {
+    async fn load_page(&mut self, hub: &Hub, videos: &mut VideoCache) -> Result<()> {
         if self.is_loaded() {
             // fully loaded
-            return;
+            return Ok(());
         };
         let next_page = self.next_page_token.as_ref().unwrap();
         let request = hub
@@ -146,22 +251,32 @@ impl Playlist {
             .playlist_id(&self.id)
             .max_results(MAX_RESULT)
             .page_token(next_page);
-        let (_, result) = request.doit().await.unwrap_or_default();
+        let (_, result) = request.doit().await?;
+        // the page is only skipped once its songs are added, so that it is fetched again
+        // if a request fails
+        self.add_songs(&result, hub, videos).await?;
         self.next_page_token = result.next_page_token.clone();
-        self.add_songs(&result, hub).await;
         if self.next_page_token.is_none() {
             self.length = self.songs.len();
         }
+        Ok(())
     }
 
-    async fn load_all(&mut self, hub: &Hub, tasks: MpscSender<Task>) {
-        self.load_page(hub).await;
+    async fn load_all(
+        &mut self,
+        hub: &Hub,
+        videos: &mut VideoCache,
+        tasks: MpscSender<Task>,
+        token: CancellationToken,
+    ) -> Result<()> {
+        self.load_page(hub, videos).await?;
         if !self.is_loaded() {
             // ignore failure to send task
             let _ = tasks
-                .send(Task::Playlist(self.id(), ActionPlaylist::LoadAll))
+                .send(Task::Playlist(self.id(), ActionPlaylist::LoadAll, token))
                 .await;
         }
+        Ok(())
     }
 
     fn is_loaded(&self) -> bool {
@@ -169,56 +284,77 @@ impl Playlist {
         self.next_page_token.is_none()
     }
 
-    async fn handle_task(&mut self, task: ActionPlaylist, hub: &Hub, tasks: MpscSender<Task>) {
+    /// Forget the songs loaded so that they are fetched again
+    fn reload(&mut self) {
+        self.songs.clear();
+        self.next_page_token = Some(String::new());
+        // the songs loaded next are those of a newer version
+        self.etag.clear();
+    }
+
+    async fn handle_task(
+        &mut self,
+        task: ActionPlaylist,
+        hub: &Hub,
+        videos: &mut VideoCache,
+        tasks: MpscSender<Task>,
+        token: CancellationToken,
+    ) -> Result<()> {
         match task {
-            ActionPlaylist::LoadAll => self.load_all(hub, tasks).await,
+            ActionPlaylist::LoadAll => self.load_all(hub, videos, tasks, token).await,
             ActionPlaylist::LoadPage => todo!(),
         }
     }
 
-    async fn filter(&self, songs: &[Song], hub: &Hub) -> Vec<Song> {
-        let ids: Vec<String> = songs.iter().map(|s| s.id.clone()).collect();
-        let request = hub
-            .videos()
-            .list(&vec![
-                "snippet".to_string(),
-                "contentDetails".to_string(),
-                "status".to_string(),
-            ])
-            .max_results(MAX_RESULT);
-        let request = ids.iter().fold(request, |r, s| r.add_id(s));
-        let (_, result) = request.doit().await.unwrap_or_default();
-        let videos: Vec<Video> = result.items.unwrap_or_default();
-        let videos: Vec<&Video> = videos
-            .iter()
-            .filter(|&v| check_video_available(v))
-            .collect();
-        let ids_video: Vec<String> = videos
+    /// Fill in the duration of `songs`, flagging the videos that cannot be played,
+    /// only the videos missing from `videos` are queried, by batches of `MAX_RESULT`
+    async fn check_availability(
+        songs: &[Song],
+        hub: &Hub,
+        videos: &mut VideoCache,
+    ) -> Result<Vec<Song>> {
+        let mut ids: Vec<String> = songs
             .iter()
-            .map(|v| v.id.clone().unwrap_or_default())
+            .filter(|s| videos.get(&s.id).is_none())
+            .map(|s| s.id.clone())
             .collect();
-        let songs: Vec<&Song> = songs.iter().filter(|s| ids_video.contains(&s.id)).collect();
+        ids.sort();
+        ids.dedup();
+        let region = region();
+        let mut fetched = Vec::new();
+        for chunk in ids.chunks(MAX_RESULT as usize) {
+            let request = hub
+                .videos()
+                .list(&vec!["contentDetails".to_string(), "status".to_string()])
+                .max_results(MAX_RESULT);
+            let request = chunk.iter().fold(request, |r, s| r.add_id(s));
+            let (_, result) = request.doit().await?;
+            let found: Vec<Video> = result.items.unwrap_or_default();
+            for id in chunk {
+                // the videos deleted or made private are not returned by youtube
+                let video = found.iter().find(|v| v.id.as_deref() == Some(id.as_str()));
+                let details = match video {
+                    Some(video) if check_video_available(video, &region) => {
+                        VideoDetails::new(video_duration(video), false)
+                    }
+                    _ => VideoDetails::new(Duration::ZERO, true),
+                };
+                fetched.push((id.clone(), details));
+            }
+        }
+        videos.extend(fetched);
         let songs: Vec<Song> = songs
-            .to_owned()
             .iter()
-            .map(|&s| {
-                let song: Song = s.clone();
-                let video = videos
-                    .iter()
-                    .find(|v| v.id.clone().unwrap_or_default() == s.id)
-                    .unwrap();
-                let duration = video
-                    .content_details
-                    .clone()
-                    .unwrap_or_default()
-                    .duration
-                    .unwrap_or_default();
-                let duration = duration.parse::<iso8601_duration::Duration>().unwrap();
-                let duration = duration.to_std().unwrap_or_default();
-                Song { duration, ..song }
+            .map(|s| match videos.get(&s.id) {
+                Some(details) => Song {
+                    duration: details.duration,
+                    unavailable: details.unavailable,
+                    ..s.clone()
+                },
+                None => s.clone(),
             })
             .collect();
-        songs
+        Ok(songs)
     }
 }
 
@@ -242,7 +378,8 @@ enum ActionPlaylistList {
 #[derive(Debug)]
 enum Task {
     PlaylistList(ActionPlaylistList),
-    Playlist(String, ActionPlaylist),
+    /// the load is dropped once its token is cancelled, the next pages loaded keep the token
+    Playlist(String, ActionPlaylist, CancellationToken),
     Command(Request),
 }
 
@@ -256,6 +393,22 @@ pub struct Backend {
     tasks: VecDeque<Task>,
     task_receiver: MpscReceiver<Task>,
     task_sender: MpscSender<Task>,
+    #[cfg(feature = "musicbrainz")]
+    enricher: Option<Enricher>,
+    offline: Arc<Mutex<OfflineStore>>,
+    /// playlists kept offline to sync once they are fully loaded
+    pending_sync: HashSet<String>,
+    /// playlist selected by the user, loaded before the others
+    selected: Option<String>,
+    /// token of the playlists being loaded, the loads of the playlists deselected are
+    /// cancelled unless they are kept offline
+    loads: HashMap<String, CancellationToken>,
+    last_sync: Option<Instant>,
+    backoff: Backoff,
+    cache: EtagCache,
+    /// duration and availability of the videos already looked up
+    videos: VideoCache,
+    sent: SentPlaylists,
 }
 
 impl Backend {
@@ -276,6 +429,17 @@ impl Backend {
             tasks: Default::default(),
             task_sender,
             task_receiver,
+            #[cfg(feature = "musicbrainz")]
+            enricher: Enricher::from_config(),
+            offline: Arc::new(Mutex::new(OfflineStore::load("youtube"))),
+            pending_sync: Default::default(),
+            selected: None,
+            loads: Default::default(),
+            last_sync: None,
+            backoff: Default::default(),
+            cache: EtagCache::load(),
+            videos: VideoCache::load(&region()),
+            sent: SentPlaylists::default(),
         };
         Ok(client)
     }
@@ -285,77 +449,334 @@ impl Backend {
             // ignore if already fetched
             return;
         };
-        self.fetch_liked_playlist().await;
+        if let Err(err) = self.fetch_liked_playlist().await {
+            self.request_failed("the liked videos", err).await;
+            return;
+        }
         // TODO: load multiple pages
         let request = self
             .hub
             .playlists()
-            .list(&vec!["snippet".to_string(), "contentDetails".to_string()])
+            .list(&PLAYLIST_PARTS.map(String::from).to_vec())
             .mine(true)
             .max_results(MAX_RESULT);
-        let (_, result) = request.doit().await.unwrap();
-        self.set_playlists(result);
-        self.all_playlist_fetched = true;
+        match request.doit().await {
+            Ok((_, result)) => {
+                self.set_playlists(result);
+                self.all_playlist_fetched = true;
+                let ids: Vec<String> = self.playlists.keys().cloned().collect();
+                self.cache.retain(&ids);
+            }
+            Err(err) => self.request_failed("the playlists", err.into()).await,
+        }
     }
-    async fn fetch_liked_playlist(&mut self) {
+    async fn fetch_liked_playlist(&mut self) -> Result<()> {
         let request = self
             .hub
             .playlists()
-            .list(&vec!["snippet".to_string(), "contentDetails".to_string()])
-            .add_id("LL")
+            .list(&PLAYLIST_PARTS.map(String::from).to_vec())
+            .add_id(LIKED_VIDEOS)
             .max_results(MAX_RESULT);
-        let (_, result) = request.doit().await.unwrap();
+        let (_, result) = request.doit().await?;
         let results = result.items.unwrap_or_default();
         if !results.is_empty() {
             let playlist = Playlist::new(results[0].clone(), Some(0));
-            self.playlists.insert(playlist.id.clone(), playlist);
+            self.update_playlist(playlist);
+        }
+        Ok(())
+    }
+    /// Report the failure of a request loading `what`, requests are held back
+    /// if the api reported a rate limit
+    async fn request_failed(&mut self, what: &str, err: anyhow::Error) {
+        error!("[Youtube] Failed to load {what}: {err}");
+        if is_rate_limited(&err) {
+            let notification = self.backoff.hold("Youtube");
+            self.send(notification.into()).await;
         }
     }
     fn set_playlists(&mut self, playlists: PlaylistListResponse) {
         let playlists = playlists.items.unwrap_or_default();
         // index 0 is reserved for Liked videos
         let mut index = 1;
+        let ids: Vec<String> = playlists.iter().filter_map(|p| p.id.clone()).collect();
+        // forget the deleted playlists, except the liked videos
+        self.playlists
+            .retain(|id, playlist| playlist.index == 0 || ids.contains(id));
         for playlist in playlists {
             let playlist = Playlist::new(playlist, Some(index));
-            self.playlists.insert(playlist.id(), playlist);
+            self.update_playlist(playlist);
             index += 1;
         }
     }
+    /// Add or replace `playlist`, the songs already loaded are kept if its etag is unchanged,
+    /// otherwise they are restored from the cache when it holds its current version
+    fn update_playlist(&mut self, mut playlist: Playlist) {
+        if let Some(known) = self.playlists.get_mut(&playlist.id) {
+            if !known.etag.is_empty() && known.etag == playlist.etag {
+                known.index = playlist.index;
+                known.title = playlist.title;
+                return;
+            }
+        }
+        if let Some(songs) = self.cache.get(&playlist.id, &playlist.etag) {
+            debug!("[Youtube] {} restored from the cache", playlist.title);
+            playlist.set_songs(songs.clone());
+        }
+        self.playlists.insert(playlist.id(), playlist);
+    }
 
     pub async fn main_loop(&mut self) {
-        let delay = Duration::from_millis(100);
-        let mut interval = tokio::time::interval(delay);
-        while !self.cancel_token.is_cancelled() {
-            if let Some(task) = self.tasks.pop_front() {
-                self.handle_task(task).await;
-            }
+        let sync_minutes = config::get_config().offline_sync_interval;
+        let sync_delay = Duration::from_secs(sync_minutes.saturating_mul(60));
+        let mut enrich_interval = tokio::time::interval(ENRICH_INTERVAL);
+        loop {
+            let next_sync = self
+                .last_sync
+                .map_or_else(Instant::now, |last| last + sync_delay);
+            // hold the tasks back until the api accepts requests again
+            let resume = self.backoff.until();
+            let ready = resume.is_none() && !self.tasks.is_empty();
+            // only look up metadata when there is nothing more urgent to do
+            let enrich = cfg!(feature = "musicbrainz") && self.tasks.is_empty();
             use tokio::sync::broadcast::error;
-            match self.receiver.try_recv() {
-                Ok(command) => self.tasks.push_back(Task::Command(command)),
-                Err(err) => match err {
-                    error::TryRecvError::Empty => (),
-                    error::TryRecvError::Closed => self.cancel_token.cancel(),
-                    error::TryRecvError::Lagged(_) => {
+            tokio::select! {
+                _ = self.cancel_token.cancelled() => break,
+                _ = tokio::time::sleep_until(next_sync.into()), if sync_minutes > 0 => {
+                    self.sync_offline().await
+                }
+                _ = tokio::time::sleep_until(resume.unwrap_or_else(Instant::now).into()),
+                    if resume.is_some() => (),
+                _ = std::future::ready(()), if ready => {
+                    if let Some(task) = self.tasks.pop_front() {
+                        self.handle_task(task).await;
+                    }
+                }
+                _ = enrich_interval.tick(), if enrich => self.enrich_next().await,
+                Some(task) = self.task_receiver.recv() => self.queue(task),
+                request = self.receiver.recv() => match request {
+                    Ok(Request::Get(get)) if self.backoff.is_waiting() => {
+                        self.send(Answer::Busy(get.clone())).await;
+                        // refreshes are sent again periodically, no need to queue them
+                        if !matches!(get, GetRequest::PlaylistList | GetRequest::Playlist(_)) {
+                            self.tasks.push_back(Task::Command(get.into()));
+                        }
+                    }
+                    Ok(command) => {
+                        // only the playlist selected by the user is requested
+                        if let Request::Get(GetRequest::Playlist(id)) = &command {
+                            self.select(id);
+                        }
+                        self.tasks.push_back(Task::Command(command))
+                    }
+                    Err(error::RecvError::Closed) => self.cancel_token.cancel(),
+                    Err(error::RecvError::Lagged(_)) => {
                         // resubscribe to broadcast ignoring all messages
                         // pending
                         self.receiver = self.receiver.resubscribe()
                     }
                 },
             }
-            if let Ok(task) = self.task_receiver.try_recv() {
-                self.tasks.push_back(task);
-            }
-            interval.tick().await;
         }
     }
     async fn handle_command(&mut self, request: Request) {
         match request {
             Request::PlayerAction(_) => (),
             Request::Get(request) => self.handle_get(request).await,
-            Request::Set(_) => todo!(),
+            Request::Set(SetRequest::AddSongToPlaylist { song, playlist }) => {
+                let notification = match self.add_song(&song.id, &playlist).await {
+                    Ok(title) => Notification::new(Severity::Info, format!("Added to {title}")),
+                    Err(err) => {
+                        error!("[Youtube] Failed to add {} to {playlist}: {err}", song.id);
+                        let message = format!("Failed to add the song: {err}");
+                        Notification::new(Severity::Error, message)
+                    }
+                };
+                self.send(notification.into()).await;
+            }
+            Request::Set(SetRequest::RemoveSongFromPlaylist { song, playlist }) => {
+                let notification = match self.remove_song(&song.id, &playlist).await {
+                    Ok(title) => Notification::new(Severity::Info, format!("Removed from {title}")),
+                    Err(err) => {
+                        error!("[Youtube] Failed to remove {} from {playlist}: {err}", song.id);
+                        let message = format!("Failed to remove the song: {err}");
+                        Notification::new(Severity::Error, message)
+                    }
+                };
+                self.send(notification.into()).await;
+            }
             Request::Command(_) => (),
+            Request::Revalidate => self.revalidate().await,
+            Request::Reconnect => self.reconnect().await,
+            Request::Download(songs) => {
+                tokio::spawn(download::download(songs, self.sender.clone()));
+            }
+            Request::ToggleOffline(id) => self.toggle_offline(id).await,
+            Request::CreatePlaylist { title, songs } => {
+                let notification = match self.create_playlist(&title, &songs).await {
+                    Ok(_) => Notification::new(Severity::Info, format!("Created {title}")),
+                    Err(err) => {
+                        error!("[Youtube] Failed to create {title}: {err}");
+                        let message = format!("Failed to create {title}: {err}");
+                        Notification::new(Severity::Error, message)
+                    }
+                };
+                self.send(notification.into()).await;
+            }
+        }
+    }
+    /// Create a private playlist named `title` made of `songs`
+    async fn create_playlist(&mut self, title: &str, songs: &[SongInfo]) -> Result<()> {
+        let playlist = YtPlaylist {
+            snippet: Some(PlaylistSnippet {
+                title: Some(title.to_string()),
+                ..Default::default()
+            }),
+            status: Some(PlaylistStatus {
+                privacy_status: Some("private".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let (_, playlist) = self.hub.playlists().insert(playlist).doit().await?;
+        let id = playlist.id.clone().unwrap_or_default();
+        // items are appended one at a time, the api has no batch insertion
+        for song in songs {
+            self.insert_item(&id, &song.id).await?;
+        }
+        let playlist = Playlist::new(playlist, Some(self.playlists.len()));
+        self.playlists.insert(id, playlist);
+        Ok(())
+    }
+    /// Append the video `video` to the playlist `playlist`
+    async fn insert_item(&self, playlist: &str, video: &str) -> Result<()> {
+        let item = PlaylistItem {
+            snippet: Some(PlaylistItemSnippet {
+                playlist_id: Some(playlist.to_string()),
+                resource_id: Some(ResourceId {
+                    kind: Some("youtube#video".to_string()),
+                    video_id: Some(video.to_string()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        self.hub.playlist_items().insert(item).doit().await?;
+        Ok(())
+    }
+    /// Append the video `song` to the playlist `playlist` and load the playlist again,
+    /// returns the title of the playlist
+    async fn add_song(&mut self, song: &str, playlist: &str) -> Result<String> {
+        if !self.playlists.contains_key(playlist) {
+            bail!("unknown playlist");
+        }
+        self.insert_item(playlist, song).await?;
+        let loaded = match self.playlists.get_mut(playlist) {
+            Some(loaded) => loaded,
+            None => bail!("unknown playlist"),
+        };
+        loaded.length += 1;
+        let title = loaded.title.clone();
+        // a playlist still loading will include the song once loaded
+        if loaded.is_loaded() {
+            loaded.reload();
+            self.load(playlist.to_string());
+        }
+        Ok(title)
+    }
+    /// Remove the first occurrence of the video `song` from the playlist `playlist`,
+    /// returns the title of the playlist
+    async fn remove_song(&mut self, song: &str, playlist: &str) -> Result<String> {
+        let loaded = match self.playlists.get_mut(playlist) {
+            Some(loaded) => loaded,
+            None => bail!("unknown playlist"),
+        };
+        // the items are only known once loaded
+        let index = match loaded.songs.iter().position(|s| s.id == song) {
+            Some(index) => index,
+            None => bail!("song not found in {}", loaded.title),
+        };
+        self.hub
+            .playlist_items()
+            .delete(&loaded.songs[index].item_id)
+            .doit()
+            .await?;
+        loaded.songs.remove(index);
+        loaded.length = loaded.length.saturating_sub(1);
+        Ok(loaded.title.clone())
+    }
+    /// Start or stop keeping the playlist `id` offline
+    async fn toggle_offline(&mut self, id: String) {
+        self.fetch_all_playlists().await;
+        let title = match self.playlists.get(&id) {
+            Some(playlist) => playlist.title.clone(),
+            None => return,
+        };
+        let offline = self.offline.lock().unwrap().toggle(&id);
+        let message = if offline {
+            self.pending_sync.insert(id.clone());
+            self.load(id.clone());
+            format!("{title} will be kept offline")
+        } else {
+            format!("{title} is no longer kept offline")
+        };
+        self.send(Notification::new(Severity::Info, message).into())
+            .await;
+        // update the urls of the songs
+        self.tasks
+            .push_back(Task::Command(GetRequest::Playlist(id).into()));
+    }
+    /// Fetch the playlists kept offline again to compare them with their offline copy
+    async fn sync_offline(&mut self) {
+        self.last_sync = Some(Instant::now());
+        let ids: Vec<String> = self.offline.lock().unwrap().playlists().cloned().collect();
+        if ids.is_empty() {
+            // no quota is spent on fetching the playlists
+            return;
+        }
+        // the playlists whose etag changed are loaded again
+        self.all_playlist_fetched = false;
+        self.fetch_all_playlists().await;
+        for id in ids {
+            if self.playlists.contains_key(&id) {
+                self.pending_sync.insert(id.clone());
+                self.load(id);
+            }
+        }
+    }
+    /// Query the api to refresh the token if needed
+    async fn revalidate(&mut self) {
+        debug!("[Youtube] Checking connection");
+        let request = self
+            .hub
+            .channels()
+            .list(&vec!["id".to_string()])
+            .mine(true);
+        if let Err(err) = request.doit().await {
+            error!("[Youtube] Connection check failed: {err}");
         }
     }
+    /// Delete the saved token and authenticate again from scratch
+    async fn reconnect(&mut self) {
+        log::info!("[Youtube] Reconnecting");
+        let cache = Self::token_cache();
+        if let Err(err) = secrets::remove(TOKEN_NAME, &cache) {
+            debug!("[Youtube] Cannot delete {}: {err}", cache.display());
+        }
+        match Self::create_hub(self.sender.clone()).await {
+            Ok(hub) => self.hub = hub,
+            Err(err) => {
+                error!("[Youtube] Failed to reconnect: {err}");
+                let message = format!("Failed to reconnect: {err}");
+                self.send(Notification::new(Severity::Error, message).into())
+                    .await;
+                return;
+            }
+        }
+        self.backoff.success();
+        // the first request starts the authentication flow
+        self.revalidate().await;
+    }
     async fn send_playlistlist(&mut self) {
         self.fetch_all_playlists().await;
         let mut playlistlist: Vec<&Playlist> = vec![];
@@ -363,22 +784,119 @@ impl Backend {
             playlistlist.push(p)
         }
         playlistlist.sort_unstable_by_key(|playlist| playlist.index);
-        let playlistlist = playlistlist.iter().map(|p| p.info()).collect();
+        let playlistlist = playlistlist.iter().map(|p| self.playlist_info(p)).collect();
         self.send(Answer::PlaylistList(playlistlist)).await;
     }
-    pub async fn load_all_playlists(&mut self) {
-        self.fetch_all_playlists().await;
-        for (_, p) in self.playlists.iter() {
-            self.tasks
-                .push_back(Task::Playlist(p.id.clone(), ActionPlaylist::LoadAll));
-        }
-    }
     async fn send_playlist(&mut self, id: String) {
         self.fetch_all_playlists().await; //ensure all playlist are loaded
-        if let Some(p) = self.playlists.get(&id) {
-            self.tasks
-                .push_back(Task::Playlist(id, ActionPlaylist::LoadAll));
-            self.send(Answer::Playlist(p.info())).await;
+        let info = match self.playlists.get(&id) {
+            Some(p) => self.playlist_info(p),
+            None => return,
+        };
+        // a playlist deselected since it was requested is not loaded
+        if self.selected.as_ref() == Some(&id) {
+            self.load(id);
+        }
+        self.send(Answer::Playlist(info)).await;
+    }
+    /// Load the pages of the playlist `id` left to load, unless it is already loading
+    fn load(&mut self, id: String) {
+        if self.loads.contains_key(&id) {
+            return;
+        }
+        let token = CancellationToken::new();
+        self.loads.insert(id.clone(), token.clone());
+        self.queue(Task::Playlist(id, ActionPlaylist::LoadAll, token));
+    }
+    /// Cancel the loads of the playlists other than `id` once it is selected, except those
+    /// kept offline, their pages left to load are dropped from the queue and the songs
+    /// loaded so far are kept so that the load resumes if they are selected again
+    fn select(&mut self, id: &str) {
+        if self.selected.as_deref() == Some(id) {
+            return;
+        }
+        self.selected = Some(id.to_string());
+        let pending_sync = &self.pending_sync;
+        self.loads.retain(|playlist, token| {
+            let kept = playlist == id || pending_sync.contains(playlist);
+            if !kept {
+                debug!("[Youtube] Cancelling the load of {playlist}");
+                token.cancel();
+            }
+            kept
+        });
+        self.tasks
+            .retain(|task| !matches!(task, Task::Playlist(_, _, token) if token.is_cancelled()));
+    }
+    /// Queue `task`, the pages of the playlist selected are loaded before the others
+    fn queue(&mut self, task: Task) {
+        match &task {
+            Task::Playlist(id, _, _) if self.selected.as_ref() == Some(id) => {
+                self.tasks.push_front(task)
+            }
+            _ => self.tasks.push_back(task),
+        }
+    }
+    /// Send the songs of the playlist `id` loaded from `offset` onwards
+    async fn send_page(&mut self, id: &str, offset: usize) {
+        let (songs, total) = match self.playlists.get(id) {
+            Some(playlist) if playlist.songs.len() > offset => (
+                playlist.songs[offset..].iter().map(Song::info).collect(),
+                playlist.length,
+            ),
+            _ => return,
+        };
+        let mut page = PlaylistInfo {
+            songs,
+            ..Default::default()
+        };
+        self.fill_info(&mut page);
+        self.send(Answer::PlaylistPage {
+            id: id.to_string(),
+            offset,
+            songs: page.songs,
+            total,
+        })
+        .await;
+    }
+    /// Info of `playlist` with missing metadata filled from the enrichment cache,
+    /// songs kept offline point to their downloaded file
+    fn playlist_info(&self, playlist: &Playlist) -> PlaylistInfo {
+        let mut info = playlist.info();
+        self.fill_info(&mut info);
+        info
+    }
+    fn fill_info(&self, info: &mut PlaylistInfo) {
+        #[cfg(feature = "musicbrainz")]
+        if let Some(enricher) = &self.enricher {
+            for song in info.songs.iter_mut() {
+                enricher.merge_cached(song)
+            }
+        }
+        self.offline.lock().unwrap().use_local_files(info);
+    }
+    #[cfg(not(feature = "musicbrainz"))]
+    async fn enrich_next(&mut self) {}
+    /// Look up the metadata of the next song missing some, if the enricher is ready
+    #[cfg(feature = "musicbrainz")]
+    async fn enrich_next(&mut self) {
+        let enricher = match self.enricher.as_mut() {
+            Some(enricher) => enricher,
+            None => return,
+        };
+        // the metadata found is filled in when the songs are sent
+        enricher.collect();
+        if !enricher.is_ready() {
+            return;
+        }
+        let song = self
+            .playlists
+            .values()
+            .flat_map(|p| p.songs.iter())
+            .map(|s| s.info())
+            .find(|s| enricher.needs_lookup(s));
+        if let Some(song) = song {
+            enricher.lookup(song);
         }
     }
     async fn handle_get(&mut self, request: GetRequest) {
@@ -386,10 +904,67 @@ impl Backend {
             GetRequest::PlaylistList => self.send_playlistlist().await,
             GetRequest::Playlist(id) => self.send_playlist(id).await,
             GetRequest::PlayerInfo => (),
+            GetRequest::Search(query) => self.search(query).await,
+            GetRequest::Capabilities => self.send(Answer::Capabilities(CAPABILITIES)).await,
+            GetRequest::Artists | GetRequest::Albums(_) | GetRequest::Album(_) => (),
+            GetRequest::Related(song) => self.related(song).await,
         }
     }
 
+    async fn search(&mut self, query: String) {
+        let songs = self.find_videos(&query).await;
+        self.send(Answer::SearchResults { query, songs }).await;
+    }
+
+    /// Videos of the channel of `song`, youtube no longer lists the videos related to another
+    async fn related(&mut self, song: SongInfo) {
+        let songs = self
+            .find_videos(&song.artist)
+            .await
+            .into_iter()
+            .filter(|s| s.id != song.id)
+            .collect();
+        let answer = Answer::Related {
+            seed: song.id,
+            songs,
+        };
+        self.send(answer).await;
+    }
+
+    /// Available videos matching `query`
+    async fn find_videos(&mut self, query: &str) -> Vec<SongInfo> {
+        let request = self
+            .hub
+            .search()
+            .list(&vec!["snippet".to_string()])
+            .q(query)
+            .add_type("video")
+            .max_results(MAX_RESULT);
+        let songs = match request.doit().await {
+            Ok((_, result)) => {
+                let songs: Vec<Song> = result
+                    .items
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(Song::from_search)
+                    .collect();
+                let songs = Playlist::check_availability(&songs, &self.hub, &mut self.videos).await;
+                songs.map(|songs| songs.into_iter().filter(|s| !s.unavailable).collect())
+            }
+            Err(err) => Err(err.into()),
+        };
+        let songs = match songs {
+            Ok(songs) => songs,
+            Err(err) => {
+                self.request_failed("the search results", err).await;
+                Vec::new()
+            }
+        };
+        songs.iter().map(|s| s.info()).collect()
+    }
+
     async fn send(&mut self, answer: Answer) {
+        let answer = self.sent.track(answer);
         if self.sender.send(answer).await.is_err() {
             self.cancel_token.cancel()
         }
@@ -398,48 +973,103 @@ impl Backend {
     async fn handle_task(&mut self, task: Task) {
         match task {
             Task::PlaylistList(_) => todo!(),
-            Task::Playlist(id, task) => {
-                if let Some(playlist) = self.playlists.get_mut(&id) {
-                    playlist
-                        .handle_task(task, &self.hub, self.task_sender.clone())
-                        .await
+            Task::Playlist(id, task, token) => {
+                if token.is_cancelled() {
+                    return;
+                }
+                let playlist = match self.playlists.get_mut(&id) {
+                    Some(playlist) => playlist,
+                    None => return,
+                };
+                let offset = playlist.songs.len();
+                let was_loaded = playlist.is_loaded();
+                let result = playlist
+                    .handle_task(
+                        task,
+                        &self.hub,
+                        &mut self.videos,
+                        self.task_sender.clone(),
+                        token,
+                    )
+                    .await;
+                let title = playlist.title.clone();
+                let loaded = result.is_ok() && playlist.is_loaded();
+                if loaded || result.is_err() {
+                    // the load is started again the next time the playlist is requested
+                    self.loads.remove(&id);
+                }
+                if loaded && !was_loaded {
+                    let songs = playlist.songs.clone();
+                    self.cache.insert(id.clone(), playlist.etag.clone(), songs);
+                }
+                if loaded && self.pending_sync.remove(&id) {
+                    let info = playlist.info();
+                    self.spawn_sync(info);
+                }
+                match result {
+                    Ok(()) => {
+                        self.backoff.success();
+                        self.send_page(&id, offset).await;
+                    }
+                    Err(err) => self.request_failed(&title, err).await,
                 }
             }
             Task::Command(command) => self.handle_command(command).await,
         }
     }
 
+    /// Sync the offline copy of `playlist` in the background,
+    /// then send the playlist again so that its songs are played from the disk
+    fn spawn_sync(&self, playlist: PlaylistInfo) {
+        let offline = self.offline.clone();
+        let sender = self.sender.clone();
+        let tasks = self.task_sender.clone();
+        tokio::spawn(async move {
+            let id = playlist.id.clone();
+            offline::sync(offline, playlist, sender).await;
+            let _ = tasks
+                .send(Task::Command(GetRequest::Playlist(id).into()))
+                .await;
+        });
+    }
+
+    /// File in which the oauth tokens are saved
+    fn token_cache() -> PathBuf {
+        let dirs = config::get_dirs();
+        let mut cache = dirs.cache_dir().to_path_buf();
+        cache.push("youtube_token_cache.json");
+        cache
+    }
+
     async fn create_hub(sender: MpscSender<Answer>) -> Result<Hub> {
         // Get an ApplicationSecret instance by some means. It contains the `client_id` and
         // `client_secret`, among other things.
         // TODO: set own configuration
-        let secrets_location = config::get_config().yt_secret_location;
-        let secret_path = PathBuf::from(secrets_location);
-        let secret = oauth2::read_application_secret(secret_path).await;
-        let secret = match secret {
+        let secret = match read_secret() {
             Err(e) => {
                 error!("Cannot find credentials for youtube client : {}", e);
-                return Err(e.into());
+                return Err(e);
             }
             Ok(secret) => secret,
         };
         // Instantiate the authenticator. It will choose a suitable authentication flow for you,
         // unless you replace  `None` with the desired Flow.
         // Provide your own `AuthenticatorDelegate` to adjust the way it operates and get feedback about
-        // what's going on. You probably want to bring in your own `TokenStorage` to persist tokens and
-        // retrieve them from storage.
-        let dirs = config::get_dirs();
-        let mut cache = dirs.cache_dir().to_path_buf();
-        cache.push("youtube_token_cache.json");
+        // what's going on.
         let auth = oauth2::InstalledFlowAuthenticator::builder(
             secret,
             oauth2::InstalledFlowReturnMethod::HTTPRedirect,
-        )
-        .persist_tokens_to_disk(cache)
-        .flow_delegate(Box::new(CustomFlowDelegate::new(sender)))
-        .build()
-        .await
-        .unwrap();
+        );
+        let auth = if secrets::in_keyring() {
+            auth.with_storage(Box::new(KeyringStorage))
+        } else {
+            auth.persist_tokens_to_disk(Self::token_cache())
+        };
+        let auth = auth
+            .flow_delegate(Box::new(CustomFlowDelegate::new(sender)))
+            .build()
+            .await
+            .unwrap();
 
         Ok(YouTube::new(
             hyper::Client::builder().build(
@@ -491,14 +1121,9 @@ async fn present_user_url(
 ) -> Result<String, String> {
     debug!("[Youtube] Initiating flow");
     // try to open url in browser
+    let message = "Go to your browser to authenticate to Youtube";
     let _ = out
-        .send(
-            Widget::Alert {
-                title: "Connect to Youtube".to_string(),
-                content: "Go to your browser to authenticate".to_string(),
-            }
-            .into(),
-        )
+        .send(Notification::new(Severity::Info, message).into())
         .await;
     if open::that(url).is_ok() {
         return Ok(String::new());
@@ -532,16 +1157,130 @@ async fn present_user_url(
     }
 }
 
-fn check_video_available(video: &Video) -> bool {
+/// name of the client secret in the keyring
+const SECRET_NAME: &str = "youtube_secrets";
+/// name of the oauth tokens in the keyring
+const TOKEN_NAME: &str = "youtube_token";
+
+/// Keeps the oauth tokens in the keyring rather than in the token cache,
+/// the tokens are indexed by the scopes they grant
+struct KeyringStorage;
+
+impl KeyringStorage {
+    fn read() -> HashMap<String, TokenInfo> {
+        secrets::read(TOKEN_NAME, &Backend::token_cache())
+            .ok()
+            .and_then(|tokens| serde_json::from_str(&tokens).ok())
+            .unwrap_or_default()
+    }
+
+    fn key(scopes: &[&str]) -> String {
+        let mut scopes = scopes.to_vec();
+        scopes.sort_unstable();
+        scopes.join(" ")
+    }
+}
+
+#[async_trait::async_trait]
+impl TokenStorage for KeyringStorage {
+    async fn set(&self, scopes: &[&str], token: TokenInfo) -> anyhow::Result<()> {
+        let mut tokens = Self::read();
+        tokens.insert(Self::key(scopes), token);
+        let tokens = serde_json::to_string(&tokens)?;
+        secrets::write(TOKEN_NAME, &Backend::token_cache(), &tokens)
+    }
+
+    async fn get(&self, scopes: &[&str]) -> Option<TokenInfo> {
+        Self::read().remove(&Self::key(scopes))
+    }
+}
+
+/// Client secret of the application, read from its file or from the keyring
+fn read_secret() -> Result<oauth2::ApplicationSecret> {
+    let location = config::get_config().yt_secret_location;
+    let secret = secrets::read(SECRET_NAME, Path::new(&location))?;
+    Ok(oauth2::parse_application_secret(secret)?)
+}
+
+/// Check that the credentials can be read and that the api can be reached
+pub async fn diagnose() -> Vec<Check> {
+    let secrets_location = config::get_config().yt_secret_location;
+    let secret = read_secret()
+        .map(|secret| format!("client id {}", secret.client_id))
+        .map_err(|err| anyhow::anyhow!("cannot read {secrets_location}: {err}"));
+    vec![
+        Check::new("youtube credentials", secret),
+        Check::new("youtube api", doctor::reachable("www.googleapis.com").await),
+    ]
+}
+
+/// Country code whose restrictions apply, the configured one or else the one of the locale,
+/// e.g. `FR` for `fr_FR.UTF-8`
+fn region() -> String {
+    let region = config::get_config().youtube_region;
+    if !region.is_empty() {
+        return region.to_uppercase();
+    }
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find_map(|locale| {
+            let locale = locale.split(['.', '@']).next()?.to_string();
+            let (_, country) = locale.split_once('_')?;
+            Some(country.to_uppercase())
+        })
+        .unwrap_or_default()
+}
+
+/// returns `true` if `err` reports that too many requests were sent or that the quota
+/// of the api is exhausted, either with the status 429 or with a 403 giving the reason
+fn is_rate_limited(err: &anyhow::Error) -> bool {
+    const REASONS: [&str; 3] = ["rateLimitExceeded", "userRateLimitExceeded", "quotaExceeded"];
+    match err.downcast_ref::<youtube3::Error>() {
+        Some(youtube3::Error::Failure(response)) => {
+            response.status() == hyper::StatusCode::TOO_MANY_REQUESTS
+        }
+        Some(youtube3::Error::BadRequest(body)) => {
+            let error = &body["error"];
+            let reasons = error["errors"].as_array().into_iter().flatten();
+            error["code"] == 429
+                || reasons
+                    .filter_map(|detail| detail["reason"].as_str())
+                    .any(|reason| REASONS.contains(&reason))
+        }
+        _ => false,
+    }
+}
+
+/// Duration of `video`, zero if it is unknown
+fn video_duration(video: &Video) -> Duration {
+    let duration = video
+        .content_details
+        .clone()
+        .unwrap_or_default()
+        .duration
+        .unwrap_or_default();
+    duration
+        .parse::<iso8601_duration::Duration>()
+        .map(|duration| duration.to_std().unwrap_or_default())
+        .unwrap_or_default()
+}
+
+/// returns `true` if `video` is public and can be watched in `region`,
+/// the region is ignored if it is unknown
+fn check_video_available(video: &Video, region: &str) -> bool {
     let content_details = video.content_details.clone().unwrap_or_default();
     let region_restriction = content_details.region_restriction.unwrap_or_default();
     let status = video.status.clone().unwrap_or_default();
     let mut available = true;
-    /* if let Some(allowed) = region_restriction.allowed {
-        available = allowed.contains(&"fr".to_string());
-    } */
-    if let Some(blocked) = region_restriction.blocked {
-        available = available && !blocked.contains(&"fr".to_string());
+    if !region.is_empty() {
+        // youtube lists the countries using uppercase codes
+        if let Some(allowed) = region_restriction.allowed {
+            available = allowed.iter().any(|r| r.eq_ignore_ascii_case(region));
+        }
+        if let Some(blocked) = region_restriction.blocked {
+            available = available && !blocked.iter().any(|r| r.eq_ignore_ascii_case(region));
+        }
     }
     if let Some(privacy) = status.privacy_status {
         available = available && privacy != *"private";
@@ -0,0 +1,144 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::backend::Song;
+use crate::{config, json_store};
+
+/// Time after which the availability of a video is checked again
+const VIDEO_MAX_AGE: Duration = Duration::from_secs(7 * 24 * 3600);
+
+fn cache_path(name: &str) -> PathBuf {
+    let mut path = config::get_dirs().cache_dir().to_path_buf();
+    path.push(name);
+    path
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CachedPlaylist {
+    etag: String,
+    songs: Vec<Song>,
+}
+
+/// Songs of the fully loaded playlists, saved on disk along with the ETag of the playlist,
+/// the client library cannot send `If-None-Match` so the ETag returned with the list of
+/// playlists is compared instead, sparing the quota spent on the items of unchanged playlists
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct EtagCache {
+    /// cached playlists indexed by id
+    playlists: HashMap<String, CachedPlaylist>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl EtagCache {
+    pub fn load() -> Self {
+        let path = cache_path("youtube_playlists.json");
+        let cache: Self = json_store::load(&path);
+        Self { path, ..cache }
+    }
+
+    fn save(&self) {
+        json_store::save(&self.path, self);
+    }
+
+    /// Songs of the playlist `id`, if they were cached for the ETag `etag`
+    pub fn get(&self, id: &str, etag: &str) -> Option<&Vec<Song>> {
+        self.playlists
+            .get(id)
+            .filter(|cached| !etag.is_empty() && cached.etag == etag)
+            .map(|cached| &cached.songs)
+    }
+
+    pub fn insert(&mut self, id: String, etag: String, songs: Vec<Song>) {
+        let cached = CachedPlaylist { etag, songs };
+        self.playlists.insert(id, cached);
+        self.save();
+    }
+
+    /// Forget the playlists whose id is not in `ids`
+    pub fn retain(&mut self, ids: &[String]) {
+        let len = self.playlists.len();
+        self.playlists.retain(|id, _| ids.contains(id));
+        if self.playlists.len() != len {
+            self.save();
+        }
+    }
+}
+
+/// Duration and availability of a video, as returned by `videos().list`
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct VideoDetails {
+    pub duration: Duration,
+    pub unavailable: bool,
+    /// seconds since the epoch at which the video was checked
+    checked_at: u64,
+}
+
+impl VideoDetails {
+    pub fn new(duration: Duration, unavailable: bool) -> Self {
+        Self {
+            duration,
+            unavailable,
+            checked_at: now(),
+        }
+    }
+}
+
+/// Details of the videos already looked up, saved on disk so that only the videos
+/// never seen before are queried when a page of a playlist is loaded
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct VideoCache {
+    /// details indexed by video id
+    videos: HashMap<String, VideoDetails>,
+    /// region the availability was checked for
+    region: String,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl VideoCache {
+    /// Load the cache, dropping it if it was filled for another region
+    pub fn load(region: &str) -> Self {
+        let path = cache_path("youtube_videos.json");
+        let cache: Self = Some(json_store::load(&path))
+            .filter(|cache: &Self| cache.region == region)
+            .unwrap_or_default();
+        Self {
+            path,
+            region: region.to_string(),
+            ..cache
+        }
+    }
+
+    /// Details of the video `id`, unless they are missing or outdated
+    pub fn get(&self, id: &str) -> Option<VideoDetails> {
+        let oldest = now().saturating_sub(VIDEO_MAX_AGE.as_secs());
+        self.videos
+            .get(id)
+            .filter(|details| details.checked_at >= oldest)
+            .copied()
+    }
+
+    pub fn extend(&mut self, videos: Vec<(String, VideoDetails)>) {
+        if videos.is_empty() {
+            return;
+        }
+        let oldest = now().saturating_sub(VIDEO_MAX_AGE.as_secs());
+        self.videos
+            .retain(|_, details| details.checked_at >= oldest);
+        self.videos.extend(videos);
+        json_store::save(&self.path, self);
+    }
+}
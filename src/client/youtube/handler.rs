@@ -3,10 +3,14 @@ use std::time::Duration;
 use anyhow::Result;
 use tokio::sync::broadcast::Sender as BroadSender;
 use tokio::sync::mpsc::{self, Receiver as MpscReceiver, Sender as MpscSender};
-use tokio::task::JoinSet;
+use tokio::task::{JoinHandle, JoinSet};
 use tokio_util::sync::CancellationToken;
 
-use crate::client::interface::{Answer, Request};
+use crate::client::{
+    interface::{Answer, Request},
+    ClientFactory,
+};
+use crate::config;
 
 use super::super::mpv::PlayerHandler;
 use super::Backend;
@@ -54,8 +58,9 @@ impl Client {
             self.request_tx.subscribe(),
             answer_tx.clone(),
             self.cancel_token_backend.clone(),
+            config::get_config().youtube_video,
         );
-        self.tasks.spawn(async move { backend.load_all_playlists().await; backend.main_loop().await });
+        self.tasks.spawn(async move { backend.main_loop().await });
         self.tasks.spawn(async move { player.main_loop().await });
         loop {
             tokio::select! {
@@ -100,3 +105,22 @@ impl Client {
         }
     }
 }
+
+/// Starts the YouTube client
+pub struct Factory;
+
+impl ClientFactory for Factory {
+    fn name(&self) -> &str {
+        "youtube"
+    }
+
+    fn spawn(
+        &self,
+        receiver: MpscReceiver<Request>,
+        sender: MpscSender<Answer>,
+        cancel_token: CancellationToken,
+    ) -> JoinHandle<Result<()>> {
+        let mut client = Client::create(receiver, sender, cancel_token);
+        tokio::spawn(async move { client.main_loop().await })
+    }
+}
@@ -1,5 +1,7 @@
 mod backend;
 pub use backend::*;
 
+mod cache;
+
 mod handler;
-pub use handler::Client;
+pub use handler::Factory;
@@ -3,20 +3,196 @@ use std::path::PathBuf;
 
 use crossterm::event::KeyCode;
 use directories::{ProjectDirs, UserDirs};
-use ratatui::style::Color;
+use log::error;
+use ratatui::{layout::Constraint, style::Color};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    client::interface::{PlayerAction, SeekMode, Volume},
-    orchestrator::{Action, MenuCtrl},
+    client::{
+        self,
+        interface::{PlayerAction, SeekMode},
+    },
+    covers::ImageProtocol,
+    logging::LOG_FILE,
+    orchestrator::{Action, MenuCtrl, Tab},
+    playlist_file::PlaylistFormat,
+    shuffle::ShuffleMode,
 };
 
+/// Column that can be displayed in the song list
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SongColumn {
+    Title,
+    Artist,
+    Album,
+    Year,
+    Duration,
+    /// name of the client providing the song
+    Source,
+    /// stars given to the song, preceded by a heart if it is a favorite
+    Rating,
+}
+
+/// Width of a column, mirrors [Constraint] which cannot be (de)serialized
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnWidth {
+    Percentage(u16),
+    Length(u16),
+    Min(u16),
+    Max(u16),
+}
+
+impl From<ColumnWidth> for Constraint {
+    fn from(value: ColumnWidth) -> Self {
+        match value {
+            ColumnWidth::Percentage(p) => Constraint::Percentage(p),
+            ColumnWidth::Length(l) => Constraint::Length(l),
+            ColumnWidth::Min(m) => Constraint::Min(m),
+            ColumnWidth::Max(m) => Constraint::Max(m),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ColumnConfig {
+    pub column: SongColumn,
+    pub width: ColumnWidth,
+}
+
+/// What to do with a client when the session goes idle or is locked
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(default)]
+pub struct IdlePolicy {
+    /// pause the playback when the session goes idle or is locked
+    pub pause: bool,
+    /// resume the playback paused this way once the session is active again
+    pub resume: bool,
+}
+
+/// Where the secrets and tokens of the clients are kept
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SecretStorage {
+    /// json files, readable by anyone with access to the user's files
+    #[default]
+    File,
+    /// the Secret Service on Linux or the keychain on macOS, requires the `keyring` feature,
+    /// the existing files are moved there the first time they are read
+    Keyring,
+}
+
+/// Where the logs are written
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogDestination {
+    #[default]
+    File,
+    /// the systemd journal, read with `journalctl -t yama`
+    Journald,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct LogConfig {
+    /// level of the records kept, from `off` to `trace`
+    pub level: String,
+    /// level of the records of each module, e.g. `yama_v3::client::spotify`
+    /// or a dependency like `hyper`, overriding `level`
+    pub modules: HashMap<String, String>,
+    pub destination: LogDestination,
+    pub file: PathBuf,
+    /// size in KiB above which the log file is rotated, 0 to never rotate on size
+    pub max_size_kb: u64,
+    /// rotate the log file every day
+    pub daily: bool,
+    /// number of rotated files kept, named after the log file followed by their index
+    pub kept_files: u32,
+}
+
+/// Sizes and visibility of the panels of the interface
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct LayoutConfig {
+    /// width in percent of the column on the left of the library and artists tabs
+    pub sidebar_width: u16,
+    /// height in lines of the sources panel, borders included
+    pub sources_height: u16,
+    /// height in lines of the playlists panel, borders included
+    pub playlists_height: u16,
+    pub show_sources: bool,
+    pub show_options: bool,
+    /// show only the songs of the library and the player bar,
+    /// toggled with [Action::ToggleZen]
+    pub zen: bool,
+    /// show the playlists as a grid of covers in place of the list,
+    /// toggled with [Action::TogglePlaylistGrid]
+    pub playlist_grid: bool,
+    /// protocol used to draw the covers of the playlist grid
+    pub images: ImageProtocol,
+}
+
+impl Default for LayoutConfig {
+    fn default() -> Self {
+        Self {
+            sidebar_width: 25,
+            sources_height: 8,
+            playlists_height: 8,
+            show_sources: true,
+            show_options: true,
+            zen: false,
+            playlist_grid: false,
+            images: ImageProtocol::Auto,
+        }
+    }
+}
+
+/// Backend run as a subprocess, speaking the protocol of [crate::client::plugin]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct PluginConfig {
+    /// program started, looked up in the `PATH` if it is not a path
+    pub command: String,
+    pub args: Vec<String>,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: "debug".to_string(),
+            modules: HashMap::new(),
+            destination: LogDestination::File,
+            file: PathBuf::from(LOG_FILE),
+            max_size_kb: 0,
+            daily: false,
+            kept_files: 3,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
+#[serde(default)]
 pub struct Config {
     keymap: HashMap<KeyCode, Action>,
+    /// key starting the recording of a macro into the register named by the next key,
+    /// and stopping it when pressed again, it takes precedence over the keymap
+    pub macro_record_key: char,
+    /// key replaying the macro in the register named by the next key
+    pub macro_replay_key: char,
+    /// key putting the mark named by the next key on the selected song of the playlist
+    pub mark_set_key: char,
+    /// key selecting the song under the mark named by the next key
+    pub mark_jump_key: char,
     pub yt_secret_location: String,
     pub spotify_secret_location: String,
+    /// where the client secrets and the spotify token are kept
+    pub secret_storage: SecretStorage,
     pub folders: Vec<PathBuf>,
+    /// folders of the additional local clients indexed by the name of their source,
+    /// e.g. `Podcasts`, they are all started with yama
+    pub local_sources: HashMap<String, Vec<PathBuf>>,
+    /// clients started with yama, the others can be started with `client enable <name>`
+    pub clients: Vec<String>,
+    /// backends run as subprocesses indexed by the name of their source, they are all started
+    /// with yama, requires the `plugins` feature
+    pub plugins: HashMap<String, PluginConfig>,
     pub focused_fg: Color,
     pub focused_bg: Color,
     pub focused_highlight_fg: Color,
@@ -27,6 +203,79 @@ pub struct Config {
     pub unfocused_highlight_bg: Color,
     pub border_focus: Color,
     pub border_unfocus: Color,
+    /// colors of the notifications displayed in the status bar, depending on their severity
+    pub info_fg: Color,
+    pub warning_fg: Color,
+    pub error_fg: Color,
+    /// accent color of each client indexed by name, used for the borders of the playlist
+    /// and song panels and for the currently playing song
+    pub client_accents: HashMap<String, Color>,
+    /// columns displayed in the song list, in order
+    pub song_columns: Vec<ColumnConfig>,
+    /// fill missing song metadata using MusicBrainz,
+    /// requires the `musicbrainz` feature
+    pub metadata_enrichment: bool,
+    /// api key used to identify local files by their fingerprint with AcoustID,
+    /// `fpcalc` from chromaprint must be installed
+    pub acoustid_key: String,
+    /// behaviour of each client, indexed by name, when the session goes idle,
+    /// clients not listed keep playing
+    pub idle_policies: HashMap<String, IdlePolicy>,
+    /// duration in milliseconds of the fade out when pausing, stopping or skipping a song
+    /// played by mpv and of the fade in when resuming it, 0 to disable
+    pub fade_duration_ms: u64,
+    /// highest volume in percent of the songs played by mpv, above 100 the sound is amplified
+    /// which helps with quiet recordings but may distort it
+    pub max_volume: u8,
+    /// number of seconds skipped by [Action::SeekForward] and [Action::SeekBackward]
+    pub seek_step: u64,
+    /// number of seconds skipped by the large seeks
+    pub seek_step_large: u64,
+    /// change of the volume in percent by [Action::VolumeUp] and [Action::VolumeDown]
+    pub volume_step: u8,
+    /// songs played by mpv lasting at least this number of minutes, e.g. podcasts, mixes
+    /// or audiobooks, resume where they were left the next time they are played, 0 to disable
+    pub resume_min_minutes: u64,
+    /// number of seconds a notification stays in the status bar
+    pub notification_timeout: u64,
+    /// number of times per second the interface is refreshed
+    pub framerate: f64,
+    /// number of times per second the interface is refreshed while the terminal
+    /// does not have the focus
+    pub unfocused_framerate: f64,
+    /// let mpv open a window to display the video of YouTube songs,
+    /// can be toggled at runtime with the `video` command
+    pub youtube_video: bool,
+    /// country code, e.g. `FR`, whose restrictions decide which YouTube videos are available,
+    /// detected from the locale when empty
+    pub youtube_region: String,
+    /// forget the spotify device selected with `devices select` when stopping,
+    /// so that playback resumes on the device active at that time
+    pub spotify_release_device: bool,
+    /// display the time remaining instead of the time elapsed in the player bar,
+    /// toggled with [Action::ToggleRemaining]
+    pub show_remaining: bool,
+    /// address on which the remote control api listens, requires the `http` feature,
+    /// only a loopback address is accepted unless `http_token` is set
+    pub http_address: String,
+    /// token the requests to the remote control api must send in an
+    /// `Authorization: Bearer <token>` header, no token is required when empty
+    pub http_token: String,
+    /// folder in which the songs are downloaded
+    pub download_folder: PathBuf,
+    /// number of minutes between two syncs of the playlists kept offline,
+    /// 0 disables the periodic syncs
+    pub offline_sync_interval: u64,
+    /// folder in which the playlists are exported
+    pub export_folder: PathBuf,
+    /// format used by the `export` command when none is given
+    pub export_format: PlaylistFormat,
+    /// order of the songs played by mpv when shuffling
+    pub shuffle_mode: ShuffleMode,
+    /// gains in dB of the equalizer bands, from 31Hz to 16kHz, applied with `eq <name>`
+    pub equalizer_presets: HashMap<String, Vec<i8>>,
+    pub layout: LayoutConfig,
+    pub logging: LogConfig,
 }
 
 impl Config {
@@ -46,116 +295,88 @@ impl Default for Config {
         keymap.insert(KeyCode::Char('h'), MenuCtrl::PrevMenu.into());
         keymap.insert(KeyCode::Char(' '), PlayerAction::PlayPauseToggle.into());
         keymap.insert(KeyCode::Char('a'), Action::ToggleAuto);
-        keymap.insert(
-            KeyCode::Left,
-            PlayerAction::Seek {
-                dt: -5,
-                mode: SeekMode::Relative,
-            }
-            .into(),
-        );
-        keymap.insert(
-            KeyCode::Right,
-            PlayerAction::Seek {
-                dt: 5,
-                mode: SeekMode::Relative,
-            }
-            .into(),
-        );
+        keymap.insert(KeyCode::Left, Action::SeekBackward { large: false });
+        keymap.insert(KeyCode::Right, Action::SeekForward { large: false });
+        keymap.insert(KeyCode::Char('['), Action::SeekBackward { large: true });
+        keymap.insert(KeyCode::Char(']'), Action::SeekForward { large: true });
         keymap.insert(KeyCode::Char('<'), PlayerAction::Prev.into());
         keymap.insert(KeyCode::Char('>'), PlayerAction::Next.into());
-        keymap.insert(
-            KeyCode::Char('d'),
-            PlayerAction::SetVolume(Volume::Relative(-5)).into(),
-        );
-        keymap.insert(
-            KeyCode::Char('f'),
-            PlayerAction::SetVolume(Volume::Relative(5)).into(),
-        );
+        keymap.insert(KeyCode::Char('{'), PlayerAction::PrevChapter.into());
+        keymap.insert(KeyCode::Char('}'), PlayerAction::NextChapter.into());
+        keymap.insert(KeyCode::Char('c'), Action::Chapters);
+        keymap.insert(KeyCode::Char('d'), Action::VolumeDown);
+        keymap.insert(KeyCode::Char('f'), Action::VolumeUp);
+        keymap.insert(KeyCode::Char('M'), PlayerAction::MuteToggle.into());
+        keymap.insert(KeyCode::Char('t'), Action::ToggleRemaining);
+        keymap.insert(KeyCode::Char('z'), Action::ToggleZen);
+        keymap.insert(KeyCode::Char('C'), Action::ToggleCompact);
+        keymap.insert(KeyCode::Char('G'), Action::TogglePlaylistGrid);
+        keymap.insert(KeyCode::Char('o'), Action::ToggleRadio);
         keymap.insert(KeyCode::Char('g'), Action::GoToCurrent);
+        keymap.insert(KeyCode::Char('i'), Action::SongDetails);
+        keymap.insert(KeyCode::Char('A'), Action::ActivityLog);
+        keymap.insert(KeyCode::Char('L'), Action::CycleLogLevel);
         keymap.insert(KeyCode::Char('r'), PlayerAction::CycleRepeat.into());
         keymap.insert(KeyCode::Char('y'), PlayerAction::ShuffleToggle.into());
+        keymap.insert(KeyCode::Char('F'), Action::ToggleFavorite);
+        keymap.insert(KeyCode::Char('e'), Action::Equalizer);
+        keymap.insert(KeyCode::Char('m'), Action::MovePlayback);
+        keymap.insert(KeyCode::Char('p'), Action::AddToPlaylist);
+        keymap.insert(KeyCode::Char('x'), Action::RemoveFromPlaylist);
+        keymap.insert(KeyCode::Char('n'), Action::Enqueue { next: true });
+        keymap.insert(KeyCode::Char('N'), Action::Enqueue { next: false });
+        keymap.insert(KeyCode::Char('/'), Action::FindPrompt);
+        keymap.insert(KeyCode::Char('Y'), Action::Yank);
+        keymap.insert(KeyCode::Char('O'), Action::OpenInBrowser);
+        keymap.insert(KeyCode::Char(';'), Action::NextMatch);
+        keymap.insert(KeyCode::Char(','), Action::PrevMatch);
+        keymap.insert(KeyCode::Char('v'), Action::GoToArtist);
+        keymap.insert(KeyCode::Char('V'), Action::GoToAlbum);
+        keymap.insert(KeyCode::Char('R'), Action::Reconnect);
         keymap.insert(
             KeyCode::Char('&'),
-            PlayerAction::Seek {
-                dt: 10,
-                mode: SeekMode::AbsolutePercent,
-            }
-            .into(),
+            PlayerAction::Seek(SeekMode::AbsolutePercent(10)).into(),
         );
         keymap.insert(
             KeyCode::Char('é'),
-            PlayerAction::Seek {
-                dt: 20,
-                mode: SeekMode::AbsolutePercent,
-            }
-            .into(),
+            PlayerAction::Seek(SeekMode::AbsolutePercent(20)).into(),
         );
         keymap.insert(
             KeyCode::Char('"'),
-            PlayerAction::Seek {
-                dt: 30,
-                mode: SeekMode::AbsolutePercent,
-            }
-            .into(),
+            PlayerAction::Seek(SeekMode::AbsolutePercent(30)).into(),
         );
         keymap.insert(
             KeyCode::Char('\''),
-            PlayerAction::Seek {
-                dt: 40,
-                mode: SeekMode::AbsolutePercent,
-            }
-            .into(),
+            PlayerAction::Seek(SeekMode::AbsolutePercent(40)).into(),
         );
         keymap.insert(
             KeyCode::Char('('),
-            PlayerAction::Seek {
-                dt: 50,
-                mode: SeekMode::AbsolutePercent,
-            }
-            .into(),
+            PlayerAction::Seek(SeekMode::AbsolutePercent(50)).into(),
         );
         keymap.insert(
             KeyCode::Char('-'),
-            PlayerAction::Seek {
-                dt: 60,
-                mode: SeekMode::AbsolutePercent,
-            }
-            .into(),
+            PlayerAction::Seek(SeekMode::AbsolutePercent(60)).into(),
         );
         keymap.insert(
             KeyCode::Char('è'),
-            PlayerAction::Seek {
-                dt: 70,
-                mode: SeekMode::AbsolutePercent,
-            }
-            .into(),
+            PlayerAction::Seek(SeekMode::AbsolutePercent(70)).into(),
         );
         keymap.insert(
             KeyCode::Char('_'),
-            PlayerAction::Seek {
-                dt: 80,
-                mode: SeekMode::AbsolutePercent,
-            }
-            .into(),
+            PlayerAction::Seek(SeekMode::AbsolutePercent(80)).into(),
         );
         keymap.insert(
             KeyCode::Char('ç'),
-            PlayerAction::Seek {
-                dt: 90,
-                mode: SeekMode::AbsolutePercent,
-            }
-            .into(),
+            PlayerAction::Seek(SeekMode::AbsolutePercent(90)).into(),
         );
         keymap.insert(
             KeyCode::Char('à'),
-            PlayerAction::Seek {
-                dt: 0,
-                mode: SeekMode::AbsolutePercent,
-            }
-            .into(),
+            PlayerAction::Seek(SeekMode::AbsolutePercent(0)).into(),
         );
         keymap.insert(KeyCode::Char(':'), Action::CommandPrompt);
+        for (key, tab) in ('1'..='8').zip(Tab::ALL) {
+            keymap.insert(KeyCode::Char(key), Action::SelectTab(tab));
+        }
         let dirs = get_dirs();
         let mut yt_secrets_loc: PathBuf = PathBuf::from(dirs.config_dir());
         yt_secrets_loc.push("yt_secrets.json");
@@ -165,9 +386,17 @@ impl Default for Config {
         let audio_dir = user_dirs.audio_dir().unwrap();
         Self {
             keymap,
+            macro_record_key: 'Q',
+            macro_replay_key: '@',
+            mark_set_key: 'b',
+            mark_jump_key: '`',
             yt_secret_location: format!("{}", yt_secrets_loc.display()),
             spotify_secret_location: format!("{}", spotify_secrets_loc.display()),
-            folders: vec![audio_dir.into()],
+            secret_storage: SecretStorage::default(),
+            folders: vec![audio_dir.to_path_buf()],
+            local_sources: HashMap::new(),
+            clients: client::names().map(String::from).collect(),
+            plugins: HashMap::new(),
             focused_fg: Color::Rgb(202, 211, 245),
             focused_bg: Color::Reset,
             focused_highlight_fg: Color::Rgb(202, 211, 245),
@@ -178,6 +407,67 @@ impl Default for Config {
             unfocused_highlight_bg: Color::Rgb(110, 115, 141),
             border_focus: Color::Rgb(183, 189, 248),
             border_unfocus: Color::Rgb(110, 115, 141),
+            info_fg: Color::Rgb(138, 173, 244),
+            warning_fg: Color::Rgb(238, 212, 159),
+            error_fg: Color::Rgb(237, 135, 150),
+            client_accents: HashMap::from([
+                ("local".to_string(), Color::Rgb(166, 218, 149)),
+                ("youtube".to_string(), Color::Rgb(237, 135, 150)),
+                ("spotify".to_string(), Color::Rgb(139, 213, 202)),
+            ]),
+            song_columns: vec![
+                ColumnConfig {
+                    column: SongColumn::Title,
+                    width: ColumnWidth::Percentage(45),
+                },
+                ColumnConfig {
+                    column: SongColumn::Artist,
+                    width: ColumnWidth::Percentage(35),
+                },
+                ColumnConfig {
+                    column: SongColumn::Rating,
+                    width: ColumnWidth::Length(7),
+                },
+                ColumnConfig {
+                    column: SongColumn::Duration,
+                    width: ColumnWidth::Length(9),
+                },
+                ColumnConfig {
+                    column: SongColumn::Source,
+                    width: ColumnWidth::Min(8),
+                },
+            ],
+            metadata_enrichment: false,
+            acoustid_key: String::new(),
+            idle_policies: HashMap::new(),
+            fade_duration_ms: 300,
+            max_volume: 100,
+            seek_step: 5,
+            seek_step_large: 30,
+            volume_step: 5,
+            resume_min_minutes: 20,
+            notification_timeout: 3,
+            framerate: 10.0,
+            unfocused_framerate: 1.0,
+            youtube_video: false,
+            youtube_region: String::new(),
+            spotify_release_device: false,
+            show_remaining: false,
+            http_address: "127.0.0.1:8480".to_string(),
+            http_token: String::new(),
+            download_folder: audio_dir.join("yama"),
+            offline_sync_interval: 60,
+            export_folder: audio_dir.join("playlists"),
+            export_format: PlaylistFormat::M3u,
+            shuffle_mode: ShuffleMode::Spread,
+            equalizer_presets: HashMap::from([
+                ("flat".to_string(), vec![0; 10]),
+                ("bass".to_string(), vec![6, 5, 4, 2, 0, 0, 0, 0, 0, 0]),
+                ("vocal".to_string(), vec![-2, -2, -1, 0, 2, 4, 4, 2, 0, -1]),
+                ("treble".to_string(), vec![0, 0, 0, 0, 0, 0, 2, 4, 5, 6]),
+            ]),
+            layout: LayoutConfig::default(),
+            logging: LogConfig::default(),
         }
     }
 }
@@ -186,6 +476,22 @@ pub fn get_config() -> Config {
     confy::load("yamav3", None).unwrap_or_default()
 }
 
+/// Apply `update` to the configuration file, keeping the settings changed at runtime,
+/// a file which cannot be read is left untouched rather than replaced by the defaults
+pub fn update_config(update: impl FnOnce(&mut Config)) {
+    let mut config: Config = match confy::load("yamav3", None) {
+        Ok(config) => config,
+        Err(err) => {
+            error!("Not saving the configuration as it cannot be read: {err}");
+            return;
+        }
+    };
+    update(&mut config);
+    if let Err(err) = confy::store("yamav3", None, config) {
+        error!("Failed to save the configuration: {err}");
+    }
+}
+
 pub fn get_dirs() -> ProjectDirs {
     // TODO do something better or not
     ProjectDirs::from("com", "sofamaniac", "yamav3").unwrap()
@@ -0,0 +1,207 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::Result;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Semaphore;
+
+use crate::{config, tui::base64};
+
+/// Escape sequences used to draw images in the terminal
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ImageProtocol {
+    /// guess the protocol from the environment of the terminal
+    #[default]
+    Auto,
+    /// no image is drawn
+    None,
+    /// graphics protocol of kitty, only PNG images can be displayed
+    Kitty,
+    /// inline images of iTerm2, also understood by WezTerm
+    Iterm,
+}
+
+impl ImageProtocol {
+    /// Resolve [ImageProtocol::Auto] to the protocol supported by the terminal
+    pub fn detect(self) -> Self {
+        if self != Self::Auto {
+            return self;
+        }
+        let term_program = std::env::var("TERM_PROGRAM").unwrap_or_default();
+        if std::env::var_os("KITTY_WINDOW_ID").is_some() {
+            Self::Kitty
+        } else if term_program == "iTerm.app" || term_program == "WezTerm" {
+            Self::Iterm
+        } else {
+            Self::None
+        }
+    }
+
+    /// Sequence drawing the image at `path` over `cols` x `rows` cells starting
+    /// at the position of the cursor, `None` if the image cannot be displayed
+    pub fn sequence(self, path: &Path, cols: u16, rows: u16) -> Option<String> {
+        match self {
+            Self::Kitty => {
+                let data = fs::read(path).ok()?;
+                if !data.starts_with(b"\x89PNG") {
+                    return None;
+                }
+                let path = path.to_str()?;
+                Some(format!(
+                    "\x1b_Ga=T,f=100,t=f,c={cols},r={rows},q=2;{}\x1b\\",
+                    base64(path.as_bytes())
+                ))
+            }
+            Self::Iterm => {
+                let data = fs::read(path).ok()?;
+                Some(format!(
+                    "\x1b]1337;File=inline=1;width={cols};height={rows};preserveAspectRatio=1:{}\x07",
+                    base64(&data)
+                ))
+            }
+            Self::Auto | Self::None => None,
+        }
+    }
+
+    /// Sequence removing the images drawn by [ImageProtocol::sequence], iTerm2 images are
+    /// part of the cells and disappear once the cells are redrawn
+    pub fn clear(self) -> Option<&'static str> {
+        match self {
+            Self::Kitty => Some("\x1b_Ga=d,q=2\x1b\\"),
+            _ => None,
+        }
+    }
+}
+
+/// Local file holding the cover at `url` if there is one, covers of remote urls are
+/// available once [fetch] has downloaded them
+pub fn path(url: &str) -> Option<PathBuf> {
+    if url.is_empty() {
+        return None;
+    }
+    let path = match url.strip_prefix("file://") {
+        Some(path) => PathBuf::from(path),
+        None if url.starts_with('/') => PathBuf::from(url),
+        None => cache_path(url),
+    };
+    path.is_file().then_some(path)
+}
+
+/// The cover at `url` is not on disk and must be downloaded
+fn is_remote(url: &str) -> bool {
+    (url.starts_with("http://") || url.starts_with("https://")) && path(url).is_none()
+}
+
+/// FNV-1a hash of `data`, unlike the hasher of the standard library
+/// it does not change between releases
+fn stable_hash(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Covers are named after the hash of their url
+fn cache_path(url: &str) -> PathBuf {
+    let mut path = config::get_dirs().cache_dir().to_path_buf();
+    path.push("covers");
+    path.push(format!("{:016x}", stable_hash(url.as_bytes())));
+    path
+}
+
+/// Downloads the remote covers in the background, each url is only requested
+/// once unless its download fails
+#[derive(Debug, Clone)]
+pub struct Fetcher {
+    /// urls requested and not failed
+    requested: Arc<Mutex<HashSet<String>>>,
+    /// limits the number of simultaneous downloads
+    permits: Arc<Semaphore>,
+    #[cfg(feature = "covers")]
+    client: reqwest::Client,
+}
+
+impl Fetcher {
+    /// number of covers downloaded at the same time
+    const MAX_DOWNLOADS: usize = 4;
+    /// time before the download of a cover that failed is tried again
+    const RETRY_DELAY: Duration = Duration::from_secs(60);
+    #[cfg(feature = "covers")]
+    const TIMEOUT: Duration = Duration::from_secs(30);
+    /// covers larger than this are not downloaded
+    #[cfg(feature = "covers")]
+    const MAX_SIZE: u64 = 10 * 1024 * 1024;
+
+    pub fn new() -> Self {
+        Self {
+            requested: Arc::default(),
+            permits: Arc::new(Semaphore::new(Self::MAX_DOWNLOADS)),
+            #[cfg(feature = "covers")]
+            client: reqwest::Client::builder()
+                .timeout(Self::TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Download the cover at `url` in the background unless it is already on disk or requested
+    pub fn request(&self, url: &str) {
+        if !is_remote(url) {
+            return;
+        }
+        let mut requested = self.requested.lock().unwrap_or_else(|err| err.into_inner());
+        if !requested.insert(url.to_string()) {
+            return;
+        }
+        let fetcher = self.clone();
+        let url = url.to_string();
+        tokio::spawn(async move {
+            let result = match fetcher.permits.acquire().await {
+                Ok(_permit) => fetcher.fetch(&url).await,
+                Err(err) => Err(err.into()),
+            };
+            if let Err(err) = result {
+                debug!("Failed to fetch the cover {url}: {err}");
+                tokio::time::sleep(Self::RETRY_DELAY).await;
+                let mut requested = fetcher
+                    .requested
+                    .lock()
+                    .unwrap_or_else(|err| err.into_inner());
+                requested.remove(&url);
+            }
+        });
+    }
+
+    /// Download the cover at `url` in the cache
+    #[cfg(feature = "covers")]
+    async fn fetch(&self, url: &str) -> Result<()> {
+        let mut response = self.client.get(url).send().await?.error_for_status()?;
+        if response.content_length().unwrap_or_default() > Self::MAX_SIZE {
+            anyhow::bail!("The cover is larger than {} bytes", Self::MAX_SIZE);
+        }
+        let mut data = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            data.extend_from_slice(&chunk);
+            if data.len() as u64 > Self::MAX_SIZE {
+                anyhow::bail!("The cover is larger than {} bytes", Self::MAX_SIZE);
+            }
+        }
+        let path = cache_path(url);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, data)?;
+        Ok(())
+    }
+
+    /// Remote covers are not downloaded without the `covers` feature
+    #[cfg(not(feature = "covers"))]
+    async fn fetch(&self, url: &str) -> Result<()> {
+        anyhow::bail!("Cannot download {url}, yama was built without the covers feature")
+    }
+}
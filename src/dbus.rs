@@ -1,17 +1,19 @@
 use anyhow::Result;
 use log::debug;
 use std::collections::hash_map::DefaultHasher;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::hash::{Hash, Hasher};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::sync::watch;
 use zbus::zvariant::{ObjectPath, Value};
-use zbus::{dbus_interface, zvariant, ConnectionBuilder};
+use zbus::{dbus_interface, fdo, zvariant, ConnectionBuilder, SignalContext};
 
 use crate::client::interface::{
-    Playback, PlayerAction, PlayerInfo, Repeat, SeekMode, SongInfo, Volume,
+    Playback, PlayerAction, PlayerInfo, Repeat, SeekMode, SongInfo, Volume, MAX_SPEED, MIN_SPEED,
 };
 use crate::orchestrator::{Action, MyEvents};
+use crate::remote::RemoteState;
 
 /// Create [ObjectPath] from `song`, note that the DBus specification asks
 /// that trackid be unique for each entrie in a tracklist, including duplicates
@@ -24,6 +26,61 @@ fn make_trackid(song: &SongInfo) -> ObjectPath {
     ObjectPath::try_from(format!("/org/mpris/MediaPlayer2/TrackList/{}", trackid)).unwrap()
 }
 
+/// trackid used when there is no track, as defined by the specification
+fn no_track() -> ObjectPath<'static> {
+    ObjectPath::from_static_str_unchecked("/org/mpris/MediaPlayer2/TrackList/NoTrack")
+}
+
+/// Difference between the expected and the reported position above which
+/// the position is considered to have jumped
+const SEEK_TOLERANCE: Duration = Duration::from_secs(1);
+
+/// Returns `true` if the position of `new` cannot be explained by the playback
+/// progressing normally since `old` was received `elapsed` ago, a change of track
+/// is not a jump as the new track announces its own position
+fn has_jumped(old: &PlayerInfo, new: &PlayerInfo, elapsed: Duration) -> bool {
+    // the trackid is made from the id of the song
+    if old.song_info.as_ref().map(|song| &song.id) != new.song_info.as_ref().map(|song| &song.id) {
+        return false;
+    }
+    let expected = if old.playback == Playback::Play {
+        let speed = old.speed.unwrap_or(100);
+        old.position + elapsed * u32::from(speed) / 100
+    } else {
+        old.position
+    };
+    new.position.abs_diff(expected) > SEEK_TOLERANCE
+}
+
+/// Returns `true` if the current song can be sought
+fn can_seek(state: &PlayerInfo) -> bool {
+    state.can_seek && state.song_info.is_some()
+}
+
+/// Returns `true` if a next song is expected to play after calling Next
+fn can_go_next(state: &PlayerInfo) -> bool {
+    let len = state.tracklist.songs.len();
+    match state.track_index {
+        Some(index) => index + 1 < len || (len > 0 && state.repeat == Repeat::Playlist),
+        None => len > 0,
+    }
+}
+
+/// Returns `true` if a song is expected to play after calling Previous
+fn can_go_previous(state: &PlayerInfo) -> bool {
+    !state.tracklist.songs.is_empty()
+}
+
+/// Returns `true` if there is something to play
+fn can_play(state: &PlayerInfo) -> bool {
+    state.song_info.is_some() || !state.tracklist.songs.is_empty()
+}
+
+/// Returns `true` if a song is loaded
+fn can_pause(state: &PlayerInfo) -> bool {
+    state.song_info.is_some()
+}
+
 fn make_metadata(song: &SongInfo) -> HashMap<&str, Value> {
     let mut res = HashMap::new();
     res.insert("mpris:trackid", make_trackid(song).into());
@@ -33,6 +90,7 @@ fn make_metadata(song: &SongInfo) -> HashMap<&str, Value> {
     );
     res.insert("xesam:title", Value::Str(song.title.clone().into()));
     res.insert("xesam:artist", Value::Str(song.artist.clone().into()));
+    res.insert("xesam:album", Value::Str(song.album.clone().into()));
     res.insert("xesam:url", Value::Str(song.url.clone().into()));
     res.insert("mpris:artUrl", Value::Str(song.cover_url.clone().into()));
 
@@ -105,17 +163,56 @@ impl TrackListInterface {
     const fn add_track(&self) {}
     const fn remove_track(&self) {}
 
-    const fn go_to(&self) {}
+    async fn go_to(&self, track_id: zvariant::ObjectPath<'_>) {
+        let index = self
+            .state
+            .tracklist
+            .songs
+            .iter()
+            .position(|s| make_trackid(s) == track_id);
+        if let Some(index) = index {
+            let _ = self
+                .sender
+                .send(PlayerAction::PlayIndex(index).into())
+                .await;
+        }
+    }
 
     #[dbus_interface(property)]
     async fn tracks(&self) -> Vec<zvariant::ObjectPath> {
-        // as per recommendation of the specification
-        // limit the number of items returned to 20
+        self.window()
+    }
+
+    #[dbus_interface(property)]
+    const fn can_edit_tracks(&self) -> bool {
+        false
+    }
+
+    #[dbus_interface(signal)]
+    async fn track_list_replaced(
+        ctxt: &SignalContext<'_>,
+        tracks: Vec<ObjectPath<'_>>,
+        current_track: ObjectPath<'_>,
+    ) -> zbus::Result<()>;
+
+    #[dbus_interface(signal)]
+    async fn track_added(
+        ctxt: &SignalContext<'_>,
+        metadata: HashMap<&str, Value<'_>>,
+        after_track: ObjectPath<'_>,
+    ) -> zbus::Result<()>;
+
+    #[dbus_interface(signal)]
+    async fn track_removed(ctxt: &SignalContext<'_>, track_id: ObjectPath<'_>) -> zbus::Result<()>;
+}
+
+impl TrackListInterface {
+    /// Tracks exposed on the bus, as per recommendation of the specification
+    /// limit the number of items returned to 20
+    fn window(&self) -> Vec<ObjectPath> {
         if let Some(start) = self.state.track_index {
-            // not sure if necessary to compute the end or if it cannot go
-            // outside of range by default
             let end = (start + 20).min(self.state.tracklist.songs.len());
-            self.state.tracklist.songs[start..end]
+            self.state.tracklist.songs[start.min(end)..end]
                 .iter()
                 .map(|s| make_trackid(s))
                 .collect()
@@ -124,12 +221,38 @@ impl TrackListInterface {
         }
     }
 
-    #[dbus_interface(property)]
-    const fn can_edit_tracks(&self) -> bool {
-        false
+    /// Emit the signals describing the changes from `old` to the current state
+    async fn notify_changes(&self, old: &PlayerInfo, ctxt: &SignalContext<'_>) -> zbus::Result<()> {
+        let new = &self.state;
+        if old.tracklist.id != new.tracklist.id {
+            let current = new.song_info.as_ref().map_or(no_track(), make_trackid);
+            return Self::track_list_replaced(ctxt, self.window(), current).await;
+        }
+        if old.tracklist.songs != new.tracklist.songs {
+            let old_ids: HashSet<ObjectPath> =
+                old.tracklist.songs.iter().map(make_trackid).collect();
+            let new_ids: Vec<ObjectPath> = new.tracklist.songs.iter().map(make_trackid).collect();
+            let new_set: HashSet<&ObjectPath> = new_ids.iter().collect();
+            for id in old_ids.iter().filter(|id| !new_set.contains(id)) {
+                Self::track_removed(ctxt, id.clone()).await?;
+            }
+            for (i, song) in new.tracklist.songs.iter().enumerate() {
+                if !old_ids.contains(&new_ids[i]) {
+                    let after = if i == 0 {
+                        no_track()
+                    } else {
+                        new_ids[i - 1].clone()
+                    };
+                    Self::track_added(ctxt, make_metadata(song), after).await?;
+                }
+            }
+        }
+        if old.track_index != new.track_index {
+            // the tracks exposed depend on the current one
+            self.tracks_changed(ctxt).await?;
+        }
+        Ok(())
     }
-
-    // TODO: send signal when tracklist has been replaced
 }
 
 pub struct PlayerInterface {
@@ -160,7 +283,7 @@ impl PlayerInterface {
     async fn play(&self) {
         let _ = self
             .sender
-            .send(PlayerAction::PlayPause(self.state.playback == Playback::Pause).into())
+            .send(PlayerAction::PlayPause(false).into())
             .await;
     }
     async fn stop(&self) {
@@ -168,41 +291,24 @@ impl PlayerInterface {
     }
     /// seek to current position + `offset` with `offset` in microseconds
     async fn seek(&self, offset: i64) {
-        let offset = offset / 1_000_000;
-        let _ = self
-            .sender
-            .send(
-                PlayerAction::Seek {
-                    dt: offset,
-                    mode: SeekMode::Relative,
-                }
-                .into(),
-            )
-            .await;
+        let offset = offset / 1_000;
+        let action = PlayerAction::Seek(SeekMode::Relative(offset));
+        let _ = self.sender.send(action.into()).await;
     }
     /// `position` is in microseconds, ignore if `trackid` is different
     /// from the currently playing `trackid`
     async fn set_position(&self, trackid: ObjectPath<'_>, position: i64) {
         if let Some(song) = self.state.song_info.as_ref() {
-            // position in seconds
-            let position = position / 1_000_000;
             if position < 0
-                || Duration::from_secs(position as u64) > song.duration
+                || Duration::from_micros(position as u64) > song.duration
                 || trackid != make_trackid(song)
             {
                 // ignore if position is not in range
                 // or if the track id does not match
             } else {
-                let _ = self
-                    .sender
-                    .send(
-                        PlayerAction::Seek {
-                            dt: position,
-                            mode: SeekMode::Absolute,
-                        }
-                        .into(),
-                    )
-                    .await;
+                let position = Duration::from_micros(position as u64);
+                let action = PlayerAction::Seek(SeekMode::Absolute(position));
+                let _ = self.sender.send(action.into()).await;
             }
         }
     }
@@ -224,16 +330,29 @@ impl PlayerInterface {
     }
 
     #[dbus_interface(property)]
-    const fn rate(&self) -> f32 {
-        1.0
+    fn rate(&self) -> f64 {
+        f64::from(self.state.speed.unwrap_or(100)) / 100.0
+    }
+    #[dbus_interface(property)]
+    async fn set_rate(&self, val: f64) {
+        // the specification asks to pause rather than setting a null rate
+        let action = if val <= 0.0 {
+            PlayerAction::PlayPause(true)
+        } else {
+            PlayerAction::SetSpeed((val * 100.0).round() as u16)
+        };
+        let _ = self.sender.send(action.into()).await;
     }
+    /// the rate cannot change unless the player reports its speed
     #[dbus_interface(property)]
-    const fn maximum_rate(&self) -> f32 {
-        1.0
+    fn maximum_rate(&self) -> f64 {
+        let speed = self.state.speed.map_or(100, |_| MAX_SPEED);
+        f64::from(speed) / 100.0
     }
     #[dbus_interface(property)]
-    const fn minimum_rate(&self) -> f32 {
-        1.0
+    fn minimum_rate(&self) -> f64 {
+        let speed = self.state.speed.map_or(100, |_| MIN_SPEED);
+        f64::from(speed) / 100.0
     }
     #[dbus_interface(property)]
     fn shuffle(&self) -> bool {
@@ -245,7 +364,8 @@ impl PlayerInterface {
     }
     #[dbus_interface(property)]
     async fn set_volume(&self, val: f64) {
-        let target: usize = ((val * 100.0) as usize).min(100);
+        // the player caps the volume to its maximum, which may be above 100
+        let target = (val.max(0.0) * 100.0) as usize;
         let _ = self
             .sender
             .send(PlayerAction::SetVolume(Volume::Absolute(target)).into())
@@ -265,36 +385,112 @@ impl PlayerInterface {
     }
 
     #[dbus_interface(property)]
-    const fn can_go_next(&self) -> bool {
-        true
+    fn can_go_next(&self) -> bool {
+        can_go_next(&self.state)
     }
     #[dbus_interface(property)]
-    const fn can_go_previous(&self) -> bool {
-        true
+    fn can_go_previous(&self) -> bool {
+        can_go_previous(&self.state)
     }
     #[dbus_interface(property)]
-    const fn can_play(&self) -> bool {
-        true
+    fn can_play(&self) -> bool {
+        can_play(&self.state)
     }
     #[dbus_interface(property)]
-    const fn can_pause(&self) -> bool {
-        true
+    fn can_pause(&self) -> bool {
+        can_pause(&self.state)
     }
     #[dbus_interface(property)]
-    const fn can_seek(&self) -> bool {
-        true
+    fn can_seek(&self) -> bool {
+        can_seek(&self.state)
     }
+    /// the specification forbids this property from changing
     #[dbus_interface(property)]
     const fn can_control(&self) -> bool {
         true
     }
+
+    /// `position` is the new position in microseconds
+    #[dbus_interface(signal)]
+    async fn seeked(ctxt: &SignalContext<'_>, position: i64) -> zbus::Result<()>;
+}
+
+/// Interface specific to yama, giving scripts access to the library and the commands
+struct ControlInterface {
+    state: watch::Receiver<RemoteState>,
+    sender: Sender<MyEvents>,
 }
 
-pub async fn start(sender: Sender<MyEvents>, receiver: &mut Receiver<PlayerInfo>) -> Result<()> {
+#[dbus_interface(name = "org.yama.Control")]
+impl ControlInterface {
+    /// names of the enabled sources
+    fn list_sources(&self) -> Vec<String> {
+        self.state.borrow().sources.clone()
+    }
+
+    /// id, title and number of songs of each playlist of the selected source
+    fn list_playlists(&self) -> Vec<(String, String, u32)> {
+        self.state
+            .borrow()
+            .playlists
+            .iter()
+            .map(|playlist| {
+                let length = u32::try_from(playlist.length).unwrap_or(u32::MAX);
+                (playlist.id.clone(), playlist.title.clone(), length)
+            })
+            .collect()
+    }
+
+    /// select the source `name` in the library, its playlists are listed once loaded
+    async fn select_source(&self, name: String) -> fdo::Result<()> {
+        if !self.state.borrow().sources.contains(&name) {
+            return Err(fdo::Error::InvalidArgs(format!("{name} is not enabled")));
+        }
+        self.run_command(format!("source {name}")).await
+    }
+
+    /// select the playlist `id` of the selected source in the library
+    async fn select_playlist(&self, id: String) -> fdo::Result<()> {
+        let known = self.state.borrow().playlists.iter().any(|p| p.id == id);
+        if !known {
+            return Err(fdo::Error::InvalidArgs(format!("No playlist {id}")));
+        }
+        self.run_command(format!("open {id}")).await
+    }
+
+    /// run `command` as if it was typed in the prompt
+    async fn run_command(&self, command: String) -> fdo::Result<()> {
+        self.sender
+            .send(MyEvents::Command(command))
+            .await
+            .map_err(|_| fdo::Error::Failed("yama is quitting".to_string()))
+    }
+
+    /// sources, player, tracklist and playlists as a JSON object,
+    /// positions and durations are in milliseconds
+    fn get_state(&self) -> String {
+        self.state.borrow().full_json().to_string()
+    }
+
+    #[dbus_interface(property)]
+    fn selected_source(&self) -> String {
+        self.state.borrow().selected.clone().unwrap_or_default()
+    }
+}
+
+pub async fn start(
+    sender: Sender<MyEvents>,
+    receiver: &mut Receiver<PlayerInfo>,
+    state: watch::Receiver<RemoteState>,
+) -> Result<()> {
     debug!("Starting dbus");
     let base = BaseInterface {
         sender: sender.clone(),
     };
+    let control = ControlInterface {
+        state,
+        sender: sender.clone(),
+    };
     let player = PlayerInterface {
         sender: sender.clone(),
         state: PlayerInfo::default(),
@@ -304,11 +500,13 @@ pub async fn start(sender: Sender<MyEvents>, receiver: &mut Receiver<PlayerInfo>
         state: PlayerInfo::default(),
     };
     let mut old_state = PlayerInfo::default();
+    let mut last_update = Instant::now();
     let conn = ConnectionBuilder::session()?
         .name("org.mpris.MediaPlayer2.yama")?
         .serve_at("/org/mpris/MediaPlayer2", base)?
         .serve_at("/org/mpris/MediaPlayer2", player)?
         .serve_at("/org/mpris/MediaPlayer2", tracklist)?
+        .serve_at("/org/yama/Control", control)?
         .build()
         .await?;
     let player_iface_ref = conn
@@ -317,7 +515,7 @@ pub async fn start(sender: Sender<MyEvents>, receiver: &mut Receiver<PlayerInfo>
         .await?;
     let tracklist_iface_ref = conn
         .object_server()
-        .interface::<_, PlayerInterface>("/org/mpris/MediaPlayer2")
+        .interface::<_, TrackListInterface>("/org/mpris/MediaPlayer2")
         .await?;
     // run until the connection is closed
     while let Some(state) = receiver.recv().await {
@@ -346,13 +544,41 @@ pub async fn start(sender: Sender<MyEvents>, receiver: &mut Receiver<PlayerInfo>
         if old_state.volume != state.volume {
             player_iface.volume_changed(context).await?;
         }
-        old_state = state.clone();
+        if old_state.speed != state.speed {
+            player_iface.rate_changed(context).await?;
+        }
+        if old_state.speed.is_some() != state.speed.is_some() {
+            player_iface.minimum_rate_changed(context).await?;
+            player_iface.maximum_rate_changed(context).await?;
+        }
+        if can_go_next(&old_state) != can_go_next(&state) {
+            player_iface.can_go_next_changed(context).await?;
+        }
+        if can_go_previous(&old_state) != can_go_previous(&state) {
+            player_iface.can_go_previous_changed(context).await?;
+        }
+        if can_play(&old_state) != can_play(&state) {
+            player_iface.can_play_changed(context).await?;
+        }
+        if can_pause(&old_state) != can_pause(&state) {
+            player_iface.can_pause_changed(context).await?;
+        }
+        if can_seek(&old_state) != can_seek(&state) {
+            player_iface.can_seek_changed(context).await?;
+        }
+        if state.song_info.is_some() && has_jumped(&old_state, &state, last_update.elapsed()) {
+            let position = state.position.as_micros() as i64;
+            PlayerInterface::seeked(context, position).await?;
+        }
+        last_update = Instant::now();
         // /!\ MUST be dropped before accessing interface
         drop(player_iface);
         let mut tracklist_iface = tracklist_iface_ref.get_mut().await;
         tracklist_iface.state = state.clone();
-        // TODO send tracklistchanged signal when necessary
+        let context = tracklist_iface_ref.signal_context();
+        tracklist_iface.notify_changes(&old_state, context).await?;
         drop(tracklist_iface);
+        old_state = state;
     }
     Ok(())
 }
@@ -0,0 +1,103 @@
+#[cfg(any(feature = "youtube", feature = "spotify"))]
+use std::time::Duration;
+
+#[cfg(any(feature = "youtube", feature = "spotify"))]
+use anyhow::bail;
+use anyhow::Result;
+#[cfg(any(feature = "youtube", feature = "spotify"))]
+use tokio::net::TcpStream;
+
+use crate::config::Config;
+
+/// delay before considering a host unreachable
+#[cfg(any(feature = "youtube", feature = "spotify"))]
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Outcome of a single verification of `yama doctor`
+pub struct Check {
+    pub name: String,
+    /// details on success, cause of the failure otherwise
+    pub result: Result<String>,
+}
+
+impl Check {
+    pub fn new(name: impl Into<String>, result: Result<String>) -> Self {
+        Self {
+            name: name.into(),
+            result,
+        }
+    }
+}
+
+/// Check that a TCP connection can be opened to the HTTPS port of `host`,
+/// the TLS handshake is left to the clients
+#[cfg(any(feature = "youtube", feature = "spotify"))]
+pub async fn reachable(host: &str) -> Result<String> {
+    match tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect((host, 443))).await {
+        Ok(Ok(stream)) => Ok(format!("{host} reachable at {}", stream.peer_addr()?)),
+        Ok(Err(err)) => bail!("cannot connect to {host}: {err}"),
+        Err(_) => bail!("connection to {host} timed out"),
+    }
+}
+
+fn check_config() -> Check {
+    let result = confy::get_configuration_file_path("yamav3", None)
+        .map_err(anyhow::Error::from)
+        .and_then(|path| {
+            if !path.exists() {
+                return Ok(format!("{} not found, using defaults", path.display()));
+            }
+            confy::load::<Config>("yamav3", None)?;
+            Ok(format!("{} is valid", path.display()))
+        });
+    Check::new("config", result)
+}
+
+#[cfg(any(feature = "mpris", feature = "logind"))]
+async fn check_dbus() -> Vec<Check> {
+    let mut checks = Vec::new();
+    #[cfg(feature = "mpris")]
+    {
+        let result = zbus::Connection::session()
+            .await
+            .map(|conn| format!("connected as {:?}", conn.unique_name()));
+        checks.push(Check::new("dbus session bus", result.map_err(Into::into)));
+    }
+    #[cfg(feature = "logind")]
+    {
+        let result = zbus::Connection::system()
+            .await
+            .map(|conn| format!("connected as {:?}", conn.unique_name()));
+        checks.push(Check::new("dbus system bus", result.map_err(Into::into)));
+    }
+    checks
+}
+
+/// Verify the environment required by the enabled features and print a report,
+/// returns `true` if every check passed
+pub async fn run() -> bool {
+    #[allow(unused_mut)]
+    let mut checks = vec![check_config()];
+    #[cfg(feature = "mpv")]
+    checks.push(crate::client::mpv::diagnose());
+    #[cfg(any(feature = "mpris", feature = "logind"))]
+    checks.extend(check_dbus().await);
+    #[cfg(feature = "local")]
+    checks.extend(crate::client::local::diagnose());
+    #[cfg(feature = "youtube")]
+    checks.extend(crate::client::youtube::diagnose().await);
+    #[cfg(feature = "spotify")]
+    checks.extend(crate::client::spotify::diagnose().await);
+
+    let mut healthy = true;
+    for check in checks {
+        match check.result {
+            Ok(details) => println!("[ OK ] {}: {details}", check.name),
+            Err(err) => {
+                healthy = false;
+                println!("[FAIL] {}: {err:#}", check.name)
+            }
+        }
+    }
+    healthy
+}
@@ -0,0 +1,107 @@
+use std::{
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{client::interface::SongInfo, config, json_store};
+
+/// Song played, as recorded in the history
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub song: SongInfo,
+    /// name of the client that played the song
+    pub source: String,
+    /// seconds since the unix epoch when the song started playing
+    pub played_at: u64,
+    /// furthest position reached in the song, in percent
+    pub completion: u8,
+}
+
+impl HistoryEntry {
+    pub fn new(song: SongInfo, source: String) -> Self {
+        let played_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        Self {
+            song,
+            source,
+            played_at,
+            completion: 0,
+        }
+    }
+
+    /// Keep the furthest position reached in the song
+    pub fn update_completion(&mut self, position: Duration) {
+        if self.song.duration.is_zero() {
+            return;
+        }
+        let percent = position.as_secs_f64() / self.song.duration.as_secs_f64() * 100.;
+        self.completion = self.completion.max(percent.min(100.) as u8);
+    }
+
+    /// Time elapsed since the song was played, e.g. `5m ago`
+    pub fn age(&self) -> String {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let secs = now.saturating_sub(self.played_at);
+        match secs {
+            0..=59 => format!("{secs}s ago"),
+            60..=3599 => format!("{}m ago", secs / 60),
+            3600..=86399 => format!("{}h ago", secs / 3600),
+            _ => format!("{}d ago", secs / 86400),
+        }
+    }
+
+    /// returns `true` if the title, artist or album of the song contains `query`, ignoring case
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.to_lowercase();
+        [&self.song.title, &self.song.artist, &self.song.album]
+            .iter()
+            .any(|field| field.to_lowercase().contains(&query))
+    }
+}
+
+/// Songs played by any client, saved on disk, the most recent last
+#[derive(Debug, Default)]
+pub struct History {
+    entries: Vec<HistoryEntry>,
+    path: PathBuf,
+}
+
+impl History {
+    /// number of entries kept, the oldest ones are dropped first
+    const MAX_ENTRIES: usize = 1000;
+
+    pub fn load() -> Self {
+        let mut path = config::get_dirs().data_dir().to_path_buf();
+        path.push("history.json");
+        let entries = json_store::load(&path);
+        Self { entries, path }
+    }
+
+    pub fn push(&mut self, entry: HistoryEntry) {
+        self.entries.push(entry);
+        let excess = self.entries.len().saturating_sub(Self::MAX_ENTRIES);
+        self.entries.drain(..excess);
+        self.save();
+    }
+
+    /// Entries matching `query`, the most recent first
+    pub fn search(&self, query: &str) -> Vec<HistoryEntry> {
+        self.entries
+            .iter()
+            .rev()
+            .filter(|entry| entry.matches(query))
+            .cloned()
+            .collect()
+    }
+
+    fn save(&self) {
+        json_store::save(&self.path, &self.entries);
+    }
+}
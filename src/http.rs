@@ -0,0 +1,216 @@
+use std::{convert::Infallible, net::SocketAddr, sync::Arc};
+
+use anyhow::{bail, Result};
+use futures::{SinkExt, StreamExt};
+use hyper::{
+    header,
+    service::{make_service_fn, service_fn},
+    upgrade::Upgraded,
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use log::{debug, error};
+use serde_json::{json, Value};
+use tokio::sync::{mpsc::Sender, watch};
+use tokio_tungstenite::{
+    tungstenite::{handshake::derive_accept_key, protocol::Role, Message},
+    WebSocketStream,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    client::interface::PlayerAction,
+    config,
+    orchestrator::MyEvents,
+    remote::{parse_volume, RemoteState},
+};
+
+/// Serve the remote control api on the configured address until `cancel_token` is cancelled
+pub async fn start(
+    sender: Sender<MyEvents>,
+    state: watch::Receiver<RemoteState>,
+    cancel_token: CancellationToken,
+) -> Result<()> {
+    let config = config::get_config();
+    let address: SocketAddr = match config.http_address.parse() {
+        Ok(address) => address,
+        Err(err) => {
+            error!("[Http] Invalid address: {err}");
+            return Err(err.into());
+        }
+    };
+    // anyone able to reach the api could control the player
+    if config.http_token.is_empty() && !address.ip().is_loopback() {
+        error!("[Http] Refusing to listen on {address} without an http_token");
+        bail!("an http_token is required to listen on {address}");
+    }
+    let token: Arc<str> = config.http_token.into();
+    debug!("Starting http server on {address}");
+    let make_service = make_service_fn(move |_| {
+        let sender = sender.clone();
+        let state = state.clone();
+        let token = token.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                handle(request, sender.clone(), state.clone(), token.clone())
+            }))
+        }
+    });
+    let server = match Server::try_bind(&address) {
+        Ok(server) => server,
+        Err(err) => {
+            error!("[Http] Cannot listen on {address}: {err}");
+            return Err(err.into());
+        }
+    };
+    server
+        .serve(make_service)
+        .with_graceful_shutdown(async move { cancel_token.cancelled().await })
+        .await?;
+    Ok(())
+}
+
+async fn handle(
+    request: Request<Body>,
+    sender: Sender<MyEvents>,
+    state: watch::Receiver<RemoteState>,
+    token: Arc<str>,
+) -> Result<Response<Body>, Infallible> {
+    if !is_authorized(&request, &token) {
+        return Ok(status(StatusCode::UNAUTHORIZED));
+    }
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    debug!("[Http] {method} {path}");
+    let event: MyEvents = match (&method, path.as_str()) {
+        (&Method::GET, "/state") => return Ok(json(state.borrow().player_json())),
+        (&Method::GET, "/playlists") => return Ok(json(state.borrow().playlists_json())),
+        (&Method::GET, "/events") => return Ok(upgrade(request, state)),
+        // the flag of PlayPause tells whether the playback ends up paused, on every backend
+        (&Method::POST, "/play") => PlayerAction::PlayPause(false).into(),
+        (&Method::POST, "/pause") => PlayerAction::PlayPause(true).into(),
+        (&Method::POST, "/toggle") => PlayerAction::PlayPauseToggle.into(),
+        (&Method::POST, "/stop") => PlayerAction::Stop.into(),
+        (&Method::POST, "/next") => PlayerAction::Next.into(),
+        (&Method::POST, "/previous") => PlayerAction::Prev.into(),
+        (&Method::POST, "/seek") => {
+            // same syntax as the seek command
+            let target = read_body(request).await;
+            MyEvents::Command(format!("seek {}", target.trim()))
+        }
+        (&Method::POST, "/volume") => match parse_volume(read_body(request).await.trim()) {
+            Some(volume) => PlayerAction::SetVolume(volume).into(),
+            None => return Ok(status(StatusCode::BAD_REQUEST)),
+        },
+        (&Method::POST, "/mute") => PlayerAction::MuteToggle.into(),
+        (&Method::GET | &Method::POST, _) => return Ok(status(StatusCode::NOT_FOUND)),
+        _ => return Ok(status(StatusCode::METHOD_NOT_ALLOWED)),
+    };
+    if sender.send(event).await.is_err() {
+        // the orchestrator has quit
+        return Ok(status(StatusCode::SERVICE_UNAVAILABLE));
+    }
+    Ok(status(StatusCode::NO_CONTENT))
+}
+
+/// Upgrade the connection to a websocket on which the changes of the state are pushed
+fn upgrade(mut request: Request<Body>, state: watch::Receiver<RemoteState>) -> Response<Body> {
+    let accept = match request.headers().get(header::SEC_WEBSOCKET_KEY) {
+        Some(key) => derive_accept_key(key.as_bytes()),
+        None => return status(StatusCode::BAD_REQUEST),
+    };
+    let on_upgrade = hyper::upgrade::on(&mut request);
+    tokio::spawn(async move {
+        match on_upgrade.await {
+            Ok(upgraded) => {
+                let socket = WebSocketStream::from_raw_socket(upgraded, Role::Server, None).await;
+                if let Err(err) = stream_events(socket, state).await {
+                    debug!("[Http] Websocket closed: {err}");
+                }
+            }
+            Err(err) => error!("[Http] Websocket upgrade failed: {err}"),
+        }
+    });
+    Response::builder()
+        .status(StatusCode::SWITCHING_PROTOCOLS)
+        .header(header::CONNECTION, "Upgrade")
+        .header(header::UPGRADE, "websocket")
+        .header(header::SEC_WEBSOCKET_ACCEPT, accept)
+        .body(Body::empty())
+        .unwrap_or_default()
+}
+
+/// Send the state of the player and its tracklist each time they change,
+/// until the client closes the connection
+async fn stream_events(
+    mut socket: WebSocketStream<Upgraded>,
+    mut state: watch::Receiver<RemoteState>,
+) -> Result<()> {
+    let mut last_player = Value::Null;
+    let mut last_tracklist = Value::Null;
+    loop {
+        let (player, tracklist) = {
+            let state = state.borrow_and_update();
+            (state.player_json(), state.tracklist_json())
+        };
+        if tracklist != last_tracklist {
+            let event = json!({"type": "tracklist", "data": tracklist});
+            socket.send(Message::Text(event.to_string())).await?;
+            last_tracklist = tracklist;
+        }
+        if player != last_player {
+            let event = json!({"type": "player", "data": player});
+            socket.send(Message::Text(event.to_string())).await?;
+            last_player = player;
+        }
+        tokio::select! {
+            changed = state.changed() => {
+                if changed.is_err() {
+                    // the orchestrator has quit
+                    break;
+                }
+            }
+            message = socket.next() => match message {
+                Some(Ok(Message::Close(_))) | None => return Ok(()),
+                Some(Err(err)) => return Err(err.into()),
+                // messages from the client are ignored
+                Some(Ok(_)) => (),
+            }
+        }
+    }
+    socket.close(None).await?;
+    Ok(())
+}
+
+/// Returns `true` if no token is configured or if `request` carries it as a bearer token
+fn is_authorized(request: &Request<Body>, token: &str) -> bool {
+    if token.is_empty() {
+        return true;
+    }
+    request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|sent| sent.trim() == token)
+}
+
+async fn read_body(request: Request<Body>) -> String {
+    match hyper::body::to_bytes(request.into_body()).await {
+        Ok(bytes) => String::from_utf8_lossy(&bytes).to_string(),
+        Err(_) => String::new(),
+    }
+}
+
+fn json(value: Value) -> Response<Body> {
+    Response::builder()
+        .header("Content-Type", "application/json")
+        .body(Body::from(value.to_string()))
+        .unwrap_or_default()
+}
+
+fn status(code: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(code)
+        .body(Body::empty())
+        .unwrap_or_default()
+}
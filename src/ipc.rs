@@ -0,0 +1,238 @@
+use std::{
+    fs::{self, DirBuilder, Permissions},
+    os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Result};
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+    sync::{mpsc::Sender, watch},
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    client::interface::PlayerAction,
+    config,
+    orchestrator::{Action, MyEvents},
+    remote::{parse_volume, RemoteState},
+};
+
+/// Message sent on the control socket, one per line
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IpcRequest {
+    Action(Action),
+    /// same syntax as the commands typed in the prompt
+    Command(String),
+    /// get the state of the player
+    Status,
+    /// get the songs of the tracklist of the player
+    Queue,
+    /// get the playlists of the selected client
+    Playlists,
+}
+
+/// Answer to an [IpcRequest], one per line
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct IpcResponse {
+    pub ok: bool,
+    /// answer to the requests for information
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl IpcResponse {
+    fn data(data: Value) -> Self {
+        Self {
+            ok: true,
+            data: Some(data),
+            error: None,
+        }
+    }
+    fn error(message: impl Into<String>) -> Self {
+        Self {
+            ok: false,
+            error: Some(message.into()),
+            ..Default::default()
+        }
+    }
+}
+
+/// Location of the control socket, in the runtime directory if there is one
+/// or else in a folder of the user in the temporary directory
+pub fn socket_path() -> PathBuf {
+    let dirs = config::get_dirs();
+    let dir = match dirs.runtime_dir() {
+        Some(dir) => dir.to_path_buf(),
+        None => {
+            let user = std::env::var("USER").unwrap_or_default();
+            std::env::temp_dir().join(format!("yamav3-{user}"))
+        }
+    };
+    dir.join("yamav3.sock")
+}
+
+/// Only let the user connect to the socket at `path`, its folder must belong to the user
+/// and not be writable by the others, who could otherwise replace the socket
+fn restrict(path: &Path) -> Result<()> {
+    fs::set_permissions(path, Permissions::from_mode(0o600))?;
+    let dir = match path.parent() {
+        Some(dir) => dir,
+        None => return Ok(()),
+    };
+    // the socket was just created by the user
+    let owner = fs::metadata(path)?.uid();
+    let dir_metadata = fs::metadata(dir)?;
+    if dir_metadata.uid() != owner || dir_metadata.mode() & 0o022 != 0 {
+        bail!("{} can be written to by other users", dir.display());
+    }
+    Ok(())
+}
+
+/// Accept commands on the control socket until `cancel_token` is cancelled
+pub async fn start(
+    sender: Sender<MyEvents>,
+    state: watch::Receiver<RemoteState>,
+    cancel_token: CancellationToken,
+) -> Result<()> {
+    let path = socket_path();
+    if UnixStream::connect(&path).await.is_ok() {
+        error!("[Ipc] {} is used by another instance", path.display());
+        bail!("{} is used by another instance", path.display());
+    }
+    // the socket of a previous instance that did not exit properly
+    let _ = fs::remove_file(&path);
+    if let Some(dir) = path.parent() {
+        let _ = DirBuilder::new().recursive(true).mode(0o700).create(dir);
+    }
+    let listener = match UnixListener::bind(&path) {
+        Ok(listener) => listener,
+        Err(err) => {
+            error!("[Ipc] Cannot listen on {}: {err}", path.display());
+            return Err(err.into());
+        }
+    };
+    if let Err(err) = restrict(&path) {
+        error!("[Ipc] Cannot secure {}: {err}", path.display());
+        drop(listener);
+        let _ = fs::remove_file(&path);
+        return Err(err);
+    }
+    debug!("Listening on {}", path.display());
+    loop {
+        tokio::select! {
+            _ = cancel_token.cancelled() => break,
+            connection = listener.accept() => match connection {
+                Ok((stream, _)) => {
+                    tokio::spawn(serve(stream, sender.clone(), state.clone()));
+                }
+                Err(err) => error!("[Ipc] Failed to accept connection: {err}"),
+            }
+        }
+    }
+    let _ = fs::remove_file(&path);
+    Ok(())
+}
+
+/// Answer the requests sent on `stream` until it is closed
+async fn serve(stream: UnixStream, sender: Sender<MyEvents>, state: watch::Receiver<RemoteState>) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let response = match serde_json::from_str(&line) {
+            Ok(IpcRequest::Status) => IpcResponse::data(state.borrow().player_json()),
+            Ok(IpcRequest::Queue) => IpcResponse::data(state.borrow().tracklist_json()),
+            Ok(IpcRequest::Playlists) => IpcResponse::data(state.borrow().playlists_json()),
+            Ok(IpcRequest::Action(action)) => send(&sender, action.into()).await,
+            Ok(IpcRequest::Command(command)) => send(&sender, MyEvents::Command(command)).await,
+            Err(err) => IpcResponse::error(format!("Invalid request: {err}")),
+        };
+        let mut response = serde_json::to_string(&response).unwrap_or_default();
+        response.push('\n');
+        if writer.write_all(response.as_bytes()).await.is_err() {
+            break;
+        }
+    }
+}
+
+async fn send(sender: &Sender<MyEvents>, event: MyEvents) -> IpcResponse {
+    match sender.send(event).await {
+        Ok(_) => IpcResponse {
+            ok: true,
+            ..Default::default()
+        },
+        Err(_) => IpcResponse::error("yama is quitting"),
+    }
+}
+
+/// Convert the arguments of `yama ctl` to a request, `play` and `pause` leave the playback
+/// in that state whatever the backend, arguments that are not recognized are sent as a command
+fn parse_args(args: &[String]) -> Option<IpcRequest> {
+    let action: PlayerAction = match args.first()?.as_str() {
+        "status" => return Some(IpcRequest::Status),
+        "queue" => return Some(IpcRequest::Queue),
+        "playlists" => return Some(IpcRequest::Playlists),
+        "play" => PlayerAction::PlayPause(false),
+        "pause" => PlayerAction::PlayPause(true),
+        "toggle" => PlayerAction::PlayPauseToggle,
+        "stop" => PlayerAction::Stop,
+        "next" => PlayerAction::Next,
+        "prev" | "previous" => PlayerAction::Prev,
+        "shuffle" => PlayerAction::ShuffleToggle,
+        "repeat" => PlayerAction::CycleRepeat,
+        "volume" => PlayerAction::SetVolume(parse_volume(args.get(1)?)?),
+        "mute" => PlayerAction::MuteToggle,
+        "quit" => return Some(IpcRequest::Action(Action::Quit)),
+        _ => return Some(IpcRequest::Command(args.join(" "))),
+    };
+    Some(IpcRequest::Action(action.into()))
+}
+
+async fn request(request: &IpcRequest) -> Result<IpcResponse> {
+    let stream = UnixStream::connect(socket_path()).await?;
+    let (reader, mut writer) = stream.into_split();
+    let mut line = serde_json::to_string(request)?;
+    line.push('\n');
+    writer.write_all(line.as_bytes()).await?;
+    match BufReader::new(reader).lines().next_line().await? {
+        Some(line) => Ok(serde_json::from_str(&line)?),
+        None => bail!("connection closed"),
+    }
+}
+
+/// Send the request described by `args` to the running instance and print the answer,
+/// returns `true` on success
+pub async fn ctl(args: &[String]) -> bool {
+    let request = match parse_args(args) {
+        Some(request) => request,
+        None => {
+            eprintln!(
+                "Usage: yama ctl <status|queue|playlists|play|pause|toggle|stop|next|prev|\
+                 shuffle|repeat|volume [+|-]<n>|mute|quit|<command>...>"
+            );
+            return false;
+        }
+    };
+    match self::request(&request).await {
+        Ok(response) => {
+            if let Some(data) = &response.data {
+                println!("{data}");
+            }
+            if let Some(error) = &response.error {
+                eprintln!("{error}");
+            }
+            response.ok
+        }
+        Err(err) => {
+            eprintln!("Cannot reach yama on {}: {err}", socket_path().display());
+            false
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use log::error;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// `path` with `extension` appended to its file name
+fn with_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(extension);
+    path.with_file_name(name)
+}
+
+/// Read the json file at `path`, a missing file gives the default value. A file that
+/// cannot be parsed is renamed with a `.bad` extension so that it is not overwritten
+pub fn load<T: DeserializeOwned + Default>(path: &Path) -> T {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == ErrorKind::NotFound => return T::default(),
+        Err(err) => {
+            error!("[Store] Failed to open {}: {err}", path.display());
+            return T::default();
+        }
+    };
+    match serde_json::from_reader(BufReader::new(file)) {
+        Ok(value) => value,
+        Err(err) => {
+            let bad = with_extension(path, ".bad");
+            error!(
+                "[Store] Failed to parse {}, moved to {}: {err}",
+                path.display(),
+                bad.display()
+            );
+            if let Err(err) = fs::rename(path, &bad) {
+                error!("[Store] Failed to move {}: {err}", path.display());
+            }
+            T::default()
+        }
+    }
+}
+
+fn write<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let tmp = with_extension(path, ".tmp");
+    let mut writer = BufWriter::new(File::create(&tmp)?);
+    serde_json::to_writer(&mut writer, value)?;
+    writer.into_inner()?.sync_all()?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Write `value` as json to `path` through a temporary file renamed over it,
+/// so that the previous content is kept if writing fails midway
+pub fn save<T: Serialize>(path: &Path, value: &T) {
+    if let Err(err) = write(path, value) {
+        error!("[Store] Failed to write {}: {err}", path.display());
+    }
+}
@@ -1,36 +1,281 @@
+use std::{
+    collections::VecDeque,
+    os::unix::net::UnixDatagram,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
 use anyhow::Result;
-use log::LevelFilter;
+use log::{warn, Level, LevelFilter, Record};
 use log4rs::{
-    append::file::FileAppender,
-    config::{Appender, Config, Root},
+    append::{
+        file::FileAppender,
+        rolling_file::{
+            policy::compound::{
+                roll::{delete::DeleteRoller, fixed_window::FixedWindowRoller, Roll},
+                trigger::Trigger,
+                CompoundPolicy,
+            },
+            LogFile, RollingFileAppender,
+        },
+        Append,
+    },
+    config::{Appender, Config, Logger, Root},
     encode::pattern::PatternEncoder,
 };
 
+use crate::config::{self, LogConfig, LogDestination};
+
+pub const LOG_FILE: &str = "/tmp/yamav3.log";
+
+/// number of records kept in memory for the log viewer
+const BUFFER_LEN: usize = 1000;
+
+/// most recent records, the most recent last
+static BUFFER: Mutex<VecDeque<LogEntry>> = Mutex::new(VecDeque::new());
+
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    pub level: Level,
+    /// module that emitted the record
+    pub target: String,
+    pub message: String,
+}
+
+/// Records displayed in the log viewer
+#[derive(Debug, Clone)]
+pub struct LogFilter {
+    /// most verbose level displayed
+    pub level: LevelFilter,
+    /// only display records whose module contains this string
+    pub module: String,
+}
+
+impl Default for LogFilter {
+    fn default() -> Self {
+        Self {
+            level: LevelFilter::Debug,
+            module: String::new(),
+        }
+    }
+}
+
+impl LogFilter {
+    pub fn matches(&self, entry: &LogEntry) -> bool {
+        entry.level <= self.level && entry.target.contains(&self.module)
+    }
+    /// show one level less verbose, wrapping around to the most verbose
+    pub fn cycle_level(&mut self) {
+        self.level = match self.level {
+            LevelFilter::Trace | LevelFilter::Off => LevelFilter::Debug,
+            LevelFilter::Debug => LevelFilter::Info,
+            LevelFilter::Info => LevelFilter::Warn,
+            LevelFilter::Warn => LevelFilter::Error,
+            LevelFilter::Error => LevelFilter::Trace,
+        }
+    }
+}
+
+/// Keep the last records in memory
+#[derive(Debug)]
+struct BufferAppender;
+
+impl Append for BufferAppender {
+    fn append(&self, record: &Record) -> Result<()> {
+        let entry = LogEntry {
+            level: record.level(),
+            target: record.target().to_string(),
+            message: record.args().to_string(),
+        };
+        // ignore the record rather than panicking if the lock is poisoned
+        if let Ok(mut buffer) = BUFFER.lock() {
+            if buffer.len() >= BUFFER_LEN {
+                buffer.pop_front();
+            }
+            buffer.push_back(entry);
+        }
+        Ok(())
+    }
+
+    fn flush(&self) {}
+}
+
+/// Send the records to the systemd journal using its native protocol
+#[derive(Debug)]
+struct JournaldAppender {
+    socket: UnixDatagram,
+}
+
+impl JournaldAppender {
+    const SOCKET: &'static str = "/run/systemd/journal/socket";
+
+    fn new() -> Result<Self> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(Self::SOCKET)?;
+        Ok(Self { socket })
+    }
+
+    /// Append the field `name` to `message`, values spanning several lines
+    /// are prefixed by their length
+    fn field(message: &mut Vec<u8>, name: &str, value: &str) {
+        message.extend_from_slice(name.as_bytes());
+        if value.contains('\n') {
+            message.push(b'\n');
+            message.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        } else {
+            message.push(b'=');
+        }
+        message.extend_from_slice(value.as_bytes());
+        message.push(b'\n');
+    }
+}
+
+impl Append for JournaldAppender {
+    fn append(&self, record: &Record) -> Result<()> {
+        // syslog priorities
+        let priority = match record.level() {
+            Level::Error => "3",
+            Level::Warn => "4",
+            Level::Info => "6",
+            Level::Debug | Level::Trace => "7",
+        };
+        let mut message = Vec::new();
+        Self::field(&mut message, "MESSAGE", &record.args().to_string());
+        Self::field(&mut message, "PRIORITY", priority);
+        Self::field(&mut message, "SYSLOG_IDENTIFIER", "yama");
+        Self::field(&mut message, "TARGET", record.target());
+        self.socket.send(&message)?;
+        Ok(())
+    }
+
+    fn flush(&self) {}
+}
+
+/// Roll the log file once it exceeds a size or once a day has passed
+#[derive(Debug)]
+struct RotationTrigger {
+    /// size in bytes above which the file is rolled, 0 to ignore the size
+    max_size: u64,
+    daily: bool,
+    /// days since the unix epoch when the last record was written
+    day: AtomicU64,
+}
+
+impl RotationTrigger {
+    fn today() -> u64 {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        now.as_secs() / 86400
+    }
+}
+
+impl Trigger for RotationTrigger {
+    fn trigger(&self, file: &LogFile) -> Result<bool> {
+        let today = Self::today();
+        let new_day = self.day.swap(today, Ordering::Relaxed) != today;
+        let too_big = self.max_size > 0 && file.len_estimate() > self.max_size;
+        Ok((self.daily && new_day && file.len_estimate() > 0) || too_big)
+    }
+
+    fn is_pre_process(&self) -> bool {
+        // roll before writing the first record of the day
+        true
+    }
+}
+
+/// Appender writing to the configured destination
+fn output(config: &LogConfig) -> Result<Box<dyn Append>> {
+    // Pattern: https://docs.rs/log4rs/*/log4rs/encode/pattern/index.html
+    let encoder = Box::new(PatternEncoder::new(
+        "{d(%Y-%m-%d %H:%M:%S)} {l} {t} - {m}{n}",
+    ));
+    if config.destination == LogDestination::Journald {
+        return Ok(Box::new(JournaldAppender::new()?));
+    }
+    if config.max_size_kb == 0 && !config.daily {
+        return Ok(Box::new(
+            FileAppender::builder()
+                .encoder(encoder)
+                .build(&config.file)?,
+        ));
+    }
+    let trigger = RotationTrigger {
+        max_size: config.max_size_kb * 1024,
+        daily: config.daily,
+        day: AtomicU64::new(RotationTrigger::today()),
+    };
+    let roller: Box<dyn Roll> = if config.kept_files == 0 {
+        Box::new(DeleteRoller::new())
+    } else {
+        // yamav3.log.1 being the most recent
+        let pattern = format!("{}.{{}}", config.file.display());
+        Box::new(FixedWindowRoller::builder().build(&pattern, config.kept_files)?)
+    };
+    let policy = CompoundPolicy::new(Box::new(trigger), roller);
+    Ok(Box::new(
+        RollingFileAppender::builder()
+            .encoder(encoder)
+            .build(&config.file, Box::new(policy))?,
+    ))
+}
+
 pub fn init() -> Result<()> {
-    let file_path = "/tmp/yamav3.log";
-
-    // Logging to log file.
-    let logfile = FileAppender::builder()
-        // Pattern: https://docs.rs/log4rs/*/log4rs/encode/pattern/index.html
-        .encoder(Box::new(PatternEncoder::new("{l} - {m}\n")))
-        .build(file_path)
-        .unwrap();
-
-    // Log Trace level output to file where trace is the default level
-    // and the programmatically specified level to stderr.
-    let config = Config::builder()
-        .appender(Appender::builder().build("logfile", Box::new(logfile)))
-        .build(
-            Root::builder()
-                .appender("logfile")
-                .build(LevelFilter::Debug),
-        )
-        .unwrap();
-
-    // Use this to change log levels at runtime.
-    // This means you can change the default log level to trace
-    // if you are trying to debug an issue and need more logs on then turn it off
-    // once you are done.
-    let _handle = log4rs::init_config(config)?;
+    let config = config::get_config().logging;
+    let (output, error) = match output(&config) {
+        Ok(output) => (output, None),
+        Err(err) => {
+            // fall back on the default file rather than not logging at all
+            (output(&LogConfig::default())?, Some(err))
+        }
+    };
+    let level = config.level.parse().unwrap_or(LevelFilter::Debug);
+    let mut builder = Config::builder()
+        .appender(Appender::builder().build("output", output))
+        .appender(Appender::builder().build("buffer", Box::new(BufferAppender)));
+    for (module, module_level) in &config.modules {
+        if let Ok(module_level) = module_level.parse() {
+            builder = builder.logger(Logger::builder().build(module, module_level));
+        }
+    }
+    let log_config = builder.build(
+        Root::builder()
+            .appender("output")
+            .appender("buffer")
+            .build(level),
+    )?;
+
+    let _handle = log4rs::init_config(log_config)?;
+    if let Some(err) = error {
+        warn!("[Logging] Cannot log to {:?}: {err}", config.destination);
+    }
+    if config.level.parse::<LevelFilter>().is_err() {
+        warn!("[Logging] Unknown level {}", config.level);
+    }
+    for (module, module_level) in &config.modules {
+        if module_level.parse::<LevelFilter>().is_err() {
+            warn!("[Logging] Unknown level {module_level} for {module}");
+        }
+    }
     Ok(())
 }
+
+/// Last `count` records kept in memory matching `filter`
+pub fn entries(filter: &LogFilter, count: usize) -> Vec<LogEntry> {
+    let buffer = match BUFFER.lock() {
+        Ok(buffer) => buffer,
+        Err(_) => return Vec::new(),
+    };
+    let mut entries: Vec<LogEntry> = buffer
+        .iter()
+        .rev()
+        .filter(|entry| filter.matches(entry))
+        .take(count)
+        .cloned()
+        .collect();
+    entries.reverse();
+    entries
+}
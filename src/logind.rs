@@ -0,0 +1,302 @@
+use std::{
+    collections::HashMap,
+    process::Stdio,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use futures::StreamExt;
+use log::{debug, error};
+use tokio::{
+    io::{AsyncBufReadExt, BufReader, Lines},
+    process::{Child, ChildStdout, Command},
+    sync::mpsc::Sender,
+};
+use tokio_util::sync::CancellationToken;
+use zbus::{
+    dbus_proxy,
+    zvariant::{ObjectPath, OwnedFd, OwnedValue, Value},
+    Connection, MatchRule, Message, MessageStream, MessageType, Proxy,
+};
+
+use crate::orchestrator::MyEvents;
+
+#[dbus_proxy(
+    interface = "org.freedesktop.login1.Session",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1/session/auto"
+)]
+trait Session {
+    #[dbus_proxy(signal)]
+    fn lock(&self) -> zbus::Result<()>;
+    #[dbus_proxy(signal)]
+    fn unlock(&self) -> zbus::Result<()>;
+    #[dbus_proxy(property)]
+    fn idle_hint(&self) -> zbus::Result<bool>;
+}
+
+#[dbus_proxy(
+    interface = "org.freedesktop.login1.Manager",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1"
+)]
+trait Manager {
+    fn inhibit(&self, what: &str, who: &str, why: &str, mode: &str) -> zbus::Result<OwnedFd>;
+    #[dbus_proxy(signal)]
+    fn prepare_for_sleep(&self, start: bool) -> zbus::Result<()>;
+}
+
+/// Change of the user session reported by logind
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionEvent {
+    Idle,
+    Active,
+    Lock,
+    Unlock,
+    /// the system is about to suspend
+    Sleep,
+    /// the system has resumed from suspend
+    Wake,
+    /// the audio device in use, e.g. bluetooth headphones, was disconnected
+    AudioDeviceLost,
+}
+
+/// Time given to the orchestrator to pause the playback before letting the system suspend,
+/// must be lower than `InhibitDelayMaxSec` from logind.conf (5 seconds by default)
+const SLEEP_DELAY: Duration = Duration::from_secs(1);
+/// Time during which the disconnections following the one of an audio device are ignored
+const DEVICE_LOST_DELAY: Duration = Duration::from_secs(5);
+
+/// Delay the suspend until the lock is dropped
+async fn inhibit_sleep(manager: &ManagerProxy<'_>) -> Option<OwnedFd> {
+    match manager
+        .inhibit("sleep", "yama", "Pause playback before suspend", "delay")
+        .await
+    {
+        Ok(fd) => Some(fd),
+        Err(err) => {
+            error!("[Logind] Cannot take sleep inhibitor lock: {err}");
+            None
+        }
+    }
+}
+
+/// Whether `message`, a change of the properties of a bluez object, is the disconnection
+/// of an audio device
+async fn is_audio_disconnection(conn: &Connection, message: &Message) -> bool {
+    let body: zbus::Result<(String, HashMap<String, OwnedValue>, Vec<String>)> = message.body();
+    let disconnected = match body {
+        Ok((interface, changed, _)) => {
+            interface == "org.bluez.Device1"
+                && changed
+                    .get("Connected")
+                    .is_some_and(|connected| **connected == Value::Bool(false))
+        }
+        Err(_) => false,
+    };
+    match message.path() {
+        Some(path) if disconnected => is_audio_device(conn, path).await,
+        _ => false,
+    }
+}
+
+/// Whether the bluez device at `path` plays sound, according to the profiles it supports:
+/// A2DP sink, headset or hands-free
+async fn is_audio_device(conn: &Connection, path: ObjectPath<'_>) -> bool {
+    const AUDIO_UUIDS: [&str; 3] = [
+        "0000110b-0000-1000-8000-00805f9b34fb",
+        "00001108-0000-1000-8000-00805f9b34fb",
+        "0000111e-0000-1000-8000-00805f9b34fb",
+    ];
+    let device = match Proxy::new(conn, "org.bluez", path, "org.bluez.Device1").await {
+        Ok(device) => device,
+        Err(_) => return false,
+    };
+    device
+        .get_property::<Vec<String>>("UUIDs")
+        .await
+        .is_ok_and(|uuids| {
+            uuids
+                .iter()
+                .any(|uuid| AUDIO_UUIDS.contains(&uuid.to_lowercase().as_str()))
+        })
+}
+
+/// Output of `pactl` with `args`, `pactl` is provided by both PulseAudio and PipeWire
+async fn pactl(args: &[&str]) -> Option<String> {
+    let output = Command::new("pactl").args(args).output().await.ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Name of the sink the sound server plays to by default
+async fn default_sink() -> Option<String> {
+    Some(pactl(&["get-default-sink"]).await?.trim().to_string())
+}
+
+/// Whether the sound server still has the sink named `name`
+async fn has_sink(name: &str) -> bool {
+    match pactl(&["list", "short", "sinks"]).await {
+        Some(sinks) => sinks
+            .lines()
+            .any(|line| line.split('\t').nth(1) == Some(name)),
+        // the sink cannot be said to be gone
+        None => true,
+    }
+}
+
+/// Events of the sound server, read from `pactl subscribe`
+struct SoundServer {
+    /// kept so that `pactl` is killed along with the watch
+    _child: Child,
+    events: Lines<BufReader<ChildStdout>>,
+    /// sink played to by default
+    sink: Option<String>,
+}
+
+impl SoundServer {
+    async fn subscribe() -> Option<Self> {
+        let mut child = Command::new("pactl")
+            .arg("subscribe")
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|err| debug!("[Logind] Cannot watch the sound server: {err}"))
+            .ok()?;
+        let events = BufReader::new(child.stdout.take()?).lines();
+        Some(Self {
+            _child: child,
+            events,
+            sink: default_sink().await,
+        })
+    }
+
+    /// Wait until the default sink is removed, returns `false` if `pactl` exited before
+    async fn sink_removed(&mut self) -> bool {
+        loop {
+            match self.events.next_line().await {
+                Ok(Some(event)) if self.is_sink_removal(&event).await => return true,
+                Ok(Some(_)) => (),
+                _ => return false,
+            }
+        }
+    }
+
+    /// Whether `event` reports that the default sink changed because the previous one was
+    /// removed, i.e. the audio device in use was disconnected
+    async fn is_sink_removal(&mut self, event: &str) -> bool {
+        // a change of the server is a change of its default sink or source
+        if !event.contains("on server") {
+            return false;
+        }
+        let sink = default_sink().await;
+        if sink == self.sink {
+            return false;
+        }
+        let previous = std::mem::replace(&mut self.sink, sink);
+        match previous {
+            Some(previous) => !has_sink(&previous).await,
+            None => false,
+        }
+    }
+}
+
+/// Wait until the default sink of `server` is removed, never ready without a server
+async fn sink_removed(server: &mut Option<SoundServer>) -> bool {
+    match server {
+        Some(server) => server.sink_removed().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Forward the changes of the current session to the orchestrator until `cancel_token` is
+/// cancelled
+pub async fn start(sender: Sender<MyEvents>, cancel_token: CancellationToken) -> Result<()> {
+    debug!("Watching logind session");
+    let res = watch(sender, cancel_token).await;
+    if let Err(err) = &res {
+        error!("[Logind] Cannot watch session: {err}");
+    }
+    res
+}
+
+async fn watch(sender: Sender<MyEvents>, cancel_token: CancellationToken) -> Result<()> {
+    let conn = Connection::system().await?;
+    let session = SessionProxy::new(&conn).await?;
+    let mut lock = session.receive_lock().await?;
+    let mut unlock = session.receive_unlock().await?;
+    let mut idle = session.receive_idle_hint_changed().await;
+    let manager = ManagerProxy::new(&conn).await?;
+    let mut sleep = manager.receive_prepare_for_sleep().await?;
+    let mut inhibitor = inhibit_sleep(&manager).await;
+    // bluez reports the bluetooth devices being disconnected, the other audio devices
+    // are left to the sound server
+    let rule = MatchRule::builder()
+        .msg_type(MessageType::Signal)
+        .sender("org.bluez")?
+        .interface("org.freedesktop.DBus.Properties")?
+        .member("PropertiesChanged")?
+        .build();
+    let mut devices = MessageStream::for_match_rule(rule, &conn, None).await?;
+    let mut sound_server = SoundServer::subscribe().await;
+    let mut device_lost: Option<Instant> = None;
+    loop {
+        let event = tokio::select! {
+            _ = cancel_token.cancelled() => break,
+            Some(signal) = sleep.next() => match signal.args() {
+                Ok(args) if args.start => SessionEvent::Sleep,
+                Ok(_) => SessionEvent::Wake,
+                Err(_) => continue,
+            },
+            Some(_) = lock.next() => SessionEvent::Lock,
+            Some(_) = unlock.next() => SessionEvent::Unlock,
+            Some(change) = idle.next() => match change.get().await {
+                Ok(true) => SessionEvent::Idle,
+                Ok(false) => SessionEvent::Active,
+                Err(_) => continue,
+            },
+            Some(message) = devices.next() => match message {
+                Ok(message) if is_audio_disconnection(&conn, &message).await => {
+                    SessionEvent::AudioDeviceLost
+                }
+                _ => continue,
+            },
+            removed = sink_removed(&mut sound_server) => {
+                if !removed {
+                    // `pactl` exited
+                    sound_server = None;
+                    continue;
+                }
+                SessionEvent::AudioDeviceLost
+            }
+            else => break,
+        };
+        if event == SessionEvent::AudioDeviceLost {
+            // a bluetooth device and its sink are both reported
+            if device_lost.is_some_and(|lost| lost.elapsed() < DEVICE_LOST_DELAY) {
+                continue;
+            }
+            device_lost = Some(Instant::now());
+        }
+        debug!("[Logind] {event:?}");
+        if sender.send(MyEvents::Session(event)).await.is_err() {
+            // the orchestrator has quit
+            break;
+        }
+        match event {
+            SessionEvent::Sleep => {
+                tokio::time::sleep(SLEEP_DELAY).await;
+                // releasing the lock lets the system suspend
+                inhibitor = None;
+            }
+            SessionEvent::Wake if inhibitor.is_none() => {
+                inhibitor = inhibit_sleep(&manager).await;
+            }
+            _ => (),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,85 @@
+use std::{fs, path::PathBuf, time::Duration};
+
+use crate::{client::interface::SongInfo, config};
+
+/// Line of the lyrics of a song
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LyricsLine {
+    /// time at which the line is sung, for synced lyrics
+    pub time: Option<Duration>,
+    pub text: String,
+}
+
+/// Files that may hold the lyrics of `song`: the `.lrc` file next to a local song, then
+/// `<artist> - <title>.lrc` in the `lyrics` folder of the data directory
+fn candidates(song: &SongInfo) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    if let Some(path) = song.url.strip_prefix("file://") {
+        paths.push(PathBuf::from(path).with_extension("lrc"));
+    }
+    let name = format!("{} - {}.lrc", song.artist, song.title).replace('/', "_");
+    paths.push(config::get_dirs().data_dir().join("lyrics").join(name));
+    paths
+}
+
+/// Lyrics of `song`, empty if none were found
+pub fn load(song: &SongInfo) -> Vec<LyricsLine> {
+    candidates(song)
+        .into_iter()
+        .find_map(|path| fs::read_to_string(path).ok())
+        .map(|content| parse_lrc(&content))
+        .unwrap_or_default()
+}
+
+/// Time of a `[mm:ss.xx]` tag, `None` for the other tags such as `[ar:Artist]`
+fn parse_time(tag: &str) -> Option<Duration> {
+    let (minutes, seconds) = tag.split_once(':')?;
+    let minutes: u64 = minutes.trim().parse().ok()?;
+    let seconds: f64 = seconds.trim().parse().ok()?;
+    let seconds = Duration::try_from_secs_f64(seconds).ok()?;
+    Some(Duration::from_secs(minutes.checked_mul(60)?) + seconds)
+}
+
+/// Lines of the lyrics in the LRC format, a line with several time tags is repeated
+/// at each of them. Files without time tags give lyrics that are not synced
+fn parse_lrc(content: &str) -> Vec<LyricsLine> {
+    let mut lines = Vec::new();
+    for line in content.lines() {
+        let mut text = line.trim();
+        let mut times = Vec::new();
+        let mut tagged = false;
+        while let Some(rest) = text.strip_prefix('[') {
+            let (tag, after) = match rest.split_once(']') {
+                Some(split) => split,
+                None => break,
+            };
+            tagged = true;
+            times.extend(parse_time(tag).map(Some));
+            text = after.trim_start();
+        }
+        if tagged && times.is_empty() {
+            // metadata of the file
+            continue;
+        }
+        if times.is_empty() {
+            times.push(None);
+        }
+        lines.extend(times.into_iter().map(|time| LyricsLine {
+            time,
+            text: text.to_string(),
+        }));
+    }
+    if lines.iter().any(|line| line.time.is_some()) {
+        // stray lines of synced lyrics cannot be placed
+        lines.retain(|line| line.time.is_some());
+        lines.sort_by_key(|line| line.time);
+    }
+    lines
+}
+
+/// Index in `lyrics` of the line being sung at `position`, for synced lyrics
+pub fn current_line(lyrics: &[LyricsLine], position: Duration) -> Option<usize> {
+    lyrics
+        .iter()
+        .rposition(|line| line.time.is_some_and(|time| time <= position))
+}
@@ -2,18 +2,59 @@ mod client;
 
 use anyhow::Result;
 use orchestrator::OrchestratorBuilder;
-use tokio::{sync::mpsc, task::JoinSet};
+#[cfg(feature = "mpris")]
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
 use tui::Tui;
 mod config;
+mod covers;
 #[cfg(feature = "mpris")]
 mod dbus;
+mod doctor;
+mod history;
+#[cfg(feature = "http")]
+mod http;
+#[cfg(feature = "ipc")]
+mod ipc;
+mod json_store;
 mod logging;
+#[cfg(feature = "logind")]
+mod logind;
+mod lyrics;
+mod marks;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod mirror;
 mod orchestrator;
+mod playlist_file;
+mod probe;
+mod ratings;
+#[cfg(feature = "remote")]
+mod remote;
+mod requests;
+#[cfg(any(feature = "spotify", feature = "youtube"))]
+mod secrets;
+mod shuffle;
 mod tui;
 
 #[tokio::main]
 async fn main() -> Result<()> {
     logging::init()?;
+    #[cfg(feature = "metrics")]
+    metrics::init();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("doctor") => {
+            let healthy = doctor::run().await;
+            std::process::exit(if healthy { 0 } else { 1 });
+        }
+        #[cfg(feature = "ipc")]
+        Some("ctl") => {
+            let success = ipc::ctl(&args[1..]).await;
+            std::process::exit(if success { 0 } else { 1 });
+        }
+        _ => (),
+    }
     initialize_panic_handler();
     let mut orchestrator_build = OrchestratorBuilder::new();
     let mut tasks_set = JoinSet::new();
@@ -28,45 +69,57 @@ async fn main() -> Result<()> {
         Ok(())
     });
 
+    // Publishing the state to remote controllers
+    #[cfg(feature = "remote")]
+    let (state_tx, state_rx) = tokio::sync::watch::channel(Default::default());
+    #[cfg(feature = "remote")]
+    orchestrator_build.set_remote(state_tx);
+
     // Creating Dbus session
     #[cfg(feature = "mpris")]
     {
         let (dbus_sender, mut dbus_receiver) = mpsc::channel(2);
         orchestrator_build.set_dbus(dbus_sender);
-        tasks_set.spawn(async move { crate::dbus::start(event_tx.clone(), &mut dbus_receiver).await });
+        let state_rx = state_rx.clone();
+        tasks_set.spawn(async move {
+            crate::dbus::start(event_tx.clone(), &mut dbus_receiver, state_rx).await
+        });
     }
 
-    // Creating local client
-    #[cfg(feature = "local")]
+    // Serving the remote control api
+    #[cfg(feature = "http")]
     {
-        let (request_tx, request_rx) = mpsc::channel(32);
-        let (answer_tx, answer_rx) = mpsc::channel(32);
+        let state_rx = state_rx.clone();
+        let event_tx = orchestrator_build.get_event_tx();
         let cancel_token = orchestrator_build.get_cancel_token();
-        let mut loc_client = client::local::Client::create(request_rx, answer_tx, cancel_token);
-        orchestrator_build.add_client("local".to_string(), request_tx, answer_rx);
-        tasks_set.spawn(async move { loc_client.main_loop().await });
-    };
+        tasks_set.spawn(async move { crate::http::start(event_tx, state_rx, cancel_token).await });
+    }
 
-    // Creating Youtube client
-    #[cfg(feature = "youtube")]
+    // Listening on the control socket
+    #[cfg(feature = "ipc")]
     {
-        let (request_tx, request_rx) = mpsc::channel(32);
-        let (answer_tx, answer_rx) = mpsc::channel(32);
+        let state_rx = state_rx.clone();
+        let event_tx = orchestrator_build.get_event_tx();
         let cancel_token = orchestrator_build.get_cancel_token();
-        let mut yt_client = client::youtube::Client::create(request_rx, answer_tx, cancel_token.clone());
-        orchestrator_build.add_client("youtube".to_string(), request_tx, answer_rx);
-        tasks_set.spawn(async move { yt_client.main_loop().await });
+        tasks_set.spawn(async move { crate::ipc::start(event_tx, state_rx, cancel_token).await });
     }
 
-    // Creating Spotify client
-    #[cfg(feature = "spotify")]
+    // Watching session to pause when idle
+    #[cfg(feature = "logind")]
     {
-        let (request_tx, request_rx) = mpsc::channel(32);
-        let (answer_tx, answer_rx) = mpsc::channel(32);
+        let event_tx = orchestrator_build.get_event_tx();
         let cancel_token = orchestrator_build.get_cancel_token();
-        let mut spot_client = client::spotify::Client::create(request_rx, answer_tx, cancel_token.clone());
-        orchestrator_build.add_client("spotify".to_string(), request_tx, answer_rx);
-        tasks_set.spawn(async move { spot_client.main_loop().await });
+        tasks_set.spawn(async move { crate::logind::start(event_tx, cancel_token).await });
+    }
+
+    // Creating clients, the others can be started later with `client enable <name>`
+    let config = config::get_config();
+    let sources = config.local_sources.into_keys();
+    let plugins = config.plugins.into_keys();
+    for name in config.clients.into_iter().chain(sources).chain(plugins) {
+        if !orchestrator_build.start_client(&name) {
+            log::warn!("Unknown client {name}");
+        }
     }
 
     // Starting tasks
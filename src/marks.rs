@@ -0,0 +1,48 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+};
+
+use crate::{config, json_store};
+
+/// Named positions in the playlists of every client, saved on disk
+#[derive(Debug, Default)]
+pub struct Marks {
+    /// index of the song under each mark indexed by its name, for each playlist indexed by its id
+    playlists: HashMap<String, BTreeMap<char, usize>>,
+    path: PathBuf,
+}
+
+impl Marks {
+    pub fn load() -> Self {
+        let mut path = config::get_dirs().data_dir().to_path_buf();
+        path.push("marks.json");
+        let playlists = json_store::load(&path);
+        Self { playlists, path }
+    }
+
+    pub fn get(&self, playlist: &str, name: char) -> Option<usize> {
+        self.playlists.get(playlist)?.get(&name).copied()
+    }
+
+    /// Marks of `playlist` sorted by name
+    pub fn list(&self, playlist: &str) -> Vec<(char, usize)> {
+        self.playlists
+            .get(playlist)
+            .map(|marks| marks.iter().map(|(name, index)| (*name, *index)).collect())
+            .unwrap_or_default()
+    }
+
+    /// Put the mark `name` of `playlist` on the song at `index`, replacing any previous one
+    pub fn set(&mut self, playlist: &str, name: char, index: usize) {
+        self.playlists
+            .entry(playlist.to_string())
+            .or_default()
+            .insert(name, index);
+        self.save();
+    }
+
+    fn save(&self) {
+        json_store::save(&self.path, &self.playlists);
+    }
+}
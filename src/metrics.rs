@@ -0,0 +1,88 @@
+use std::{collections::BTreeMap, fmt::Write, sync::Mutex, time::Duration};
+
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+
+/// Durations measured for one operation
+#[derive(Debug, Default, Clone, Copy)]
+struct Timing {
+    count: u64,
+    total: Duration,
+    max: Duration,
+    last: Duration,
+}
+
+/// Number of messages waiting in one channel
+#[derive(Debug, Default, Clone, Copy)]
+struct Depth {
+    last: usize,
+    max: usize,
+    capacity: usize,
+}
+
+#[derive(Debug)]
+struct Metrics {
+    timings: BTreeMap<String, Timing>,
+    depths: BTreeMap<String, Depth>,
+}
+
+static METRICS: Mutex<Metrics> = Mutex::new(Metrics {
+    timings: BTreeMap::new(),
+    depths: BTreeMap::new(),
+});
+
+/// Start the server `tokio-console` connects to, yama must be built with
+/// `RUSTFLAGS="--cfg tokio_unstable"` for the tasks to be instrumented
+pub fn init() {
+    // no fmt layer, the events it prints to stdout would garble the interface
+    tracing_subscriber::registry()
+        .with(console_subscriber::ConsoleLayer::builder().spawn())
+        .init();
+}
+
+/// Record that the operation `name` took `duration`
+pub fn record_duration(name: &str, duration: Duration) {
+    // ignore the measure rather than panicking if the lock is poisoned
+    if let Ok(mut metrics) = METRICS.lock() {
+        let timing = metrics.timings.entry(name.to_string()).or_default();
+        timing.count += 1;
+        timing.total += duration;
+        timing.max = timing.max.max(duration);
+        timing.last = duration;
+    }
+}
+
+/// Record the number of messages waiting in the channel `name` holding at most `capacity`
+pub fn record_depth(name: &str, depth: usize, capacity: usize) {
+    if let Ok(mut metrics) = METRICS.lock() {
+        let entry = metrics.depths.entry(name.to_string()).or_default();
+        entry.last = depth;
+        entry.max = entry.max.max(depth);
+        entry.capacity = capacity;
+    }
+}
+
+/// Summary of the measures, one per line
+pub fn report() -> String {
+    let metrics = match METRICS.lock() {
+        Ok(metrics) => metrics,
+        Err(_) => return String::new(),
+    };
+    let mut content = String::from("Durations (last / average / max, count)\n");
+    for (name, timing) in &metrics.timings {
+        let average = timing.total / timing.count.max(1) as u32;
+        let _ = writeln!(
+            content,
+            "  {name}: {:?} / {:?} / {:?}, {}",
+            timing.last, average, timing.max, timing.count
+        );
+    }
+    content.push_str("\nChannels (waiting / max / capacity)\n");
+    for (name, depth) in &metrics.depths {
+        let _ = writeln!(
+            content,
+            "  {name}: {} / {} / {}",
+            depth.last, depth.max, depth.capacity
+        );
+    }
+    content
+}
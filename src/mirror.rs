@@ -0,0 +1,172 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use crate::client::interface::SongInfo;
+
+/// Query used to find `song` on another client
+pub fn search_query(song: &SongInfo) -> String {
+    format!("{} {}", song.artist, song.title).trim().to_string()
+}
+
+/// returns `true` if `candidate` looks like the same song as `song`,
+/// the titles of some clients include the artist or a mention like `(Official video)`
+fn is_match(song: &SongInfo, candidate: &SongInfo) -> bool {
+    let title = song.title.to_lowercase();
+    let other = candidate.title.to_lowercase();
+    !title.is_empty() && !other.is_empty() && (title.contains(&other) || other.contains(&title))
+}
+
+/// Song to play on another client, searched there by its title and artist
+#[derive(Debug)]
+pub struct Handoff {
+    /// index of the client on which the song is played
+    pub target: usize,
+    query: String,
+    song: SongInfo,
+    /// position at which to resume the song, only a song matching the original one
+    /// is played when it is set
+    pub position: Option<Duration>,
+}
+
+impl Handoff {
+    pub fn new(target: usize, song: SongInfo, position: Option<Duration>) -> Self {
+        Self {
+            target,
+            query: search_query(&song),
+            song,
+            position,
+        }
+    }
+
+    pub fn query(&self) -> String {
+        self.query.clone()
+    }
+
+    /// returns `true` if the results of `query` on the client at index `client`
+    /// are the ones expected
+    pub fn is_waiting(&self, client: usize, query: &str) -> bool {
+        client == self.target && self.query == query
+    }
+
+    /// First of `results` matching the song, or the first result if the song
+    /// does not need to be resumed
+    pub fn best_match(&self, results: Vec<SongInfo>) -> Option<SongInfo> {
+        let found = results.iter().find(|result| is_match(&self.song, result));
+        match found {
+            Some(result) => Some(result.clone()),
+            None if self.position.is_none() => results.into_iter().next(),
+            None => None,
+        }
+    }
+}
+
+/// Copy of a playlist to another client, its songs are searched one after the other
+/// on the target client
+#[derive(Debug)]
+pub struct PlaylistMirror {
+    /// index of the client on which the playlist is created
+    pub target: usize,
+    pub title: String,
+    /// songs left to search, the first one being searched
+    pending: VecDeque<SongInfo>,
+    /// query sent for the song being searched
+    query: Option<String>,
+    /// time at which `query` was sent
+    sent: Option<Instant>,
+    /// is the song being searched by its title only, after a search including its artist failed
+    title_only: bool,
+    matched: Vec<SongInfo>,
+    unmatched: Vec<SongInfo>,
+}
+
+impl PlaylistMirror {
+    /// time after which the search is given up on, longer than the retries of the request
+    const TIMEOUT: Duration = Duration::from_secs(45);
+
+    pub fn new(target: usize, title: String, songs: Vec<SongInfo>) -> Self {
+        Self {
+            target,
+            title,
+            pending: songs.into(),
+            query: None,
+            sent: None,
+            title_only: false,
+            matched: Vec::new(),
+            unmatched: Vec::new(),
+        }
+    }
+
+    /// Query to send to find the next song, `None` once every song was searched
+    pub fn next_query(&mut self) -> Option<String> {
+        self.query = self.pending.front().map(|song| {
+            if self.title_only {
+                song.title.clone()
+            } else {
+                search_query(song)
+            }
+        });
+        self.sent = self.query.as_ref().map(|_| Instant::now());
+        self.query.clone()
+    }
+
+    /// returns `true` if the target client did not answer the search in time
+    pub fn is_expired(&self) -> bool {
+        self.sent
+            .is_some_and(|sent| sent.elapsed() >= Self::TIMEOUT)
+    }
+
+    /// returns `true` if the results of `query` on the client at index `client`
+    /// are the ones expected
+    pub fn is_waiting(&self, client: usize, query: &str) -> bool {
+        client == self.target && self.query.as_deref() == Some(query)
+    }
+
+    /// Keep the first of `results` matching the song searched, if none matches
+    /// the song is searched again by its title only as some clients lack the artists
+    pub fn handle_results(&mut self, results: Vec<SongInfo>) {
+        let song = match self.pending.front() {
+            Some(song) => song,
+            None => return,
+        };
+        let found = results.into_iter().find(|result| is_match(song, result));
+        if found.is_none() && !self.title_only && !song.artist.is_empty() {
+            self.title_only = true;
+            return;
+        }
+        self.title_only = false;
+        if let Some(song) = self.pending.pop_front() {
+            match found {
+                Some(result) => self.matched.push(result),
+                None => self.unmatched.push(song),
+            }
+        }
+    }
+
+    /// Songs found on the target client, in the order of the playlist
+    pub fn matched(&self) -> Vec<SongInfo> {
+        self.matched.clone()
+    }
+
+    /// Summary of the copy listing the songs that were not found
+    pub fn report(&self) -> String {
+        let mut lines = vec![format!(
+            "{} of {} songs found",
+            self.matched.len(),
+            self.matched.len() + self.unmatched.len()
+        )];
+        if !self.unmatched.is_empty() {
+            lines.push(String::new());
+            lines.push("Not found:".to_string());
+            lines.extend(self.unmatched.iter().map(|song| {
+                if song.artist.is_empty() {
+                    song.title.clone()
+                } else {
+                    format!("{} - {}", song.artist, song.title)
+                }
+            }));
+        }
+        lines.join("\n")
+    }
+}
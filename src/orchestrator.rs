@@ -1,18 +1,54 @@
 use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt::Display,
     ops::{Deref, DerefMut},
-    time::Duration,
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
 };
 
 use anyhow::Result;
+use log::debug;
 
 use serde::{Deserialize, Serialize};
-use tokio::sync::mpsc::{self, Receiver, Sender};
+#[cfg(feature = "remote")]
+use tokio::sync::watch;
+use tokio::{
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        oneshot,
+    },
+    task::JoinHandle,
+};
 use tokio_util::sync::CancellationToken;
 
+#[cfg(feature = "logind")]
+use crate::logind::SessionEvent;
+#[cfg(feature = "metrics")]
+use crate::metrics;
+#[cfg(feature = "remote")]
+use crate::remote::RemoteState;
 use crate::{
-    client::interface::{
-        Answer, GetRequest, PlayerAction, PlayerInfo, PlaylistInfo, Request, SongInfo,
+    client::{
+        self,
+        interface::{
+            AlbumInfo, Answer, ArtistInfo, Capabilities, DownloadProgress, GetRequest,
+            Notification, Playback, PlayerAction, PlayerInfo, PlaylistInfo, Request, SeekMode,
+            SetRequest, Severity, SongInfo, Visibility, Volume, Widget as InterfaceWidget,
+            EQUALIZER_BANDS, MAX_SPEED, MIN_SPEED,
+        },
     },
+    config::{self, Config},
+    covers,
+    history::{History, HistoryEntry},
+    logging::{self, LogEntry, LogFilter},
+    lyrics::{self, LyricsLine},
+    marks::Marks,
+    mirror::{Handoff, PlaylistMirror},
+    playlist_file, probe,
+    ratings::{self, Rating, Ratings},
+    requests::Requests,
+    shuffle::{self, ShuffleMode},
     tui,
 };
 
@@ -26,10 +62,27 @@ pub struct Client {
     receiver: Receiver<Answer>,
     /// channel used to send event to `Orchestrator`
     event_tx: Sender<MyEvents>,
+    /// stops the client when it is disabled
+    cancel_token: CancellationToken,
+    /// task running the client, if it was started by yama
+    task: Option<JoinHandle<Result<()>>>,
+    /// features supported by the backend, requests needing another one are not sent
+    capabilities: Capabilities,
+    /// requests waiting for their answer
+    requests: Requests,
 
-    // cache
-    playlists_info: Vec<PlaylistInfo>,
-    player_info: PlayerInfo,
+    // cache, the playlists are shared with the state displayed and only copied when modified
+    playlists_info: Vec<Arc<PlaylistInfo>>,
+    /// ids of the playlists whose pages are still being received
+    loading: HashSet<String>,
+    artists: Vec<ArtistInfo>,
+    /// albums of the artists browsed indexed by the id of the artist,
+    /// the songs of an album are only known once it was browsed
+    albums: HashMap<String, Vec<AlbumInfo>>,
+    player_info: Arc<PlayerInfo>,
+    /// time at which the player info was last requested, to measure the latency of the backend
+    #[cfg(feature = "metrics")]
+    player_info_requested: Option<Instant>,
 }
 
 /// Interface between the front end and one backend
@@ -39,14 +92,52 @@ impl Client {
         sender: Sender<Request>,
         receiver: Receiver<Answer>,
         event_tx: Sender<MyEvents>,
+        cancel_token: CancellationToken,
     ) -> Self {
         Self {
             name,
             sender,
             receiver,
             event_tx,
+            cancel_token,
+            task: None,
+            capabilities: Capabilities::default(),
+            requests: Requests::default(),
             playlists_info: Default::default(),
+            loading: HashSet::new(),
+            artists: Vec::new(),
+            albums: HashMap::new(),
             player_info: Default::default(),
+            #[cfg(feature = "metrics")]
+            player_info_requested: None,
+        }
+    }
+    /// Start the built-in client `name` on its own task, stopped along with `cancel_token`
+    pub fn start(
+        name: &str,
+        cancel_token: &CancellationToken,
+        event_tx: Sender<MyEvents>,
+    ) -> Option<Self> {
+        let cancel_token = cancel_token.child_token();
+        let spawned = client::spawn(name, cancel_token.clone())?;
+        let mut client = Self::new(
+            name.to_string(),
+            spawned.request_tx,
+            spawned.answer_rx,
+            event_tx,
+            cancel_token,
+        );
+        client.task = Some(spawned.task);
+        // the channel was just created, there is room for the request
+        let _ = client.sender.try_send(GetRequest::Capabilities.into());
+        client.requests.sent(&GetRequest::Capabilities);
+        Some(client)
+    }
+    /// Stop the client and wait for its task to end
+    pub async fn stop(self) {
+        self.cancel_token.cancel();
+        if let Some(task) = self.task {
+            let _ = task.await;
         }
     }
     pub async fn update(&mut self) {
@@ -54,36 +145,193 @@ impl Client {
             // read all messages received
             self.handle_answer(msg).await;
         }
+        self.retry_requests().await;
+    }
+    /// Send again the requests which timed out, warning when the backend stops answering
+    async fn retry_requests(&mut self) {
+        let healthy = self.requests.is_healthy();
+        for request in self.requests.expired() {
+            debug!(
+                "[{}] Request timed out, sending it again: {request:?}",
+                self.name
+            );
+            if self.sender.try_send(request.into()).is_err() {
+                self.requests.failed();
+            }
+        }
+        if healthy && !self.requests.is_healthy() {
+            let message = format!("{} is not answering", self.name);
+            let notification = Notification::new(Severity::Warning, message);
+            let _ = self
+                .event_tx
+                .send(MyEvents::Notification(notification))
+                .await;
+        }
+    }
+    /// Send `request` to the backend, tracking it until it is answered
+    async fn get(&mut self, request: GetRequest) {
+        // ignore the fact that backend has dropped connection
+        if self.send(request.clone().into()).await.is_ok() {
+            self.requests.sent(&request);
+        }
+    }
+    /// The backend answers, unless too many requests went unanswered
+    pub fn is_healthy(&self) -> bool {
+        self.requests.is_healthy()
     }
     pub async fn handle_answer(&mut self, msg: Answer) {
+        if !self.requests.is_healthy() {
+            let message = format!("{} is answering again", self.name);
+            let notification = Notification::new(Severity::Info, message);
+            let _ = self
+                .event_tx
+                .send(MyEvents::Notification(notification))
+                .await;
+        }
+        self.requests.answered(&msg);
         match msg {
             Answer::PlayerInfo(info) => {
-                self.player_info = info;
+                self.player_info = Arc::new(info);
+                #[cfg(feature = "metrics")]
+                if let Some(requested) = self.player_info_requested.take() {
+                    let name = format!("{} player info", self.name);
+                    metrics::record_duration(&name, requested.elapsed());
+                }
                 // ignore the error if the orchestrator has dropped the connection
                 let _ = self.event_tx.send(MyEvents::RefreshPlayerState).await;
             }
-            Answer::PlaylistList(list_info) => self.playlists_info = list_info,
+            Answer::PlaylistList(list_info) => {
+                self.loading
+                    .retain(|id| list_info.iter().any(|playlist| &playlist.id == id));
+                self.playlists_info = list_info.into_iter().map(Arc::new).collect();
+            }
             Answer::Playlist(playlist_info) => {
                 let id = playlist_info.id.clone();
+                if playlist_info.songs.len() >= playlist_info.length {
+                    self.loading.remove(&id);
+                }
                 let maybe_index = self.playlists_info.iter().position(|p| p.id == id);
                 if let Some(index) = maybe_index {
-                    self.playlists_info[index] = playlist_info;
+                    self.playlists_info[index] = Arc::new(playlist_info);
                 } else {
-                    self.playlists_info.push(playlist_info)
+                    self.playlists_info.push(Arc::new(playlist_info))
+                }
+            }
+            Answer::PlaylistPage {
+                id,
+                offset,
+                songs,
+                total,
+            } => {
+                if let Some(playlist) = self.playlists_info.iter_mut().find(|p| p.id == id) {
+                    let playlist = Arc::make_mut(playlist);
+                    // pages arrive in order, a page at offset 0 means the playlist is reloaded
+                    if offset <= playlist.songs.len() {
+                        playlist.songs.truncate(offset);
+                        playlist.songs.extend(songs.iter().cloned());
+                        let page = MyEvents::PlaylistPage {
+                            client: self.name.clone(),
+                            id: id.clone(),
+                            offset,
+                            songs,
+                        };
+                        let _ = self.event_tx.send(page).await;
+                    }
+                    playlist.length = total;
+                    if playlist.songs.len() < total {
+                        self.loading.insert(id);
+                    } else {
+                        self.loading.remove(&id);
+                    }
+                }
+            }
+            Answer::PlaylistDelta {
+                id,
+                length,
+                changes,
+            } => {
+                // the playlist is left shared when nothing changed
+                let changed = |p: &&mut Arc<PlaylistInfo>| {
+                    p.id == id && (!changes.is_empty() || p.length != length)
+                };
+                if let Some(playlist) = self.playlists_info.iter_mut().find(changed) {
+                    let playlist = Arc::make_mut(playlist);
+                    for change in changes {
+                        change.apply(&mut playlist.songs);
+                    }
+                    playlist.length = length;
+                }
+                let loaded = self.playlists_info.iter().find(|p| p.id == id);
+                if loaded.is_some_and(|p| p.songs.len() >= p.length) {
+                    self.loading.remove(&id);
                 }
             }
             Answer::Widget(widget) => {
                 let _ = self.event_tx.send(MyEvents::Widget(widget)).await;
             }
+            Answer::SearchResults { query, songs } => {
+                let _ = self
+                    .event_tx
+                    .send(MyEvents::SearchResults {
+                        client: self.name.clone(),
+                        query,
+                        songs,
+                    })
+                    .await;
+            }
+            Answer::Notification(notification) => {
+                let _ = self
+                    .event_tx
+                    .send(MyEvents::Notification(notification))
+                    .await;
+            }
+            Answer::Download(progress) => {
+                let _ = self.event_tx.send(MyEvents::Download(progress)).await;
+            }
+            Answer::Capabilities(capabilities) => {
+                self.capabilities = capabilities;
+                let _ = self.event_tx.send(MyEvents::RefreshPlayerState).await;
+            }
+            Answer::Artists(artists) => self.artists = artists,
+            Answer::Albums { artist, albums } => {
+                // keep the songs of the albums already browsed
+                let known = self.albums.remove(&artist).unwrap_or_default();
+                let albums = albums
+                    .into_iter()
+                    .map(|album| match known.iter().find(|a| a.id == album.id) {
+                        Some(known) if album.songs.is_empty() => AlbumInfo {
+                            songs: known.songs.clone(),
+                            ..album
+                        },
+                        _ => album,
+                    })
+                    .collect();
+                self.albums.insert(artist, albums);
+            }
+            Answer::Album(album) => {
+                for known in self.albums.values_mut().flatten() {
+                    if known.id == album.id {
+                        *known = album.clone();
+                    }
+                }
+            }
+            Answer::Related { seed, songs } => {
+                let related = MyEvents::Related {
+                    client: self.name.clone(),
+                    seed,
+                    songs,
+                };
+                let _ = self.event_tx.send(related).await;
+            }
+            // the request is no longer waited for, refreshes are requested again periodically
+            Answer::Busy(_) => (),
             Answer::Ok => todo!(),
         }
     }
     pub async fn update_playlistlist(&mut self) {
-        let request: Request = GetRequest::PlaylistList.into();
-        // ignore the fact that backend has dropped connection
-        let _ = self.send(request).await;
+        self.get(GetRequest::PlaylistList).await;
     }
-    pub fn get_playlists(&self) -> Vec<PlaylistInfo> {
+    pub fn get_playlists(&self) -> Vec<Arc<PlaylistInfo>> {
         self.playlists_info.clone()
     }
     pub async fn update_playlist(&mut self, index: Option<usize>) {
@@ -91,18 +339,20 @@ impl Client {
             return;
         }
         let playlist = index.unwrap();
-        let request: Request =
-            GetRequest::Playlist(self.playlists_info[playlist].id.clone()).into();
-        // ignore the fact that backend has dropped connection
-        let _ = self.send(request).await;
+        let id = self.playlists_info[playlist].id.clone();
+        self.get(GetRequest::Playlist(id)).await;
     }
     pub fn get_playlist(&self, playlist: Option<usize>) -> PlaylistInfo {
         if let Some(playlist) = playlist {
-            self.playlists_info[playlist].clone()
+            PlaylistInfo::clone(&self.playlists_info[playlist])
         } else {
             Default::default()
         }
     }
+    /// ids of the playlists still loading
+    pub fn get_loading(&self) -> HashSet<String> {
+        self.loading.clone()
+    }
     pub fn get_songs(&self, playlist: Option<usize>) -> Vec<SongInfo> {
         if let Some(playlist) = playlist {
             self.playlists_info[playlist].songs.clone()
@@ -111,11 +361,48 @@ impl Client {
         }
     }
 
-    async fn update_player_info(&self) {
-        let _ = self.send(Request::Get(GetRequest::PlayerInfo)).await;
+    pub async fn update_artists(&mut self) {
+        self.get(GetRequest::Artists).await;
+    }
+    pub async fn update_albums(&mut self, artist: &ArtistInfo) {
+        self.get(GetRequest::Albums(artist.id.clone())).await;
+    }
+    pub async fn update_album(&mut self, album: &AlbumInfo) {
+        self.get(GetRequest::Album(album.id.clone())).await;
+    }
+    pub fn get_artists(&self) -> Vec<ArtistInfo> {
+        self.artists.clone()
+    }
+    /// Albums of `artist`, empty until they are received
+    pub fn get_albums(&self, artist: Option<&ArtistInfo>) -> Vec<AlbumInfo> {
+        artist
+            .and_then(|artist| self.albums.get(&artist.id))
+            .cloned()
+            .unwrap_or_default()
+    }
+    /// Songs of `album`, empty until they are received
+    pub fn get_album_songs(&self, album: Option<&AlbumInfo>) -> Vec<SongInfo> {
+        let album = match album {
+            Some(album) => album,
+            None => return Vec::new(),
+        };
+        self.albums
+            .values()
+            .flatten()
+            .find(|a| a.id == album.id)
+            .map(|a| a.songs.clone())
+            .unwrap_or_default()
+    }
+
+    async fn update_player_info(&mut self) {
+        #[cfg(feature = "metrics")]
+        if self.player_info_requested.is_none() {
+            self.player_info_requested = Some(Instant::now());
+        }
+        self.get(GetRequest::PlayerInfo).await;
     }
 
-    fn get_player_info(&self) -> PlayerInfo {
+    fn get_player_info(&self) -> Arc<PlayerInfo> {
         self.player_info.clone()
     }
 }
@@ -141,9 +428,70 @@ pub enum Menu {
     Song,
 }
 
+/// Panels of the artists tab, from the artists of a client to the songs of an album
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub enum BrowseMenu {
+    #[default]
+    Artist,
+    Album,
+    Song,
+}
+
+/// Tabs of the interface, each one owning its panels
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum Tab {
+    #[default]
+    Library,
+    Queue,
+    Search,
+    Lyrics,
+    Log,
+    History,
+    Favorites,
+    Artists,
+}
+
+impl Tab {
+    pub const ALL: [Tab; 8] = [
+        Tab::Library,
+        Tab::Queue,
+        Tab::Search,
+        Tab::Lyrics,
+        Tab::Log,
+        Tab::History,
+        Tab::Favorites,
+        Tab::Artists,
+    ];
+}
+
+impl Display for Tab {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Tab::Library => "Library",
+            Tab::Queue => "Queue",
+            Tab::Search => "Search results",
+            Tab::Lyrics => "Lyrics",
+            Tab::Log => "Log",
+            Tab::History => "History",
+            Tab::Favorites => "Favorites",
+            Tab::Artists => "Artists",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Song found by a search, along with the name of the client it comes from
+#[derive(Debug, Default, Clone)]
+pub struct SearchResult {
+    pub client: String,
+    pub song: SongInfo,
+}
+
+/// Entries of a list along with the one selected, the entries are shared with the
+/// snapshots of the state sent to the TUI and only copied when modified
 #[derive(Debug, Default, Clone)]
 pub struct ListHolder<T> {
-    pub entries: Vec<T>,
+    pub entries: Arc<Vec<T>>,
     pub select: Option<usize>,
 }
 
@@ -157,6 +505,20 @@ impl<T> ListHolder<T> {
     pub fn select(&mut self, select: Option<usize>) {
         self.select = select;
     }
+    /// Replace the entries, the selection is dropped if it is past the new ones
+    pub fn set_entries(&mut self, entries: Vec<T>) {
+        if self.select.is_some_and(|select| select >= entries.len()) {
+            self.select = None;
+        }
+        self.entries = Arc::new(entries);
+    }
+    /// Entries to modify, copied first if a snapshot of the state still holds them
+    pub fn entries_mut(&mut self) -> &mut Vec<T>
+    where
+        T: Clone,
+    {
+        Arc::make_mut(&mut self.entries)
+    }
     pub fn offset(&mut self, off: isize) {
         if self.entries.is_empty() {
             self.select(None);
@@ -181,29 +543,115 @@ impl<T: ToString> ListHolderToString for ListHolder<T> {
         self.entries.iter().map(|e| e.to_string()).collect()
     }
 }
-impl ListHolderToString for ListHolder<PlaylistInfo> {
+impl ListHolderToString for ListHolder<SongInfo> {
     fn get_strings(&self) -> Vec<String> {
         self.entries.iter().map(|e| e.title.clone()).collect()
     }
 }
-impl ListHolderToString for ListHolder<SongInfo> {
-    fn get_strings(&self) -> Vec<String> {
-        self.entries.iter().map(|e| e.title.clone()).collect()
+/// Totals of the playlist displayed in the song list
+#[derive(Debug, Default, Clone)]
+pub struct PlaylistSummary {
+    /// name of the client providing the playlist
+    pub source: String,
+    /// number of songs received so far
+    pub loaded: usize,
+    pub total: usize,
+    /// total duration of the songs received
+    pub duration: Duration,
+    pub owner: String,
+    pub visibility: Option<Visibility>,
+}
+
+impl PlaylistSummary {
+    pub fn new(source: String, playlist: &PlaylistInfo, songs: &[SongInfo]) -> Self {
+        Self {
+            source,
+            loaded: songs.len(),
+            total: playlist.length.max(songs.len()),
+            duration: songs.iter().map(|song| song.duration).sum(),
+            owner: playlist.owner.clone(),
+            visibility: playlist.visibility,
+        }
+    }
+
+    pub fn is_partial(&self) -> bool {
+        self.loaded < self.total
     }
 }
+
 #[derive(Debug, Default, Clone)]
 pub struct State {
     pub clients: ListHolder<String>,
-    pub playlists: ListHolder<PlaylistInfo>,
+    pub playlists: ListHolder<Arc<PlaylistInfo>>,
+    /// ids of the playlists of the selected client whose songs are still being received
+    pub loading: HashSet<String>,
+    /// names of the clients not answering their requests
+    pub unhealthy: HashSet<String>,
     pub songs: ListHolder<SongInfo>,
-    /// list of alerts to display
-    pub alerts: Vec<String>,
-    /// current state of active player
-    pub player: PlayerInfo,
+    /// totals of the selected playlist, displayed above its songs
+    pub playlist_summary: Option<PlaylistSummary>,
+    /// notifications waiting to be displayed in the status bar, the first one being displayed
+    pub notifications: VecDeque<Notification>,
+    /// current state of active player, as last reported
+    pub player: Arc<PlayerInfo>,
+    /// position in the song playing, advanced since the player last reported it
+    pub position: Duration,
+    /// features supported by the active player
+    pub player_capabilities: Capabilities,
     /// index of active player if any
     pub active_player: Option<usize>,
     /// current menu
     pub active_menu: Menu,
+    /// tab currently displayed
+    pub active_tab: Tab,
+    /// tracklist of the active player
+    pub queue: ListHolder<SongInfo>,
+    /// results of the last search
+    pub search: ListHolder<SearchResult>,
+    pub search_query: String,
+    /// names of the clients whose results for `search_query` are still awaited
+    pub search_pending: Vec<String>,
+    /// last records matching `log_filter`
+    pub log: Arc<Vec<LogEntry>>,
+    pub log_filter: LogFilter,
+    /// progress of the last download requested
+    pub download: Option<DownloadProgress>,
+    /// songs played matching `history_query`, the most recent first
+    pub history: ListHolder<HistoryEntry>,
+    pub history_query: String,
+    /// rating of each rated song indexed by [ratings::song_key]
+    pub ratings: Arc<HashMap<String, Rating>>,
+    /// favorite songs of every client
+    pub favorites: ListHolder<SearchResult>,
+    /// last volume of each client while it was the active player, indexed by name
+    pub volumes: HashMap<String, u8>,
+    /// display the time remaining in the song rather than the time elapsed
+    pub show_remaining: bool,
+    /// only the songs of the library and the player bar are displayed
+    pub zen: bool,
+    /// only the player bar and the next songs are displayed, for small terminals
+    pub compact: bool,
+    /// the playlists are displayed as a grid of covers in place of the list
+    pub playlist_grid: bool,
+    /// something displayed may have changed since the last render,
+    /// the refreshes of the interface are skipped otherwise
+    pub dirty: bool,
+    /// text searched in the playlists and songs of the library, highlighted where it appears
+    pub find: String,
+    /// songs related to the last one are appended to the tracklist when it ends
+    pub radio: bool,
+    /// panel of the artists tab having the focus
+    pub browse_menu: BrowseMenu,
+    /// artists of the selected client
+    pub artists: ListHolder<ArtistInfo>,
+    /// albums of the selected artist
+    pub albums: ListHolder<AlbumInfo>,
+    /// songs of the selected album
+    pub album_songs: ListHolder<SongInfo>,
+    /// lyrics of the song playing
+    pub lyrics: Arc<Vec<LyricsLine>>,
+    /// configuration read when starting, rather than on every tick or render
+    pub config: Arc<Config>,
 }
 
 impl State {
@@ -222,7 +670,50 @@ impl State {
         }
     }
     pub fn is_active_menu(&self, menu: Menu) -> bool {
-        self.active_menu == menu && self.alerts.is_empty()
+        self.active_menu == menu
+    }
+    pub fn go_next_browse_menu(&mut self) {
+        self.browse_menu = match self.browse_menu {
+            BrowseMenu::Artist => BrowseMenu::Album,
+            BrowseMenu::Album | BrowseMenu::Song => BrowseMenu::Song,
+        }
+    }
+    pub fn go_prev_browse_menu(&mut self) {
+        self.browse_menu = match self.browse_menu {
+            BrowseMenu::Artist | BrowseMenu::Album => BrowseMenu::Artist,
+            BrowseMenu::Song => BrowseMenu::Album,
+        }
+    }
+    /// Index in the song list of the song playing, if the playlist displayed is the one
+    /// being played
+    pub fn playing_song(&self) -> Option<usize> {
+        let playlist = self.playlists.get_selected()?;
+        let song = self.player.song_info.as_ref()?;
+        if self.active_player != self.clients.select || playlist.id != self.player.tracklist.id {
+            return None;
+        }
+        // the tracklist may be shuffled, the index is only trusted if it points to the song
+        let is_playing = |index: &usize| {
+            let displayed = self.songs.entries.get(*index);
+            displayed.is_some_and(|s| s.id == song.id)
+        };
+        match self.player.track_index.filter(is_playing) {
+            Some(index) => Some(index),
+            None => self.songs.entries.iter().position(|s| s.id == song.id),
+        }
+    }
+    pub fn rating(&self, song: &SongInfo) -> Rating {
+        self.ratings
+            .get(ratings::song_key(song))
+            .copied()
+            .unwrap_or_default()
+    }
+    pub fn lyrics_title(&self) -> String {
+        match &self.player.song_info {
+            Some(song) if !song.artist.is_empty() => format!("{} - {}", song.artist, song.title),
+            Some(song) => song.title.clone(),
+            None => "Lyrics".to_string(),
+        }
     }
 }
 
@@ -233,6 +724,10 @@ pub enum MenuCtrl {
     NextMenu,
     PrevMenu,
     Offset(isize),
+    /// put the mark named by the character on the selected song of the selected playlist
+    SetMark(char),
+    /// select the song under the mark named by the character in the selected playlist
+    JumpToMark(char),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -248,6 +743,69 @@ pub enum Action {
     Quit,
     Update,
     GoToCurrent,
+    /// display the details of the selected song, or of the selected playlist
+    /// while the playlists are focused
+    SongDetails,
+    SelectTab(Tab),
+    ActivityLog,
+    CycleLogLevel,
+    ToggleFavorite,
+    /// open the equalizer with the gains last applied
+    Equalizer,
+    /// resume the song playing on the selected client
+    MovePlayback,
+    /// pick a playlist of its client to which the selected song is added
+    AddToPlaylist,
+    /// remove the selected song from the selected playlist, once confirmed
+    RemoveFromPlaylist,
+    /// add the selected song to the tracklist of the active player,
+    /// right after the current song if `next` is set
+    Enqueue {
+        next: bool,
+    },
+    /// list the songs of the client of the selected song by the same artist
+    GoToArtist,
+    /// list the songs of the client of the selected song from the same album
+    GoToAlbum,
+    /// authenticate the selected client again
+    Reconnect,
+    /// switch the player bar between the time elapsed and the time remaining
+    ToggleRemaining,
+    /// keep appending songs related to the last one once the tracklist ends
+    ToggleRadio,
+    /// hide everything but the songs of the library and the player bar, or show it again
+    ToggleZen,
+    /// shrink the interface to the player bar and the next songs, or restore it
+    ToggleCompact,
+    /// display the playlists as a grid of covers, or as a list
+    TogglePlaylistGrid,
+    /// open the prompt of the text searched in the focused panel of the library
+    FindPrompt,
+    /// highlight `text` in the library and select its first match from the selected entry
+    Find(String),
+    /// select the next entry of the focused panel matching the text searched
+    NextMatch,
+    /// select the previous entry of the focused panel matching the text searched
+    PrevMatch,
+    /// copy the url of the selected song, or of the song playing, to the clipboard
+    Yank,
+    /// open the page of the selected playlist or song in the browser,
+    /// or the folder containing it for the local clients
+    OpenInBrowser,
+    /// move forward by `seek_step` seconds, or `seek_step_large` if `large` is set
+    SeekForward {
+        large: bool,
+    },
+    /// move backward by `seek_step` seconds, or `seek_step_large` if `large` is set
+    SeekBackward {
+        large: bool,
+    },
+    /// raise the volume by `volume_step`
+    VolumeUp,
+    /// lower the volume by `volume_step`
+    VolumeDown,
+    /// pick a chapter of the song playing to jump to it
+    Chapters,
 }
 
 impl From<PlayerAction> for Action {
@@ -267,6 +825,29 @@ pub enum MyEvents {
     Action(Action),
     Command(String),
     Widget(crate::client::interface::Widget),
+    Notification(Notification),
+    Download(DownloadProgress),
+    SearchResults {
+        /// name of the client that answered
+        client: String,
+        query: String,
+        songs: Vec<SongInfo>,
+    },
+    /// Songs of a playlist received by a client, starting at `offset`
+    PlaylistPage {
+        client: String,
+        id: String,
+        offset: usize,
+        songs: Vec<SongInfo>,
+    },
+    /// Songs similar to the song whose id is `seed`, suggested by a client
+    Related {
+        client: String,
+        seed: String,
+        songs: Vec<SongInfo>,
+    },
+    #[cfg(feature = "logind")]
+    Session(SessionEvent),
 }
 impl From<Action> for MyEvents {
     fn from(value: Action) -> Self {
@@ -287,6 +868,8 @@ pub struct OrchestratorBuilder {
     clients: Vec<Client>,
     #[cfg(feature = "mpris")]
     dbus: Option<Sender<PlayerInfo>>,
+    #[cfg(feature = "remote")]
+    remote: Option<watch::Sender<RemoteState>>,
     event_rx: Receiver<MyEvents>,
     event_tx: Sender<MyEvents>,
     tui_tx: Option<Sender<crate::tui::Event>>,
@@ -296,26 +879,16 @@ pub struct OrchestratorBuilder {
 impl OrchestratorBuilder {
     pub fn new() -> Self {
         let (event_tx, event_rx) = mpsc::channel(32);
-        #[cfg(feature = "mpris")]
-        {
-            Self {
-                clients: Vec::new(),
-                dbus: None,
-                event_rx,
-                event_tx,
-                tui_tx: None,
-                cancel_token: CancellationToken::new(),
-            }
-        }
-        #[cfg(not(feature = "mpris"))]
-        {
-            Self {
-                clients: Vec::new(),
-                event_rx,
-                event_tx,
-                tui_tx: None,
-                cancel_token: CancellationToken::new(),
-            }
+        Self {
+            clients: Vec::new(),
+            #[cfg(feature = "mpris")]
+            dbus: None,
+            #[cfg(feature = "remote")]
+            remote: None,
+            event_rx,
+            event_tx,
+            tui_tx: None,
+            cancel_token: CancellationToken::new(),
         }
     }
     pub fn get_event_tx(&self) -> Sender<MyEvents> {
@@ -324,53 +897,112 @@ impl OrchestratorBuilder {
     pub fn get_cancel_token(&self) -> CancellationToken {
         self.cancel_token.clone()
     }
-    pub fn add_client(
-        &mut self,
-        name: String,
-        chan_tx: Sender<Request>,
-        chan_rx: Receiver<Answer>,
-    ) {
-        self.clients
-            .push(Client::new(name, chan_tx, chan_rx, self.event_tx.clone()))
+    /// Start the built-in client `name`, returns `false` if it is not compiled in
+    pub fn start_client(&mut self, name: &str) -> bool {
+        match Client::start(name, &self.cancel_token, self.event_tx.clone()) {
+            Some(client) => {
+                self.clients.push(client);
+                true
+            }
+            None => false,
+        }
     }
     #[cfg(feature = "mpris")]
     pub fn set_dbus(&mut self, dbus_sender: Sender<PlayerInfo>) {
         self.dbus = Some(dbus_sender);
     }
+    #[cfg(feature = "remote")]
+    pub fn set_remote(&mut self, remote: watch::Sender<RemoteState>) {
+        self.remote = Some(remote);
+    }
     pub fn set_tui(&mut self, tui_tx: Sender<crate::tui::Event>) {
         self.tui_tx = Some(tui_tx)
     }
     pub fn build(self) -> Orchestrator {
         let tui = self.tui_tx.expect("No TUI provided");
-        let clients = self.clients.iter().map(|c| c.name.clone()).collect();
+        let clients: Vec<String> = self.clients.iter().map(|c| c.name.clone()).collect();
         let clients = ListHolder {
-            entries: clients,
+            entries: clients.into(),
             select: None,
         };
+        let config = config::get_config();
+        // start on the first panel displayed
+        let active_menu = if config.layout.zen {
+            Menu::Song
+        } else if !config.layout.show_sources {
+            Menu::Playlist
+        } else {
+            Menu::Client
+        };
         let state = State {
             clients,
+            show_remaining: config.show_remaining,
+            zen: config.layout.zen,
+            playlist_grid: config.layout.playlist_grid,
+            dirty: true,
+            active_menu,
+            config: Arc::new(config),
             ..Default::default()
         };
-        Orchestrator {
+        let mut orchestrator = Orchestrator {
             clients: self.clients,
             #[cfg(feature = "mpris")]
             dbus: self.dbus.expect("No DBus channel provided"),
+            #[cfg(feature = "remote")]
+            remote: self.remote,
             event_rx: self.event_rx,
+            event_tx: self.event_tx,
             tui_tx: tui,
             state,
             cancel_token: self.cancel_token,
             tui_refresh: true,
             timeout_duration: Duration::from_millis(100),
-        }
+            pending_handoff: None,
+            resume: None,
+            mirror: None,
+            search_sent: None,
+            notification_shown: None,
+            last_render: None,
+            propagated: Arc::default(),
+            activity: VecDeque::new(),
+            history: History::load(),
+            listening: None,
+            ratings: Ratings::load(),
+            marks: Marks::load(),
+            position: None,
+            covers: covers::Fetcher::new(),
+            equalizer: vec![0; EQUALIZER_BANDS.len()],
+            radio_seed: None,
+            lyrics_song: None,
+            #[cfg(feature = "logind")]
+            paused_on_idle: false,
+        };
+        orchestrator.update_ratings();
+        orchestrator
     }
 }
 
+/// Last position reported by the active player, from which the position displayed advances
+struct PositionAnchor {
+    reported: Duration,
+    /// id of the song playing
+    song: Option<String>,
+    playing: bool,
+    /// when the position was reported, `None` while waiting for the position after a seek
+    received: Option<Instant>,
+}
+
 pub struct Orchestrator {
     clients: Vec<Client>,
     /// channel to send info on DBus
     #[cfg(feature = "mpris")]
     dbus: Sender<PlayerInfo>,
+    /// channel to publish the state to remote controllers
+    #[cfg(feature = "remote")]
+    remote: Option<watch::Sender<RemoteState>>,
     event_rx: Receiver<MyEvents>,
+    /// channel used by background tasks to send their results back
+    event_tx: Sender<MyEvents>,
     tui_tx: Sender<crate::tui::Event>,
     state: State,
     cancel_token: CancellationToken,
@@ -378,9 +1010,57 @@ pub struct Orchestrator {
     tui_refresh: bool,
     // duration before timing out when sending something to the TUI, the DBus or a client
     timeout_duration: Duration,
+    /// song searched on another client to play it there
+    pending_handoff: Option<Handoff>,
+    /// index of a client, url of the song handed off to it and position at which
+    /// to resume it once it plays
+    resume: Option<(usize, String, Duration)>,
+    /// playlist being copied to another client
+    mirror: Option<PlaylistMirror>,
+    /// when the last search was sent to the clients
+    search_sent: Option<Instant>,
+    /// when the first notification started to be displayed
+    notification_shown: Option<Instant>,
+    /// when the state was last sent to the TUI
+    last_render: Option<Instant>,
+    /// state of the player last sent to D-Bus
+    propagated: Arc<PlayerInfo>,
+    /// recent actions and answers, the most recent last
+    activity: VecDeque<(Instant, String)>,
+    history: History,
+    /// song being played, recorded in the history once it changes
+    listening: Option<HistoryEntry>,
+    ratings: Ratings,
+    marks: Marks,
+    position: Option<PositionAnchor>,
+    /// downloads the covers of the playlist grid
+    covers: covers::Fetcher,
+    /// gains of the equalizer last applied
+    equalizer: Vec<i8>,
+    /// id of the song whose related songs were last requested for the radio
+    radio_seed: Option<String>,
+    /// url of the song whose lyrics were last loaded
+    lyrics_song: Option<String>,
+    /// has the playback been paused because the session went idle
+    #[cfg(feature = "logind")]
+    paused_on_idle: bool,
 }
 
 impl Orchestrator {
+    /// number of records displayed in the log tab
+    const LOG_LINES: usize = 200;
+    /// maximum number of notifications waiting to be displayed
+    const MAX_NOTIFICATIONS: usize = 5;
+    /// number of entries kept in the activity log
+    const ACTIVITY_LEN: usize = 200;
+    /// maximum number of related songs appended at once by the radio
+    const RADIO_BATCH: usize = 10;
+    /// longest time without rendering, the changes made in the background
+    /// are displayed at least as often
+    const IDLE_RENDER: Duration = Duration::from_secs(1);
+    /// time after which the results of a search are no longer waited for
+    const SEARCH_TIMEOUT: Duration = Duration::from_secs(45);
+
     pub async fn run(&mut self) -> Result<()> {
         self.state.clients.select(Some(0));
         let cancel_token = self.cancel_token.clone();
@@ -408,11 +1088,15 @@ impl Orchestrator {
                 }
                 _ = state_delay => {
                     self.update_state().await;
-                    self.send_dbus(self.state.player.clone()).await;
-                    self.render().await;
+                    self.propagate_player().await;
+                    self.render_tick().await;
                 }
             }
         }
+        // let the clients save their state before quitting
+        for client in std::mem::take(&mut self.clients) {
+            client.stop().await;
+        }
         Ok(())
     }
     /// Allow clients to check if they have received any message from their
@@ -433,8 +1117,42 @@ impl Orchestrator {
         if let Some(player) = self.get_active_player() {
             self.clients[player].update_player_info().await;
         }
+        if self.state.active_tab == Tab::Log {
+            self.update_log();
+        }
+        if self.state.active_tab == Tab::History {
+            self.update_history();
+        }
+        if self.state.active_tab == Tab::Artists {
+            self.browse_artists().await;
+        }
+        #[cfg(feature = "metrics")]
+        self.record_channel_depths();
         self.update_state().await;
     }
+    /// Record the number of messages waiting in the channels of the orchestrator
+    #[cfg(feature = "metrics")]
+    fn record_channel_depths(&self) {
+        let events = [
+            (
+                "events",
+                self.event_tx.capacity(),
+                self.event_tx.max_capacity(),
+            ),
+            ("tui", self.tui_tx.capacity(), self.tui_tx.max_capacity()),
+        ];
+        let clients = self.clients.iter().map(|client| {
+            let name = format!("{} requests", client.name);
+            (name, client.capacity(), client.max_capacity())
+        });
+        let channels = events
+            .map(|(name, capacity, max)| (name.to_string(), capacity, max))
+            .into_iter()
+            .chain(clients);
+        for (name, capacity, max_capacity) in channels {
+            metrics::record_depth(&name, max_capacity - capacity, max_capacity);
+        }
+    }
     fn get_current_client(&self) -> Option<&Client> {
         let client = self.state.clients.select?;
         Some(&self.clients[client])
@@ -447,18 +1165,114 @@ impl Orchestrator {
     fn get_active_player(&self) -> Option<usize> {
         self.state.active_player
     }
+    /// Make the client at index `player` the active one,
+    /// restoring the volume it had the last time it was active
+    async fn set_active_player(&mut self, player: Option<usize>) {
+        let changed = player != self.state.active_player;
+        self.state.active_player = player;
+        let player = match player {
+            Some(player) if changed => player,
+            _ => return,
+        };
+        if let Some(volume) = self.state.volumes.get(&self.clients[player].name) {
+            let action = PlayerAction::SetVolume(Volume::Absolute(*volume as usize));
+            self.send_client(player, action.into()).await;
+        }
+    }
     async fn update_state(&mut self) {
+        self.expire_notification();
+        if self.mirror.as_ref().is_some_and(PlaylistMirror::is_expired) {
+            self.abort_mirror("the search was not answered").await;
+        }
+        self.expire_search();
+        self.state.unhealthy = self
+            .clients
+            .iter()
+            .filter(|client| !client.is_healthy())
+            .map(|client| client.name.clone())
+            .collect();
         if let Some(player) = self.get_active_player() {
             self.clients[player].update().await;
             let player_info = self.clients[player].get_player_info();
+            let len = player_info.tracklist.songs.len();
+            // follow the playing song when the tracklist changes
+            if player_info.tracklist.id != self.state.player.tracklist.id
+                || !matches!(self.state.queue.select, Some(select) if select < len)
+            {
+                self.state.queue.select = player_info.track_index;
+            }
+            if *self.state.queue.entries != player_info.tracklist.songs {
+                self.state.queue.entries = player_info.tracklist.songs.clone().into();
+            }
+            self.record_player_changes(player, &player_info);
+            self.track_listening(player, &player_info);
+            self.resume_handoff(player, &player_info).await;
+            self.continue_radio(player, &player_info).await;
+            let name = self.clients[player].name.clone();
+            self.state.volumes.insert(name, player_info.volume);
+            self.anchor_position(&player_info);
             self.state.player = player_info;
+            self.update_lyrics();
+            self.interpolate_position();
+            self.state.player_capabilities = self.clients[player].capabilities;
         }
         if let Some(client) = self.state.clients.select {
             self.clients[client].update().await;
             let select = self.state.playlists.select;
-            self.state.playlists.entries = self.clients[client].get_playlists();
-            self.state.songs.entries = self.clients[client].get_songs(select);
+            let displayed = self.state.playlists.get_selected().cloned();
+            self.state.playlists.entries = self.clients[client].get_playlists().into();
+            self.state.loading = self.clients[client].get_loading();
+            // a playlist is replaced whenever it is modified, its songs are only copied
+            // again if the playlist displayed was
+            let unchanged = match (&displayed, self.state.playlists.get_selected()) {
+                (Some(displayed), Some(playlist)) => Arc::ptr_eq(displayed, playlist),
+                _ => false,
+            };
+            if !unchanged {
+                self.state.songs.entries = self.clients[client].get_songs(select).into();
+            }
+            self.update_playlist_summary();
+            if self.state.active_tab == Tab::Artists {
+                let client = &self.clients[client];
+                let artists = client.get_artists();
+                self.state.artists.set_entries(artists);
+                let albums = client.get_albums(self.state.artists.get_selected());
+                self.state.albums.set_entries(albums);
+                let songs = client.get_album_songs(self.state.albums.get_selected());
+                self.state.album_songs.set_entries(songs);
+            }
+        }
+    }
+    /// Ask the selected client for its artists, the albums of the selected artist
+    /// and the songs of the selected album
+    async fn browse_artists(&mut self) {
+        let client = match self.state.clients.select {
+            Some(client) => &mut self.clients[client],
+            None => return,
+        };
+        if !client.capabilities.can_browse_artists {
+            return;
+        }
+        client.update_artists().await;
+        if let Some(artist) = self.state.artists.get_selected() {
+            client.update_albums(artist).await;
         }
+        if let Some(album) = self.state.albums.get_selected() {
+            client.update_album(album).await;
+        }
+    }
+    fn update_playlist_summary(&mut self) {
+        let source = self
+            .state
+            .clients
+            .get_selected()
+            .cloned()
+            .unwrap_or_default();
+        self.state.playlist_summary = self
+            .state
+            .playlists
+            .get_selected()
+            .map(|playlist| PlaylistSummary::new(source, playlist, &self.state.songs.entries));
     }
     async fn send_dbus(&self, info: PlayerInfo) {
         // ignore errors when sending to dbus
@@ -467,115 +1281,2109 @@ impl Orchestrator {
             let _ = self.dbus.send_timeout(info, self.timeout_duration).await;
         }
     }
+    /// Notify D-Bus and the remote controllers of the state of the player, unless it did
+    /// not change since they last were, the TUI is only rendered again if it changed
+    async fn propagate_player(&mut self) {
+        self.publish_state();
+        let changed = !Arc::ptr_eq(&self.propagated, &self.state.player)
+            && *self.propagated != *self.state.player;
+        // compare the pointers next time
+        self.propagated = Arc::clone(&self.state.player);
+        if changed {
+            self.send_dbus(PlayerInfo::clone(&self.state.player)).await;
+            self.state.dirty = true;
+        }
+    }
+    /// Publish the state to the remote controllers, unless it did not change
+    fn publish_state(&self) {
+        #[cfg(feature = "remote")]
+        if let Some(remote) = &self.remote {
+            let state = RemoteState {
+                client: self
+                    .get_active_player()
+                    .map(|player| self.clients[player].name.clone()),
+                sources: self.state.clients.entries.to_vec(),
+                player: PlayerInfo::clone(&self.state.player),
+                selected: self.get_current_client().map(|client| client.name.clone()),
+                playlists: self
+                    .state
+                    .playlists
+                    .entries
+                    .iter()
+                    .map(|playlist| playlist.as_ref().into())
+                    .collect(),
+            };
+            // the receivers are only woken up by actual changes
+            remote.send_if_modified(|current| {
+                let modified = *current != state;
+                if modified {
+                    *current = state;
+                }
+                modified
+            });
+        }
+    }
     async fn handle_event(&mut self, event: MyEvents) {
+        // the state of the player is compared with the one last displayed
+        if !matches!(
+            event,
+            MyEvents::Action(Action::Render) | MyEvents::RefreshPlayerState
+        ) {
+            self.state.dirty = true;
+        }
         match event {
             MyEvents::RefreshPlayerState => {
                 self.update_state().await;
                 // immediatly notify dbus and tui of new state
-                self.send_dbus(self.state.player.clone()).await;
-                self.render().await;
+                self.propagate_player().await;
+                if self.state.dirty {
+                    self.render().await;
+                }
             }
             MyEvents::Action(action) => self.handle_action(action).await,
             MyEvents::Widget(widget) => {
+                self.record(format!("Popup: {}", widget.title()));
                 let _ = self.tui_tx.send(tui::Widget::Widget(widget).into()).await;
             }
-            MyEvents::Command(command) => {
-                if let Some(client) = self.state.clients.select {
-                    let _ = self.clients[client].send(Request::Command(command)).await;
+            MyEvents::Notification(notification) => self.notify(notification).await,
+            MyEvents::Download(progress) => self.handle_download(progress).await,
+            MyEvents::Command(command) => self.handle_command(command).await,
+            MyEvents::SearchResults {
+                client,
+                query,
+                songs,
+            } => self.handle_search_results(client, query, songs).await,
+            MyEvents::PlaylistPage {
+                client,
+                id,
+                offset,
+                songs,
+            } => self.handle_playlist_page(client, id, offset, songs).await,
+            MyEvents::Related {
+                client,
+                seed,
+                songs,
+            } => self.handle_related(client, seed, songs).await,
+            #[cfg(feature = "logind")]
+            MyEvents::Session(event) => self.handle_session(event).await,
+        }
+    }
+
+    /// Grow the song list as the pages of the playlist displayed arrive,
+    /// rather than waiting for the next update of the state
+    async fn handle_playlist_page(
+        &mut self,
+        client: String,
+        id: String,
+        offset: usize,
+        songs: Vec<SongInfo>,
+    ) {
+        let displayed = self.get_current_client().is_some_and(|c| c.name == client)
+            && self
+                .state
+                .playlists
+                .get_selected()
+                .is_some_and(|p| p.id == id);
+        if !displayed || offset > self.state.songs.entries.len() {
+            return;
+        }
+        // the selection is kept, so the list can be scrolled while it grows
+        self.state.songs.entries_mut().truncate(offset);
+        self.state.songs.entries_mut().extend(songs);
+        self.update_playlist_summary();
+        self.render().await;
+    }
+
+    /// Pause or resume the active player as configured when the session goes idle
+    /// or becomes active again, and pause before the system suspends or when an audio device
+    /// is disconnected, the playback is then resumed by the user
+    #[cfg(feature = "logind")]
+    async fn handle_session(&mut self, event: SessionEvent) {
+        self.record(format!("Session: {event:?}"));
+        if event == SessionEvent::Wake {
+            // tokens may have expired and connections been dropped while suspended
+            for index in 0..self.clients.len() {
+                self.send_client(index, Request::Revalidate).await;
+            }
+            return;
+        }
+        let player = match self.get_active_player() {
+            Some(player) => player,
+            None => return,
+        };
+        let policy = config::get_config()
+            .idle_policies
+            .get(&self.clients[player].name)
+            .copied()
+            .unwrap_or_default();
+        let playing = self.state.player.playback == Playback::Play;
+        match event {
+            SessionEvent::Sleep if playing => {
+                self.handle_player(PlayerAction::PlayPause(true)).await;
+            }
+            SessionEvent::AudioDeviceLost if playing => {
+                self.handle_player(PlayerAction::PlayPause(true)).await;
+                let message = "Paused as an audio device was disconnected";
+                self.notify(Notification::new(Severity::Info, message))
+                    .await;
+            }
+            SessionEvent::Idle | SessionEvent::Lock => {
+                if policy.pause && playing {
+                    self.paused_on_idle = true;
+                    self.handle_player(PlayerAction::PlayPause(true)).await;
+                }
+            }
+            SessionEvent::Active | SessionEvent::Unlock => {
+                // never resume a playback paused by the user
+                if self.paused_on_idle {
+                    self.paused_on_idle = false;
+                    if policy.resume {
+                        self.handle_player(PlayerAction::PlayPause(false)).await;
+                    }
                 }
             }
+            SessionEvent::Sleep | SessionEvent::Wake | SessionEvent::AudioDeviceLost => (),
         }
     }
 
-    async fn handle_action(&mut self, action: Action) {
-        match action {
-            Action::Render => self.render().await,
-            Action::PauseRender(val) => self.tui_refresh = val,
-            Action::Player(action) => self.handle_player(action).await,
-            Action::Menu(action) => self.handle_menu(action).await,
-            Action::Quit => self.quit().await,
-            Action::Update => self.update_state().await,
-            Action::CloseAlert => {
-                let _ = self.state.alerts.pop();
+    /// Handle the commands meant for the orchestrator,
+    /// other commands are forwarded to the selected client
+    async fn handle_command(&mut self, command: String) {
+        self.record(format!("Command: {command}"));
+        if let Some(target) = command.strip_prefix("play on ") {
+            self.play_on(target.trim()).await;
+        } else if command == "move" {
+            self.move_playback().await;
+        } else if let Some(target) = command.strip_prefix("seek ") {
+            match parse_seek(target.trim()) {
+                Some(action) => self.handle_player(action).await,
+                None => {
+                    let message = "Usage: seek [+|-][[HH:]MM:]SS[.mmm] | seek [+|-]<percent>%";
+                    self.notify(Notification::new(Severity::Warning, message))
+                        .await
+                }
             }
-            Action::Alert(alert) => self.state.alerts.push(alert),
-            Action::ToggleAuto => self.toggle_auto().await,
-            Action::GoToCurrent => self.select_playing(),
-            Action::CommandPrompt => {
-                let _ = self.tui_tx.send(tui::Widget::CommandPrompt.into()).await;
+        } else if let Some(speed) = command.strip_prefix("speed ") {
+            match speed.trim().parse::<f64>() {
+                Ok(speed) if speed > 0.0 => {
+                    let percent = (speed * 100.0).round() as u16;
+                    self.handle_player(PlayerAction::SetSpeed(percent)).await
+                }
+                _ => {
+                    let message = format!(
+                        "Usage: speed <{}-{}>",
+                        f64::from(MIN_SPEED) / 100.0,
+                        f64::from(MAX_SPEED) / 100.0
+                    );
+                    self.notify(Notification::new(Severity::Warning, message))
+                        .await
+                }
+            }
+        } else if let Some(name) = command.strip_prefix("source ") {
+            self.select_source(name.trim()).await;
+        } else if let Some(id) = command.strip_prefix("open ") {
+            self.open_playlist(id.trim()).await;
+        } else if let Some(name) = command.strip_prefix("client enable ") {
+            self.enable_client(name.trim()).await;
+        } else if let Some(name) = command.strip_prefix("client disable ") {
+            self.disable_client(name.trim()).await;
+        } else if command == "reconnect" {
+            self.reconnect().await;
+        } else if command == "artist" {
+            self.show_related(false).await;
+        } else if command == "album" {
+            self.show_related(true).await;
+        } else if command == "metrics" {
+            self.show_metrics().await;
+        } else if command == "probe" {
+            self.probe();
+        } else if command == "download" {
+            self.download_song().await;
+        } else if command == "download playlist" {
+            self.download_playlist().await;
+        } else if let Some(target) = command.strip_prefix("sync ") {
+            self.mirror_playlist(target.trim()).await;
+        } else if command == "export" {
+            self.export_playlist(None).await;
+        } else if let Some(format) = command.strip_prefix("export ") {
+            self.export_playlist(Some(format.trim())).await;
+        } else if let Some(path) = command.strip_prefix("import ") {
+            self.import_playlist(path.trim()).await;
+        } else if command == "marks" {
+            self.show_marks().await;
+        } else if command == "favorite" {
+            self.toggle_favorite().await;
+        } else if let Some(stars) = command.strip_prefix("rate ") {
+            match stars.trim().parse() {
+                Ok(stars) if stars <= Rating::MAX_STARS => self.rate(stars).await,
+                _ => {
+                    let message = format!("Usage: rate <0-{}>", Rating::MAX_STARS);
+                    self.notify(Notification::new(Severity::Warning, message))
+                        .await
+                }
             }
+        } else if command == "eq" {
+            self.open_equalizer().await;
+        } else if let Some(preset) = command.strip_prefix("eq ") {
+            self.apply_equalizer_preset(preset.trim()).await;
+        } else if command == "offline" {
+            self.toggle_offline().await;
+        } else if command == "history" {
+            self.show_history(String::new()).await;
+        } else if let Some(query) = command.strip_prefix("history ") {
+            self.show_history(query.trim().to_string()).await;
+        } else if let Some(filter) = command.strip_prefix("log ") {
+            self.filter_log(filter.trim()).await;
+        } else if let Some(query) = command.strip_prefix("search-all ") {
+            self.search_all(query.trim().to_string()).await;
+        } else if let Some(query) = command.strip_prefix("search ") {
+            self.search(query.trim().to_string()).await;
+        } else if let Some(client) = self.state.clients.select {
+            let _ = self.clients[client].send(Request::Command(command)).await;
         }
     }
 
-    async fn render(&mut self) {
-        if self.tui_refresh {
-            match self
-                .tui_tx
-                .send_timeout(
-                    tui::Event::Render(Box::new(self.state.clone())),
-                    self.timeout_duration,
-                )
-                .await
-            {
-                Ok(_) => (),
-                Err(mpsc::error::SendTimeoutError::Closed(_)) => self.quit().await, // if the tui has
-                // crashed quit
-                Err(mpsc::error::SendTimeoutError::Timeout(_)) => (), // ignore if timeout
+    /// Search the selected song (or the one currently playing) on the client named `target`
+    /// to play the best match there instead
+    async fn play_on(&mut self, target: &str) {
+        let song = self
+            .state
+            .songs
+            .get_selected()
+            .or(self.state.player.song_info.as_ref())
+            .cloned();
+        let index = self.clients.iter().position(|c| c.name == target);
+        match (song, index) {
+            (Some(song), Some(index)) => self.hand_off(Handoff::new(index, song, None)).await,
+            (_, None) => {
+                let message = format!("Unknown client {target}");
+                self.notify(Notification::new(Severity::Warning, message))
+                    .await
+            }
+            (None, _) => {
+                self.notify(Notification::new(Severity::Warning, "No song selected"))
+                    .await
             }
         }
     }
 
-    async fn quit(&mut self) {
-        self.cancel_token.cancel();
-        self.event_rx.close();
-        while self.event_rx.recv().await.is_some() {}
+    /// Ask the selected client to authenticate again, e.g. after its session expired
+    async fn reconnect(&mut self) {
+        let client = match self.state.clients.select {
+            Some(client) => client,
+            None => return,
+        };
+        self.record(format!("Reconnecting {}", self.clients[client].name));
+        self.send_client(client, Request::Reconnect).await;
     }
 
-    async fn handle_player(&mut self, action: PlayerAction) {
-        // TODO: avoid multiple active player at once
-        if let Some(player) = self.get_active_player() {
-            // TODO send_timeout to player
-            if self.clients[player].send(action.into()).await.is_err() {
-                // if the player has crashed, drop the client
-                self.clients.remove(player);
+    /// Stop the song playing and resume it at the same position on the selected client,
+    /// provided the song is found there
+    async fn move_playback(&mut self) {
+        let (player, target) = match (self.get_active_player(), self.state.clients.select) {
+            (Some(player), Some(target)) => (player, target),
+            _ => {
+                self.notify(Notification::new(Severity::Warning, "Nothing is playing"))
+                    .await;
                 return;
             }
-            self.update_state().await;
-            self.render().await;
+        };
+        let song = match &self.state.player.song_info {
+            Some(song) if self.state.player.playback != Playback::Stop => song.clone(),
+            _ => {
+                self.notify(Notification::new(Severity::Warning, "Nothing is playing"))
+                    .await;
+                return;
+            }
+        };
+        if player == target {
+            let message = format!("Already playing on {}", self.clients[target].name);
+            self.notify(Notification::new(Severity::Warning, message))
+                .await;
+            return;
         }
+        let position = self.state.position;
+        self.hand_off(Handoff::new(target, song, Some(position)))
+            .await;
     }
 
-    async fn handle_menu(&mut self, action: MenuCtrl) {
-        match action {
-            MenuCtrl::Next => self.offset(1),
-            MenuCtrl::Prev => self.offset(-1),
-            MenuCtrl::NextMenu => {
-                self.state.go_next_menu();
-                self.offset(0)
-            }
-            MenuCtrl::PrevMenu => {
-                self.state.go_prev_menu();
-                self.offset(0)
-            }
-            MenuCtrl::Offset(off) => self.offset(off),
+    /// Search the song of `handoff` on its target client, it is played once the results arrive
+    async fn hand_off(&mut self, handoff: Handoff) {
+        let target = handoff.target;
+        let request = GetRequest::Search(handoff.query()).into();
+        let missing = self.clients[target].capabilities.missing(&request);
+        if self.lacks(target, missing).await || !self.can_play(target).await {
+            return;
         }
-        self.refresh().await;
-        self.render().await;
+        self.pending_handoff = Some(handoff);
+        self.send_client(target, request).await;
     }
 
-    fn offset(&mut self, offset: isize) {
-        match self.state.active_menu {
-            Menu::Client => {
-                self.state.clients.offset(offset);
-                self.state.playlists.entries = self.get_current_client().unwrap().get_playlists();
-                self.state.playlists.select = None;
+    /// Seek to the position of the song handed off to `player` once it has started playing
+    async fn resume_handoff(&mut self, player: usize, info: &PlayerInfo) {
+        let playing = match (&self.resume, &info.song_info) {
+            (Some((client, url, _)), Some(song)) => {
+                *client == player && *url == song.url && info.playback == Playback::Play
             }
-            Menu::Playlist => {
-                self.state.playlists.offset(offset);
-                if let Some(client) = self.get_current_client() {
-                    self.state.songs.entries = client.get_songs(self.state.playlists.select);
-                }
-                self.state.songs.select = None;
+            _ => false,
+        };
+        if let (true, Some((_, _, position))) = (playing, self.resume.take()) {
+            let action = PlayerAction::Seek(SeekMode::Absolute(position));
+            self.send_client(player, action.into()).await;
+        }
+    }
+
+    /// Ask `player` for songs related to the last song of its tracklist once it plays it,
+    /// they are appended to the tracklist when they arrive
+    async fn continue_radio(&mut self, player: usize, info: &PlayerInfo) {
+        let capabilities = self.clients[player].capabilities;
+        if !self.state.radio || !capabilities.suggests_related || !capabilities.supports_queue {
+            return;
+        }
+        let last = info.tracklist.songs.len().checked_sub(1);
+        let song = match (&info.song_info, info.track_index) {
+            (Some(song), Some(index)) if Some(index) == last => song,
+            _ => return,
+        };
+        if self.radio_seed.as_ref() == Some(&song.id) {
+            return;
+        }
+        self.radio_seed = Some(song.id.clone());
+        self.record(format!(
+            "{}: radio after {}",
+            self.clients[player].name, song.title
+        ));
+        self.send_client(player, GetRequest::Related(song.clone()).into())
+            .await;
+    }
+
+    /// Append the songs related to `seed` which are not in the tracklist yet,
+    /// if `client` is still the active player and the radio is still on
+    async fn handle_related(&mut self, client: String, seed: String, songs: Vec<SongInfo>) {
+        let player = match self.get_active_player() {
+            Some(player) if self.clients[player].name == client => player,
+            _ => return,
+        };
+        if !self.state.radio || self.radio_seed.as_ref() != Some(&seed) {
+            return;
+        }
+        let tracklist = &self.state.player.tracklist.songs;
+        let songs: Vec<SongInfo> = songs
+            .into_iter()
+            .filter(|song| !song.unavailable && !tracklist.iter().any(|s| s.id == song.id))
+            .take(Self::RADIO_BATCH)
+            .collect();
+        if songs.is_empty() {
+            let message = format!("{client} found no new song related to the last one");
+            self.notify(Notification::new(Severity::Warning, message))
+                .await;
+            return;
+        }
+        self.record(format!("{client}: radio added {} songs", songs.len()));
+        for song in songs {
+            let action = PlayerAction::Enqueue { song, next: false };
+            self.send_client(player, action.into()).await;
+        }
+    }
+
+    /// Turn the radio on or off, it needs an active player able to suggest related songs
+    async fn toggle_radio(&mut self) {
+        if !self.state.radio {
+            if let Some(player) = self.get_active_player() {
+                let capabilities = self.clients[player].capabilities;
+                let missing = if !capabilities.suggests_related {
+                    Some("related songs")
+                } else {
+                    (!capabilities.supports_queue).then_some("a queue")
+                };
+                if self.lacks(player, missing).await {
+                    return;
+                }
+            }
+        }
+        self.state.radio = !self.state.radio;
+        self.radio_seed = None;
+        let message = if self.state.radio {
+            "Radio on, related songs are added once the tracklist ends"
+        } else {
+            "Radio off"
+        };
+        self.notify(Notification::new(Severity::Info, message))
+            .await;
+        self.render().await;
+    }
+
+    /// Change the filter of the log tab, `filter` is either `level <level>` or `module [name]`
+    async fn filter_log(&mut self, filter: &str) {
+        let (kind, value) = filter.split_once(' ').unwrap_or((filter, ""));
+        match kind {
+            "level" => match value.trim().parse() {
+                Ok(level) => self.state.log_filter.level = level,
+                Err(_) => {
+                    let message = format!("Unknown log level {value}");
+                    self.notify(Notification::new(Severity::Warning, message))
+                        .await;
+                    return;
+                }
+            },
+            "module" => self.state.log_filter.module = value.trim().to_string(),
+            _ => {
+                let message = "Usage: log level <level> | log module [name]";
+                self.notify(Notification::new(Severity::Warning, message))
+                    .await;
+                return;
+            }
+        }
+        self.state.active_tab = Tab::Log;
+        self.update_log();
+        self.render().await;
+    }
+
+    fn update_log(&mut self) {
+        self.state.log = logging::entries(&self.state.log_filter, Self::LOG_LINES).into();
+    }
+
+    /// Display the songs played whose title, artist or album contains `query`
+    async fn show_history(&mut self, query: String) {
+        self.state.history_query = query;
+        self.state.history.select = None;
+        self.state.active_tab = Tab::History;
+        self.update_history();
+        self.render().await;
+    }
+
+    fn update_history(&mut self) {
+        let entries = self.history.search(&self.state.history_query);
+        if !matches!(self.state.history.select, Some(select) if select < entries.len()) {
+            self.state.history.select = None;
+        }
+        self.state.history.entries = entries.into();
+    }
+
+    /// Follow the song played by the client at index `player`,
+    /// recording the previous one in the history when it changes
+    fn track_listening(&mut self, player: usize, info: &PlayerInfo) {
+        let source = self.clients[player].name.clone();
+        let song = match &info.song_info {
+            Some(song) if info.playback != Playback::Stop => song,
+            _ => {
+                self.stop_listening();
+                return;
+            }
+        };
+        match &mut self.listening {
+            Some(entry) if entry.song.url == song.url && entry.source == source => {
+                entry.update_completion(info.position)
+            }
+            _ if info.playback == Playback::Play => {
+                self.stop_listening();
+                let mut entry = HistoryEntry::new(song.clone(), source);
+                entry.update_completion(info.position);
+                self.listening = Some(entry);
+            }
+            _ => (),
+        }
+    }
+
+    /// Load the lyrics of the song playing once it changed
+    fn update_lyrics(&mut self) {
+        let song = self.state.player.song_info.as_ref();
+        if song.map(|song| &song.url) == self.lyrics_song.as_ref() {
+            return;
+        }
+        self.lyrics_song = song.map(|song| song.url.clone());
+        self.state.lyrics = Arc::new(song.map(lyrics::load).unwrap_or_default());
+    }
+
+    /// Record the song being played in the history
+    fn stop_listening(&mut self) {
+        if let Some(entry) = self.listening.take() {
+            self.history.push(entry);
+            if self.state.active_tab == Tab::History {
+                self.update_history();
+            }
+        }
+    }
+
+    /// Change the rating of the selected song
+    async fn update_rating(
+        &mut self,
+        update: impl FnOnce(&mut Rating),
+    ) -> Option<(String, Rating)> {
+        let (client, song) = match self.selected_song() {
+            Some((client, song)) => (client, song.clone()),
+            None => {
+                self.notify(Notification::new(Severity::Warning, "No song selected"))
+                    .await;
+                return None;
+            }
+        };
+        let source = self.clients[client].name.clone();
+        self.ratings.update(&song, &source, update);
+        self.update_ratings();
+        self.render().await;
+        let rating = self.ratings.get(&song);
+        Some((song.title, rating))
+    }
+
+    async fn toggle_favorite(&mut self) {
+        let update = |rating: &mut Rating| rating.favorite = !rating.favorite;
+        if let Some((title, rating)) = self.update_rating(update).await {
+            if rating.favorite {
+                self.record(format!("Added {title} to the favorites"));
+            } else {
+                self.record(format!("Removed {title} from the favorites"));
+            }
+        }
+    }
+
+    async fn rate(&mut self, stars: u8) {
+        if let Some((title, _)) = self.update_rating(|rating| rating.stars = stars).await {
+            self.record(format!("Rated {title} {stars}/{}", Rating::MAX_STARS));
+        }
+    }
+
+    fn update_ratings(&mut self) {
+        self.state.ratings = Arc::new(self.ratings.all());
+        let favorites: Vec<SearchResult> = self
+            .ratings
+            .favorites()
+            .into_iter()
+            .map(|rated| SearchResult {
+                client: rated.source,
+                song: rated.song,
+            })
+            .collect();
+        if !matches!(self.state.favorites.select, Some(select) if select < favorites.len()) {
+            self.state.favorites.select = None;
+        }
+        self.state.favorites.entries = favorites.into();
+    }
+
+    /// Play the selected entry of the history on the client it was played on
+    async fn play_history_entry(&mut self) {
+        if let Some(entry) = self.state.history.get_selected().cloned() {
+            match self.clients.iter().position(|c| c.name == entry.source) {
+                Some(index) => self.play_song_on(index, entry.song).await,
+                None => {
+                    let message = format!("Unknown client {}", entry.source);
+                    self.notify(Notification::new(Severity::Warning, message))
+                        .await
+                }
+            }
+        }
+    }
+
+    /// Search `query` on the selected client, results are displayed in the search tab
+    async fn search(&mut self, query: String) {
+        if let Some(client) = self.state.clients.select {
+            self.search_on(query, vec![client]).await;
+        }
+    }
+
+    /// Search `query` on every client, their results being merged
+    async fn search_all(&mut self, query: String) {
+        let clients = (0..self.clients.len())
+            .filter(|&client| self.clients[client].capabilities.can_search)
+            .collect();
+        self.search_on(query, clients).await;
+    }
+
+    /// Stop waiting for the results of the clients which did not answer the search in time
+    fn expire_search(&mut self) {
+        let expired = self
+            .search_sent
+            .is_some_and(|sent| sent.elapsed() >= Self::SEARCH_TIMEOUT);
+        if expired && !self.state.search_pending.is_empty() {
+            let names = self.state.search_pending.join(", ");
+            self.record(format!("No search results from {names}"));
+            self.state.search_pending.clear();
+            self.search_sent = None;
+        }
+    }
+
+    /// Send `query` to the clients at indices `clients`, their results are displayed
+    /// together as they arrive
+    async fn search_on(&mut self, query: String, clients: Vec<usize>) {
+        let names: Vec<String> = clients
+            .iter()
+            .map(|&client| self.clients[client].name.clone())
+            .collect();
+        self.record(format!("Searching \"{query}\" on {}", names.join(", ")));
+        self.state.search_query = query.clone();
+        self.state.search = Default::default();
+        self.state.search_pending = names;
+        self.search_sent = Some(Instant::now());
+        for client in clients {
+            self.send_client(client, GetRequest::Search(query.clone()).into())
+                .await;
+        }
+    }
+
+    async fn handle_search_results(&mut self, client: String, query: String, songs: Vec<SongInfo>) {
+        self.record(format!(
+            "{client} found {} results for \"{query}\"",
+            songs.len()
+        ));
+        let index = self.clients.iter().position(|c| c.name == client);
+        if let (Some(handoff), Some(index)) = (&self.pending_handoff, index) {
+            if handoff.is_waiting(index, &query) {
+                let position = handoff.position;
+                let found = handoff.best_match(songs);
+                self.pending_handoff = None;
+                match found {
+                    Some(song) => {
+                        if let Some(position) = position {
+                            self.resume = Some((index, song.url.clone(), position));
+                        }
+                        self.play_song_on(index, song).await
+                    }
+                    None => {
+                        let message = format!("No match found on {client}");
+                        self.notify(Notification::new(Severity::Warning, message))
+                            .await
+                    }
+                }
+                return;
+            }
+        }
+        if let (Some(mirror), Some(index)) = (&mut self.mirror, index) {
+            if mirror.is_waiting(index, &query) {
+                mirror.handle_results(songs);
+                self.search_next_mirrored().await;
+                return;
+            }
+        }
+        if query == self.state.search_query && self.state.search_pending.contains(&client) {
+            self.state.search_pending.retain(|name| *name != client);
+            self.state
+                .search
+                .entries_mut()
+                .extend(songs.into_iter().map(|song| SearchResult {
+                    client: client.clone(),
+                    song,
+                }));
+            // group the results by client, in the order of the clients
+            let clients = &self.clients;
+            self.state
+                .search
+                .entries_mut()
+                .sort_by_key(|result| clients.iter().position(|c| c.name == result.client));
+            self.state.active_tab = Tab::Search;
+            self.render().await;
+        }
+    }
+
+    /// Copy the selected playlist to the client named `target`,
+    /// looking for each of its songs there
+    async fn mirror_playlist(&mut self, target: &str) {
+        let (source, playlist) = match (
+            self.state.clients.select,
+            self.state.playlists.get_selected(),
+        ) {
+            (Some(client), Some(playlist)) => (client, PlaylistInfo::clone(playlist)),
+            _ => {
+                self.notify(Notification::new(Severity::Warning, "No playlist selected"))
+                    .await;
+                return;
+            }
+        };
+        let target = match self.clients.iter().position(|c| c.name == target) {
+            Some(target) if target != source => target,
+            Some(_) => {
+                let message = "Cannot sync a playlist with its own client";
+                self.notify(Notification::new(Severity::Warning, message))
+                    .await;
+                return;
+            }
+            None => {
+                let message = format!("Unknown client {target}");
+                self.notify(Notification::new(Severity::Warning, message))
+                    .await;
+                return;
+            }
+        };
+        if playlist.songs.len() < playlist.length {
+            let message = format!("{} is still loading, try again later", playlist.title);
+            self.notify(Notification::new(Severity::Warning, message))
+                .await;
+            return;
+        }
+        if self.mirror.is_some() {
+            let message = "A playlist is already being synced";
+            self.notify(Notification::new(Severity::Warning, message))
+                .await;
+            return;
+        }
+        let message = format!(
+            "Looking for the {} songs of {} on {}",
+            playlist.songs.len(),
+            playlist.title,
+            self.clients[target].name
+        );
+        self.notify(Notification::new(Severity::Info, message))
+            .await;
+        if !self.can_mirror_to(target).await {
+            return;
+        }
+        self.mirror = Some(PlaylistMirror::new(target, playlist.title, playlist.songs));
+        self.search_next_mirrored().await;
+    }
+
+    /// Create a playlist on the selected client from the file at `path`,
+    /// looking for each of its songs there
+    async fn import_playlist(&mut self, path: &str) {
+        let target = match self.state.clients.select {
+            Some(target) => target,
+            None => return,
+        };
+        if self.mirror.is_some() {
+            let message = "A playlist is already being synced";
+            self.notify(Notification::new(Severity::Warning, message))
+                .await;
+            return;
+        }
+        let playlist = match playlist_file::import(&playlist_file::expand_home(path)) {
+            Ok(playlist) => playlist,
+            Err(err) => {
+                let message = format!("Failed to import {path}: {err}");
+                self.notify(Notification::new(Severity::Error, message))
+                    .await;
+                return;
+            }
+        };
+        let message = format!(
+            "Looking for the {} songs of {} on {}",
+            playlist.songs.len(),
+            playlist.title,
+            self.clients[target].name
+        );
+        self.notify(Notification::new(Severity::Info, message))
+            .await;
+        if !self.can_mirror_to(target).await {
+            return;
+        }
+        self.mirror = Some(PlaylistMirror::new(target, playlist.title, playlist.songs));
+        self.search_next_mirrored().await;
+    }
+
+    /// Search the next song of the playlist being copied,
+    /// or create the copy once every song was searched
+    async fn search_next_mirrored(&mut self) {
+        let (target, query) = match &mut self.mirror {
+            Some(mirror) => (mirror.target, mirror.next_query()),
+            None => return,
+        };
+        if let Some(query) = query {
+            let request = GetRequest::Search(query).into();
+            if !self.try_send_client(target, request).await {
+                self.abort_mirror("the search could not be sent").await;
+            }
+            return;
+        }
+        if let Some(mirror) = self.mirror.take() {
+            let songs = mirror.matched();
+            let title = format!("{} on {}", mirror.title, self.clients[target].name);
+            self.record(format!("{title}: {} songs found", songs.len()));
+            if !songs.is_empty() {
+                let request = Request::CreatePlaylist {
+                    title: mirror.title.clone(),
+                    songs,
+                };
+                self.send_client(target, request).await;
+            }
+            self.alert(&title, mirror.report()).await;
+        }
+    }
+
+    /// Stop copying the playlist being mirrored, the songs found so far are dropped
+    async fn abort_mirror(&mut self, reason: &str) {
+        if let Some(mirror) = self.mirror.take() {
+            let message = format!("Stopped syncing {}, {reason}", mirror.title);
+            self.record(message.clone());
+            self.notify(Notification::new(Severity::Error, message))
+                .await;
+        }
+    }
+
+    /// Jump to the song selected in the queue
+    async fn play_queue_entry(&mut self) {
+        if let (Some(player), Some(index)) = (self.get_active_player(), self.state.queue.select) {
+            self.record(format!(
+                "{}: jump to entry {index}",
+                self.clients[player].name
+            ));
+            self.send_client(player, PlayerAction::PlayIndex(index).into())
+                .await;
+        }
+    }
+
+    /// Play the selected album on the selected client, from the selected song if the songs
+    /// of the album have the focus
+    async fn play_album(&mut self) {
+        let (client, album) = match (self.state.clients.select, self.state.albums.get_selected()) {
+            (Some(client), Some(album)) => (client, album.clone()),
+            _ => return,
+        };
+        if !self.can_play(client).await {
+            return;
+        }
+        if let Some(player) = self.get_active_player() {
+            if player != client {
+                self.send_client(player, PlayerAction::Autoplay(false).into())
+                    .await;
+                self.send_client(player, PlayerAction::Stop.into()).await;
+            }
+        }
+        self.set_active_player(Some(client)).await;
+        self.record(format!(
+            "Playing {} on {}",
+            album.title, self.clients[client].name
+        ));
+        let start = match self.state.browse_menu {
+            BrowseMenu::Song => self.state.album_songs.select,
+            _ => None,
+        };
+        let songs = self.state.album_songs.entries.to_vec();
+        let tracklist = PlaylistInfo {
+            title: format!("{} - {}", album.artist, album.title),
+            length: songs.len(),
+            cover_url: album.cover_url,
+            id: album.id,
+            songs,
+            ..Default::default()
+        };
+        self.send_client(client, PlayerAction::SetTrackList(tracklist).into())
+            .await;
+        if let Some(index) = start {
+            self.send_client(client, PlayerAction::PlayIndex(index).into())
+                .await;
+        }
+        self.send_client(client, PlayerAction::Autoplay(true).into())
+            .await;
+    }
+
+    /// Play the selected search result or favorite on the client it comes from
+    async fn play_search_result(&mut self) {
+        let result = match self.state.active_tab {
+            Tab::Favorites => self.state.favorites.get_selected(),
+            _ => self.state.search.get_selected(),
+        };
+        if let Some(result) = result.cloned() {
+            if let Some(index) = self.clients.iter().position(|c| c.name == result.client) {
+                self.play_song_on(index, result.song).await;
+            }
+        }
+    }
+
+    /// Song selected in the current tab, or the one playing if none is selected
+    /// along with the index of the client it comes from
+    fn selected_song(&self) -> Option<(usize, &SongInfo)> {
+        let selected = match self.state.active_tab {
+            Tab::Library => self
+                .state
+                .clients
+                .select
+                .zip(self.state.songs.get_selected()),
+            Tab::Queue => self
+                .get_active_player()
+                .zip(self.state.queue.get_selected()),
+            Tab::Search => self.state.search.get_selected().and_then(|result| {
+                let client = self.clients.iter().position(|c| c.name == result.client)?;
+                Some((client, &result.song))
+            }),
+            Tab::History => self.state.history.get_selected().and_then(|entry| {
+                let client = self.clients.iter().position(|c| c.name == entry.source)?;
+                Some((client, &entry.song))
+            }),
+            Tab::Favorites => self.state.favorites.get_selected().and_then(|result| {
+                let client = self.clients.iter().position(|c| c.name == result.client)?;
+                Some((client, &result.song))
+            }),
+            Tab::Artists => self
+                .state
+                .clients
+                .select
+                .zip(self.state.album_songs.get_selected()),
+            Tab::Lyrics | Tab::Log => None,
+        };
+        selected.or_else(|| {
+            self.get_active_player()
+                .zip(self.state.player.song_info.as_ref())
+        })
+    }
+
+    /// Let the user pick one of the playlists of the client of the selected song,
+    /// the song is then added to it
+    async fn add_to_playlist(&mut self) {
+        let (client, song) = match self.selected_song() {
+            Some((client, song)) => (client, song.clone()),
+            None => {
+                self.notify(Notification::new(Severity::Warning, "No song selected"))
+                    .await;
+                return;
+            }
+        };
+        if !self.can_edit_playlists(client).await {
+            return;
+        }
+        let playlists: Vec<Arc<PlaylistInfo>> = self.clients[client]
+            .get_playlists()
+            .into_iter()
+            .filter(|playlist| playlist.editable)
+            .collect();
+        if playlists.is_empty() {
+            let message = format!("{} has no editable playlist", self.clients[client].name);
+            self.notify(Notification::new(Severity::Warning, message))
+                .await;
+            return;
+        }
+        let (backchannel, choice) = oneshot::channel();
+        let widget = InterfaceWidget::Radioboxes {
+            title: "Add to playlist".to_string(),
+            content: playlists
+                .iter()
+                .enumerate()
+                .map(|(i, playlist)| (i == 0, playlist.title.clone()))
+                .collect(),
+            backchannel,
+        };
+        let _ = self.tui_tx.send(tui::Widget::Widget(widget).into()).await;
+        let sender = self.clients[client].sender.clone();
+        tokio::spawn(async move {
+            // the popup may be closed without choosing any playlist
+            if let Some(playlist) = choice.await.ok().and_then(|i| playlists.get(i)) {
+                let request = SetRequest::AddSongToPlaylist {
+                    song,
+                    playlist: playlist.id.clone(),
+                };
+                let _ = sender.send(request.into()).await;
+            }
+        });
+    }
+
+    /// Copy the url of the selected song to the clipboard, or its id if it has none,
+    /// the song playing is copied when no song is selected
+    async fn yank(&mut self) {
+        let selected = self.selected_song().map(|(_, song)| song);
+        let text = match selected.or(self.state.player.song_info.as_ref()) {
+            Some(song) if !song.url.is_empty() => song.url.clone(),
+            Some(song) => song.id.clone(),
+            None => {
+                self.notify(Notification::new(Severity::Warning, "No song selected"))
+                    .await;
+                return;
+            }
+        };
+        let _ = self.tui_tx.send(tui::Event::Copy(text.clone())).await;
+        let message = format!("Copied {text}");
+        self.notify(Notification::new(Severity::Info, message))
+            .await;
+    }
+
+    /// Open the selected playlist while the playlists are focused, or the selected song,
+    /// with the default application
+    async fn open_in_browser(&mut self) {
+        let playlist_focused =
+            self.state.active_tab == Tab::Library && self.state.is_active_menu(Menu::Playlist);
+        let location = if playlist_focused {
+            let client = self.get_current_client().map(|client| client.name.as_str());
+            client
+                .zip(self.state.playlists.get_selected())
+                .and_then(|(client, playlist)| playlist_location(client, &playlist.id))
+        } else {
+            self.selected_song()
+                .and_then(|(_, song)| song_location(song))
+        };
+        let location = match location {
+            Some(location) => location,
+            None => {
+                let message = "Nothing to open, select a song or a playlist";
+                self.notify(Notification::new(Severity::Warning, message))
+                    .await;
+                return;
+            }
+        };
+        self.record(format!("Opening {location}"));
+        // the launcher may take some time to return
+        let target = location.clone();
+        let opened = tokio::task::spawn_blocking(move || open::that(target))
+            .await
+            .map_err(anyhow::Error::from)
+            .and_then(|opened| opened.map_err(anyhow::Error::from));
+        let notification = match opened {
+            Ok(()) => Notification::new(Severity::Info, format!("Opened {location}")),
+            Err(err) => {
+                Notification::new(Severity::Error, format!("Cannot open {location}: {err}"))
+            }
+        };
+        self.notify(notification).await;
+    }
+
+    /// Let the user pick a chapter of the song playing, the current one is preselected
+    async fn pick_chapter(&mut self) {
+        let player = match self.get_active_player() {
+            Some(player) => player,
+            None => {
+                self.notify(Notification::new(Severity::Warning, "Nothing is playing"))
+                    .await;
+                return;
+            }
+        };
+        let chapters = &self.state.player.chapters;
+        if chapters.is_empty() {
+            self.notify(Notification::new(
+                Severity::Warning,
+                "The song playing has no chapter",
+            ))
+            .await;
+            return;
+        }
+        let current = self.state.player.chapter;
+        let (backchannel, choice) = oneshot::channel();
+        let widget = InterfaceWidget::Radioboxes {
+            title: "Chapters".to_string(),
+            content: chapters
+                .iter()
+                .enumerate()
+                .map(|(i, chapter)| {
+                    let start = tui::duration_to_string(&chapter.start);
+                    (Some(i) == current, format!("{start} {}", chapter.title))
+                })
+                .collect(),
+            backchannel,
+        };
+        let _ = self.tui_tx.send(tui::Widget::Widget(widget).into()).await;
+        let sender = self.clients[player].sender.clone();
+        tokio::spawn(async move {
+            // the popup may be closed without choosing any chapter
+            if let Ok(index) = choice.await {
+                let _ = sender.send(PlayerAction::SetChapter(index).into()).await;
+            }
+        });
+    }
+
+    /// List in the search tab the songs in the playlists of the client of the selected song
+    /// from the same album if `album` is set, or by the same artist otherwise
+    async fn show_related(&mut self, album: bool) {
+        let (client, song) = match self.selected_song() {
+            Some((client, song)) => (client, song.clone()),
+            None => {
+                self.notify(Notification::new(Severity::Warning, "No song selected"))
+                    .await;
+                return;
+            }
+        };
+        let (kind, value) = if album {
+            ("album", &song.album)
+        } else {
+            ("artist", &song.artist)
+        };
+        if value.is_empty() {
+            let message = format!("The {kind} of {} is unknown", song.title);
+            self.notify(Notification::new(Severity::Warning, message))
+                .await;
+            return;
+        }
+        let query = format!("{kind}:{value}");
+        let value = value.to_lowercase();
+        let name = self.clients[client].name.clone();
+        let mut results: Vec<SearchResult> = Vec::new();
+        for song in self.clients[client]
+            .playlists_info
+            .iter()
+            .flat_map(|playlist| playlist.songs.iter())
+        {
+            let field = if album { &song.album } else { &song.artist };
+            if field.to_lowercase() == value && !results.iter().any(|r| r.song.url == song.url) {
+                results.push(SearchResult {
+                    client: name.clone(),
+                    song: song.clone(),
+                });
+            }
+        }
+        self.state.search_query = query;
+        self.state.search_pending.clear();
+        self.state.search.select = results.iter().position(|r| r.song.url == song.url);
+        self.state.search.entries = results.into();
+        self.state.active_tab = Tab::Search;
+        self.render().await;
+    }
+
+    /// Add the selected song to the tracklist of the active player, which must be
+    /// the client of the song, the client becomes the active player if none is
+    async fn enqueue(&mut self, next: bool) {
+        let (client, song) = match self.selected_song() {
+            Some((client, song)) => (client, song.clone()),
+            None => {
+                self.notify(Notification::new(Severity::Warning, "No song selected"))
+                    .await;
+                return;
+            }
+        };
+        match self.get_active_player() {
+            Some(player) if player != client => {
+                let message = format!(
+                    "Only songs from {} can be queued",
+                    self.clients[player].name
+                );
+                self.notify(Notification::new(Severity::Warning, message))
+                    .await;
+            }
+            _ => {
+                let action = PlayerAction::Enqueue { song, next };
+                let missing = self.clients[client].capabilities.missing_action(&action);
+                if self.lacks(client, missing).await {
+                    return;
+                }
+                self.set_active_player(Some(client)).await;
+                self.handle_player(action).await;
+            }
+        }
+    }
+
+    /// Ask for confirmation before removing the selected song from the selected playlist,
+    /// the playlist is fetched again once the song is removed
+    async fn remove_from_playlist(&mut self) {
+        let (client, playlist, song) = match (
+            self.state.clients.select,
+            self.state.playlists.get_selected(),
+            self.state.songs.get_selected(),
+        ) {
+            (Some(client), Some(playlist), Some(song)) => (client, playlist.clone(), song.clone()),
+            _ => {
+                self.notify(Notification::new(Severity::Warning, "No song selected"))
+                    .await;
+                return;
+            }
+        };
+        if !self.can_edit_playlists(client).await {
+            return;
+        }
+        if !playlist.editable {
+            let message = format!("{} cannot be edited", playlist.title);
+            self.notify(Notification::new(Severity::Warning, message))
+                .await;
+            return;
+        }
+        let (backchannel, choice) = oneshot::channel();
+        let widget = InterfaceWidget::Radioboxes {
+            title: format!("Remove {} from {}?", song.title, playlist.title),
+            content: vec![(true, "No".to_string()), (false, "Yes".to_string())],
+            backchannel,
+        };
+        let _ = self.tui_tx.send(tui::Widget::Widget(widget).into()).await;
+        let sender = self.clients[client].sender.clone();
+        tokio::spawn(async move {
+            if choice.await != Ok(1) {
+                return;
+            }
+            let request = SetRequest::RemoveSongFromPlaylist {
+                song,
+                playlist: playlist.id.clone(),
+            };
+            let _ = sender.send(request.into()).await;
+            let _ = sender
+                .send(GetRequest::Playlist(playlist.id.clone()).into())
+                .await;
+        });
+    }
+
+    /// Ask the client of the selected song to download it
+    async fn download_song(&mut self) {
+        match self.selected_song() {
+            Some((client, song)) => {
+                let song = song.clone();
+                self.record(format!("Downloading {}", song.title));
+                self.send_client(client, Request::Download(vec![song]))
+                    .await;
+            }
+            None => {
+                self.notify(Notification::new(Severity::Warning, "No song selected"))
+                    .await
+            }
+        }
+    }
+
+    /// Ask the selected client to download all the songs of the selected playlist
+    async fn download_playlist(&mut self) {
+        let (client, playlist) = match (
+            self.state.clients.select,
+            self.state.playlists.get_selected(),
+        ) {
+            (Some(client), Some(playlist)) => (client, PlaylistInfo::clone(playlist)),
+            _ => {
+                self.notify(Notification::new(Severity::Warning, "No playlist selected"))
+                    .await;
+                return;
+            }
+        };
+        if playlist.songs.len() < playlist.length {
+            let message = format!("{} is still loading, try again later", playlist.title);
+            self.notify(Notification::new(Severity::Warning, message))
+                .await;
+            return;
+        }
+        self.record(format!("Downloading {}", playlist.title));
+        self.send_client(client, Request::Download(playlist.songs))
+            .await;
+    }
+
+    /// Write the selected playlist to a file in the export folder,
+    /// using `format` or the configured one
+    async fn export_playlist(&mut self, format: Option<&str>) {
+        let config = config::get_config();
+        let format = match format.map(str::parse).unwrap_or(Ok(config.export_format)) {
+            Ok(format) => format,
+            Err(err) => {
+                let message = format!("{err}, expected m3u, json or csv");
+                self.notify(Notification::new(Severity::Warning, message))
+                    .await;
+                return;
+            }
+        };
+        let playlist = match self.state.playlists.get_selected() {
+            Some(playlist) => playlist.clone(),
+            None => {
+                self.notify(Notification::new(Severity::Warning, "No playlist selected"))
+                    .await;
+                return;
+            }
+        };
+        if playlist.songs.len() < playlist.length {
+            let message = format!("{} is still loading, try again later", playlist.title);
+            self.notify(Notification::new(Severity::Warning, message))
+                .await;
+            return;
+        }
+        let notification = match playlist_file::export(&playlist, format, &config.export_folder) {
+            Ok(path) => {
+                let message = format!("Exported {} to {}", playlist.title, path.display());
+                Notification::new(Severity::Info, message)
+            }
+            Err(err) => {
+                let message = format!("Failed to export {}: {err}", playlist.title);
+                Notification::new(Severity::Error, message)
+            }
+        };
+        self.notify(notification).await;
+    }
+
+    /// Ask the selected client to start or stop keeping the selected playlist offline
+    async fn toggle_offline(&mut self) {
+        match (
+            self.state.clients.select,
+            self.state.playlists.get_selected(),
+        ) {
+            (Some(client), Some(playlist)) => {
+                let id = playlist.id.clone();
+                self.send_client(client, Request::ToggleOffline(id)).await;
+            }
+            _ => {
+                self.notify(Notification::new(Severity::Warning, "No playlist selected"))
+                    .await
+            }
+        }
+    }
+
+    /// Open the equalizer widget with the gains last applied
+    async fn open_equalizer(&mut self) {
+        let widget = tui::Widget::Equalizer {
+            gains: self.equalizer.clone(),
+            band: 0,
+        };
+        let _ = self.tui_tx.send(widget.into()).await;
+    }
+
+    /// Apply the equalizer preset named `name` in the config
+    async fn apply_equalizer_preset(&mut self, name: &str) {
+        let presets = config::get_config().equalizer_presets;
+        match presets.get(name) {
+            Some(gains) => {
+                self.handle_player(PlayerAction::SetEqualizer(gains.clone()))
+                    .await
+            }
+            None => {
+                let mut names: Vec<&String> = presets.keys().collect();
+                names.sort();
+                let names: Vec<&str> = names.iter().map(|name| name.as_str()).collect();
+                let message = format!(
+                    "Unknown preset {name}, expected one of {}",
+                    names.join(", ")
+                );
+                self.notify(Notification::new(Severity::Warning, message))
+                    .await
+            }
+        }
+    }
+
+    /// Display the progress of a download, errors are notified
+    async fn handle_download(&mut self, progress: DownloadProgress) {
+        if let Some(error) = &progress.error {
+            let message = format!("Failed to download {}: {error}", progress.title);
+            self.notify(Notification::new(Severity::Error, message))
+                .await;
+        } else if progress.is_finished() {
+            self.state.download = None;
+            let folder = config::get_config().download_folder;
+            let message = format!(
+                "Downloaded {} songs into {}",
+                progress.total,
+                folder.display()
+            );
+            self.notify(Notification::new(Severity::Info, message))
+                .await;
+        } else {
+            self.state.download = Some(progress);
+            self.render().await;
+        }
+    }
+
+    /// Inspect the selected song in the background and display the available formats
+    /// or the errors encountered in a popup
+    fn probe(&mut self) {
+        let song = match self.selected_song() {
+            Some((_, song)) => song.clone(),
+            None => return,
+        };
+        self.record(format!("Probing {}", song.title));
+        let event_tx = self.event_tx.clone();
+        tokio::spawn(async move {
+            let content = probe::probe(&song).await;
+            let widget = InterfaceWidget::Alert {
+                title: format!("Probe: {}", song.title),
+                content,
+            };
+            let _ = event_tx.send(MyEvents::Widget(widget)).await;
+        });
+    }
+
+    /// Stop the active player and play `song` on the client at `index` instead
+    async fn play_song_on(&mut self, index: usize, song: SongInfo) {
+        if !self.can_play(index).await {
+            return;
+        }
+        if let Some(player) = self.get_active_player() {
+            if player != index {
+                self.send_client(player, PlayerAction::Autoplay(false).into())
+                    .await;
+                self.send_client(player, PlayerAction::Stop.into()).await;
+            }
+        }
+        self.set_active_player(Some(index)).await;
+        self.record(format!(
+            "Playing {} on {}",
+            song.title, self.clients[index].name
+        ));
+        let tracklist = PlaylistInfo {
+            title: song.title.clone(),
+            length: 1,
+            songs: vec![song],
+            ..Default::default()
+        };
+        self.send_client(index, PlayerAction::SetTrackList(tracklist).into())
+            .await;
+        self.send_client(index, PlayerAction::Autoplay(true).into())
+            .await;
+    }
+
+    /// Display all the metadata of the selected song in a popup
+    async fn show_song_details(&self) {
+        if let Some(song) = self.state.songs.get_selected() {
+            let source = self
+                .state
+                .clients
+                .get_selected()
+                .cloned()
+                .unwrap_or_default();
+            let year = song.year.map(|y| y.to_string()).unwrap_or_default();
+            let content = [
+                format!("Title: {}", song.title),
+                format!("Artist: {}", song.artist),
+                format!("Album: {}", song.album),
+                format!("Year: {year}"),
+                format!("Duration: {}", tui::duration_to_string(&song.duration)),
+                format!("Id: {}", song.id),
+                format!("URL: {}", song.url),
+                format!("Source: {source}"),
+            ]
+            .join("\n");
+            self.alert("Song details", content).await;
+        }
+    }
+
+    /// Display all the metadata of the selected playlist in a popup
+    async fn show_playlist_details(&self) {
+        if let Some(playlist) = self.state.playlists.get_selected() {
+            let source = self
+                .state
+                .clients
+                .get_selected()
+                .cloned()
+                .unwrap_or_default();
+            let visibility = playlist
+                .visibility
+                .map(|v| v.to_string())
+                .unwrap_or_default();
+            let editable = if playlist.editable { "yes" } else { "no" };
+            let content = [
+                format!("Title: {}", playlist.title),
+                format!("Owner: {}", playlist.owner),
+                format!("Visibility: {visibility}"),
+                format!("Editable: {editable}"),
+                format!("Songs: {}", playlist.length),
+                format!("Id: {}", playlist.id),
+                format!("Source: {source}"),
+                format!("Description: {}", playlist.description),
+            ]
+            .join("\n");
+            self.alert("Playlist details", content).await;
+        }
+    }
+
+    /// Queue `notification` to be displayed in the status bar,
+    /// the oldest ones are dropped if too many are waiting
+    async fn notify(&mut self, notification: Notification) {
+        self.record(format!(
+            "{:?}: {}",
+            notification.severity, notification.message
+        ));
+        if self.state.notifications.is_empty() {
+            self.notification_shown = Some(Instant::now());
+        }
+        self.state.notifications.push_back(notification);
+        while self.state.notifications.len() > Self::MAX_NOTIFICATIONS {
+            // never drop the notification being displayed
+            self.state.notifications.remove(1);
+        }
+        self.render().await;
+    }
+
+    /// Remove the notification displayed once its timeout has passed
+    fn expire_notification(&mut self) {
+        let timeout = Duration::from_secs(self.state.config.notification_timeout);
+        if let Some(shown) = self.notification_shown {
+            if shown.elapsed() >= timeout {
+                self.dismiss_notification();
+            }
+        }
+    }
+
+    fn dismiss_notification(&mut self) {
+        self.state.notifications.pop_front();
+        self.state.dirty = true;
+        self.notification_shown = if self.state.notifications.is_empty() {
+            None
+        } else {
+            Some(Instant::now())
+        };
+    }
+
+    /// Add `message` to the activity log, dropping the oldest entry if full
+    fn record(&mut self, message: String) {
+        debug!("[Activity] {message}");
+        if self.activity.len() >= Self::ACTIVITY_LEN {
+            self.activity.pop_front();
+        }
+        self.activity.push_back((Instant::now(), message));
+    }
+
+    /// Record the changes of playback and song of the client at index `player`
+    fn record_player_changes(&mut self, player: usize, info: &PlayerInfo) {
+        let name = self.clients[player].name.clone();
+        if info.playback != self.state.player.playback {
+            self.record(format!(
+                "{name}: {} -> {}",
+                self.state.player.playback, info.playback
+            ));
+        }
+        let title = info.song_info.as_ref().map(|song| &song.title);
+        if title != self.state.player.song_info.as_ref().map(|song| &song.title) {
+            if let Some(title) = title {
+                self.record(format!("{name}: now playing {title}"));
+            }
+        }
+    }
+
+    /// Display the activity log in a popup, the most recent entries first
+    async fn show_activity(&self) {
+        let content = self
+            .activity
+            .iter()
+            .rev()
+            .map(|(time, message)| format!("{:>4}s ago  {message}", time.elapsed().as_secs()))
+            .collect::<Vec<String>>()
+            .join("\n");
+        self.alert("Activity", content).await;
+    }
+
+    /// Show the latencies and channel depths measured so far
+    async fn show_metrics(&mut self) {
+        #[cfg(feature = "metrics")]
+        self.alert("Metrics", metrics::report()).await;
+        #[cfg(not(feature = "metrics"))]
+        {
+            let message = "yama was built without the metrics feature";
+            self.notify(Notification::new(Severity::Warning, message))
+                .await;
+        }
+    }
+
+    async fn alert(&self, title: &str, content: String) {
+        let widget = InterfaceWidget::Alert {
+            title: title.to_string(),
+            content,
+        };
+        let _ = self.tui_tx.send(tui::Widget::Widget(widget).into()).await;
+    }
+
+    async fn handle_action(&mut self, action: Action) {
+        match action {
+            Action::Render => self.render_tick().await,
+            Action::PauseRender(val) => self.tui_refresh = val,
+            Action::Player(action) => self.handle_player(action).await,
+            Action::Menu(action) => self.handle_menu(action).await,
+            Action::Quit => self.quit().await,
+            Action::Update => self.update_state().await,
+            Action::CloseAlert => self.dismiss_notification(),
+            Action::Alert(alert) => self.notify(Notification::new(Severity::Info, alert)).await,
+            Action::ToggleAuto => match self.state.active_tab {
+                Tab::Queue => self.play_queue_entry().await,
+                Tab::Search | Tab::Favorites => self.play_search_result().await,
+                Tab::History => self.play_history_entry().await,
+                Tab::Artists => self.play_album().await,
+                _ => self.toggle_auto().await,
+            },
+            Action::GoToCurrent => match self.state.active_tab {
+                Tab::Queue => self.state.queue.select = self.state.player.track_index,
+                _ => self.select_playing(),
+            },
+            Action::ActivityLog => self.show_activity().await,
+            Action::CycleLogLevel => {
+                self.state.log_filter.cycle_level();
+                self.update_log();
+                self.render().await;
+            }
+            Action::SelectTab(tab) => {
+                if tab == Tab::Artists {
+                    if let Some(client) = self.state.clients.select {
+                        let missing = self.clients[client]
+                            .capabilities
+                            .missing(&GetRequest::Artists.into());
+                        if self.lacks(client, missing).await {
+                            return;
+                        }
+                    }
+                }
+                self.state.active_tab = tab;
+                self.refresh().await;
+                self.render().await;
+            }
+            Action::SongDetails if self.state.is_active_menu(Menu::Playlist) => {
+                self.show_playlist_details().await
+            }
+            Action::SongDetails => self.show_song_details().await,
+            Action::ToggleFavorite => self.toggle_favorite().await,
+            Action::Equalizer => self.open_equalizer().await,
+            Action::MovePlayback => self.move_playback().await,
+            Action::AddToPlaylist => self.add_to_playlist().await,
+            Action::Chapters => self.pick_chapter().await,
+            Action::RemoveFromPlaylist => self.remove_from_playlist().await,
+            Action::Enqueue { next } => self.enqueue(next).await,
+            Action::GoToArtist => self.show_related(false).await,
+            Action::GoToAlbum => self.show_related(true).await,
+            Action::Reconnect => self.reconnect().await,
+            Action::ToggleRemaining => {
+                self.state.show_remaining = !self.state.show_remaining;
+                let show_remaining = self.state.show_remaining;
+                config::update_config(|config| config.show_remaining = show_remaining);
+                self.render().await;
+            }
+            Action::CommandPrompt => {
+                let _ = self.tui_tx.send(tui::Widget::CommandPrompt.into()).await;
+            }
+            Action::ToggleRadio => self.toggle_radio().await,
+            Action::ToggleZen => {
+                self.state.zen = !self.state.zen;
+                if self.state.zen {
+                    // the songs are the only panel left
+                    self.state.active_tab = Tab::Library;
+                    self.state.active_menu = Menu::Song;
+                }
+                let zen = self.state.zen;
+                config::update_config(|config| config.layout.zen = zen);
+                self.render().await;
+            }
+            Action::ToggleCompact => {
+                self.state.compact = !self.state.compact;
+                self.render().await;
+            }
+            Action::TogglePlaylistGrid => {
+                self.state.playlist_grid = !self.state.playlist_grid;
+                let grid = self.state.playlist_grid;
+                config::update_config(|config| config.layout.playlist_grid = grid);
+                self.render().await;
+            }
+            Action::Yank => self.yank().await,
+            Action::OpenInBrowser => self.open_in_browser().await,
+            Action::FindPrompt => {
+                let _ = self.tui_tx.send(tui::Widget::FindPrompt.into()).await;
+            }
+            Action::Find(text) => {
+                self.state.find = text;
+                self.select_match(false, true);
+                self.render().await;
+            }
+            Action::NextMatch | Action::PrevMatch => {
+                let forward = action == Action::NextMatch;
+                if !self.select_match(true, forward) && !self.state.find.is_empty() {
+                    let message = format!("No match for {}", self.state.find);
+                    self.notify(Notification::new(Severity::Warning, message))
+                        .await;
+                }
+                self.refresh().await;
+                self.render().await;
+            }
+            Action::SeekForward { large } => {
+                let offset = seek_step(&self.state.config, large);
+                self.handle_player(PlayerAction::Seek(SeekMode::Relative(offset)))
+                    .await
+            }
+            Action::SeekBackward { large } => {
+                let offset = -seek_step(&self.state.config, large);
+                self.handle_player(PlayerAction::Seek(SeekMode::Relative(offset)))
+                    .await
+            }
+            Action::VolumeUp => {
+                let step = self.state.config.volume_step as isize;
+                self.handle_player(PlayerAction::SetVolume(Volume::Relative(step)))
+                    .await
+            }
+            Action::VolumeDown => {
+                let step = self.state.config.volume_step as isize;
+                self.handle_player(PlayerAction::SetVolume(Volume::Relative(-step)))
+                    .await
+            }
+        }
+    }
+
+    /// Render on a refresh of the interface, unless nothing displayed changed
+    async fn render_tick(&mut self) {
+        // the position, the spinners and the notifications change on their own
+        let live = self.state.player.playback == Playback::Play
+            || !self.state.loading.is_empty()
+            || !self.state.notifications.is_empty()
+            || self.state.download.is_some();
+        let idle = match self.last_render {
+            Some(last_render) => last_render.elapsed() >= Self::IDLE_RENDER,
+            None => true,
+        };
+        if self.state.dirty || live || idle {
+            self.render().await;
+        }
+    }
+    async fn render(&mut self) {
+        self.interpolate_position();
+        if self.state.playlist_grid {
+            self.fetch_covers();
+        }
+        if self.tui_refresh {
+            match self
+                .tui_tx
+                .send_timeout(
+                    tui::Event::Render(Box::new(self.state.clone())),
+                    self.timeout_duration,
+                )
+                .await
+            {
+                Ok(_) => {
+                    self.state.dirty = false;
+                    self.last_render = Some(Instant::now());
+                }
+                Err(mpsc::error::SendTimeoutError::Closed(_)) => self.quit().await, // if the tui has
+                // crashed quit
+                Err(mpsc::error::SendTimeoutError::Timeout(_)) => (), // ignore if timeout
+            }
+        }
+    }
+
+    async fn quit(&mut self) {
+        self.stop_listening();
+        self.cancel_token.cancel();
+        self.event_rx.close();
+        while self.event_rx.recv().await.is_some() {}
+    }
+
+    async fn handle_player(&mut self, action: PlayerAction) {
+        // TODO: avoid multiple active player at once
+        if let Some(player) = self.get_active_player() {
+            let missing = self.clients[player].capabilities.missing_action(&action);
+            if self.lacks(player, missing).await {
+                return;
+            }
+            self.record(format!(
+                "{}: {}",
+                self.clients[player].name,
+                describe(&action)
+            ));
+            if let PlayerAction::SetEqualizer(gains) = &action {
+                self.equalizer = gains.clone();
+            }
+            let shuffles = matches!(
+                action,
+                PlayerAction::Shuffle(true) | PlayerAction::ShuffleToggle
+            );
+            if shuffles && config::get_config().shuffle_mode == ShuffleMode::Weighted {
+                let songs = &self.state.player.tracklist.songs;
+                let weights = shuffle::weights(songs, &self.ratings, &self.history);
+                let action = PlayerAction::SetShuffleWeights(weights);
+                // a player that cannot be reached is dropped when the shuffle is sent
+                let _ = self.clients[player].send(action.into()).await;
+            }
+            let jumps = matches!(
+                action,
+                PlayerAction::Seek(_)
+                    | PlayerAction::NextChapter
+                    | PlayerAction::PrevChapter
+                    | PlayerAction::SetChapter(_)
+            );
+            if let (true, Some(anchor)) = (jumps, &mut self.position) {
+                // the position stays still until the player reports the new one
+                anchor.received = None;
+            }
+            // TODO send_timeout to player
+            if self.clients[player].send(action.into()).await.is_err() {
+                // if the player has crashed, drop the client
+                let message = format!("Lost connection to {}", self.clients[player].name);
+                self.notify(Notification::new(Severity::Error, message))
+                    .await;
+                self.remove_client(player);
+                return;
+            }
+            self.update_state().await;
+            self.render().await;
+        }
+    }
+
+    /// Start advancing the position from the one reported in `info`, unless it is the
+    /// same report as before
+    fn anchor_position(&mut self, info: &PlayerInfo) {
+        let song = info.song_info.as_ref().map(|song| song.id.clone());
+        let playing = info.playback == Playback::Play;
+        let same = matches!(&self.position, Some(anchor) if anchor.reported == info.position
+            && anchor.song == song
+            && anchor.playing == playing);
+        if !same {
+            self.position = Some(PositionAnchor {
+                reported: info.position,
+                song,
+                playing,
+                received: Some(Instant::now()),
+            });
+        }
+    }
+    /// Advance the position of the song playing since it was last reported, the players
+    /// only report it every second or so
+    fn interpolate_position(&mut self) {
+        let player = &self.state.player;
+        let song = player.song_info.as_ref().map(|song| &song.id);
+        let anchor = match &self.position {
+            Some(anchor) if anchor.song.as_ref() == song => anchor,
+            // the player changed since the position was reported
+            _ => {
+                self.state.position = player.position;
+                return;
+            }
+        };
+        let elapsed = match anchor.received {
+            Some(received) if anchor.playing => received.elapsed(),
+            _ => Duration::ZERO,
+        };
+        let speed = player.speed.unwrap_or(100) as f64 / 100.0;
+        let position = anchor.reported + elapsed.mul_f64(speed);
+        self.state.position = match &player.song_info {
+            // the duration is unknown for live streams
+            Some(song) if !song.duration.is_zero() => position.min(song.duration),
+            _ => position,
+        };
+    }
+
+    async fn handle_menu(&mut self, action: MenuCtrl) {
+        match action {
+            MenuCtrl::Next | MenuCtrl::Prev | MenuCtrl::NextMenu | MenuCtrl::PrevMenu
+                if self.in_playlist_grid() =>
+            {
+                self.grid_move(action)
+            }
+            MenuCtrl::Next => self.offset(1),
+            MenuCtrl::Prev => self.offset(-1),
+            // the songs are the only panel displayed
+            MenuCtrl::NextMenu | MenuCtrl::PrevMenu
+                if self.state.zen && self.state.active_tab == Tab::Library => {}
+            // only the library and artists tabs have several menus
+            MenuCtrl::NextMenu if self.state.active_tab == Tab::Library => {
+                self.state.go_next_menu();
+                self.offset(0)
+            }
+            MenuCtrl::PrevMenu if self.state.active_tab == Tab::Library => {
+                self.state.go_prev_menu();
+                if self.state.is_active_menu(Menu::Client) && !self.state.config.layout.show_sources
+                {
+                    // the sources are hidden
+                    self.state.go_next_menu();
+                }
+                self.offset(0)
+            }
+            MenuCtrl::NextMenu if self.state.active_tab == Tab::Artists => {
+                self.state.go_next_browse_menu();
+                self.offset(0)
+            }
+            MenuCtrl::PrevMenu if self.state.active_tab == Tab::Artists => {
+                self.state.go_prev_browse_menu();
+                self.offset(0)
+            }
+            MenuCtrl::NextMenu | MenuCtrl::PrevMenu => (),
+            MenuCtrl::Offset(off) => self.offset(off),
+            MenuCtrl::SetMark(name) => self.set_mark(name).await,
+            MenuCtrl::JumpToMark(name) => self.jump_to_mark(name).await,
+        }
+        self.refresh().await;
+        self.render().await;
+    }
+
+    /// Mark the selected song of the selected playlist with `name`
+    async fn set_mark(&mut self, name: char) {
+        let playlist = match self.state.playlists.get_selected() {
+            Some(playlist) if self.state.active_tab == Tab::Library => playlist.id.clone(),
+            _ => {
+                self.notify(Notification::new(Severity::Warning, "No playlist selected"))
+                    .await;
+                return;
+            }
+        };
+        let index = match self.state.songs.select {
+            Some(index) => index,
+            None => {
+                self.notify(Notification::new(Severity::Warning, "No song selected"))
+                    .await;
+                return;
+            }
+        };
+        self.marks.set(&playlist, name, index);
+        let message = format!("Mark {name} set on song {}", index + 1);
+        self.notify(Notification::new(Severity::Info, message))
+            .await;
+    }
+
+    /// Select the song marked with `name` in the selected playlist
+    async fn jump_to_mark(&mut self, name: char) {
+        let mark = self
+            .state
+            .playlists
+            .get_selected()
+            .filter(|_| self.state.active_tab == Tab::Library)
+            .and_then(|playlist| self.marks.get(&playlist.id, name));
+        match mark {
+            // the playlist may have shrunk since the mark was set
+            Some(index) if !self.state.songs.entries.is_empty() => {
+                let last = self.state.songs.entries.len() - 1;
+                self.state.songs.select = Some(index.min(last));
+                self.state.active_menu = Menu::Song;
+            }
+            _ => {
+                let message = format!("No mark {name} in the selected playlist");
+                self.notify(Notification::new(Severity::Warning, message))
+                    .await;
+            }
+        }
+    }
+
+    /// List the marks of the selected playlist along with the songs under them
+    async fn show_marks(&mut self) {
+        let playlist = match self.state.playlists.get_selected() {
+            Some(playlist) => playlist,
+            None => {
+                self.notify(Notification::new(Severity::Warning, "No playlist selected"))
+                    .await;
+                return;
+            }
+        };
+        let marks: Vec<String> = self
+            .marks
+            .list(&playlist.id)
+            .into_iter()
+            .map(|(name, index)| {
+                let title = self
+                    .state
+                    .songs
+                    .entries
+                    .get(index)
+                    .map_or("", |song| song.title.as_str());
+                format!("{name}  {:>5}  {title}", index + 1)
+            })
+            .collect();
+        let content = if marks.is_empty() {
+            "No mark in this playlist".to_string()
+        } else {
+            marks.join("\n")
+        };
+        let title = format!("Marks of {}", playlist.title);
+        self.alert(&title, content).await;
+    }
+
+    /// Select the first entry of the focused panel of the library matching [State::find],
+    /// starting from the selected one, or after it if `skip_current` is set, and going
+    /// backwards unless `forward` is set, returns whether an entry matched
+    fn select_match(&mut self, skip_current: bool, forward: bool) -> bool {
+        if self.state.active_tab != Tab::Library || self.state.find.is_empty() {
+            return false;
+        }
+        let find = &self.state.find;
+        let matching: Vec<bool> = match self.state.active_menu {
+            Menu::Client => return false,
+            Menu::Playlist => self
+                .state
+                .playlists
+                .entries
+                .iter()
+                .map(|playlist| tui::matches(&playlist.title, find))
+                .collect(),
+            Menu::Song => self
+                .state
+                .songs
+                .entries
+                .iter()
+                .map(|song| {
+                    [&song.title, &song.artist, &song.album]
+                        .iter()
+                        .any(|text| tui::matches(text, find))
+                })
+                .collect(),
+        };
+        let len = matching.len();
+        let current = match self.state.active_menu {
+            Menu::Playlist => self.state.playlists.select,
+            _ => self.state.songs.select,
+        };
+        let current = current.unwrap_or_default();
+        let start = usize::from(skip_current);
+        // wrap around the end of the list
+        let found = (start..len + start)
+            .map(|step| {
+                if forward {
+                    (current + step) % len
+                } else {
+                    (current + len - step % len) % len
+                }
+            })
+            .find(|index| matching[*index]);
+        let index = match found {
+            Some(index) => index,
+            None => return false,
+        };
+        if self.state.active_menu == Menu::Playlist {
+            self.state.playlists.select(Some(index));
+            if let Some(client) = self.get_current_client() {
+                self.state.songs.entries = client.get_songs(Some(index)).into();
+            }
+            self.state.songs.select = None;
+        } else {
+            self.state.songs.select(Some(index));
+        }
+        true
+    }
+
+    /// The playlists grid of the library is focused
+    fn in_playlist_grid(&self) -> bool {
+        self.state.playlist_grid
+            && !self.state.zen
+            && self.state.active_tab == Tab::Library
+            && self.state.is_active_menu(Menu::Playlist)
+    }
+    /// Move in the playlist grid, up and down go to the row above and below,
+    /// left and right leave the grid past the edges of a row
+    fn grid_move(&mut self, action: MenuCtrl) {
+        let columns = tui::grid_columns(self.state.config.layout.sidebar_width) as isize;
+        let column = self.state.playlists.select.unwrap_or_default() as isize % columns;
+        let last = match self.state.playlists.select {
+            Some(select) => select + 1 >= self.state.playlists.entries.len(),
+            None => true,
+        };
+        match action {
+            MenuCtrl::Next => self.offset(columns),
+            MenuCtrl::Prev => self.offset(-columns),
+            MenuCtrl::NextMenu if column + 1 < columns && !last => self.offset(1),
+            MenuCtrl::PrevMenu if column > 0 => self.offset(-1),
+            MenuCtrl::NextMenu => {
+                self.state.go_next_menu();
+                self.offset(0)
+            }
+            MenuCtrl::PrevMenu => {
+                self.state.go_prev_menu();
+                if !self.state.config.layout.show_sources {
+                    self.state.go_next_menu();
+                }
+                self.offset(0)
+            }
+            _ => (),
+        }
+    }
+    /// Download in the background the covers of the playlists displayed in the grid
+    fn fetch_covers(&mut self) {
+        for playlist in self.state.playlists.entries.iter() {
+            self.covers.request(&playlist.cover_url);
+        }
+    }
+    fn offset(&mut self, offset: isize) {
+        match self.state.active_tab {
+            Tab::Library => self.library_offset(offset),
+            Tab::Artists => self.artists_offset(offset),
+            Tab::Queue => self.state.queue.offset(offset),
+            Tab::Search => self.state.search.offset(offset),
+            Tab::History => self.state.history.offset(offset),
+            Tab::Favorites => self.state.favorites.offset(offset),
+            Tab::Lyrics | Tab::Log => (),
+        }
+    }
+    fn artists_offset(&mut self, offset: isize) {
+        let client = match self.state.clients.select {
+            Some(client) => &self.clients[client],
+            None => return,
+        };
+        match self.state.browse_menu {
+            BrowseMenu::Artist => {
+                self.state.artists.offset(offset);
+                self.state.albums.entries =
+                    client.get_albums(self.state.artists.get_selected()).into();
+                self.state.albums.select = None;
+            }
+            BrowseMenu::Album => {
+                self.state.albums.offset(offset);
+                let album = self.state.albums.get_selected();
+                self.state.album_songs.entries = client.get_album_songs(album).into();
+                self.state.album_songs.select = None;
+            }
+            BrowseMenu::Song => self.state.album_songs.offset(offset),
+        }
+    }
+    fn library_offset(&mut self, offset: isize) {
+        match self.state.active_menu {
+            Menu::Client => {
+                self.state.clients.offset(offset);
+                if let Some(client) = self.get_current_client() {
+                    self.state.playlists.entries = client.get_playlists().into();
+                } else {
+                    self.state.playlists.entries = Arc::default();
+                    self.state.songs.entries = Arc::default();
+                }
+                self.state.playlists.select = None;
+            }
+            Menu::Playlist => {
+                self.state.playlists.offset(offset);
+                if let Some(client) = self.get_current_client() {
+                    self.state.songs.entries = client.get_songs(self.state.playlists.select).into();
+                }
+                self.state.songs.select = None;
             }
             Menu::Song => {
                 self.state.songs.offset(offset);
@@ -583,23 +3391,220 @@ impl Orchestrator {
         }
     }
     async fn send_client(&mut self, index: usize, request: Request) {
+        self.try_send_client(index, request).await;
+    }
+
+    /// Send `request` to the client at `index`, returns `false` if it was not sent
+    async fn try_send_client(&mut self, index: usize, request: Request) -> bool {
+        let missing = self.clients[index].capabilities.missing(&request);
+        if self.lacks(index, missing).await {
+            return false;
+        }
+        let get = match &request {
+            Request::Get(get) => Some(get.clone()),
+            _ => None,
+        };
         match self.clients[index]
             .send_timeout(request, self.timeout_duration)
             .await
         {
-            Ok(_) => (),
-            Err(mpsc::error::SendTimeoutError::Timeout(_)) => (),
+            Ok(_) => {
+                if let Some(get) = get {
+                    self.clients[index].requests.sent(&get);
+                }
+                true
+            }
+            Err(mpsc::error::SendTimeoutError::Timeout(_)) => {
+                self.clients[index].requests.failed();
+                false
+            }
             Err(mpsc::error::SendTimeoutError::Closed(_)) => {
                 // the client has drop the connection
-                self.clients.remove(index);
-                self.state.clients.select = None;
+                let message = format!("Lost connection to {}", self.clients[index].name);
+                self.notify(Notification::new(Severity::Error, message))
+                    .await;
+                self.remove_client(index);
+                false
+            }
+        }
+    }
+
+    /// Warn that the client at `index` does not support `feature`, if set,
+    /// returns whether it was set
+    async fn lacks(&mut self, index: usize, feature: Option<&str>) -> bool {
+        let feature = match feature {
+            Some(feature) => feature,
+            None => return false,
+        };
+        let message = format!("{} does not support {feature}", self.clients[index].name);
+        self.notify(Notification::new(Severity::Warning, message))
+            .await;
+        true
+    }
+
+    /// Warn when the client at `index` cannot play songs, returns whether it can
+    async fn can_play(&mut self, index: usize) -> bool {
+        let missing = (!self.clients[index].capabilities.can_play).then_some("playback");
+        !self.lacks(index, missing).await
+    }
+
+    /// Warn when the client at `index` cannot edit its playlists, returns whether it can
+    async fn can_edit_playlists(&mut self, index: usize) -> bool {
+        let capabilities = self.clients[index].capabilities;
+        let missing = (!capabilities.can_edit_playlists).then_some("editing playlists");
+        !self.lacks(index, missing).await
+    }
+
+    /// Warn when the client at `index` cannot receive a copy of a playlist, which requires
+    /// searching its songs and creating a playlist, returns whether it can
+    async fn can_mirror_to(&mut self, index: usize) -> bool {
+        if !self.clients[index].capabilities.can_search {
+            self.lacks(index, Some("searching")).await;
+            return false;
+        }
+        self.can_edit_playlists(index).await
+    }
+
+    /// Start the built-in client `name` if it is not running yet
+    async fn enable_client(&mut self, name: &str) {
+        let notification = if self.clients.iter().any(|c| c.name == name) {
+            Notification::new(Severity::Info, format!("{name} is already enabled"))
+        } else {
+            match Client::start(name, &self.cancel_token, self.event_tx.clone()) {
+                Some(client) => {
+                    self.record(format!("Enabling {name}"));
+                    self.clients.push(client);
+                    self.state.clients.entries_mut().push(name.to_string());
+                    if self.state.clients.select.is_none() {
+                        self.state.clients.select(Some(0));
+                    }
+                    Notification::new(Severity::Info, format!("Enabled {name}"))
+                }
+                None => {
+                    let clients = client::available().join(", ");
+                    let message = format!("Unknown client {name}, available: {clients}");
+                    Notification::new(Severity::Warning, message)
+                }
+            }
+        };
+        self.notify(notification).await;
+    }
+
+    /// Select the client `name` in the library
+    async fn select_source(&mut self, name: &str) {
+        let index = match self.clients.iter().position(|c| c.name == name) {
+            Some(index) => index,
+            None => {
+                let message = format!("{name} is not enabled");
+                self.notify(Notification::new(Severity::Warning, message))
+                    .await;
+                return;
+            }
+        };
+        self.state.clients.select(Some(index));
+        self.state.playlists.entries = self.clients[index].get_playlists().into();
+        self.state.playlists.select = None;
+        self.state.songs.entries = Arc::default();
+        self.state.songs.select = None;
+        self.state.active_tab = Tab::Library;
+        self.state.active_menu = Menu::Playlist;
+        self.refresh().await;
+        self.render().await;
+    }
+
+    /// Select the playlist `id` of the selected client in the library
+    async fn open_playlist(&mut self, id: &str) {
+        let index = self
+            .state
+            .playlists
+            .entries
+            .iter()
+            .position(|playlist| playlist.id == id);
+        let index = match index {
+            Some(index) => index,
+            None => {
+                let message = format!("No playlist {id} in the selected client");
+                self.notify(Notification::new(Severity::Warning, message))
+                    .await;
+                return;
+            }
+        };
+        self.state.playlists.select(Some(index));
+        if let Some(client) = self.get_current_client() {
+            self.state.songs.entries = client.get_songs(Some(index)).into();
+        }
+        self.state.songs.select = None;
+        self.state.active_tab = Tab::Library;
+        self.state.active_menu = Menu::Song;
+        self.refresh().await;
+        self.render().await;
+    }
+
+    /// Stop the client `name` and forget about it
+    async fn disable_client(&mut self, name: &str) {
+        let index = match self.clients.iter().position(|c| c.name == name) {
+            Some(index) => index,
+            None => {
+                let message = format!("{name} is not enabled");
+                self.notify(Notification::new(Severity::Warning, message))
+                    .await;
+                return;
             }
+        };
+        self.record(format!("Disabling {name}"));
+        let client = self.remove_client(index);
+        client.stop().await;
+        let message = format!("Disabled {name}");
+        self.notify(Notification::new(Severity::Info, message))
+            .await;
+    }
+
+    /// Remove the client at `index`, updating the indices referring to the other clients
+    fn remove_client(&mut self, index: usize) -> Client {
+        let client = self.clients.remove(index);
+        self.state.clients.entries_mut().remove(index);
+        self.state
+            .search_pending
+            .retain(|name| *name != client.name);
+        // index of a client once the one at `index` is removed
+        let shift = |i: usize| match i.cmp(&index) {
+            std::cmp::Ordering::Less => Some(i),
+            std::cmp::Ordering::Equal => None,
+            std::cmp::Ordering::Greater => Some(i - 1),
+        };
+        if self.state.active_player == Some(index) {
+            self.state.player = Arc::default();
+            self.state.queue.entries = Arc::default();
+            self.position = None;
         }
+        self.state.active_player = self.state.active_player.and_then(shift);
+        if self.state.clients.select == Some(index) {
+            self.state.clients.select = None;
+            self.state.playlists.entries = Arc::default();
+            self.state.playlists.select = None;
+            self.state.songs.entries = Arc::default();
+            self.state.songs.select = None;
+        }
+        self.state.clients.select = self.state.clients.select.and_then(shift);
+        self.resume = self
+            .resume
+            .take()
+            .and_then(|(i, url, position)| Some((shift(i)?, url, position)));
+        self.pending_handoff = self.pending_handoff.take().and_then(|mut handoff| {
+            handoff.target = shift(handoff.target)?;
+            Some(handoff)
+        });
+        self.mirror = self.mirror.take().and_then(|mut mirror| {
+            mirror.target = shift(mirror.target)?;
+            Some(mirror)
+        });
+        client
     }
 
     async fn toggle_auto(&mut self) {
         if self.state.player.autoplay {
             if let Some(player) = self.get_active_player() {
+                self.record(format!("Stopping {}", self.clients[player].name));
                 self.send_client(player, PlayerAction::Autoplay(false).into())
                     .await;
                 // immediatly stop the active player when deactivating autoplay
@@ -607,9 +3612,18 @@ impl Orchestrator {
                 self.send_client(player, PlayerAction::Stop.into()).await
             }
         } else if let Some(select) = self.state.playlists.select {
-            self.state.active_player = self.state.clients.select;
+            if let Some(client) = self.state.clients.select {
+                if !self.can_play(client).await {
+                    return;
+                }
+            }
+            self.set_active_player(self.state.clients.select).await;
             if let Some(client) = self.state.clients.select {
                 let playlist = self.clients[client].get_playlist(Some(select));
+                self.record(format!(
+                    "Playing {} on {}",
+                    playlist.title, self.clients[client].name
+                ));
                 self.send_client(client, PlayerAction::SetTrackList(playlist).into())
                     .await;
                 self.send_client(client, PlayerAction::Autoplay(true).into())
@@ -630,7 +3644,171 @@ impl Orchestrator {
                     .position(|p| p.id == self.state.player.tracklist.id);
                 self.state.songs.select = Some(index);
                 self.state.active_menu = Menu::Song;
+                self.state.active_tab = Tab::Library;
+            }
+        }
+    }
+}
+
+/// Short description of `action` for the activity log
+fn describe(action: &PlayerAction) -> String {
+    match action {
+        PlayerAction::SetTrackList(tracklist) => format!("SetTrackList({})", tracklist.title),
+        PlayerAction::Enqueue { song, next } => format!("Enqueue({}, next: {next})", song.title),
+        PlayerAction::SetShuffleWeights(weights) => format!("SetShuffleWeights({})", weights.len()),
+        action => format!("{action:?}"),
+    }
+}
+
+/// Parse the target of the `seek` command, either a percentage of the song (`45%`)
+/// or a timestamp (`1:23:45`, `83:45.5` or `5025`), relative if prefixed by `+` or `-`
+fn parse_seek(target: &str) -> Option<PlayerAction> {
+    let (sign, target) = match target.chars().next()? {
+        '+' => (Some(1), &target[1..]),
+        '-' => (Some(-1), &target[1..]),
+        _ => (None, target),
+    };
+    if let Some(percent) = target.strip_suffix('%') {
+        let percent = u8::try_from(parse_digits(percent.trim())?).ok()?;
+        let mode = match sign {
+            Some(sign) => SeekMode::RelativePercent(sign * i64::from(percent)),
+            None => SeekMode::AbsolutePercent(percent),
+        };
+        return (percent <= 100).then_some(PlayerAction::Seek(mode));
+    }
+    let parts: Vec<&str> = target.split(':').collect();
+    if parts.len() > 3 {
+        return None;
+    }
+    let (last, parts) = parts.split_last()?;
+    let last = last.trim();
+    let (whole, fraction) = last.split_once('.').unwrap_or((last, ""));
+    if !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    // the digits past the milliseconds are dropped
+    let fraction = format!("{fraction:0<3.3}");
+    let mut secs: u64 = 0;
+    for part in parts.iter().map(|part| part.trim()).chain([whole]) {
+        secs = secs.checked_mul(60)?.checked_add(parse_digits(part)?)?;
+    }
+    let millis = secs
+        .checked_mul(1000)?
+        .checked_add(parse_digits(&fraction)?)?;
+    let mode = match sign {
+        Some(sign) => SeekMode::Relative(sign * i64::try_from(millis).ok()?),
+        None => SeekMode::Absolute(Duration::from_millis(millis)),
+    };
+    Some(PlayerAction::Seek(mode))
+}
+
+/// Value of `digits` if it is only made of ascii digits, signs and exponents are rejected
+fn parse_digits(digits: &str) -> Option<u64> {
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+/// Offset in milliseconds of the configured seek step, the large one if `large` is set
+fn seek_step(config: &Config, large: bool) -> i64 {
+    let secs = if large {
+        config.seek_step_large
+    } else {
+        config.seek_step
+    };
+    secs as i64 * 1000
+}
+
+/// Web page of the playlist `id` of the client named `client`, or the folder holding it
+/// for the local clients, `None` if it is unknown
+fn playlist_location(client: &str, id: &str) -> Option<String> {
+    match client {
+        "youtube" => Some(format!("https://www.youtube.com/playlist?list={id}")),
+        "spotify" => {
+            // the id may be given as a uri, `spotify:playlist:<id>`
+            let id = id.rsplit(':').next().unwrap_or(id);
+            Some(format!("https://open.spotify.com/playlist/{id}"))
+        }
+        // the local playlists are folders or playlist files
+        _ => {
+            let path = Path::new(id);
+            if path.is_dir() {
+                Some(id.to_string())
+            } else {
+                path.parent()
+                    .filter(|_| path.is_file())
+                    .map(|folder| folder.display().to_string())
             }
         }
     }
 }
+
+/// Web page of `song`, or the folder holding it if it is a file
+fn song_location(song: &SongInfo) -> Option<String> {
+    if let Some(path) = song.url.strip_prefix("file://") {
+        return Path::new(path)
+            .parent()
+            .map(|folder| folder.display().to_string());
+    }
+    // the url of a spotify track is the one of the api
+    if song.url.starts_with("https://api.spotify.com/") {
+        let id = song.id.rsplit(':').next().unwrap_or(&song.id);
+        return Some(format!("https://open.spotify.com/track/{id}"));
+    }
+    (!song.url.is_empty()).then(|| song.url.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seek(mode: SeekMode) -> Option<PlayerAction> {
+        Some(PlayerAction::Seek(mode))
+    }
+
+    #[test]
+    fn parse_seek_absolute() {
+        let absolute = |millis| seek(SeekMode::Absolute(Duration::from_millis(millis)));
+        assert_eq!(parse_seek("5025"), absolute(5_025_000));
+        assert_eq!(parse_seek("83:45.5"), absolute(5_025_500));
+        assert_eq!(parse_seek("1:23:45"), absolute(5_025_000));
+        assert_eq!(parse_seek("0:01.2345"), absolute(1_234));
+    }
+
+    #[test]
+    fn parse_seek_relative() {
+        assert_eq!(parse_seek("+10"), seek(SeekMode::Relative(10_000)));
+        assert_eq!(parse_seek("-1:30"), seek(SeekMode::Relative(-90_000)));
+        assert_eq!(parse_seek("+0.5"), seek(SeekMode::Relative(500)));
+    }
+
+    #[test]
+    fn parse_seek_percent() {
+        assert_eq!(parse_seek("45%"), seek(SeekMode::AbsolutePercent(45)));
+        assert_eq!(parse_seek("100%"), seek(SeekMode::AbsolutePercent(100)));
+        assert_eq!(parse_seek("+5%"), seek(SeekMode::RelativePercent(5)));
+        assert_eq!(parse_seek("-20%"), seek(SeekMode::RelativePercent(-20)));
+        assert_eq!(parse_seek("101%"), None);
+        assert_eq!(parse_seek("300%"), None);
+    }
+
+    #[test]
+    fn parse_seek_rejects_huge_inputs() {
+        assert_eq!(parse_seek("99999999999999999999"), None);
+        assert_eq!(parse_seek("18446744073709551615"), None);
+        assert_eq!(parse_seek("+9223372036854775807"), None);
+        assert_eq!(parse_seek("99999999999999999:00:00"), None);
+        assert_eq!(parse_seek("99999999999999999999%"), None);
+    }
+
+    #[test]
+    fn parse_seek_rejects_malformed_inputs() {
+        for target in [
+            "", "+", "-", "%", "abc", "1:2:3:4", "1::2", ":30", "1.5:00", "1.2.3", "1.x", "+-5",
+            "--5", "1e3", "%5", "-+5%",
+        ] {
+            assert_eq!(parse_seek(target), None, "{target:?} was accepted");
+        }
+    }
+}
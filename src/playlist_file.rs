@@ -0,0 +1,281 @@
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Result};
+use directories::UserDirs;
+use serde::{Deserialize, Serialize};
+
+use crate::client::interface::{PlaylistInfo, SongInfo};
+
+/// Format of the files to which playlists are exported
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PlaylistFormat {
+    /// extended m3u, understood by most players
+    #[default]
+    M3u,
+    /// the playlist with all the metadata of its songs
+    Json,
+    /// one line per song
+    Csv,
+}
+
+impl PlaylistFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            PlaylistFormat::M3u => "m3u",
+            PlaylistFormat::Json => "json",
+            PlaylistFormat::Csv => "csv",
+        }
+    }
+}
+
+impl FromStr for PlaylistFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "m3u" | "m3u8" => Ok(PlaylistFormat::M3u),
+            "json" => Ok(PlaylistFormat::Json),
+            "csv" => Ok(PlaylistFormat::Csv),
+            _ => Err(anyhow!("unknown playlist format {s}")),
+        }
+    }
+}
+
+/// columns of the csv files
+const CSV_HEADER: [&str; 7] = ["title", "artist", "album", "year", "duration", "url", "id"];
+
+/// Location of a song in a m3u file, local files are written as paths
+fn song_location(song: &SongInfo) -> &str {
+    song.url.strip_prefix("file://").unwrap_or(&song.url)
+}
+
+fn to_m3u(playlist: &PlaylistInfo) -> String {
+    let mut content = format!("#EXTM3U\n#PLAYLIST:{}\n", playlist.title);
+    for song in &playlist.songs {
+        let name = if song.artist.is_empty() {
+            song.title.clone()
+        } else {
+            format!("{} - {}", song.artist, song.title)
+        };
+        let _ = writeln!(content, "#EXTINF:{},{name}", song.duration.as_secs());
+        if !song.album.is_empty() {
+            let _ = writeln!(content, "#EXTALB:{}", song.album);
+        }
+        let _ = writeln!(content, "{}", song_location(song));
+    }
+    content
+}
+
+/// Quote `field` if it contains a separator, a quote or a line break
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn to_csv(playlist: &PlaylistInfo) -> String {
+    let mut content = CSV_HEADER.join(",");
+    content.push('\n');
+    for song in &playlist.songs {
+        let year = song.year.map(|y| y.to_string()).unwrap_or_default();
+        let duration = song.duration.as_secs().to_string();
+        let fields = [
+            &song.title,
+            &song.artist,
+            &song.album,
+            &year,
+            &duration,
+            &song.url,
+            &song.id,
+        ];
+        let line: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        content.push_str(&line.join(","));
+        content.push('\n');
+    }
+    content
+}
+
+/// Name of the file of the playlist `title`, without the characters forbidden in paths
+fn file_name(title: &str, format: PlaylistFormat) -> String {
+    let name: String = title
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c => c,
+        })
+        .collect();
+    let name = if name.trim().is_empty() {
+        "playlist"
+    } else {
+        name.trim()
+    };
+    format!("{name}.{}", format.extension())
+}
+
+/// Write `playlist` in `folder` using `format`, returns the path of the file
+pub fn export(playlist: &PlaylistInfo, format: PlaylistFormat, folder: &Path) -> Result<PathBuf> {
+    let content = match format {
+        PlaylistFormat::M3u => to_m3u(playlist),
+        PlaylistFormat::Json => serde_json::to_string_pretty(playlist)?,
+        PlaylistFormat::Csv => to_csv(playlist),
+    };
+    fs::create_dir_all(folder)?;
+    let path = folder.join(file_name(&playlist.title, format));
+    fs::write(&path, content)?;
+    Ok(path)
+}
+
+/// Replace a leading `~` by the home directory
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), UserDirs::new()) {
+        (Some(path), Some(dirs)) => dirs.home_dir().join(path),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Split the name of a m3u entry, usually `artist - title`, into its artist and title
+fn split_name(name: &str) -> (String, String) {
+    match name.split_once(" - ") {
+        Some((artist, title)) => (artist.trim().to_string(), title.trim().to_string()),
+        None => (String::new(), name.trim().to_string()),
+    }
+}
+
+/// Url of an entry of the m3u file in `folder`, relative paths start from `folder`
+fn entry_url(location: &str, folder: &Path) -> String {
+    if location.contains("://") {
+        location.to_string()
+    } else {
+        format!("file://{}", folder.join(location).display())
+    }
+}
+
+fn from_m3u(content: &str, folder: &Path) -> PlaylistInfo {
+    let mut playlist = PlaylistInfo::default();
+    let mut song = SongInfo::default();
+    for line in content.lines().map(str::trim) {
+        if let Some(title) = line.strip_prefix("#PLAYLIST:") {
+            playlist.title = title.trim().to_string();
+        } else if let Some(info) = line.strip_prefix("#EXTINF:") {
+            let (duration, name) = info.split_once(',').unwrap_or((info, ""));
+            let secs: f64 = duration.trim().parse().unwrap_or_default();
+            // unknown durations are written as -1, infinite or huge ones are ignored as well
+            song.duration = Duration::try_from_secs_f64(secs).unwrap_or_default();
+            (song.artist, song.title) = split_name(name);
+        } else if let Some(album) = line.strip_prefix("#EXTALB:") {
+            song.album = album.trim().to_string();
+        } else if !line.is_empty() && !line.starts_with('#') {
+            song.url = entry_url(line, folder);
+            if song.title.is_empty() {
+                // untagged entries are named after their file
+                let name = Path::new(line).file_stem().unwrap_or_default();
+                (song.artist, song.title) = split_name(&name.to_string_lossy());
+            }
+            playlist.songs.push(std::mem::take(&mut song));
+        }
+    }
+    playlist
+}
+
+/// Split a line of a csv file into its fields, `lines` provides the following lines
+/// when a quoted field contains a line break
+fn csv_record<'a>(line: &'a str, lines: &mut impl Iterator<Item = &'a str>) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut line = line.to_string();
+    loop {
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            match (c, quoted) {
+                ('"', true) if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                ('"', _) => quoted = !quoted,
+                (',', false) => fields.push(std::mem::take(&mut field)),
+                (c, _) => field.push(c),
+            }
+        }
+        match lines.next() {
+            Some(next) if quoted => {
+                field.push('\n');
+                line = next.to_string();
+            }
+            _ => break,
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+fn from_csv(content: &str) -> Result<PlaylistInfo> {
+    let mut lines = content.lines();
+    let header = match lines.next() {
+        Some(header) => csv_record(header, &mut lines),
+        None => bail!("empty file"),
+    };
+    let columns: HashMap<String, usize> = header
+        .iter()
+        .enumerate()
+        .map(|(i, name)| (name.trim().to_lowercase(), i))
+        .collect();
+    if !columns.contains_key("title") {
+        bail!("missing title column");
+    }
+    let mut playlist = PlaylistInfo::default();
+    while let Some(line) = lines.next() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record = csv_record(line, &mut lines);
+        let field = |name: &str| {
+            columns
+                .get(name)
+                .and_then(|&i| record.get(i))
+                .cloned()
+                .unwrap_or_default()
+        };
+        playlist.songs.push(SongInfo {
+            title: field("title"),
+            artist: field("artist"),
+            album: field("album"),
+            year: field("year").parse().ok(),
+            duration: Duration::from_secs(field("duration").parse().unwrap_or_default()),
+            url: field("url"),
+            id: field("id"),
+            ..Default::default()
+        });
+    }
+    Ok(playlist)
+}
+
+/// Read the playlist in the file at `path`, its format is deduced from its extension,
+/// the playlist is named after the file if the file does not name it
+pub fn import(path: &Path) -> Result<PlaylistInfo> {
+    let content = fs::read_to_string(path)?;
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let mut playlist = match extension.parse().unwrap_or_default() {
+        PlaylistFormat::M3u => from_m3u(&content, path.parent().unwrap_or(Path::new("."))),
+        PlaylistFormat::Json => serde_json::from_str(&content)?,
+        PlaylistFormat::Csv => from_csv(&content)?,
+    };
+    if playlist.title.is_empty() {
+        let name = path.file_stem().unwrap_or_default();
+        playlist.title = name.to_string_lossy().to_string();
+    }
+    playlist.length = playlist.songs.len();
+    Ok(playlist)
+}
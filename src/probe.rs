@@ -0,0 +1,35 @@
+use log::debug;
+use tokio::process::Command;
+
+use crate::client::interface::SongInfo;
+
+/// Describe the streams available for `song` to find out why it cannot be played,
+/// local files are inspected with `ffprobe` and remote ones with `yt-dlp`
+pub async fn probe(song: &SongInfo) -> String {
+    let mut command = match song.url.strip_prefix("file://") {
+        Some(path) => {
+            let mut command = Command::new("ffprobe");
+            command.args(["-hide_banner", "-show_format", "-show_streams", path]);
+            command
+        }
+        None => {
+            let mut command = Command::new("yt-dlp");
+            command.args(["--list-formats", "--no-playlist", &song.url]);
+            command
+        }
+    };
+    debug!("[Probe] {command:?}");
+    let output = match command.output().await {
+        Ok(output) => output,
+        Err(err) => {
+            let program = command.as_std().get_program().to_string_lossy().to_string();
+            return format!("Cannot run {program}: {err}");
+        }
+    };
+    let mut report = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    let errors = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() || !errors.trim().is_empty() {
+        report.push_str(&format!("\n\n{}\n{}", output.status, errors.trim()));
+    }
+    report
+}
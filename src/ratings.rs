@@ -0,0 +1,108 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{client::interface::SongInfo, config, json_store};
+
+/// Rating given to a song, whatever the client it comes from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rating {
+    /// number of stars, from 0 to [Rating::MAX_STARS]
+    pub stars: u8,
+    pub favorite: bool,
+}
+
+impl Rating {
+    pub const MAX_STARS: u8 = 5;
+
+    pub fn is_empty(&self) -> bool {
+        self.stars == 0 && !self.favorite
+    }
+}
+
+/// Rated song along with the name of the client it comes from
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RatedSong {
+    pub song: SongInfo,
+    pub source: String,
+    pub rating: Rating,
+}
+
+/// Key under which the rating of `song` is stored, its url or its id if it has no url
+pub fn song_key(song: &SongInfo) -> &str {
+    if song.url.is_empty() {
+        &song.id
+    } else {
+        &song.url
+    }
+}
+
+/// Ratings of the songs of every client, saved on disk
+#[derive(Debug, Default)]
+pub struct Ratings {
+    songs: HashMap<String, RatedSong>,
+    path: PathBuf,
+}
+
+impl Ratings {
+    pub fn load() -> Self {
+        let mut path = config::get_dirs().data_dir().to_path_buf();
+        path.push("ratings.json");
+        let songs = json_store::load(&path);
+        Self { songs, path }
+    }
+
+    pub fn get(&self, song: &SongInfo) -> Rating {
+        self.songs
+            .get(song_key(song))
+            .map(|rated| rated.rating)
+            .unwrap_or_default()
+    }
+
+    /// Change the rating of `song` coming from the client named `source`
+    pub fn update(&mut self, song: &SongInfo, source: &str, update: impl FnOnce(&mut Rating)) {
+        let key = song_key(song).to_string();
+        let mut rating = self.get(song);
+        update(&mut rating);
+        if rating.is_empty() {
+            self.songs.remove(&key);
+        } else {
+            let rated = RatedSong {
+                song: song.clone(),
+                source: source.to_string(),
+                rating,
+            };
+            self.songs.insert(key, rated);
+        }
+        self.save();
+    }
+
+    /// Rating of each song indexed by [song_key]
+    pub fn all(&self) -> HashMap<String, Rating> {
+        self.songs
+            .iter()
+            .map(|(key, rated)| (key.clone(), rated.rating))
+            .collect()
+    }
+
+    /// Favorite songs from every client, the best rated first
+    pub fn favorites(&self) -> Vec<RatedSong> {
+        let mut favorites: Vec<RatedSong> = self
+            .songs
+            .values()
+            .filter(|rated| rated.rating.favorite)
+            .cloned()
+            .collect();
+        favorites.sort_by(|a, b| {
+            b.rating
+                .stars
+                .cmp(&a.rating.stars)
+                .then_with(|| a.song.title.cmp(&b.song.title))
+        });
+        favorites
+    }
+
+    fn save(&self) {
+        json_store::save(&self.path, &self.songs);
+    }
+}
@@ -0,0 +1,113 @@
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::client::interface::{PlayerInfo, PlaylistInfo, SongInfo, Volume};
+
+/// Playlist as listed to remote controllers, without its songs
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct PlaylistSummary {
+    pub id: String,
+    pub title: String,
+    pub length: usize,
+}
+
+impl From<&PlaylistInfo> for PlaylistSummary {
+    fn from(value: &PlaylistInfo) -> Self {
+        Self {
+            id: value.id.clone(),
+            title: value.title.clone(),
+            length: value.length.max(value.songs.len()),
+        }
+    }
+}
+
+/// State exposed to remote controllers, published by the orchestrator each time it changes
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RemoteState {
+    /// name of the client playing, if any
+    pub client: Option<String>,
+    /// names of the enabled clients
+    pub sources: Vec<String>,
+    pub player: PlayerInfo,
+    /// name of the selected client
+    pub selected: Option<String>,
+    /// playlists of the selected client
+    pub playlists: Vec<PlaylistSummary>,
+}
+
+/// `50` sets the volume, `+5` and `-5` change it
+pub fn parse_volume(text: &str) -> Option<Volume> {
+    if text.starts_with(['+', '-']) {
+        text.parse().ok().map(Volume::Relative)
+    } else {
+        text.parse::<usize>()
+            .ok()
+            .map(|volume| Volume::Absolute(volume.min(100)))
+    }
+}
+
+fn song_json(song: &SongInfo) -> Value {
+    json!({
+        "id": song.id,
+        "title": song.title,
+        "artist": song.artist,
+        "album": song.album,
+        "year": song.year,
+        "url": song.url,
+        "cover_url": song.cover_url,
+        "duration_ms": song.duration.as_millis() as u64,
+    })
+}
+
+impl RemoteState {
+    /// State of the player, positions and durations are in milliseconds
+    pub fn player_json(&self) -> Value {
+        let player = &self.player;
+        let song = player.song_info.as_ref().map(song_json);
+        json!({
+            "client": self.client,
+            "playback": player.playback.to_string(),
+            "song": song,
+            "position_ms": player.position.as_millis() as u64,
+            "volume": player.volume,
+            "muted": player.muted,
+            "shuffled": player.shuffled,
+            "autoplay": player.autoplay,
+            "repeat": player.repeat,
+            "track_index": player.track_index,
+            "tracklist": {
+                "id": player.tracklist.id,
+                "title": player.tracklist.title,
+                "length": player.tracklist.songs.len(),
+            },
+        })
+    }
+
+    /// Songs of the tracklist of the player
+    pub fn tracklist_json(&self) -> Value {
+        let tracklist = &self.player.tracklist;
+        let songs: Vec<Value> = tracklist.songs.iter().map(song_json).collect();
+        json!({
+            "id": tracklist.id,
+            "title": tracklist.title,
+            "songs": songs,
+        })
+    }
+
+    pub fn playlists_json(&self) -> Value {
+        json!({
+            "client": self.selected,
+            "playlists": self.playlists,
+        })
+    }
+
+    /// Everything known to remote controllers, in a single object
+    pub fn full_json(&self) -> Value {
+        json!({
+            "sources": self.sources,
+            "player": self.player_json(),
+            "tracklist": self.tracklist_json(),
+            "library": self.playlists_json(),
+        })
+    }
+}
@@ -0,0 +1,109 @@
+use std::time::{Duration, Instant};
+
+use crate::client::interface::{Answer, GetRequest};
+
+/// Request sent to a backend and still waiting for its answer
+#[derive(Debug)]
+struct Pending {
+    request: GetRequest,
+    sent: Instant,
+    /// number of times the request was sent
+    attempts: u32,
+}
+
+/// Requests of a client waiting for their answer, those timing out are sent again
+/// and the client is considered unhealthy once several of them went unanswered
+#[derive(Debug, Default)]
+pub struct Requests {
+    pending: Vec<Pending>,
+    /// requests given up on since the backend last answered
+    failures: u32,
+}
+
+impl Requests {
+    /// time after which a request is considered lost
+    const TIMEOUT: Duration = Duration::from_secs(10);
+    /// number of times a request is sent before giving up on it
+    const MAX_ATTEMPTS: u32 = 3;
+    /// number of requests given up on before the client is considered unhealthy
+    const MAX_FAILURES: u32 = 3;
+
+    /// Track `request` once sent, a request identical to one already waiting is ignored
+    pub fn sent(&mut self, request: &GetRequest) {
+        if self
+            .pending
+            .iter()
+            .any(|pending| pending.request == *request)
+        {
+            return;
+        }
+        self.pending.push(Pending {
+            request: request.clone(),
+            sent: Instant::now(),
+            attempts: 1,
+        });
+    }
+
+    /// The backend answered, the requests satisfied by `answer` are not waiting anymore
+    pub fn answered(&mut self, answer: &Answer) {
+        self.failures = 0;
+        self.pending
+            .retain(|pending| !answers(&pending.request, answer));
+    }
+
+    /// A request could not be sent
+    pub fn failed(&mut self) {
+        self.failures += 1;
+    }
+
+    /// Requests timing out to send again, the others timing out are given up on
+    pub fn expired(&mut self) -> Vec<GetRequest> {
+        let mut retries = Vec::new();
+        let mut failures = 0;
+        self.pending.retain_mut(|pending| {
+            if pending.sent.elapsed() < Self::TIMEOUT {
+                true
+            } else if pending.attempts < Self::MAX_ATTEMPTS && retried(&pending.request) {
+                pending.sent = Instant::now();
+                pending.attempts += 1;
+                retries.push(pending.request.clone());
+                true
+            } else {
+                failures += 1;
+                false
+            }
+        });
+        self.failures += failures;
+        retries
+    }
+
+    /// Returns `false` if too many requests went unanswered
+    pub fn is_healthy(&self) -> bool {
+        self.failures < Self::MAX_FAILURES
+    }
+}
+
+/// Returns `true` if `answer` is the answer to `request`
+fn answers(request: &GetRequest, answer: &Answer) -> bool {
+    match (request, answer) {
+        (GetRequest::PlaylistList, Answer::PlaylistList(_))
+        | (GetRequest::PlayerInfo, Answer::PlayerInfo(_))
+        | (GetRequest::Capabilities, Answer::Capabilities(_))
+        | (GetRequest::Artists, Answer::Artists(_)) => true,
+        (GetRequest::Playlist(id), Answer::Playlist(playlist)) => *id == playlist.id,
+        (GetRequest::Playlist(id), Answer::PlaylistPage { id: page, .. })
+        | (GetRequest::Playlist(id), Answer::PlaylistDelta { id: page, .. }) => id == page,
+        (GetRequest::Search(query), Answer::SearchResults { query: answer, .. }) => query == answer,
+        (GetRequest::Albums(id), Answer::Albums { artist, .. }) => id == artist,
+        (GetRequest::Album(id), Answer::Album(album)) => *id == album.id,
+        (GetRequest::Related(song), Answer::Related { seed, .. }) => song.id == *seed,
+        (request, Answer::Busy(busy)) => request == busy,
+        _ => false,
+    }
+}
+
+/// Returns `false` if sending `request` again could have side effects, the songs related to
+/// another may be picked at random and both answers would be added to the tracklist
+fn retried(request: &GetRequest) -> bool {
+    !matches!(request, GetRequest::Related(_))
+}
@@ -0,0 +1,99 @@
+use std::{fs, path::Path};
+
+use anyhow::Result;
+#[cfg(feature = "keyring")]
+use log::info;
+#[cfg(not(feature = "keyring"))]
+use log::warn;
+
+use crate::config::{self, SecretStorage};
+
+/// name of the service under which the secrets are stored in the keyring
+#[cfg(feature = "keyring")]
+const SERVICE: &str = "yama";
+
+/// returns `true` if the secrets are kept in the keyring
+pub fn in_keyring() -> bool {
+    config::get_config().secret_storage == SecretStorage::Keyring
+}
+
+/// Read the secret `name`, from the file at `path` unless the keyring is used
+pub fn read(name: &str, path: &Path) -> Result<String> {
+    if in_keyring() {
+        read_keyring(name, path)
+    } else {
+        Ok(fs::read_to_string(path)?)
+    }
+}
+
+/// Save `secret` under `name`, in the file at `path` unless the keyring is used
+pub fn write(name: &str, path: &Path, secret: &str) -> Result<()> {
+    if in_keyring() {
+        write_keyring(name, path, secret)
+    } else {
+        Ok(fs::write(path, secret)?)
+    }
+}
+
+/// Delete the secret `name`, its file at `path` unless the keyring is used
+#[cfg(feature = "youtube")]
+pub fn remove(name: &str, path: &Path) -> Result<()> {
+    if in_keyring() {
+        remove_keyring(name, path)
+    } else {
+        Ok(fs::remove_file(path)?)
+    }
+}
+
+/// Read the secret `name` from the keyring, a secret still in the file at `path`
+/// is moved to the keyring
+#[cfg(feature = "keyring")]
+fn read_keyring(name: &str, path: &Path) -> Result<String> {
+    let entry = keyring::Entry::new(SERVICE, name)?;
+    match entry.get_password() {
+        Ok(secret) => Ok(secret),
+        Err(keyring::Error::NoEntry) => {
+            let secret = fs::read_to_string(path)?;
+            entry.set_password(&secret)?;
+            // the file may have been provided by the user, it is not ours to delete
+            info!(
+                "[Secrets] Copied {} to the keyring, the file can be deleted",
+                path.display()
+            );
+            Ok(secret)
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+#[cfg(feature = "keyring")]
+fn write_keyring(name: &str, _path: &Path, secret: &str) -> Result<()> {
+    keyring::Entry::new(SERVICE, name)?.set_password(secret)?;
+    Ok(())
+}
+
+#[cfg(all(feature = "keyring", feature = "youtube"))]
+fn remove_keyring(name: &str, _path: &Path) -> Result<()> {
+    match keyring::Entry::new(SERVICE, name)?.delete_password() {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+#[cfg(not(feature = "keyring"))]
+fn read_keyring(name: &str, path: &Path) -> Result<String> {
+    warn!("[Secrets] Built without the keyring feature, reading {name} from its file");
+    Ok(fs::read_to_string(path)?)
+}
+
+#[cfg(not(feature = "keyring"))]
+fn write_keyring(name: &str, path: &Path, secret: &str) -> Result<()> {
+    warn!("[Secrets] Built without the keyring feature, writing {name} to its file");
+    Ok(fs::write(path, secret)?)
+}
+
+#[cfg(all(not(feature = "keyring"), feature = "youtube"))]
+fn remove_keyring(name: &str, path: &Path) -> Result<()> {
+    warn!("[Secrets] Built without the keyring feature, deleting the file of {name}");
+    Ok(fs::remove_file(path)?)
+}
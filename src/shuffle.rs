@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+#[cfg(feature = "mpv")]
+use std::collections::HashSet;
+
+#[cfg(feature = "mpv")]
+use rand::{seq::SliceRandom, thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+
+use crate::{client::interface::SongInfo, history::History, ratings::Ratings};
+
+/// How the songs of a playlist are ordered when it is shuffled
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ShuffleMode {
+    /// any order, a song may come back right after the playlist looped
+    Random,
+    /// random order in which the songs played recently come last
+    #[default]
+    Spread,
+    /// like [ShuffleMode::Spread], the best rated and most played songs tending to come first
+    Weighted,
+}
+
+/// Weight of `song` in a weighted shuffle in hundredths, at least 100
+fn weight(song: &SongInfo, ratings: &Ratings, plays: &HashMap<String, usize>) -> u32 {
+    let rating = ratings.get(song);
+    let favorite = if rating.favorite { 2. } else { 0. };
+    let plays = plays.get(&song.url).copied().unwrap_or_default() as f64;
+    let weight = 1. + f64::from(rating.stars) + favorite + plays.ln_1p();
+    (weight * 100.).round() as u32
+}
+
+/// Weights of `songs` in a weighted shuffle in hundredths, indexed by url
+pub fn weights(songs: &[SongInfo], ratings: &Ratings, history: &History) -> HashMap<String, u32> {
+    let mut plays: HashMap<String, usize> = HashMap::new();
+    for entry in history.search("") {
+        *plays.entry(entry.song.url).or_default() += 1;
+    }
+    songs
+        .iter()
+        .map(|song| (song.url.clone(), weight(song, ratings, &plays)))
+        .collect()
+}
+
+/// Order `indices` randomly, each index having a chance to come first proportional to
+/// the weight of its song in `weights`, songs without a weight weigh 100
+#[cfg(feature = "mpv")]
+fn weighted_shuffle(songs: &[SongInfo], indices: &mut [usize], weights: &HashMap<String, u32>) {
+    let mut rng = thread_rng();
+    // weighted sampling without replacement: the keys u^(1/w) are sorted in decreasing order
+    let mut keys: HashMap<usize, f64> = HashMap::new();
+    for &index in indices.iter() {
+        let weight = weights
+            .get(&songs[index].url)
+            .copied()
+            .unwrap_or(100)
+            .max(1);
+        let weight = f64::from(weight) / 100.;
+        keys.insert(index, rng.gen::<f64>().powf(1. / weight));
+    }
+    indices.sort_by(|a, b| keys[b].total_cmp(&keys[a]));
+}
+
+/// Shuffled order of `songs` as indices, `recent` are the indices of the songs played
+/// recently, the most recent last, `weights` are used by [ShuffleMode::Weighted]
+#[cfg(feature = "mpv")]
+pub fn shuffle(
+    songs: &[SongInfo],
+    recent: &[usize],
+    mode: ShuffleMode,
+    weights: &HashMap<String, u32>,
+) -> Vec<usize> {
+    let mut indices: Vec<usize> = (0..songs.len()).collect();
+    if mode == ShuffleMode::Random {
+        indices.shuffle(&mut thread_rng());
+        return indices;
+    }
+    let recent: Vec<usize> = recent
+        .iter()
+        .copied()
+        .filter(|&i| i < songs.len())
+        .collect();
+    let played: HashSet<usize> = recent.iter().copied().collect();
+    indices.retain(|i| !played.contains(i));
+    if mode == ShuffleMode::Weighted {
+        weighted_shuffle(songs, &mut indices, weights);
+    } else {
+        indices.shuffle(&mut thread_rng());
+    }
+    // the songs played the longest time ago come back first
+    indices.extend(recent);
+    indices
+}
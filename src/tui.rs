@@ -1,21 +1,33 @@
 use std::{
+    collections::HashMap,
     fmt::{self, Display},
+    io::Write,
     ops::{Deref, DerefMut},
-    time::Duration,
+    path::PathBuf,
+    rc::Rc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use anyhow::Result;
 use crossterm::{
     cursor,
-    event::{DisableMouseCapture, EnableMouseCapture, KeyCode, KeyEventKind},
+    event::{
+        DisableFocusChange, DisableMouseCapture, EnableFocusChange, EnableMouseCapture, KeyCode,
+        KeyEventKind,
+    },
     terminal::{EnterAlternateScreen, LeaveAlternateScreen},
 };
 use futures::{FutureExt, StreamExt};
+use log::Level;
 use ratatui::{
     backend::CrosstermBackend,
     layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Style, Stylize},
-    widgets::{Block, BorderType, Borders, Clear, List, ListItem, ListState, Paragraph, Wrap},
+    text::{Line, Span},
+    widgets::{
+        block::Title, Block, BorderType, Borders, Cell, Clear, List, ListItem, ListState,
+        Paragraph, Row, Table, TableState, Tabs, Wrap,
+    },
     Frame,
 };
 use thiserror::Error;
@@ -26,9 +38,18 @@ use tokio::{
 use tokio_util::sync::CancellationToken;
 
 use crate::{
-    client::interface::Widget as InterfaceWidget,
-    config::{self, Config},
-    orchestrator::{Action, ListHolderToString, Menu, MenuCtrl, MyEvents, State},
+    client::interface::{
+        Chapter, Notification, PlayerAction, Severity, SongInfo, Widget as InterfaceWidget,
+        EQUALIZER_BANDS, EQUALIZER_MAX_GAIN,
+    },
+    config::{self, ColumnConfig, Config, SongColumn},
+    covers::{self, ImageProtocol},
+    lyrics,
+    orchestrator::{
+        Action, BrowseMenu, ListHolderToString, Menu, MenuCtrl, MyEvents, PlaylistSummary, State,
+        Tab,
+    },
+    ratings::Rating,
 };
 
 type Backend<T> = CrosstermBackend<T>;
@@ -42,16 +63,25 @@ impl Display for Error {
 }
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub(crate) enum Widget {
     Widget(InterfaceWidget),
     CommandPrompt,
+    /// text searched in the focused panel, sent with each change
+    FindPrompt,
+    /// gain of each band of the equalizer, `band` being the one adjusted
+    Equalizer {
+        gains: Vec<i8>,
+        band: usize,
+    },
 }
 
 impl Widget {
     pub fn captures_output(&self) -> bool {
         match self {
             Widget::Widget(widget) => widget.captures_output(),
-            Widget::CommandPrompt => true,
+            Widget::CommandPrompt | Widget::FindPrompt => true,
+            Widget::Equalizer { .. } => false,
         }
     }
 }
@@ -66,6 +96,8 @@ impl From<InterfaceWidget> for Widget {
 pub enum Event {
     Render(Box<State>),
     Widget(Widget),
+    /// put the text in the clipboard of the terminal
+    Copy(String),
 }
 
 impl From<Widget> for Event {
@@ -85,27 +117,82 @@ pub struct Tui {
     terminal: ratatui::Terminal<Backend<std::io::Stderr>>,
     tasks: JoinHandle<()>,
     framerate: f64,
+    unfocused_framerate: f64,
+    /// the terminal has the focus, the interface is refreshed less often otherwise
+    focused: bool,
+    /// last state rendered, drawn again when the widgets change
+    state: Option<Box<State>>,
     cancel_token: CancellationToken,
     orchestrator_tx: Sender<MyEvents>,
     event_rx: Receiver<Event>,
     widgets: Vec<Widget>,
     prompt_string: String,
+    /// entry under the cursor when the widget displayed is a list of choices
+    cursor: usize,
     pub event_tx: Sender<Event>,
     /// Accumulate events to send a single [MenuCtrl::Offset] event, instead of overloading the
     /// channel with [MenuCtrl::Prev] or [MenuCtrl::Next] events
     offset: isize,
+    macros: Macros,
+    /// key pressed, waiting for the name of a register or of a mark
+    pending: Option<PendingKey>,
+    /// protocol used to draw the covers, [ImageProtocol::None] if the terminal has no support
+    images: ImageProtocol,
+    /// covers drawn over the last frame
+    thumbnails: Vec<Thumbnail>,
+}
+
+/// Cover drawn over an area of the screen once the frame is rendered
+#[derive(Debug, PartialEq, Eq)]
+struct Thumbnail {
+    area: Rect,
+    path: PathBuf,
+}
+
+/// Sequences of actions recorded into registers named by a key, to be replayed later
+#[derive(Default)]
+struct Macros {
+    registers: HashMap<char, Vec<Action>>,
+    /// register being recorded along with the actions recorded so far
+    recording: Option<(char, Vec<Action>)>,
+}
+
+/// Key whose action depends on the character typed next
+#[derive(Clone, Copy)]
+enum PendingKey {
+    RecordMacro,
+    ReplayMacro,
+    SetMark,
+    JumpToMark,
+}
+
+/// `framerate` bounded to the rates the interface can be refreshed at,
+/// `default` if it is not a number
+fn valid_framerate(framerate: f64, default: f64) -> f64 {
+    if framerate.is_finite() {
+        framerate.clamp(0.1, 240.0)
+    } else {
+        default
+    }
 }
 
 impl Tui {
     pub fn new(orchestrator_tx: Sender<MyEvents>, cancel_token: CancellationToken) -> Result<Self> {
-        let framerate = 10.0;
+        let config = config::get_config();
+        let defaults = Config::default();
         let terminal = ratatui::Terminal::new(Backend::new(std::io::stderr()))?;
         let (event_tx, event_rx) = mpsc::channel(32);
         let tasks = tokio::spawn(async {});
         Ok(Self {
             terminal,
             tasks,
-            framerate,
+            framerate: valid_framerate(config.framerate, defaults.framerate),
+            unfocused_framerate: valid_framerate(
+                config.unfocused_framerate,
+                defaults.unfocused_framerate,
+            ),
+            focused: true,
+            state: None,
             cancel_token,
             orchestrator_tx,
             event_rx,
@@ -113,21 +200,36 @@ impl Tui {
             widgets: Vec::new(),
             offset: 0,
             prompt_string: String::new(),
+            cursor: 0,
+            macros: Macros::default(),
+            pending: None,
+            images: config.layout.images.detect(),
+            thumbnails: Vec::new(),
         })
     }
     pub async fn run(&mut self) {
-        let frame_duration = std::time::Duration::from_secs_f64(1.0 / self.framerate);
+        let mut frame_duration = self.frame_duration();
         let cancel_token = self.cancel_token.clone();
         let mut reader = crossterm::event::EventStream::new();
         let mut render_interval = tokio::time::interval(frame_duration);
         loop {
+            if self.frame_duration() != frame_duration {
+                frame_duration = self.frame_duration();
+                render_interval = tokio::time::interval(frame_duration);
+            }
             let render_delay = render_interval.tick();
             let event = reader.next().fuse();
             tokio::select! {
                 _ = cancel_token.cancelled() => break,
                 Some(event) = event => {
                     if let Ok(event) =  event {
-                        if let Some(event) = self.handle_tui_event(event).await {
+                        // the orchestrator does not know about the widgets being edited
+                        let redraw = self.in_prompt() || matches!(event, crossterm::event::Event::Resize(..));
+                        let event = self.handle_tui_event(event).await;
+                        if redraw || self.in_prompt() {
+                            self.redraw();
+                        }
+                        if let Some(event) = event {
                             let _ = self.send_event(event, frame_duration).await;
                         };
                     } else {
@@ -152,6 +254,15 @@ impl Tui {
             }
         }
     }
+    /// Time between two refreshes of the interface
+    fn frame_duration(&self) -> Duration {
+        let framerate = if self.focused {
+            self.framerate
+        } else {
+            self.unfocused_framerate
+        };
+        Duration::from_secs_f64(1.0 / framerate)
+    }
     async fn send_event(&mut self, event: MyEvents, timeout: Duration) -> Result<()> {
         match self.orchestrator_tx.send_timeout(event, timeout).await {
             Ok(_) => Ok(()),
@@ -165,8 +276,35 @@ impl Tui {
 
     fn handle_event(&mut self, event: Event) {
         match event {
-            Event::Render(state) => self.render(&state),
-            Event::Widget(widget) => self.widgets.push(widget),
+            Event::Render(state) => {
+                self.render(&state);
+                self.state = Some(state);
+            }
+            Event::Widget(widget) => {
+                // start on the entry already selected
+                self.cursor = match &widget {
+                    Widget::Widget(widget) => widget
+                        .choices()
+                        .and_then(|choices| choices.iter().position(|(checked, _)| *checked))
+                        .unwrap_or_default(),
+                    _ => 0,
+                };
+                self.widgets.push(widget);
+                self.redraw();
+            }
+            Event::Copy(text) => self.copy(&text),
+        }
+    }
+    /// Ask the terminal to put `text` in the system clipboard with the OSC 52 sequence,
+    /// terminals not supporting it ignore the request
+    fn copy(&self, text: &str) {
+        let mut stderr = std::io::stderr();
+        let sequence = format!("\x1b]52;c;{}\x07", base64(text.as_bytes()));
+        if let Err(err) = stderr
+            .write_all(sequence.as_bytes())
+            .and_then(|()| stderr.flush())
+        {
+            log::error!("Failed to copy to the clipboard: {err}");
         }
     }
     pub fn enter(&mut self) -> Result<()> {
@@ -175,6 +313,7 @@ impl Tui {
             std::io::stdout(),
             EnterAlternateScreen,
             EnableMouseCapture,
+            EnableFocusChange,
             cursor::Hide
         )?;
         Ok(())
@@ -187,6 +326,7 @@ impl Tui {
                 std::io::stdout(),
                 LeaveAlternateScreen,
                 DisableMouseCapture,
+                DisableFocusChange,
                 cursor::Show
             )?;
             crossterm::terminal::disable_raw_mode()?;
@@ -216,27 +356,82 @@ impl Tui {
         !self.widgets.is_empty()
     }
 
+    /// Draw the last state rendered again
+    fn redraw(&mut self) {
+        if let Some(state) = self.state.take() {
+            self.render(&state);
+            self.state = Some(state);
+        }
+    }
     fn render(&mut self, state: &State) {
+        let thumbnails = self.draw_ui(state);
+        if thumbnails == self.thumbnails {
+            return;
+        }
+        if !self.thumbnails.is_empty() {
+            // the previous covers are hidden by drawing every cell again
+            if let Some(clear) = self.images.clear() {
+                let _ = std::io::stderr().write_all(clear.as_bytes());
+            }
+            let _ = self.terminal.clear();
+            self.draw_ui(state);
+        }
+        self.draw_thumbnails(&thumbnails);
+        self.thumbnails = thumbnails;
+    }
+    /// Draw the interface and returns the covers to draw over it
+    fn draw_ui(&mut self, state: &State) -> Vec<Thumbnail> {
         // ignore any failure
         let prompt_string = self.prompt_string.clone();
         let widget = self
             .widgets
             .last()
-            .map(|w| make_render_widget(w, prompt_string));
-        let _ = self.draw(|f| ui(f, state, widget));
+            .map(|w| make_render_widget(w, prompt_string, self.cursor));
+        // the covers would hide the widget
+        let images = self.images != ImageProtocol::None && widget.is_none();
+        let mut thumbnails = Vec::new();
+        #[cfg(feature = "metrics")]
+        let start = std::time::Instant::now();
+        let _ = self.draw(|f| ui(f, state, widget, images.then_some(&mut thumbnails)));
+        #[cfg(feature = "metrics")]
+        crate::metrics::record_duration("render", start.elapsed());
+        thumbnails
+    }
+    fn draw_thumbnails(&self, thumbnails: &[Thumbnail]) {
+        let mut stderr = std::io::stderr();
+        for thumbnail in thumbnails {
+            let Rect {
+                x,
+                y,
+                width,
+                height,
+            } = thumbnail.area;
+            if let Some(sequence) = self.images.sequence(&thumbnail.path, width, height) {
+                let _ = crossterm::queue!(stderr, cursor::MoveTo(x, y));
+                let _ = stderr.write_all(sequence.as_bytes());
+            }
+        }
+        if let Err(err) = stderr.flush() {
+            log::error!("Failed to draw the covers: {err}");
+        }
     }
     async fn handle_tui_event(&mut self, event: crossterm::event::Event) -> Option<MyEvents> {
         use crossterm::event;
         match event {
-            event::Event::FocusGained => Some(Action::PauseRender(false).into()),
-            event::Event::FocusLost => Some(Action::PauseRender(true).into()),
+            event::Event::FocusGained => {
+                self.focused = true;
+                None
+            }
+            event::Event::FocusLost => {
+                self.focused = false;
+                None
+            }
             event::Event::Key(key) => {
                 if !self.widgets.is_empty() {
                     self.widget_event(key).await;
                     None
                 } else if key.kind == KeyEventKind::Press {
-                    let action = config::get_config().get_action(&key.code)?;
-                    Some(action.into())
+                    self.handle_key(key.code).await
                 } else {
                     None
                 }
@@ -255,7 +450,7 @@ impl Tui {
             },
             event::Event::Paste(string) => {
                 if self.in_prompt() {
-                    self.prompt_string.push_str(&string)
+                    self.type_text(&string)
                 };
                 None
             }
@@ -263,13 +458,111 @@ impl Tui {
         }
     }
 
+    /// Resolve `code` to the action bound to it, recording it if a macro is being recorded,
+    /// the macro and mark keys and the name following them are handled here
+    async fn handle_key(&mut self, code: KeyCode) -> Option<MyEvents> {
+        let config = config::get_config();
+        if let Some(pending) = self.pending.take() {
+            // any other key than a character cancels
+            let register = match code {
+                KeyCode::Char(register) => register,
+                _ => return None,
+            };
+            let action: Action = match pending {
+                PendingKey::RecordMacro => {
+                    self.macros.recording = Some((register, Vec::new()));
+                    let message = format!("Recording @{register}");
+                    return Some(MyEvents::Notification(Notification::new(
+                        Severity::Info,
+                        message,
+                    )));
+                }
+                PendingKey::ReplayMacro => return self.replay(register).await,
+                PendingKey::SetMark => MenuCtrl::SetMark(register).into(),
+                PendingKey::JumpToMark => MenuCtrl::JumpToMark(register).into(),
+            };
+            return Some(self.record(action).into());
+        }
+        if code == KeyCode::Char(config.macro_record_key) {
+            if let Some((register, actions)) = self.macros.recording.take() {
+                let message = format!("Recorded {} actions in @{register}", actions.len());
+                self.macros.registers.insert(register, actions);
+                return Some(MyEvents::Notification(Notification::new(
+                    Severity::Info,
+                    message,
+                )));
+            }
+            self.pending = Some(PendingKey::RecordMacro);
+            return None;
+        }
+        let pending = match code {
+            KeyCode::Char(c) if c == config.macro_replay_key => Some(PendingKey::ReplayMacro),
+            KeyCode::Char(c) if c == config.mark_set_key => Some(PendingKey::SetMark),
+            KeyCode::Char(c) if c == config.mark_jump_key => Some(PendingKey::JumpToMark),
+            _ => None,
+        };
+        if pending.is_some() {
+            self.pending = pending;
+            return None;
+        }
+        let action = config.get_action(&code)?;
+        Some(self.record(action).into())
+    }
+
+    /// Add `action` to the macro being recorded, if any
+    fn record(&mut self, action: Action) -> Action {
+        if let Some((_, actions)) = &mut self.macros.recording {
+            actions.push(action.clone());
+        }
+        action
+    }
+
+    /// Send the actions recorded in `register` to the orchestrator, in order
+    async fn replay(&mut self, register: char) -> Option<MyEvents> {
+        let actions = match self.macros.registers.get(&register) {
+            Some(actions) => actions.clone(),
+            None => {
+                let message = format!("Nothing recorded in @{register}");
+                return Some(MyEvents::Notification(Notification::new(
+                    Severity::Warning,
+                    message,
+                )));
+            }
+        };
+        if let Some((_, recording)) = &mut self.macros.recording {
+            // replaying a macro while recording another records its actions
+            recording.extend(actions.iter().cloned());
+        }
+        for action in actions {
+            if self.orchestrator_tx.send(action.into()).await.is_err() {
+                let _ = self.exit();
+                break;
+            }
+        }
+        None
+    }
+
     async fn handle_widget_send(&mut self) {
         let widget = self.widgets.pop().unwrap();
         match widget {
             Widget::Widget(widget) => match widget {
-                crate::client::interface::Widget::Alert { .. } => todo!(),
-                crate::client::interface::Widget::Checkboxes { .. } => todo!(),
-                crate::client::interface::Widget::Radioboxes { .. } => todo!(),
+                // alerts are simply closed
+                crate::client::interface::Widget::Alert { .. } => (),
+                crate::client::interface::Widget::Checkboxes {
+                    content,
+                    backchannel,
+                    ..
+                } => {
+                    let _ = backchannel.send(content);
+                }
+                crate::client::interface::Widget::Radioboxes {
+                    content,
+                    backchannel,
+                    ..
+                } => {
+                    let choice = content.iter().position(|(checked, _)| *checked);
+                    let _ = backchannel.send(choice.unwrap_or(self.cursor));
+                }
                 crate::client::interface::Widget::PromptBox {
                     title: _,
                     content: _,
@@ -277,6 +570,13 @@ impl Tui {
                 } => {
                     let _ = backchannel.send(self.prompt_string.clone());
                 }
+                crate::client::interface::Widget::Form {
+                    fields,
+                    backchannel,
+                    ..
+                } => {
+                    let _ = backchannel.send(fields.into_iter().map(|field| field.value).collect());
+                }
             },
             Widget::CommandPrompt => {
                 let _ = self
@@ -285,6 +585,103 @@ impl Tui {
                     .await;
                 self.prompt_string = String::new();
             }
+            // the matches stay highlighted
+            Widget::FindPrompt => self.prompt_string = String::new(),
+            Widget::Equalizer { gains, .. } => {
+                let _ = self
+                    .orchestrator_tx
+                    .send(PlayerAction::SetEqualizer(gains).into())
+                    .await;
+            }
+        }
+    }
+
+    /// Search the text typed so far if the find prompt is displayed
+    async fn send_find(&mut self) {
+        if let Some(Widget::FindPrompt) = self.widgets.last() {
+            let action = Action::Find(self.prompt_string.clone());
+            let _ = self.orchestrator_tx.send(action.into()).await;
+        }
+    }
+
+    /// Move the selection of the widget displayed with the arrows
+    fn arrow_event(&mut self, key: KeyCode) {
+        match self.widgets.last_mut() {
+            // select a band with the left and right arrows and change its gain
+            // with the up and down arrows
+            Some(Widget::Equalizer { gains, band }) => match key {
+                KeyCode::Left => *band = band.saturating_sub(1),
+                KeyCode::Right => *band = (*band + 1).min(EQUALIZER_BANDS.len() - 1),
+                KeyCode::Up | KeyCode::Down => {
+                    gains.resize(EQUALIZER_BANDS.len(), 0);
+                    let step = if key == KeyCode::Up { 1 } else { -1 };
+                    gains[*band] =
+                        (gains[*band] + step).clamp(-EQUALIZER_MAX_GAIN, EQUALIZER_MAX_GAIN);
+                }
+                _ => (),
+            },
+            Some(Widget::Widget(widget)) => {
+                let len = match widget {
+                    InterfaceWidget::Form { fields, .. } => fields.len(),
+                    _ => widget.choices().map_or(0, Vec::len),
+                };
+                self.cursor = match key {
+                    KeyCode::Up | KeyCode::BackTab => self.cursor.saturating_sub(1),
+                    KeyCode::Down | KeyCode::Tab => (self.cursor + 1).min(len.saturating_sub(1)),
+                    _ => self.cursor,
+                };
+                // the selection of radioboxes follows the cursor
+                if matches!(widget, InterfaceWidget::Radioboxes { .. }) {
+                    self.toggle_choice();
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Append `text` to the prompt or to the field of the form under the cursor
+    fn type_text(&mut self, text: &str) {
+        match self.widgets.last_mut() {
+            Some(Widget::Widget(InterfaceWidget::Form { fields, .. })) => {
+                if let Some(field) = fields.get_mut(self.cursor) {
+                    field.value.push_str(text);
+                }
+            }
+            Some(widget) if widget.captures_output() => self.prompt_string.push_str(text),
+            _ => (),
+        }
+    }
+
+    /// Remove the last character of the prompt or of the field of the form under the cursor
+    fn erase(&mut self) {
+        match self.widgets.last_mut() {
+            Some(Widget::Widget(InterfaceWidget::Form { fields, .. })) => {
+                if let Some(field) = fields.get_mut(self.cursor) {
+                    field.value.pop();
+                }
+            }
+            Some(widget) if widget.captures_output() => {
+                self.prompt_string.pop();
+            }
+            _ => (),
+        }
+    }
+
+    /// Check or uncheck the entry under the cursor, checking an entry of radioboxes
+    /// unchecks the others
+    fn toggle_choice(&mut self) {
+        match self.widgets.last_mut() {
+            Some(Widget::Widget(InterfaceWidget::Checkboxes { content, .. })) => {
+                if let Some((checked, _)) = content.get_mut(self.cursor) {
+                    *checked = !*checked;
+                }
+            }
+            Some(Widget::Widget(InterfaceWidget::Radioboxes { content, .. })) => {
+                for (i, (checked, _)) in content.iter_mut().enumerate() {
+                    *checked = i == self.cursor;
+                }
+            }
+            _ => (),
         }
     }
 
@@ -293,31 +690,46 @@ impl Tui {
             match key.code {
                 KeyCode::Char(c) => {
                     if self.widgets.last().unwrap().captures_output() {
-                        self.prompt_string.push(c);
+                        self.type_text(&c.to_string());
+                        self.send_find().await;
+                    } else if c == ' ' {
+                        self.toggle_choice();
                     }
                 }
                 KeyCode::Enter => self.handle_widget_send().await,
                 KeyCode::Backspace => {
-                    if self.widgets.last().unwrap().captures_output() {
-                        self.prompt_string.pop();
-                    }
+                    self.erase();
+                    self.send_find().await;
                 }
                 KeyCode::Esc => {
-                    self.widgets.pop();
+                    if let Some(Widget::FindPrompt) = self.widgets.pop() {
+                        // cancelling the search removes the highlighting
+                        let _ = self
+                            .orchestrator_tx
+                            .send(Action::Find(String::new()).into())
+                            .await;
+                    }
                     self.prompt_string = String::new()
                 }
+                KeyCode::Left
+                | KeyCode::Right
+                | KeyCode::Up
+                | KeyCode::Down
+                | KeyCode::Tab
+                | KeyCode::BackTab => self.arrow_event(key.code),
                 _ => (),
             }
         }
     }
 }
+/// Area centered in `size`, the height is capped to the one of `size`
 fn centered_rec(size: Rect, max_height: Option<u16>) -> Rect {
     let center_x = size.width / 2;
     let center_y = size.height / 2;
     let width = size.width * 3 / 4;
-    let height = max_height.unwrap_or(size.height * 3 / 4);
-    let corner_x = center_x - (width / 2);
-    let corner_y = center_y - (height / 2);
+    let height = max_height.unwrap_or(size.height * 3 / 4).min(size.height);
+    let corner_x = center_x.saturating_sub(width / 2);
+    let corner_y = center_y.saturating_sub(height / 2);
     Rect {
         x: corner_x,
         y: corner_y,
@@ -325,33 +737,195 @@ fn centered_rec(size: Rect, max_height: Option<u16>) -> Rect {
         height,
     }
 }
-fn make_list_widget<'a>(list: &'a [String], title: &'a str, focused: bool) -> List<'a> {
-    let list: Vec<ListItem<'_>> = list.iter().map(|s| ListItem::new(s.clone())).collect();
+fn make_block(title: &str, focused: bool, accent: Option<Color>) -> Block<'_> {
+    Block::new()
+        .borders(Borders::ALL)
+        .title(title)
+        .style(get_border_style(focused, accent))
+}
+/// Build a list of `list`, highlighting the occurrences of `find` unless it is empty
+fn make_list_widget<'a>(
+    list: &'a [String],
+    block: Block<'a>,
+    focused: bool,
+    find: &str,
+) -> List<'a> {
+    let list: Vec<ListItem<'_>> = list
+        .iter()
+        .map(|s| ListItem::new(highlight_matches(s, find)))
+        .collect();
     let style = get_style(focused);
     let hg_style = get_highlight_style(focused);
     List::new(list)
-        .block(
-            Block::new()
-                .borders(Borders::ALL)
-                .title(title)
-                .style(get_border_style(focused)),
-        )
+        .block(block)
         .style(style)
         .highlight_style(hg_style)
 }
 
-fn get_border_style(focused: bool) -> Style {
-    let config: Config = confy::load("yamav3", None).expect("Cannot access config");
+/// Build a table with one row per song, the name of the client it comes from and its rating,
+/// each cell being truncated to fit in its column, the row at index `playing` is emphasized
+/// and the occurrences of `find` are highlighted unless it is empty
+#[allow(clippy::too_many_arguments)]
+fn make_song_table<'a>(
+    songs: &[(&SongInfo, &str, Rating)],
+    columns: &[ColumnConfig],
+    widths: &'a [Constraint],
+    playing: Option<usize>,
+    area: Rect,
+    block: Block<'a>,
+    focused: bool,
+    find: &str,
+) -> Table<'a> {
+    // compute the actual width of each column to truncate the cells accordingly,
+    // keeping one character free to separate the columns
+    let areas = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(widths)
+        .split(block.inner(area));
+    let make_row = |cells: Vec<String>| {
+        Row::new(cells.into_iter().zip(areas.iter()).map(|(cell, area)| {
+            let cell = truncate(&cell, area.width.saturating_sub(1));
+            Cell::from(highlight_matches(&cell, find))
+        }))
+    };
+    let header = make_row(columns.iter().map(|c| format!("{:?}", c.column)).collect())
+        .style(Style::default().bold());
+    let rows: Vec<Row<'_>> = songs
+        .iter()
+        .enumerate()
+        .map(|(i, (song, source, rating))| {
+            let mut cells: Vec<String> = columns
+                .iter()
+                .map(|c| song_column(song, c.column, source, rating))
+                .collect();
+            if playing == Some(i) {
+                if let Some(first) = cells.first_mut() {
+                    first.insert_str(0, "▶ ");
+                }
+                make_row(cells).style(Style::default().bold().italic())
+            } else if song.unavailable {
+                make_row(cells).style(Style::default().fg(Color::DarkGray))
+            } else {
+                make_row(cells)
+            }
+        })
+        .collect();
+    Table::new(rows)
+        .header(header)
+        .widths(widths)
+        .column_spacing(0)
+        .block(block)
+        .style(get_style(focused))
+        .highlight_style(get_highlight_style(focused))
+}
+
+fn song_column(song: &SongInfo, column: SongColumn, source: &str, rating: &Rating) -> String {
+    match column {
+        SongColumn::Title => song.title.clone(),
+        SongColumn::Artist => song.artist.clone(),
+        SongColumn::Album => song.album.clone(),
+        SongColumn::Year => song.year.map(|y| y.to_string()).unwrap_or_default(),
+        SongColumn::Duration => duration_to_string(&song.duration),
+        SongColumn::Source => source.to_string(),
+        SongColumn::Rating => {
+            let heart = if rating.favorite { "♥" } else { " " };
+            format!("{heart}{}", "★".repeat(rating.stars as usize))
+        }
+    }
+}
+
+/// Standard base64 encoding of `data`, with padding
+pub fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut res = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let mut bytes = [0; 4];
+        bytes[1..=chunk.len()].copy_from_slice(chunk);
+        let n = u32::from_be_bytes(bytes);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                res.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                res.push('=');
+            }
+        }
+    }
+    res
+}
+
+/// Returns `true` if `text` contains `find`, ignoring the case
+pub fn matches(text: &str, find: &str) -> bool {
+    !find.is_empty() && !find_matches(text, find).is_empty()
+}
+
+/// Character ranges of the occurrences of `find` in `text`, ignoring the case
+fn find_matches(text: &str, find: &str) -> Vec<std::ops::Range<usize>> {
+    let fold = |c: char| c.to_lowercase().next().unwrap_or(c);
+    let text: Vec<char> = text.chars().map(fold).collect();
+    let find: Vec<char> = find.chars().map(fold).collect();
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    while !find.is_empty() && start + find.len() <= text.len() {
+        if text[start..start + find.len()] == find[..] {
+            ranges.push(start..start + find.len());
+            start += find.len();
+        } else {
+            start += 1;
+        }
+    }
+    ranges
+}
+
+/// `text` with the occurrences of `find` highlighted
+fn highlight_matches(text: &str, find: &str) -> Line<'static> {
+    let chars: Vec<char> = text.chars().collect();
+    let style = Style::default().fg(Color::Yellow).bold();
+    let mut spans = Vec::new();
+    let mut end = 0;
+    for range in find_matches(text, find) {
+        let before: String = chars[end..range.start].iter().collect();
+        let found: String = chars[range.clone()].iter().collect();
+        spans.push(Span::raw(before));
+        spans.push(Span::styled(found, style));
+        end = range.end;
+    }
+    let after: String = chars[end..].iter().collect();
+    spans.push(Span::raw(after));
+    Line::from(spans)
+}
+
+/// Truncate `text` to at most `width` characters, marking the truncation with an ellipsis
+fn truncate(text: &str, width: u16) -> String {
+    let width = width as usize;
+    if text.chars().count() <= width {
+        text.to_string()
+    } else if width == 0 {
+        String::new()
+    } else {
+        let mut res: String = text.chars().take(width - 1).collect();
+        res.push('…');
+        res
+    }
+}
+
+/// Style of the borders, `accent` takes precedence over the configured colors
+fn get_border_style(focused: bool, accent: Option<Color>) -> Style {
+    let config = config::get_config();
     let fg = if focused {
         config.border_focus
     } else {
         config.border_unfocus
     };
-    Style::default().fg(fg)
+    Style::default().fg(accent.unwrap_or(fg))
+}
+
+/// Accent color configured for the client named `client`
+fn get_accent(client: Option<&String>) -> Option<Color> {
+    config::get_config().client_accents.get(client?).copied()
 }
 
 fn get_style(focused: bool) -> Style {
-    let config: Config = confy::load("yamav3", None).expect("Cannot access config");
+    let config = config::get_config();
     let fg = if focused {
         config.focused_fg
     } else {
@@ -366,7 +940,7 @@ fn get_style(focused: bool) -> Style {
 }
 
 fn get_highlight_style(focused: bool) -> Style {
-    let config: Config = confy::load("yamav3", None).expect("Cannot access config");
+    let config = config::get_config();
     let h_fg = if focused {
         config.focused_highlight_fg
     } else {
@@ -380,39 +954,411 @@ fn get_highlight_style(focused: bool) -> Style {
     Style::default().fg(h_fg).bg(h_bg)
 }
 
-fn ui(f: &mut Frame<'_>, state: &State, widget: Option<RenderWidget>) {
+/// Draw the interface, the covers to draw over it are pushed in `thumbnails`
+/// if the terminal can display them
+fn ui(
+    f: &mut Frame<'_>,
+    state: &State,
+    widget: Option<RenderWidget>,
+    thumbnails: Option<&mut Vec<Thumbnail>>,
+) {
+    if state.compact {
+        render_compact(f, state);
+        if let Some(widget) = widget {
+            render_widget(f, widget)
+        }
+        return;
+    }
     let block = Block::default()
         .borders(Borders::ALL)
         .title("YAMA")
         .title_alignment(Alignment::Center)
         .border_type(BorderType::Rounded);
     f.render_widget(block, f.size());
-    let player_layout = Layout::default()
+    // the tabs are hidden in the zen layout
+    let tabs_height = if state.zen { 0 } else { 1 };
+    let main_layout = Layout::default()
         .direction(Direction::Vertical)
-        .constraints(vec![Constraint::Percentage(80), Constraint::Max(4)])
+        .constraints(vec![
+            Constraint::Length(tabs_height),
+            Constraint::Min(0),
+            Constraint::Max(4),
+            Constraint::Length(1),
+        ])
         .margin(1)
         .split(f.size());
+    if !state.zen {
+        render_tabs_widget(f, main_layout[0], state);
+    }
+    match state.active_tab {
+        Tab::Library if state.zen => render_song_widget(f, main_layout[1], state),
+        Tab::Library => render_library_tab(f, main_layout[1], state, thumbnails),
+        Tab::Artists => render_artists_tab(f, main_layout[1], state),
+        Tab::Queue => render_queue_tab(f, main_layout[1], state),
+        Tab::Search => render_search_tab(f, main_layout[1], state),
+        Tab::Lyrics => render_lyrics_tab(f, main_layout[1], state),
+        Tab::Log => render_log_tab(f, main_layout[1], state),
+        Tab::History => render_history_tab(f, main_layout[1], state),
+        Tab::Favorites => render_favorites_tab(f, main_layout[1], state),
+    }
+    render_player_widget(f, main_layout[2], state);
+    render_status_bar(f, main_layout[3], state);
+    if let Some(widget) = widget {
+        render_widget(f, widget)
+    }
+}
+/// Player bar above the next songs of the tracklist, without any border around
+fn render_compact(f: &mut Frame<'_>, state: &State) {
     let layout = Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints(vec![Constraint::Percentage(25), Constraint::Percentage(75)])
-        .split(player_layout[0]);
-    let left_column = Layout::default()
         .direction(Direction::Vertical)
         .constraints(vec![
-            Constraint::Max(8),
-            Constraint::Max(8),
-            Constraint::Max(6),
+            Constraint::Length(4),
             Constraint::Min(0),
+            Constraint::Length(1),
+        ])
+        .split(f.size());
+    render_player_widget(f, layout[0], state);
+    let next = state.player.track_index.map_or(0, |index| index + 1);
+    let width = layout[1].width.saturating_sub(2);
+    let songs: Vec<String> = state
+        .player
+        .tracklist
+        .songs
+        .iter()
+        .skip(next)
+        .take(layout[1].height as usize)
+        .map(|song| truncate(&format!("{} - {}", song.title, song.artist), width))
+        .collect();
+    let widget = make_list_widget(&songs, make_block("Up next", false, None), false, "");
+    f.render_widget(widget, layout[1]);
+    render_status_bar(f, layout[2], state);
+}
+fn render_tabs_widget(f: &mut Frame<'_>, layout: Rect, state: &State) {
+    let titles: Vec<String> = Tab::ALL
+        .iter()
+        .enumerate()
+        .map(|(i, tab)| format!("{} {}", i + 1, tab))
+        .collect();
+    let select = Tab::ALL.iter().position(|t| *t == state.active_tab);
+    let widget = Tabs::new(titles)
+        .select(select.unwrap_or_default())
+        .style(get_style(false))
+        .highlight_style(get_highlight_style(true));
+    f.render_widget(widget, layout);
+}
+fn render_status_bar(f: &mut Frame<'_>, layout: Rect, state: &State) {
+    if let Some(notification) = state.notifications.front() {
+        let fg = match notification.severity {
+            Severity::Info => state.config.info_fg,
+            Severity::Warning => state.config.warning_fg,
+            Severity::Error => state.config.error_fg,
+        };
+        let message = match state.notifications.len() {
+            1 => notification.message.clone(),
+            // hint that other notifications are waiting
+            n => format!("{} (+{})", notification.message, n - 1),
+        };
+        let text = Paragraph::new(truncate(&message, layout.width)).fg(fg);
+        f.render_widget(text, layout);
+    } else if let Some(download) = &state.download {
+        let message = format!(
+            "Downloading {}/{}: {}",
+            download.done + 1,
+            download.total,
+            download.title
+        );
+        let text = Paragraph::new(truncate(&message, layout.width)).style(get_style(false));
+        f.render_widget(text, layout);
+    }
+}
+/// Left column and main area of a tab, the column taking `width` percent of the tab
+fn split_sidebar(layout: Rect, width: u16) -> Rc<[Rect]> {
+    let width = width.min(100);
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(vec![
+            Constraint::Percentage(width),
+            Constraint::Percentage(100 - width),
         ])
+        .split(layout)
+}
+fn render_library_tab(
+    f: &mut Frame<'_>,
+    layout: Rect,
+    state: &State,
+    thumbnails: Option<&mut Vec<Thumbnail>>,
+) {
+    let config = &state.config.layout;
+    let layout = split_sidebar(layout, config.sidebar_width);
+    let mut constraints = Vec::new();
+    if config.show_sources {
+        constraints.push(Constraint::Max(config.sources_height));
+    }
+    // the grid takes the place of the songs, which are displayed once they are focused
+    if !state.playlist_grid {
+        constraints.push(Constraint::Max(config.playlists_height));
+    }
+    if config.show_options {
+        constraints.push(Constraint::Max(7));
+    }
+    constraints.push(Constraint::Min(0));
+    let left_column = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(constraints)
         .split(layout[0]);
-    render_sources_widget(f, left_column[0], state);
-    render_playlist_widget(f, left_column[1], state);
-    render_song_widget(f, layout[1], state);
-    render_info_widget(f, left_column[2], state);
-    render_player_widget(f, player_layout[1], state);
-    if let Some(widget) = widget {
-        render_widget(f, widget)
+    let mut panels = left_column.iter().copied();
+    if config.show_sources {
+        render_sources_widget(f, panels.next().unwrap_or_default(), state);
+    }
+    if !state.playlist_grid {
+        render_playlist_widget(f, panels.next().unwrap_or_default(), state);
+        render_song_widget(f, layout[1], state);
+    } else if state.is_active_menu(Menu::Song) {
+        render_song_widget(f, layout[1], state);
+    } else {
+        render_playlist_grid(f, layout[1], state, thumbnails);
+    }
+    if config.show_options {
+        render_info_widget(f, panels.next().unwrap_or_default(), state);
+    }
+}
+/// Artists of the client selected in the library, the albums of the selected artist
+/// and the songs of the selected album
+fn render_artists_tab(f: &mut Frame<'_>, layout: Rect, state: &State) {
+    let layout = split_sidebar(layout, state.config.layout.sidebar_width);
+    let left_column = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(vec![Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(layout[0]);
+    let source = state.clients.get_selected();
+    let accent = get_accent(source);
+    let title = match source {
+        Some(source) => format!("Artists of {source}"),
+        None => "Artists (select a source in the library)".to_string(),
+    };
+
+    let artists = state.artists.get_strings();
+    let focused = state.browse_menu == BrowseMenu::Artist;
+    let mut tui_state = ListState::default();
+    tui_state.select(state.artists.select);
+    let widget = make_list_widget(&artists, make_block(&title, focused, accent), focused, "");
+    f.render_stateful_widget(widget, left_column[0], &mut tui_state);
+
+    let albums = state.albums.get_strings();
+    let focused = state.browse_menu == BrowseMenu::Album;
+    let mut tui_state = ListState::default();
+    tui_state.select(state.albums.select);
+    let widget = make_list_widget(&albums, make_block("Albums", focused, accent), focused, "");
+    f.render_stateful_widget(widget, left_column[1], &mut tui_state);
+
+    let columns = config::get_config().song_columns;
+    let widths: Vec<Constraint> = columns.iter().map(|c| c.width.into()).collect();
+    let title = match state.albums.get_selected() {
+        Some(album) => format!("{} by {}", album.title, album.artist),
+        None => "Songs".to_string(),
+    };
+    let source = source.cloned().unwrap_or_default();
+    let songs: Vec<(&SongInfo, &str, Rating)> = state
+        .album_songs
+        .entries
+        .iter()
+        .map(|song| (song, source.as_str(), state.rating(song)))
+        .collect();
+    let focused = state.browse_menu == BrowseMenu::Song;
+    let mut tui_state = TableState::default();
+    tui_state.select(state.album_songs.select);
+    let block = make_block(&title, focused, accent);
+    let widget = make_song_table(
+        &songs, &columns, &widths, None, layout[1], block, focused, "",
+    );
+    f.render_stateful_widget(widget, layout[1], &mut tui_state);
+}
+fn render_queue_tab(f: &mut Frame<'_>, layout: Rect, state: &State) {
+    let columns = config::get_config().song_columns;
+    let widths: Vec<Constraint> = columns.iter().map(|c| c.width.into()).collect();
+    let mut tui_state = TableState::default();
+    tui_state.select(state.queue.select);
+    let source = state
+        .active_player
+        .and_then(|player| state.clients.entries.get(player))
+        .cloned()
+        .unwrap_or_default();
+    let title = if state.player.tracklist.title.is_empty() {
+        "Queue"
+    } else {
+        &state.player.tracklist.title
+    };
+    let songs: Vec<(&SongInfo, &str, Rating)> = state
+        .queue
+        .entries
+        .iter()
+        .map(|song| (song, source.as_str(), state.rating(song)))
+        .collect();
+    let block = make_block(title, true, get_accent(Some(&source)));
+    let playing = state.player.track_index;
+    let widget = make_song_table(&songs, &columns, &widths, playing, layout, block, true, "");
+    f.render_stateful_widget(widget, layout, &mut tui_state);
+}
+fn render_search_tab(f: &mut Frame<'_>, layout: Rect, state: &State) {
+    let columns = config::get_config().song_columns;
+    let widths: Vec<Constraint> = columns.iter().map(|c| c.width.into()).collect();
+    let mut tui_state = TableState::default();
+    tui_state.select(state.search.select);
+    let title = if state.search_query.is_empty() {
+        "Search results".to_string()
+    } else if state.search_pending.is_empty() {
+        format!("Search results for \"{}\"", state.search_query)
+    } else {
+        format!(
+            "Search results for \"{}\" (waiting for {})",
+            state.search_query,
+            state.search_pending.join(", ")
+        )
+    };
+    let songs: Vec<(&SongInfo, &str, Rating)> = state
+        .search
+        .entries
+        .iter()
+        .map(|result| {
+            (
+                &result.song,
+                result.client.as_str(),
+                state.rating(&result.song),
+            )
+        })
+        .collect();
+    let block = make_block(&title, true, None);
+    let widget = make_song_table(&songs, &columns, &widths, None, layout, block, true, "");
+    f.render_stateful_widget(widget, layout, &mut tui_state);
+}
+fn render_favorites_tab(f: &mut Frame<'_>, layout: Rect, state: &State) {
+    let columns = config::get_config().song_columns;
+    let widths: Vec<Constraint> = columns.iter().map(|c| c.width.into()).collect();
+    let mut tui_state = TableState::default();
+    tui_state.select(state.favorites.select);
+    let songs: Vec<(&SongInfo, &str, Rating)> = state
+        .favorites
+        .entries
+        .iter()
+        .map(|result| {
+            (
+                &result.song,
+                result.client.as_str(),
+                state.rating(&result.song),
+            )
+        })
+        .collect();
+    let block = make_block("Favorites", true, None);
+    let widget = make_song_table(&songs, &columns, &widths, None, layout, block, true, "");
+    f.render_stateful_widget(widget, layout, &mut tui_state);
+}
+fn render_lyrics_tab(f: &mut Frame<'_>, layout: Rect, state: &State) {
+    let title = state.lyrics_title();
+    let block = make_block(&title, true, None);
+    if state.lyrics.is_empty() {
+        let text = if state.player.song_info.is_some() {
+            "No lyrics available for this song"
+        } else {
+            "Nothing is playing"
+        };
+        let widget = Paragraph::new(text)
+            .block(block)
+            .alignment(Alignment::Center)
+            .style(get_style(true));
+        f.render_widget(widget, layout);
+        return;
     }
+    // the line being sung is highlighted and kept in the middle of the panel
+    let current = lyrics::current_line(&state.lyrics, state.position);
+    let height = block.inner(layout).height as usize;
+    let scroll = current.map_or(0, |current| current.saturating_sub(height / 2));
+    let lines: Vec<Line<'_>> = state
+        .lyrics
+        .iter()
+        .enumerate()
+        .map(|(i, line)| {
+            if Some(i) == current {
+                Line::styled(line.text.as_str(), get_highlight_style(true))
+            } else {
+                Line::raw(line.text.as_str())
+            }
+        })
+        .collect();
+    let widget = Paragraph::new(lines)
+        .block(block)
+        .alignment(Alignment::Center)
+        .scroll((u16::try_from(scroll).unwrap_or(u16::MAX), 0))
+        .style(get_style(true));
+    f.render_widget(widget, layout);
+}
+fn render_log_tab(f: &mut Frame<'_>, layout: Rect, state: &State) {
+    let filter = &state.log_filter;
+    let mut title = format!("Log (level: {}", filter.level);
+    if !filter.module.is_empty() {
+        title.push_str(&format!(", module: {}", filter.module));
+    }
+    title.push(')');
+    let block = make_block(&title, true, None);
+    let config = config::get_config();
+    // only show the most recent lines that fit in the panel
+    let height = block.inner(layout).height as usize;
+    let lines: Vec<Line<'_>> = state.log[state.log.len().saturating_sub(height)..]
+        .iter()
+        .map(|entry| {
+            let fg = match entry.level {
+                Level::Error => config.error_fg,
+                Level::Warn => config.warning_fg,
+                Level::Info => config.info_fg,
+                Level::Debug | Level::Trace => config.unfocused_fg,
+            };
+            Line::from(vec![
+                Span::styled(format!("{:<5} ", entry.level), Style::default().fg(fg)),
+                Span::styled(format!("{} ", entry.target), Style::default().italic()),
+                Span::raw(entry.message.as_str()),
+            ])
+        })
+        .collect();
+    let widget = Paragraph::new(lines).block(block).style(get_style(true));
+    f.render_widget(widget, layout);
+}
+fn render_history_tab(f: &mut Frame<'_>, layout: Rect, state: &State) {
+    let title = if state.history_query.is_empty() {
+        "History".to_string()
+    } else {
+        format!("History matching \"{}\"", state.history_query)
+    };
+    let mut tui_state = TableState::default();
+    tui_state.select(state.history.select);
+    let header =
+        Row::new(["Played", "Title", "Artist", "Source", "Heard"]).style(Style::default().bold());
+    let rows: Vec<Row<'_>> = state
+        .history
+        .entries
+        .iter()
+        .map(|entry| {
+            Row::new([
+                entry.age(),
+                entry.song.title.clone(),
+                entry.song.artist.clone(),
+                entry.source.clone(),
+                format!("{}%", entry.completion),
+            ])
+        })
+        .collect();
+    let widths = [
+        Constraint::Length(9),
+        Constraint::Percentage(50),
+        Constraint::Percentage(30),
+        Constraint::Min(8),
+        Constraint::Length(5),
+    ];
+    let widget = Table::new(rows)
+        .header(header)
+        .widths(&widths)
+        .block(make_block(&title, true, None))
+        .style(get_style(true))
+        .highlight_style(get_highlight_style(true));
+    f.render_stateful_widget(widget, layout, &mut tui_state);
 }
 fn render_widget(f: &mut Frame<'_>, widget: RenderWidget) {
     let popup = Block::default()
@@ -440,12 +1386,18 @@ fn render_widget(f: &mut Frame<'_>, widget: RenderWidget) {
 }
 fn render_sources_widget(f: &mut Frame, layout: Rect, state: &State) {
     let mut names = state.clients.get_strings();
+    for name in names.iter_mut() {
+        if state.unhealthy.contains(name) {
+            name.push_str(" ⚠");
+        }
+    }
     if let Some(player) = state.active_player {
         names[player].push_str(" ");
     }
     let mut tui_state = ListState::default();
     tui_state.select(state.clients.select);
-    let widget = make_list_widget(&names, "Sources", state.is_active_menu(Menu::Client));
+    let focused = state.is_active_menu(Menu::Client);
+    let widget = make_list_widget(&names, make_block("Sources", focused, None), focused, "");
     f.render_stateful_widget(widget, layout, &mut tui_state)
 }
 fn render_playlist_widget(f: &mut Frame<'_>, layout: Rect, state: &State) {
@@ -454,40 +1406,218 @@ fn render_playlist_widget(f: &mut Frame<'_>, layout: Rect, state: &State) {
         .playlists
         .entries
         .iter()
-        .map(|p| format!("{} ({}/{})", p.title.clone(), p.songs.len(), p.length))
+        .map(|p| {
+            if state.loading.contains(&p.id) {
+                format!("{} {} ({}/{})", spinner(), p.title, p.songs.len(), p.length)
+            } else {
+                format!("{} ({}/{})", p.title.clone(), p.songs.len(), p.length)
+            }
+        })
         .collect();
     let mut tui_state = ListState::default();
     tui_state.select(state.playlists.select);
-    let widget = make_list_widget(playlists, "Playlists", state.is_active_menu(Menu::Playlist));
+    let focused = state.is_active_menu(Menu::Playlist);
+    let block = make_block(
+        "Playlists",
+        focused,
+        get_accent(state.clients.get_selected()),
+    );
+    let widget = make_list_widget(playlists, block, focused, &state.find);
     f.render_stateful_widget(widget, layout, &mut tui_state);
 }
+/// Width and height of a cell of the playlist grid, borders included
+const GRID_CELL: (u16, u16) = (22, 11);
+
+/// Number of columns and rows of the playlist grid fitting in `area`
+fn grid_shape(area: Rect) -> (usize, usize) {
+    let columns = (area.width / GRID_CELL.0).max(1);
+    let rows = (area.height / GRID_CELL.1).max(1);
+    (columns as usize, rows as usize)
+}
+
+/// Number of columns of the playlist grid for the current size of the terminal,
+/// next to a column taking `sidebar_width` percent of it
+pub fn grid_columns(sidebar_width: u16) -> usize {
+    let (width, height) = crossterm::terminal::size().unwrap_or_default();
+    // same margin as in [ui] and same borders as in [render_playlist_grid]
+    let area = Rect::new(0, 0, width.saturating_sub(2), height);
+    let area = split_sidebar(area, sidebar_width)[1];
+    grid_shape(Block::new().borders(Borders::ALL).inner(area)).0
+}
+
+/// Playlists as a grid of cells holding their cover, the rows scroll to keep
+/// the selected playlist in view
+fn render_playlist_grid(
+    f: &mut Frame<'_>,
+    layout: Rect,
+    state: &State,
+    mut thumbnails: Option<&mut Vec<Thumbnail>>,
+) {
+    let focused = state.is_active_menu(Menu::Playlist);
+    let accent = get_accent(state.clients.get_selected());
+    let block = make_block("Playlists", focused, accent);
+    let area = block.inner(layout);
+    f.render_widget(block, layout);
+    let (columns, rows) = grid_shape(area);
+    let select = state.playlists.select.unwrap_or_default();
+    let first = (select / columns).saturating_sub(rows - 1) * columns;
+    let playlists = state.playlists.entries.iter().enumerate();
+    for (index, playlist) in playlists.skip(first).take(columns * rows) {
+        let cell = Rect::new(
+            area.x + ((index - first) % columns) as u16 * GRID_CELL.0,
+            area.y + ((index - first) / columns) as u16 * GRID_CELL.1,
+            GRID_CELL.0,
+            GRID_CELL.1,
+        )
+        .intersection(area);
+        let title = truncate(&playlist.title, GRID_CELL.0 - 2);
+        let selected = state.playlists.select == Some(index);
+        let block = Block::new()
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .title(highlight_matches(&title, &state.find))
+            .style(get_border_style(selected, accent));
+        let inner = block.inner(cell);
+        f.render_widget(block, cell);
+        let count = if state.loading.contains(&playlist.id) {
+            format!("{} {}/{}", spinner(), playlist.songs.len(), playlist.length)
+        } else {
+            format!("{}/{}", playlist.songs.len(), playlist.length)
+        };
+        let cover = Rect {
+            height: inner.height.saturating_sub(1),
+            ..inner
+        };
+        let footer = Rect {
+            y: cover.y + cover.height,
+            height: inner.height - cover.height,
+            ..inner
+        };
+        f.render_widget(
+            Paragraph::new(count)
+                .alignment(Alignment::Center)
+                .style(get_style(selected && focused)),
+            footer,
+        );
+        // displayed until the cover is drawn over it
+        let placeholder = Rect {
+            y: cover.y + cover.height / 2,
+            height: cover.height.min(1),
+            ..cover
+        };
+        f.render_widget(
+            Paragraph::new("♫").alignment(Alignment::Center),
+            placeholder,
+        );
+        if let (Some(thumbnails), Some(path)) =
+            (thumbnails.as_deref_mut(), covers::path(&playlist.cover_url))
+        {
+            thumbnails.push(Thumbnail { area: cover, path });
+        }
+    }
+}
+/// Frame of the spinner displayed next to the playlists loading,
+/// it turns with each render
+fn spinner() -> char {
+    const FRAMES: [char; 8] = ['⣾', '⣽', '⣻', '⢿', '⡿', '⣟', '⣯', '⣷'];
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    FRAMES[(millis / 100) as usize % FRAMES.len()]
+}
+/// Number of songs, total duration and source of the playlist displayed, only the songs
+/// already loaded are accounted for in the duration
+fn playlist_header(summary: &PlaylistSummary) -> String {
+    let songs = if summary.total == 1 { "song" } else { "songs" };
+    let duration = duration_to_string(&summary.duration);
+    let mut header = if summary.is_partial() {
+        // hint that the duration is partial
+        format!(
+            " {}/{} {songs} loaded │ {duration}+ │ {}",
+            summary.loaded, summary.total, summary.source
+        )
+    } else {
+        format!(
+            " {} {songs} │ {duration} │ {}",
+            summary.total, summary.source
+        )
+    };
+    if !summary.owner.is_empty() {
+        header.push_str(&format!(" │ by {}", summary.owner));
+    }
+    if let Some(visibility) = summary.visibility {
+        header.push_str(&format!(" │ {visibility}"));
+    }
+    header
+}
 fn render_song_widget(f: &mut Frame<'_>, layout: Rect, state: &State) {
-    let songs = &state.songs.get_strings();
-    let mut tui_state = ListState::default();
+    let layout = match &state.playlist_summary {
+        Some(summary) => {
+            let areas = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints(vec![Constraint::Length(1), Constraint::Min(0)])
+                .split(layout);
+            f.render_widget(Paragraph::new(playlist_header(summary)), areas[0]);
+            areas[1]
+        }
+        None => layout,
+    };
+    let columns = config::get_config().song_columns;
+    let widths: Vec<Constraint> = columns.iter().map(|c| c.width.into()).collect();
+    let mut tui_state = TableState::default();
     tui_state.select(state.songs.select);
     let title = if let Some(select) = state.playlists.get_selected() {
         &select.title
     } else {
         "Songs"
     };
-    let widget = make_list_widget(songs, title, state.is_active_menu(Menu::Song));
+    let source = state.clients.get_selected().cloned().unwrap_or_default();
+    let songs: Vec<(&SongInfo, &str, Rating)> = state
+        .songs
+        .entries
+        .iter()
+        .map(|song| (song, source.as_str(), state.rating(song)))
+        .collect();
+    let focused = state.is_active_menu(Menu::Song);
+    let block = make_block(title, focused, get_accent(state.clients.get_selected()));
+    let playing = state.playing_song();
+    let find = &state.find;
+    let widget = make_song_table(
+        &songs, &columns, &widths, playing, layout, block, focused, find,
+    );
     f.render_stateful_widget(widget, layout, &mut tui_state);
 }
 fn render_info_widget(f: &mut Frame<'_>, layout: Rect, state: &State) {
     let player = &state.player;
+    // the volume is the one of the active player, each client keeping its own
+    let client = state
+        .active_player
+        .and_then(|p| state.clients.entries.get(p));
+    let mut volume = match client {
+        Some(client) => format!("Volume ({client}): {}%", player.volume),
+        None => format!("Volume: {}%", player.volume),
+    };
+    if player.volume > 100 {
+        volume.push_str(" (boost)");
+    }
+    if player.muted {
+        volume.push_str(" (muted)");
+    }
     let info = vec![
         format!("Auto: {}", player.autoplay),
         format!("Repeat: {}", player.repeat),
         format!("Shuffle: {}", player.shuffled),
-        format!("Volume: {}/100", player.volume),
+        format!("Radio: {}", state.radio),
+        volume,
     ];
-    let widget = make_list_widget(&info, "Options", true);
+    let widget = make_list_widget(&info, make_block("Options", true, None), true, "");
     f.render_widget(widget, layout);
 }
 
 /// Convert `dur` to string in the format `HH:MM:SS` if duration is longer than an hour otherwise
 /// converts to `MM:SS`
-fn duration_to_string(dur: &Duration) -> String {
+pub fn duration_to_string(dur: &Duration) -> String {
     let secs = dur.as_secs();
     let mins = secs / 60;
     let hours = mins / 60;
@@ -498,7 +1628,13 @@ fn duration_to_string(dur: &Duration) -> String {
         format!("{:0>2}:{:0>2}", mins % 60, secs % 60)
     }
 }
-fn build_player_string(pos: &Duration, dur: &Duration, length: usize) -> String {
+/// Progress bar of `length` cells, with a marker at the start of each chapter
+fn build_player_string(
+    pos: &Duration,
+    dur: &Duration,
+    chapters: &[Chapter],
+    length: usize,
+) -> String {
     let pos = pos.as_secs();
     let dur = dur.as_secs();
     if length <= 2 || dur == 0 || pos > dur {
@@ -513,37 +1649,108 @@ fn build_player_string(pos: &Duration, dur: &Duration, length: usize) -> String
         for _ in bascule..length {
             res.push('─')
         }
+        for chapter in chapters {
+            let ratio = chapter.start.as_secs() as f32 / dur as f32;
+            let cell = (length as f32 * ratio).floor() as usize;
+            if let Some(c) = res.get_mut(cell) {
+                *c = if cell < bascule { '╋' } else { '┼' };
+            }
+        }
         res[0] = '├';
         res[length - 1] = '┤';
         // from vec to string
         res.iter().collect()
     }
 }
+/// Volume as a gauge of ten cells followed by its value, or a mute icon when muted
+fn volume_gauge(volume: u8, muted: bool) -> String {
+    if muted {
+        return " 🔇 muted ".to_string();
+    }
+    let filled = (volume.min(100) as usize + 5) / 10;
+    format!(
+        " 🔊 {}{} {volume:>3}% ",
+        "━".repeat(filled),
+        "─".repeat(10 - filled)
+    )
+}
 fn render_player_widget(f: &mut Frame<'_>, layout: Rect, state: &State) {
+    let volume = Title::from(volume_gauge(state.player.volume, state.player.muted))
+        .alignment(Alignment::Right);
+    let mut header = "Player Informations".to_string();
+    if state.radio {
+        header.push_str(" │ 📻 Radio");
+    }
+    if let Some(speed) = state.player.speed.filter(|speed| *speed != 100) {
+        header.push_str(&format!(" │ ⏩ {:.2}x", f64::from(speed) / 100.0));
+    }
     let block = Block::new()
         .borders(Borders::ALL)
-        .title("Player Informations");
+        .title(header)
+        .title(volume);
     let duration = if let Some(song) = state.player.song_info.clone() {
         song.duration
     } else {
         Default::default()
     };
-    let title = state.player.song_info.clone().unwrap_or_default().title;
+    let mut title = state.player.song_info.clone().unwrap_or_default().title;
+    let chapter = state
+        .player
+        .chapter
+        .and_then(|index| state.player.chapters.get(index));
+    if let Some(chapter) = chapter {
+        title = format!("{title} │ {}", chapter.title);
+    }
     let player_string = build_player_string(
-        &state.player.position,
+        &state.position,
         &duration,
+        &state.player.chapters,
         (layout.width.checked_sub(2).unwrap_or_default()) as usize,
     );
-    let position = duration_to_string(&state.player.position);
+    let position = if state.show_remaining {
+        format!(
+            "-{}",
+            duration_to_string(&duration.saturating_sub(state.position))
+        )
+    } else {
+        duration_to_string(&state.position)
+    };
     let duration = duration_to_string(&duration);
-    let text = Paragraph::new(format!(
-        "{}/{} {}\n{}",
-        position, duration, title, player_string
-    ))
-    .block(block);
+    let accent = state
+        .active_player
+        .and_then(|player| get_accent(state.clients.entries.get(player)));
+    let title_style = accent.map_or(Style::default(), |color| Style::default().fg(color));
+    let now_playing = Line::from(vec![
+        Span::raw(format!("{}/{} ", position, duration)),
+        Span::styled(title, title_style),
+    ]);
+    // the gauge is grayed out when the player cannot seek
+    let gauge_style = if state.player_capabilities.can_seek {
+        Style::default()
+    } else {
+        Style::default().fg(Color::DarkGray)
+    };
+    let gauge = Line::styled(player_string, gauge_style);
+    let text = Paragraph::new(vec![now_playing, gauge]).block(block);
     f.render_widget(text, layout)
 }
-fn make_render_widget(widget: &Widget, prompt_string: String) -> RenderWidget {
+/// Entries of a list of choices, preceded by the first of `boxes` when they are checked
+/// and by the second otherwise
+fn choices_list(content: &[(bool, String)], cursor: usize, boxes: (&str, &str)) -> String {
+    let mut lines: Vec<String> = content
+        .iter()
+        .enumerate()
+        .map(|(i, (checked, entry))| {
+            let pointer = if i == cursor { '>' } else { ' ' };
+            let mark = if *checked { boxes.0 } else { boxes.1 };
+            format!("{pointer} {mark} {entry}")
+        })
+        .collect();
+    lines.push(String::new());
+    lines.push("↑/↓ to move, Space to select, Enter to confirm".to_string());
+    lines.join("\n")
+}
+fn make_render_widget(widget: &Widget, prompt_string: String, cursor: usize) -> RenderWidget {
     match widget {
         Widget::Widget(widget) => match widget {
             InterfaceWidget::Alert { title, content } => RenderWidget {
@@ -552,14 +1759,52 @@ fn make_render_widget(widget: &Widget, prompt_string: String) -> RenderWidget {
                 prompt: None,
                 max_height: None,
             },
-            InterfaceWidget::Checkboxes { .. } => todo!(),
-            InterfaceWidget::Radioboxes { .. } => todo!(),
+            InterfaceWidget::Checkboxes { title, content, .. } => RenderWidget {
+                title: title.clone(),
+                content: choices_list(content, cursor, ("[x]", "[ ]")),
+                prompt: None,
+                max_height: None,
+            },
+            InterfaceWidget::Radioboxes { title, content, .. } => RenderWidget {
+                title: title.clone(),
+                content: choices_list(content, cursor, ("(•)", "( )")),
+                prompt: None,
+                max_height: None,
+            },
             InterfaceWidget::PromptBox { title, content, .. } => RenderWidget {
                 title: title.clone(),
                 content: content.clone(),
                 prompt: Some(prompt_string.clone()),
                 max_height: None,
             },
+            InterfaceWidget::Form { title, fields, .. } => {
+                let width = fields
+                    .iter()
+                    .map(|f| f.label.len())
+                    .max()
+                    .unwrap_or_default();
+                let mut lines: Vec<String> = fields
+                    .iter()
+                    .enumerate()
+                    .map(|(i, field)| {
+                        let pointer = if i == cursor { '>' } else { ' ' };
+                        let value = if field.hidden {
+                            "*".repeat(field.value.chars().count())
+                        } else {
+                            field.value.clone()
+                        };
+                        format!("{pointer} {:<width$}: {value}", field.label)
+                    })
+                    .collect();
+                lines.push(String::new());
+                lines.push("Tab/↑/↓ to change field, Enter to confirm".to_string());
+                RenderWidget {
+                    title: title.clone(),
+                    max_height: Some(u16::try_from(lines.len() + 2).unwrap_or(u16::MAX)),
+                    content: lines.join("\n"),
+                    prompt: None,
+                }
+            }
         },
         Widget::CommandPrompt => RenderWidget {
             title: "Command Prompt".to_string(),
@@ -567,7 +1812,64 @@ fn make_render_widget(widget: &Widget, prompt_string: String) -> RenderWidget {
             prompt: Some(prompt_string.clone()),
             max_height: Some(3),
         },
+        Widget::FindPrompt => RenderWidget {
+            title: "Find".to_string(),
+            content: String::new(),
+            prompt: Some(prompt_string.clone()),
+            max_height: Some(3),
+        },
+        Widget::Equalizer { gains, band } => {
+            let content = equalizer_chart(gains, *band);
+            RenderWidget {
+                title: "Equalizer".to_string(),
+                max_height: Some(content.lines().count() as u16 + 2),
+                content,
+                prompt: None,
+            }
+        }
+    }
+}
+
+/// Bars of the equalizer bands, from +[EQUALIZER_MAX_GAIN] dB at the top
+/// to -[EQUALIZER_MAX_GAIN] dB at the bottom, followed by the gain of each band
+fn equalizer_chart(gains: &[i8], band: usize) -> String {
+    let step = EQUALIZER_MAX_GAIN / 4;
+    let gain = |i: usize| gains.get(i).copied().unwrap_or_default();
+    let mut lines = Vec::new();
+    for level in (-4..=4).rev().map(|i| i * step) {
+        let mut line = format!("{:<4}", format!("{level:+}"));
+        for i in 0..EQUALIZER_BANDS.len() {
+            let filled = (level > 0 && gain(i) >= level) || (level < 0 && gain(i) <= level);
+            let cell = match (filled, level) {
+                (true, _) => '█',
+                (false, 0) => '─',
+                (false, _) => ' ',
+            };
+            line.push_str(&format!("{cell:^5}"));
+        }
+        lines.push(line);
+    }
+    let mut frequencies = "Hz  ".to_string();
+    let mut values = "dB  ".to_string();
+    for (i, freq) in EQUALIZER_BANDS.iter().enumerate() {
+        let freq = if *freq >= 1000 {
+            format!("{}k", freq / 1000)
+        } else {
+            freq.to_string()
+        };
+        let value = if i == band {
+            format!("[{:+}]", gain(i))
+        } else {
+            format!("{:+}", gain(i))
+        };
+        frequencies.push_str(&format!("{freq:^5}"));
+        values.push_str(&format!("{value:^5}"));
     }
+    lines.push(frequencies);
+    lines.push(values);
+    lines.push(String::new());
+    lines.push("←/→ select a band, ↑/↓ change its gain, Enter to apply".to_string());
+    lines.join("\n")
 }
 
 impl Drop for Tui {